@@ -0,0 +1,104 @@
+//! Random producer/consumer runs through the blocking ring buffer
+//!
+//! `push` blocks while the buffer is full and `pop` while it is empty, so a
+//! free-form script could deadlock. Instead the input picks the capacity,
+//! the wait queue, how many values each producer pushes and where threads
+//! yield, and the consumers split the total between them so that every
+//! blocked call is eventually woken.
+
+#![no_main]
+
+// Base dependencies
+use std::sync::{Barrier, Condvar};
+use std::thread;
+
+// Third-party dependencies
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+// Project dependencies
+use multi_thread_rust::tools::shared_state::fair_queue::{BoundedBuffer, FifoCondition, WaitQueue};
+use multi_thread_rust_fuzz::{assert_conserved, assert_fifo_per_producer, tag, MAX_OPS, MAX_THREADS};
+
+/// Shape of one run; every `bool` says whether the thread yields before that operation
+#[derive(Arbitrary, Debug)]
+struct Run {
+    capacity: u8,
+    fifo_wakeups: bool,
+    producers: Vec<Vec<bool>>,
+    consumers: Vec<Vec<bool>>,
+}
+
+/// Run the producers and consumers, returning what each producer pushed and what each consumer popped
+fn exchange<C: WaitQueue>(buffer: BoundedBuffer<u64, C>, producers: &[&[bool]], consumers: &[&[bool]]) -> (Vec<usize>, Vec<Vec<u64>>) {
+    let total: usize = producers.iter().map(|yields| yields.len()).sum();
+    let barrier = Barrier::new(producers.len() + consumers.len());
+
+    let popped = thread::scope(|scope| {
+        for (thread_id, yields) in producers.iter().enumerate() {
+            let (buffer, barrier) = (&buffer, &barrier);
+            scope.spawn(move || {
+                barrier.wait();
+                for (sequence, yield_first) in yields.iter().enumerate() {
+                    if *yield_first {
+                        thread::yield_now();
+                    }
+                    buffer.push(tag(thread_id, sequence));
+                }
+            });
+        }
+
+        // Consumers take equal shares of the total, the first ones one extra value each for the remainder
+        let handles: Vec<_> = consumers
+            .iter()
+            .enumerate()
+            .map(|(index, yields)| {
+                let share = total / consumers.len() + usize::from(index < total % consumers.len());
+                let (buffer, barrier) = (&buffer, &barrier);
+                scope.spawn(move || {
+                    barrier.wait();
+                    (0..share)
+                        .map(|pop| {
+                            if yields.get(pop).copied().unwrap_or(false) {
+                                thread::yield_now();
+                            }
+                            buffer.pop()
+                        })
+                        .collect::<Vec<u64>>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    (producers.iter().map(|yields| yields.len()).collect(), popped)
+}
+
+fuzz_target!(|run: Run| {
+    let producers: Vec<&[bool]> = run
+        .producers
+        .iter()
+        .take(MAX_THREADS)
+        .map(|yields| &yields[..yields.len().min(MAX_OPS)])
+        .collect();
+    let consumers: Vec<&[bool]> = run
+        .consumers
+        .iter()
+        .take(MAX_THREADS)
+        .map(|yields| &yields[..yields.len().min(MAX_OPS)])
+        .collect();
+    if producers.is_empty() || consumers.is_empty() {
+        return;
+    }
+    let capacity = usize::from(run.capacity % 8) + 1;
+
+    let (pushed, popped) = if run.fifo_wakeups {
+        exchange(BoundedBuffer::new(capacity, FifoCondition::new(), FifoCondition::new()), &producers, &consumers)
+    } else {
+        exchange(BoundedBuffer::new(capacity, Condvar::new(), Condvar::new()), &producers, &consumers)
+    };
+
+    let popped: Vec<&[u64]> = popped.iter().map(Vec::as_slice).collect();
+    assert_conserved(&pushed, &popped);
+    assert_fifo_per_producer(&popped);
+});
@@ -0,0 +1,53 @@
+//! Random push/pop scripts on the Michael-Scott queue from up to four threads
+//!
+//! Every value must come out exactly once, no thread may see a producer's
+//! values out of order, and a single-thread script must behave exactly like
+//! a `VecDeque`.
+
+#![no_main]
+
+// Base dependencies
+use std::collections::VecDeque;
+
+// Third-party dependencies
+use libfuzzer_sys::fuzz_target;
+
+// Project dependencies
+use multi_thread_rust::tools::shared_state::ms_queue::{ConcurrentQueue, MsQueue};
+use multi_thread_rust_fuzz::{assert_conserved, assert_fifo_per_producer, run_script, tag, Op, Script};
+
+fuzz_target!(|script: Script| {
+    let threads = script.threads();
+    if threads.is_empty() {
+        return;
+    }
+
+    let queue = MsQueue::new();
+    let traces = run_script(&threads, |value| queue.push(value), || queue.pop());
+    let leftover: Vec<u64> = std::iter::from_fn(|| queue.pop()).collect();
+
+    let pushed: Vec<usize> = traces.iter().map(|trace| trace.pushed).collect();
+    let mut popped: Vec<&[u64]> = traces.iter().map(|trace| trace.popped.as_slice()).collect();
+    popped.push(&leftover);
+    assert_conserved(&pushed, &popped);
+    assert_fifo_per_producer(&popped);
+
+    // Alone, the queue has no excuse to differ from the sequential model
+    if let [ops] = threads.as_slice() {
+        let mut model = VecDeque::new();
+        let mut model_popped = vec![];
+        let mut pushes = 0;
+        for op in ops.iter() {
+            match op {
+                Op::Push => {
+                    model.push_back(tag(0, pushes));
+                    pushes += 1;
+                }
+                Op::Pop => model_popped.extend(model.pop_front()),
+                Op::Yield => {}
+            }
+        }
+        assert_eq!(traces[0].popped, model_popped);
+        assert_eq!(leftover, Vec::from(model));
+    }
+});
@@ -0,0 +1,49 @@
+//! Random push/pop scripts on the Treiber stack from up to four threads
+//!
+//! Every value must come out exactly once, and a single-thread script must
+//! behave exactly like a `Vec`.
+
+#![no_main]
+
+// Third-party dependencies
+use libfuzzer_sys::fuzz_target;
+
+// Project dependencies
+use multi_thread_rust::tools::shared_state::treiber::{ConcurrentStack, TreiberStack};
+use multi_thread_rust_fuzz::{assert_conserved, run_script, tag, Op, Script};
+
+fuzz_target!(|script: Script| {
+    let threads = script.threads();
+    if threads.is_empty() {
+        return;
+    }
+
+    let stack = TreiberStack::new();
+    let traces = run_script(&threads, |value| stack.push(value), || stack.pop());
+    let leftover: Vec<u64> = std::iter::from_fn(|| stack.pop()).collect();
+
+    let pushed: Vec<usize> = traces.iter().map(|trace| trace.pushed).collect();
+    let mut popped: Vec<&[u64]> = traces.iter().map(|trace| trace.popped.as_slice()).collect();
+    popped.push(&leftover);
+    assert_conserved(&pushed, &popped);
+
+    // Alone, the stack has no excuse to differ from the sequential model
+    if let [ops] = threads.as_slice() {
+        let mut model = vec![];
+        let mut model_popped = vec![];
+        let mut pushes = 0;
+        for op in ops.iter() {
+            match op {
+                Op::Push => {
+                    model.push(tag(0, pushes));
+                    pushes += 1;
+                }
+                Op::Pop => model_popped.extend(model.pop()),
+                Op::Yield => {}
+            }
+        }
+        model.reverse();
+        assert_eq!(traces[0].popped, model_popped);
+        assert_eq!(leftover, model);
+    }
+});
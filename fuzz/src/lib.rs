@@ -0,0 +1,131 @@
+/*
+    Shared harness of the fuzz targets: operation scripts, a thread runner and invariant checks
+*/
+
+// Base dependencies
+use std::sync::Barrier;
+use std::thread;
+
+// Third-party dependencies
+use arbitrary::Arbitrary;
+
+/// Most threads a single input runs, so every input stays fast
+pub const MAX_THREADS: usize = 4;
+
+/// Most operations a single thread runs
+pub const MAX_OPS: usize = 256;
+
+/// One step of a thread script
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Op {
+    Push,
+    Pop,
+    /// Give up the CPU, nudging the interleaving the input runs under
+    Yield,
+}
+
+/// One operation list per thread, all started at once
+#[derive(Arbitrary, Debug)]
+pub struct Script {
+    threads: Vec<Vec<Op>>,
+}
+
+impl Script {
+    /// The thread scripts, capped at `MAX_THREADS` threads of `MAX_OPS` operations
+    pub fn threads(&self) -> Vec<&[Op]> {
+        self.threads
+            .iter()
+            .take(MAX_THREADS)
+            .map(|ops| &ops[..ops.len().min(MAX_OPS)])
+            .collect()
+    }
+}
+
+/// Value pushed by `thread` as its `sequence`-th push, so every value is unique and traceable
+pub fn tag(thread: usize, sequence: usize) -> u64 {
+    ((thread as u64) << 32) | sequence as u64
+}
+
+/// Thread and sequence number of a tagged value
+pub fn untag(value: u64) -> (usize, usize) {
+    ((value >> 32) as usize, (value & u64::from(u32::MAX)) as usize)
+}
+
+/// What one thread did while running its script
+#[derive(Debug)]
+pub struct Trace {
+    pub pushed: usize,
+    pub popped: Vec<u64>,
+}
+
+/// Run every thread script at once against `push` and `pop`
+pub fn run_script(threads: &[&[Op]], push: impl Fn(u64) + Sync, pop: impl Fn() -> Option<u64> + Sync) -> Vec<Trace> {
+    let barrier = Barrier::new(threads.len());
+    thread::scope(|scope| {
+        let handles: Vec<_> = threads
+            .iter()
+            .enumerate()
+            .map(|(thread_id, ops)| {
+                let (barrier, push, pop) = (&barrier, &push, &pop);
+                scope.spawn(move || {
+                    barrier.wait();
+                    let mut trace = Trace {
+                        pushed: 0,
+                        popped: vec![],
+                    };
+                    for op in ops.iter() {
+                        match op {
+                            Op::Push => {
+                                push(tag(thread_id, trace.pushed));
+                                trace.pushed += 1;
+                            }
+                            Op::Pop => trace.popped.extend(pop()),
+                            Op::Yield => thread::yield_now(),
+                        }
+                    }
+                    trace
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Every pushed value came out exactly once, either popped by a thread or left over at the end
+pub fn assert_conserved(pushed: &[usize], popped: &[&[u64]]) {
+    let mut seen: Vec<Vec<bool>> = pushed.iter().map(|count| vec![false; *count]).collect();
+    for value in popped.iter().flat_map(|values| values.iter()) {
+        let (thread_id, sequence) = untag(*value);
+        let slot = seen
+            .get_mut(thread_id)
+            .and_then(|values| values.get_mut(sequence))
+            .unwrap_or_else(|| panic!("popped {:?}, which was never pushed", (thread_id, sequence)));
+        assert!(!*slot, "popped {:?} twice", (thread_id, sequence));
+        *slot = true;
+    }
+    for (thread_id, values) in seen.iter().enumerate() {
+        if let Some(sequence) = values.iter().position(|seen| !seen) {
+            panic!("lost {:?}", (thread_id, sequence));
+        }
+    }
+}
+
+/// Within each list of popped values, the values of any one producer appear in push order
+pub fn assert_fifo_per_producer(popped: &[&[u64]]) {
+    for values in popped {
+        let mut last: Vec<Option<usize>> = vec![None; MAX_THREADS];
+        for value in values.iter() {
+            let (thread_id, sequence) = untag(*value);
+            if let Some(last) = last.get_mut(thread_id) {
+                assert!(
+                    last.is_none_or(|last| last < sequence),
+                    "producer {} value {} popped after value {:?}",
+                    thread_id,
+                    sequence,
+                    last
+                );
+                *last = Some(sequence);
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/*
+    End-of-run audit of threads, Tokio tasks, traced channels and tracked Arcs
+*/
+
+// Base dependencies
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use tokio::runtime::Runtime;
+
+// Project dependencies
+use crate::common;
+use crate::traced;
+
+/// A tracked allocation, reporting how many strong references are still alive
+type StrongCount = Box<dyn Fn() -> usize + Send>;
+
+/// How long threads and tasks that were told to stop get to actually go away before they count as leaked
+///
+/// Scoped threads and Rayon pools signal completion before the OS thread is gone,
+/// and a Tokio worker releases a finished task just after its output is handed over,
+/// so the counts can briefly lag behind a correct shutdown.
+const GRACE: Duration = Duration::from_millis(200);
+
+/// Everything the audit checks once the demo has returned
+struct Auditor {
+    baseline_threads: Option<u64>,
+    runtimes: Mutex<Vec<(String, usize)>>,
+    arcs: Mutex<Vec<(String, StrongCount)>>,
+}
+
+/// Global auditor, only initialized when the audit is enabled
+static AUDITOR: OnceLock<Auditor> = OnceLock::new();
+
+/// Start auditing, taking the number of live OS threads as the baseline
+pub fn enable() {
+    // Rayon's global pool lives for the whole process, so it belongs to the baseline
+    rayon::current_num_threads();
+
+    AUDITOR.get_or_init(|| Auditor {
+        baseline_threads: common::thread_count(),
+        runtimes: Mutex::new(vec![]),
+        arcs: Mutex::new(vec![]),
+    });
+}
+
+/// Whether the end-of-run audit is enabled
+pub fn is_enabled() -> bool {
+    AUDITOR.get().is_some()
+}
+
+/// Expect every strong reference to `value` to be dropped by the end of the run
+pub fn track<T: ?Sized + Send + Sync + 'static>(name: &str, value: &Arc<T>) {
+    let Some(auditor) = AUDITOR.get() else {
+        return;
+    };
+
+    let weak: Weak<T> = Arc::downgrade(value);
+    auditor
+        .arcs
+        .lock()
+        .unwrap()
+        .push((name.to_string(), Box::new(move || weak.strong_count())));
+}
+
+/// Record the Tokio tasks still alive on a runtime that is about to be shut down
+///
+/// Dropping a runtime silently cancels its pending tasks, so this has to be
+/// called before the drop, once every task should have completed or been aborted.
+pub fn runtime(name: &str, runtime: &Runtime) {
+    let Some(auditor) = AUDITOR.get() else {
+        return;
+    };
+
+    let deadline = Instant::now() + GRACE;
+    let mut alive = runtime.metrics().num_alive_tasks();
+    while alive > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+        alive = runtime.metrics().num_alive_tasks();
+    }
+    auditor.runtimes.lock().unwrap().push((name.to_string(), alive));
+}
+
+/// Number of live OS threads once the stragglers above the baseline had the grace period to exit
+fn settled_thread_count(baseline: u64) -> Option<u64> {
+    let deadline = Instant::now() + GRACE;
+    loop {
+        let current = common::thread_count()?;
+        if current <= baseline || Instant::now() >= deadline {
+            return Some(current);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Print every leak found, returning how many there were
+pub fn report() -> usize {
+    let Some(auditor) = AUDITOR.get() else {
+        return 0;
+    };
+    let mut leaks = vec![];
+
+    // Threads: anything above the baseline was spawned and never finished
+    let current_threads = auditor.baseline_threads.and_then(settled_thread_count);
+    match (auditor.baseline_threads, current_threads) {
+        (Some(baseline), Some(current)) if current > baseline => {
+            leaks.push(format!("{} thread(s) still running ({} at start, {} now)", current - baseline, baseline, current));
+        }
+        (Some(_), Some(_)) => {}
+        _ => common::print_info("Thread count is not available on this platform, skipping the thread check"),
+    }
+
+    // Tokio tasks: alive when their runtime was about to shut down
+    for (name, alive) in auditor.runtimes.lock().unwrap().iter() {
+        if *alive > 0 {
+            leaks.push(format!("runtime '{}' was shut down with {} task(s) still alive", name, alive));
+        }
+    }
+
+    // Traced channels: messages sent but never received
+    for (name, depth) in traced::undrained() {
+        leaks.push(format!("channel '{}' still holds {} message(s)", name, depth));
+    }
+
+    // Tracked Arcs: strong references that outlived the demo
+    for (name, strong_count) in auditor.arcs.lock().unwrap().iter() {
+        let count = strong_count();
+        if count > 0 {
+            leaks.push(format!("Arc '{}' still has {} strong reference(s)", name, count));
+        }
+    }
+
+    println!();
+    if leaks.is_empty() {
+        common::print_success(&format!(
+            "Audit: no leaks ({} runtime(s), {} tracked Arc(s), every traced channel drained)",
+            auditor.runtimes.lock().unwrap().len(),
+            auditor.arcs.lock().unwrap().len()
+        ));
+    }
+    for leak in &leaks {
+        common::print_warning(&format!("Audit: {}", leak));
+    }
+    leaks.len()
+}
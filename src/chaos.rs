@@ -0,0 +1,153 @@
+/*
+    Seeded random yields and sleeps injected at instrumented points to vary thread interleavings
+*/
+
+// Base dependencies
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+// Third-party dependencies
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Project dependencies
+use crate::common;
+
+/// Longest sleep injected at a single point
+const MAX_SLEEP: Duration = Duration::from_micros(200);
+
+/// Where a perturbation is injected
+#[derive(Clone, Copy)]
+pub enum Point {
+    /// Right before a lock is acquired
+    Lock,
+    /// Right before a message is sent on a channel
+    Send,
+    /// Right before a task or job starts running
+    TaskStart,
+}
+
+impl Point {
+    const ALL: [Point; 3] = [Point::Lock, Point::Send, Point::TaskStart];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Point::Lock => "lock acquisition",
+            Point::Send => "channel send",
+            Point::TaskStart => "task start",
+        }
+    }
+}
+
+/// What was injected at a point
+enum Perturbation {
+    Nothing,
+    Yield,
+    Sleep(Duration),
+}
+
+/// Seed and counters of the chaos scheduler
+struct Chaos {
+    seed: u64,
+    next_thread: AtomicU64,
+    yields: [AtomicU64; 3],
+    sleeps: [AtomicU64; 3],
+}
+
+/// Global chaos state, only initialized when `--chaos` is given
+static CHAOS: OnceLock<Chaos> = OnceLock::new();
+
+thread_local! {
+    /// Each thread draws from its own generator, derived from the seed and the order threads first hit a point
+    static RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Start injecting perturbations, drawn from generators derived from `seed`
+pub fn enable(seed: u64) {
+    CHAOS.get_or_init(|| Chaos {
+        seed,
+        next_thread: AtomicU64::new(0),
+        yields: Default::default(),
+        sleeps: Default::default(),
+    });
+}
+
+/// Whether chaos mode is enabled
+pub fn is_enabled() -> bool {
+    CHAOS.get().is_some()
+}
+
+/// Roll the dice for one point: half the time nothing, otherwise a yield or a short sleep
+fn draw(chaos: &Chaos, point: Point) -> Perturbation {
+    let perturbation = RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let rng = rng.get_or_insert_with(|| {
+            let index = chaos.next_thread.fetch_add(1, Ordering::Relaxed);
+            StdRng::seed_from_u64(chaos.seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        });
+        match rng.gen_range(0..100) {
+            0..=49 => Perturbation::Nothing,
+            50..=84 => Perturbation::Yield,
+            _ => Perturbation::Sleep(rng.gen_range(Duration::ZERO..MAX_SLEEP)),
+        }
+    });
+
+    match perturbation {
+        Perturbation::Nothing => {}
+        Perturbation::Yield => {
+            chaos.yields[point as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        Perturbation::Sleep(_) => {
+            chaos.sleeps[point as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    perturbation
+}
+
+/// Possibly yield or sleep the current thread at an instrumented point
+pub fn perturb(point: Point) {
+    let Some(chaos) = CHAOS.get() else {
+        return;
+    };
+
+    match draw(chaos, point) {
+        Perturbation::Nothing => {}
+        Perturbation::Yield => thread::yield_now(),
+        Perturbation::Sleep(duration) => thread::sleep(duration),
+    }
+}
+
+/// Possibly yield or sleep the current task at an instrumented point, without blocking its runtime thread
+pub async fn perturb_async(point: Point) {
+    let Some(chaos) = CHAOS.get() else {
+        return;
+    };
+
+    match draw(chaos, point) {
+        Perturbation::Nothing => {}
+        Perturbation::Yield => tokio::task::yield_now().await,
+        Perturbation::Sleep(duration) => tokio::time::sleep(duration).await,
+    }
+}
+
+/// Print the seed and how many perturbations were injected at each point
+pub fn report() {
+    let Some(chaos) = CHAOS.get() else {
+        return;
+    };
+
+    println!();
+    common::print_info(&format!("Chaos seed {} (pass --chaos {} to reuse it)", chaos.seed, chaos.seed));
+    println!("{:<18} {:>10} {:>10}", "point", "yields", "sleeps");
+    for point in Point::ALL {
+        println!(
+            "{:<18} {:>10} {:>10}",
+            point.label(),
+            chaos.yields[point as usize].load(Ordering::Relaxed),
+            chaos.sleeps[point as usize].load(Ordering::Relaxed)
+        );
+    }
+}
@@ -2,6 +2,10 @@
     Common utilities and types used across examples
 */
 
+// Base dependencies
+use std::fs;
+use std::time::Duration;
+
 // Third-party dependencies
 use colored::Colorize;
     
@@ -25,3 +29,77 @@ pub fn print_info(text: &str) {
 pub fn print_warning(text: &str) {
     println!("{} {}", "⚠".yellow(), text);
 }
+
+/// Print an error message
+pub fn print_error(text: &str) {
+    println!("{} {}", "✗".red(), text);
+}
+
+/// Return the value at percentile `p` (0.0 - 100.0) of an ascending sorted slice
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+/// Read a numeric field from `/proc/self/status`
+fn proc_status_value(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Read a memory field (reported in kB) from `/proc/self/status`, in bytes
+fn proc_status_bytes(field: &str) -> Option<u64> {
+    proc_status_value(field).map(|kilobytes| kilobytes * 1024)
+}
+
+/// Number of OS threads currently alive in the process, if the platform exposes it (Linux `/proc`)
+pub fn thread_count() -> Option<u64> {
+    proc_status_value("Threads:")
+}
+
+/// Resident set size of the current process in bytes, if the platform exposes it (Linux `/proc`)
+pub fn resident_memory_bytes() -> Option<u64> {
+    proc_status_bytes("VmRSS:")
+}
+
+/// Virtual memory size of the current process in bytes, if the platform exposes it (Linux `/proc`)
+pub fn virtual_memory_bytes() -> Option<u64> {
+    proc_status_bytes("VmSize:")
+}
+
+/// CPU time consumed so far by the calling thread, if the platform exposes it
+pub fn thread_cpu_time() -> Option<Duration> {
+    #[cfg(unix)]
+    {
+        let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } == 0 {
+            return Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32));
+        }
+    }
+    None
+}
+
+/// Hand memory freed by earlier work back to the OS, so the next RSS reading starts from a clean baseline
+pub fn release_free_memory() {
+    // glibc keeps freed chunks in its arenas; other allocators and platforms are left alone
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    unsafe {
+        libc::malloc_trim(0);
+    }
+}
+
+/// Format a byte count with a binary unit
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, units[unit])
+}
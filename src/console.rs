@@ -0,0 +1,38 @@
+/*
+    tokio-console instrumentation of the async demos (feature `console`)
+*/
+
+// Project dependencies
+use crate::common;
+
+/// Address the console subscriber serves on unless `TOKIO_CONSOLE_BIND` says otherwise
+#[cfg(feature = "console")]
+const DEFAULT_ADDRESS: &str = "127.0.0.1:6669";
+
+/// Start the console subscriber, or explain why it cannot be done
+///
+/// The subscriber runs its own thread and gRPC server, and records every
+/// task the Tokio runtimes of the demos spawn: its wakes, polls and busy
+/// and idle time. `tokio-console` connects to it and shows them live.
+pub fn start() {
+    #[cfg(all(feature = "console", tokio_unstable))]
+    {
+        console_subscriber::init();
+        let address = std::env::var("TOKIO_CONSOLE_BIND").unwrap_or_else(|_| DEFAULT_ADDRESS.to_string());
+        common::print_info(&format!(
+            "tokio-console subscriber listening on {}: run `tokio-console http://{}` in another terminal",
+            address, address
+        ));
+        common::print_info("Tasks are only shown while they exist: raise --delay or --tasks to keep the demo running longer");
+    }
+
+    // Without the cfg, Tokio emits no task instrumentation and the console would stay empty
+    #[cfg(all(feature = "console", not(tokio_unstable)))]
+    common::print_warning(&format!(
+        "Not starting the console on {}: rebuild with RUSTFLAGS=\"--cfg tokio_unstable\" so Tokio instruments its tasks",
+        DEFAULT_ADDRESS
+    ));
+
+    #[cfg(not(feature = "console"))]
+    common::print_warning("Not starting the console: rebuild with `--features console` and RUSTFLAGS=\"--cfg tokio_unstable\" to enable --console");
+}
@@ -0,0 +1,122 @@
+/*
+    Global CPU budget (--cpus) applied to every demo, optionally enforced with an affinity mask
+*/
+
+// Base dependencies
+use std::sync::OnceLock;
+
+// Third-party dependencies
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use tokio::runtime::{Builder, Runtime};
+
+// Project dependencies
+use crate::common;
+use crate::Commands;
+
+/// Number of CPUs the demos may use, only set when `--cpus` is given
+static BUDGET: OnceLock<usize> = OnceLock::new();
+
+/// Limit every demo to `cpus` CPUs, and with `pin` restrict the process to that many with an affinity mask
+///
+/// Must run before any thread is spawned: threads inherit the affinity mask
+/// of the thread that spawns them, and rayon sizes its global pool only once.
+pub fn limit(cpus: usize, pin: bool) {
+    let cpus = *BUDGET.get_or_init(|| cpus.max(1));
+
+    if let Err(error) = rayon::ThreadPoolBuilder::new().num_threads(cpus).build_global() {
+        common::print_warning(&format!("Could not size the rayon pool to the CPU budget: {}", error));
+    }
+
+    let machine = num_cpus::get();
+    if cpus > machine {
+        common::print_warning(&format!(
+            "The CPU budget of {} is larger than the {} CPU(s) available, so it only sizes the pools",
+            cpus, machine
+        ));
+    }
+    if pin {
+        match pin_process(cpus) {
+            Ok(pinned) => common::print_info(&format!("Pinned to CPU(s) {:?}", pinned)),
+            Err(error) => common::print_warning(&format!("Could not pin the process: {}", error)),
+        }
+    }
+    common::print_info(&format!("CPU budget: {} CPU(s)", cpus));
+}
+
+/// The budget, if `--cpus` was given
+pub fn budget() -> Option<usize> {
+    BUDGET.get().copied()
+}
+
+/// Number of CPUs a demo should size itself for: the budget, or else every CPU of the machine
+pub fn available() -> usize {
+    budget().unwrap_or_else(num_cpus::get)
+}
+
+/// A thread count no larger than the budget
+pub fn clamp(threads: usize) -> usize {
+    budget().map_or(threads, |cpus| threads.min(cpus))
+}
+
+/// Multi-threaded Tokio runtime with one worker thread per budgeted CPU
+pub fn multi_thread_runtime() -> Runtime {
+    let mut builder = Builder::new_multi_thread();
+    if let Some(cpus) = budget() {
+        builder.worker_threads(cpus);
+    }
+    builder.enable_all().build().unwrap()
+}
+
+/// Default the command's `--threads` to the budget when it was not given on the command line
+pub fn apply_to_defaults(command: &mut Commands, matches: &ArgMatches) {
+    let Some(cpus) = budget() else {
+        return;
+    };
+    let Some((_, arguments)) = matches.subcommand() else {
+        return;
+    };
+
+    if arguments.value_source("threads") != Some(ValueSource::DefaultValue) {
+        return;
+    }
+    if let Commands::ThreadPool { threads, .. } | Commands::SharedState { threads, .. } = command {
+        *threads = cpus;
+    }
+}
+
+/// Restrict the calling thread, and every thread it spawns from now on, to the first `cpus` CPUs it may run on
+#[cfg(target_os = "linux")]
+fn pin_process(cpus: usize) -> std::io::Result<Vec<usize>> {
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+
+    // Safety: both sets are plain bitmasks of the size passed to the kernel
+    unsafe {
+        let mut allowed: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size, &mut allowed) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let chosen: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+            .filter(|cpu| libc::CPU_ISSET(*cpu, &allowed))
+            .take(cpus)
+            .collect();
+        let mut mask: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in &chosen {
+            libc::CPU_SET(*cpu, &mut mask);
+        }
+        if libc::sched_setaffinity(0, size, &mask) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(chosen)
+    }
+}
+
+/// Affinity masks are only set on Linux
+#[cfg(not(target_os = "linux"))]
+fn pin_process(_cpus: usize) -> std::io::Result<Vec<usize>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "affinity masks are only supported on Linux",
+    ))
+}
@@ -0,0 +1,131 @@
+/*
+    Log-scale latency histogram with a text rendering
+*/
+
+// Base dependencies
+use std::time::Duration;
+
+/// Number of power-of-two buckets, enough for any Duration in nanoseconds
+const BUCKETS: usize = 65;
+
+/// Width of the longest bar when printing
+const BAR_WIDTH: usize = 40;
+
+/// Latency histogram with one bucket per power of two nanoseconds
+#[derive(Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    total: u64,
+    sum_nanos: u128,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl Histogram {
+    /// Create an empty histogram
+    pub fn new() -> Self {
+        Histogram {
+            counts: [0; BUCKETS],
+            total: 0,
+            sum_nanos: 0,
+            max: Duration::ZERO,
+        }
+    }
+
+    /// Bucket `i` holds values in [2^(i-1), 2^i) nanoseconds, bucket 0 holds zero
+    fn bucket(nanos: u64) -> usize {
+        (u64::BITS - nanos.leading_zeros()) as usize
+    }
+
+    /// Lower and upper bound of a bucket
+    fn bounds(bucket: usize) -> (Duration, Duration) {
+        match bucket {
+            0 => (Duration::ZERO, Duration::from_nanos(1)),
+            _ => (
+                Duration::from_nanos(1 << (bucket - 1)),
+                Duration::from_nanos(1u64.checked_shl(bucket as u32).unwrap_or(u64::MAX)),
+            ),
+        }
+    }
+
+    /// Record one value
+    pub fn record(&mut self, value: Duration) {
+        let nanos = value.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[Histogram::bucket(nanos)] += 1;
+        self.total += 1;
+        self.sum_nanos += nanos as u128;
+        self.max = self.max.max(value);
+    }
+
+    /// Add every value recorded in another histogram
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.sum_nanos += other.sum_nanos;
+        self.max = self.max.max(other.max);
+    }
+
+    /// Number of recorded values
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Mean of the recorded values
+    pub fn mean(&self) -> Duration {
+        match self.total {
+            0 => Duration::ZERO,
+            total => Duration::from_nanos((self.sum_nanos / total as u128) as u64),
+        }
+    }
+
+    /// Largest recorded value
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Upper bound of the bucket holding percentile `p` (0.0 - 100.0), capped at the maximum
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.total == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Histogram::bounds(bucket).1.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Print one bar per bucket between the smallest and largest recorded values
+    pub fn print(&self) {
+        let Some(first) = self.counts.iter().position(|count| *count > 0) else {
+            println!("  (no samples)");
+            return;
+        };
+        let last = self.counts.iter().rposition(|count| *count > 0).unwrap();
+        let tallest = *self.counts.iter().max().unwrap();
+
+        for bucket in first..=last {
+            let (low, high) = Histogram::bounds(bucket);
+            let count = self.counts[bucket];
+            let width = (count as f64 / tallest as f64 * BAR_WIDTH as f64).ceil() as usize;
+            println!(
+                "  {:>10} - {:<10} {:>8} {}",
+                format!("{:?}", low),
+                format!("{:?}", high),
+                count,
+                "█".repeat(width)
+            );
+        }
+    }
+}
@@ -4,12 +4,29 @@
 //! and concurrency patterns in Rust.
 
 
+// Base dependencies
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
 // Third-party dependencies
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 // Re-exporting modules for easier access from main.rs
 pub mod tools;
 pub mod common;
+pub mod trace;
+pub mod metrics;
+pub mod histogram;
+pub mod traced;
+pub mod audit;
+pub mod chaos;
+pub mod pacing;
+pub mod sink;
+pub mod virtual_time;
+pub mod profile;
+pub mod cpus;
+pub mod console;
+pub mod runtime_metrics;
 
 // Base CLI definitions for the application
 #[derive(Parser)]
@@ -19,6 +36,34 @@ pub mod common;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Audit threads, Tokio tasks, traced channels and tracked Arcs for leaks after the run
+    #[arg(long, global = true)]
+    pub audit: bool,
+
+    /// Like --audit, but exit with an error when a leak is found
+    #[arg(long, global = true)]
+    pub assert: bool,
+
+    /// Inject random yields and short sleeps at locks, channel sends and task starts (random seed if none is given)
+    #[arg(long, global = true, value_name = "SEED")]
+    pub chaos: Option<Option<u64>>,
+
+    /// Sample the demo with a profiler and write a flamegraph SVG to FILE (flamegraph.svg if none is given) and its collapsed stacks to FILE with a .folded extension (needs the `profile` feature)
+    #[arg(long, global = true, value_name = "FILE", num_args = 0..=1, require_equals = true, default_missing_value = "flamegraph.svg")]
+    pub profile: Option<PathBuf>,
+
+    /// Serve tokio-console on 127.0.0.1:6669 to watch the async demos' tasks, wakers and poll times live (needs the `console` feature and RUSTFLAGS="--cfg tokio_unstable")
+    #[arg(long, global = true)]
+    pub console: bool,
+
+    /// Limit every demo to N CPUs: rayon and Tokio pools get N threads, --threads defaults to N, and CPU-count based sizing sees N
+    #[arg(long, global = true, value_name = "N")]
+    pub cpus: Option<usize>,
+
+    /// With --cpus, also restrict the process to N CPUs with an affinity mask (Linux only)
+    #[arg(long, global = true, requires = "cpus")]
+    pub pin: bool,
 }
 
 // Create an enum for the different command options
@@ -33,6 +78,14 @@ pub enum Commands {
         /// Number of tasks to execute
         #[arg(short = 'n', long, default_value_t = 10)]
         num_tasks: usize,
+
+        /// Thread pool scenario to run
+        #[arg(long, value_enum, default_value_t = ThreadPoolScenario::Tasks)]
+        scenario: ThreadPoolScenario,
+
+        /// Share of the CPU granted to background work in the cpu-quota scenario, in percent
+        #[arg(long, default_value_t = 25)]
+        quota: u8,
     },
     
     /// Run message passing examples using channels
@@ -44,6 +97,38 @@ pub enum Commands {
         /// Number of messages per sender
         #[arg(short, long, default_value_t = 5)]
         messages: usize,
+
+        /// Message passing scenario to run
+        #[arg(long, value_enum, default_value_t = MessagePassingScenario::Channels)]
+        scenario: MessagePassingScenario,
+
+        /// Record send/receive events to FILE (`.json` for Chrome trace, otherwise a Mermaid sequence diagram)
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+
+        /// Write the happens-before graph to FILE as Graphviz DOT (happens-before scenario only)
+        #[arg(long, value_name = "FILE")]
+        dot: Option<PathBuf>,
+
+        /// Sample channel backlogs during the run and write them to FILE as CSV
+        #[arg(long, value_name = "FILE")]
+        metrics_out: Option<PathBuf>,
+
+        /// Interval between two backlog samples, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 10)]
+        sample_interval: u64,
+
+        /// Print per-channel enqueue-to-dequeue latency histograms and queue depth after the run (channels scenario only)
+        #[arg(long)]
+        channel_stats: bool,
+
+        /// Send pipeline results to a sink, written by a single writer thread
+        #[arg(long, value_enum, value_name = "KIND")]
+        sink: Option<SinkKind>,
+
+        /// File written by the file and SQLite sinks (default: pipeline.csv or pipeline.db)
+        #[arg(long, value_name = "FILE")]
+        sink_path: Option<PathBuf>,
     },
     
     /// Run shared state examples using Mutex and Arc
@@ -55,6 +140,30 @@ pub enum Commands {
         /// Number of increments per thread
         #[arg(short, long, default_value_t = 1000)]
         increments: usize,
+
+        /// Shared state scenario to run
+        #[arg(long, value_enum, default_value_t = SharedStateScenario::Counter)]
+        scenario: SharedStateScenario,
+
+        /// How the counter scenario synchronizes its increments
+        #[arg(long, value_enum, default_value_t = CounterStrategy::Mutex)]
+        strategy: CounterStrategy,
+
+        /// Number of accounts in the bank and stm scenarios
+        #[arg(long, default_value_t = 16)]
+        accounts: usize,
+
+        /// Run the counter scenario for every strategy at 1, 2, 4, ... up to --threads threads
+        #[arg(long)]
+        scaling: bool,
+
+        /// Benchmark the counter workload on std Mutex, parking_lot Mutex, RwLock, atomic and sharded counters at 1, 2, 4, ... up to --threads threads, writing the matrix as CSV
+        #[arg(long, conflicts_with = "scaling")]
+        benchmark: bool,
+
+        /// File the --benchmark matrix is written to
+        #[arg(long, value_name = "FILE", default_value = "shared-state-benchmark.csv")]
+        benchmark_out: PathBuf,
     },
     
     /// Run async/await examples with Tokio
@@ -66,6 +175,78 @@ pub enum Commands {
         /// Delay in milliseconds for each task
         #[arg(short, long, default_value_t = 100)]
         delay: u64,
+
+        /// Async scenario to run
+        #[arg(long, value_enum, default_value_t = AsyncTasksScenario::Examples)]
+        scenario: AsyncTasksScenario,
+
+        /// Run timers on a paused clock that jumps to the next deadline: instant and deterministic
+        #[arg(long)]
+        virtual_time: bool,
+
+        /// Print per-channel enqueue-to-dequeue latency histograms and queue depth after the run (merge-streams scenario only)
+        #[arg(long)]
+        channel_stats: bool,
+
+        /// Most tasks the examples keep in flight at once, spawning the next one as another completes (all of them by default; n of the fan-out scenario)
+        #[arg(long, value_name = "K")]
+        max_in_flight: Option<usize>,
+
+        /// Also run the tasks behind a semaphore of N permits and compare the wall time with no limit
+        #[arg(long, value_name = "N")]
+        concurrency_limit: Option<usize>,
+
+        /// Also start the tasks through a token bucket refilled at N tokens per second, and print the rate achieved
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        rate: Option<u32>,
+
+        /// Race the concurrent tasks of the examples against Ctrl-C: cancel the ones left, print the partial results and stop
+        #[arg(long)]
+        until_ctrl_c: bool,
+
+        /// Time IO-bound, CPU-bound and mixed task sets on the given runtime flavour(s) instead of the examples
+        #[arg(long, value_enum, value_name = "FLAVOR")]
+        runtime: Option<RuntimeFlavor>,
+
+        /// Worker threads of the multi-thread runtime in the --runtime benchmark (default: one per CPU)
+        #[arg(long, value_name = "N", requires = "runtime")]
+        worker_threads: Option<usize>,
+
+        /// File of URLs for the fetch scenario, one per line (default: a local demo server)
+        #[arg(long, value_name = "FILE")]
+        urls_file: Option<PathBuf>,
+
+        /// Retries of a failed request in the fetch scenario
+        #[arg(long, default_value_t = 2)]
+        retries: u32,
+
+        /// Timeout of each request in the fetch scenario, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        request_timeout: u64,
+
+        /// Side of the tcp-echo scenario to run
+        #[arg(long, value_enum, default_value_t = EchoRole::Both)]
+        echo_role: EchoRole,
+
+        /// Port the tcp-echo server binds and its client connects to, on 127.0.0.1
+        #[arg(long, default_value_t = 7878)]
+        port: u16,
+
+        /// Run time of the scheduler scenario, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 2000)]
+        duration: u64,
+
+        /// Worker tasks of the decode, process and sink stages of the pipeline scenario
+        #[arg(long, value_name = "D,P,S", value_delimiter = ',', default_values_t = [1, 4, 1])]
+        stage_workers: Vec<usize>,
+
+        /// Host probed by the port-scan scenario; only scan hosts you are allowed to
+        #[arg(long, default_value = "127.0.0.1", requires = "ports")]
+        host: String,
+
+        /// Ports probed by the port-scan scenario, as START-END or a single port (default: a local demo range)
+        #[arg(long, value_name = "START-END", value_parser = parse_port_range)]
+        ports: Option<RangeInclusive<u16>>,
     },
     
     /// Run parallel iteration examples with Rayon
@@ -77,6 +258,329 @@ pub enum Commands {
         /// Enable benchmark mode
         #[arg(short, long)]
         benchmark: bool,
+
+        /// Run the examples in a Rayon pool of N threads (default: the global pool, one thread per CPU or the --cpus budget)
+        #[arg(short, long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        threads: Option<usize>,
+
+        /// Rerun the map, filter and sort benchmarks on 1, 2, 4, ... up to --threads Rayon threads, with speedup and efficiency per thread count
+        #[arg(long, conflicts_with = "benchmark")]
+        scaling: bool,
     },
 }
 
+// Scenarios available under the thread pool command
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ThreadPoolScenario {
+    /// Submit tasks to the pool and wait for them to complete
+    Tasks,
+
+    /// First-task latency of a cold pool vs a prewarmed one, and the steady state (num-tasks = trials)
+    ColdStart,
+
+    /// Tasks that own cloned payloads vs share them through Arc vs borrow them in a scoped pool
+    Payload,
+
+    /// Work-stealing pool starting with every task on one worker, with a per-worker steal matrix
+    WorkStealing,
+
+    /// Heartbeat health checks that flag, then replace, a deliberately wedged worker
+    HealthCheck,
+
+    /// Background pool tasks duty-cycled to a CPU quota next to latency-sensitive foreground requests (threads = background tasks, num-tasks = requests)
+    CpuQuota,
+
+    /// Three tenants with skewed loads served by FIFO vs weighted fair queuing, with per-tenant shares vs weights
+    FairShare,
+
+    /// Long tasks as resumable steps time-sliced by the pool vs run monolithically, with short-task queueing delay (num-tasks = short tasks)
+    TimeSlice,
+}
+
+// Scenarios available under the message passing command
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MessagePassingScenario {
+    /// Standard library mpsc and crossbeam channel examples
+    Channels,
+
+    /// Gossip-style version vector dissemination (senders = max nodes, messages = updates per node)
+    Gossip,
+
+    /// Batching consumer comparing throughput and latency per batch policy
+    Batching,
+
+    /// Vector clocks on messages and lock acquisitions with a happens-before report
+    HappensBefore,
+
+    /// Zero-capacity rendezvous channels where every send waits for a receiver
+    Rendezvous,
+
+    /// Watch-style configuration broadcast picked up by running workers (senders = workers)
+    ConfigBroadcast,
+
+    /// Data channel combined with a ticker in select! to flush accumulated messages periodically
+    PeriodicFlush,
+
+    /// Kafka-like consumer groups: every group gets each message once, load-balanced inside the group (senders = producers)
+    ConsumerGroups,
+
+    /// Pipelines attached and detached at runtime by sending their input channels over a control channel (senders = pipelines)
+    DynamicTopology,
+
+    /// Sleep vs spin vs hybrid pacing accuracy at sub-millisecond intervals (messages = ticks)
+    Pacing,
+}
+
+// Scenarios available under the shared state command
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SharedStateScenario {
+    /// Counter protected by a Mutex and shared through an Arc
+    Counter,
+
+    /// Bank transfers comparing STM, fine-grained locks and a global lock (increments = transfers)
+    Stm,
+
+    /// Bank transfers under several locking schemes with a continuous balance audit (increments = transfers)
+    Bank,
+
+    /// Bounded buffer built on a FIFO condition queue, compared with Condvar wakeup fairness
+    FairBuffer,
+
+    /// Time-bucketed heatmap of lock wait times as threads come online one by one
+    Contention,
+
+    /// Concurrent inserts and lookups in Mutex<HashMap>, RwLock<HashMap> and DashMap (increments = operations)
+    ConcurrentMap,
+
+    /// Producers and consumers sharing a bounded buffer through Mutex + Condvar (increments = items per producer)
+    ProducerConsumer,
+
+    /// Two locks taken in opposite orders deadlock under a watchdog, then run fixed by lock ordering and try_lock (increments = iterations)
+    DeadlockDemo,
+
+    /// Time spent waiting for vs holding the counter's Mutex, with per-thread wait histograms
+    LockProfile,
+
+    /// Flag-and-data publication and store buffering under Relaxed, Release/Acquire and SeqCst (increments = iterations)
+    MemoryOrdering,
+
+    /// Lock-free Treiber stack vs Mutex<Vec> under concurrent pushes and pops (increments = operations)
+    TreiberStack,
+
+    /// Lock-free Michael-Scott queue vs Mutex<VecDeque> and SegQueue with concurrent producers and consumers (increments = items per producer)
+    MsQueue,
+
+    /// Per-thread atomic counters packed on one cache line vs padded to 64 bytes each (increments = per thread, at most 8 threads)
+    FalseSharing,
+
+    /// Threads racing to build an expensive resource through check-then-build, OnceLock and LazyLock, counting initializer calls
+    LazyInit,
+
+    /// Thread-local histograms merged over a channel vs one shared Mutex histogram (increments = values per thread)
+    ThreadLocal,
+
+    /// Readers loading a config snapshot through ArcSwap vs RwLock while a writer swaps in new versions (threads = readers, increments = reads per reader)
+    ArcSwap,
+
+    /// Sequence lock on a multi-field struct vs an unsynchronized version, counting reader retries and torn reads (threads = readers, increments = writes)
+    Seqlock,
+
+    /// Test-and-set spinlock with exponential backoff vs Mutex for short and long critical sections (increments = short sections per thread)
+    Spinlock,
+
+    /// Writer starved by a stream of readers under a reader-preferring RwLock vs a ticketed fair one and std's RwLock (threads = readers)
+    RwStarvation,
+
+    /// Increment and max-update as compare_exchange loops vs fetch_add, with failed CAS attempts per thread at growing thread counts (increments = updates per thread)
+    CasLoop,
+
+    /// Worker threads stopped cooperatively by a shared AtomicBool flag, set by a timer or Ctrl-C (increments = run time in milliseconds)
+    Shutdown,
+
+    /// A thread panics while holding the counter lock: PoisonError unwrapped vs recovered vs parking_lot's non-poisoning Mutex (increments = updates per thread)
+    Poisoning,
+
+    /// Lazily computed cached value: a Mutex on every lookup vs double-checked locking vs OnceLock (increments = lookups per thread)
+    DoubleChecked,
+
+    /// Scripted ABA on a compare-and-swap stack of reused buffer indices, then plain vs tagged head vs epoch-based reclamation under stress (increments = rounds per thread)
+    Aba,
+
+    /// Multi-phase smoothing of a shared row separated by Barrier waits, with one straggler per phase (increments = cells per thread)
+    Barrier,
+
+    /// Threads mutating disjoint slices of a local Vec with thread::scope, against Arc<Mutex<Vec>> (increments = passes over each slice)
+    Scoped,
+
+    /// Readers holding Arc snapshots of a large structure while a writer publishes modified copies, against in-place updates under a RwLock (increments = reads per reader)
+    Cow,
+}
+
+// Scenarios available under the async tasks command
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AsyncTasksScenario {
+    /// Concurrent tasks, join!, sequential awaits and timeouts
+    Examples,
+
+    /// Spawn rate, peak memory and completion time of a huge number of trivial tasks per runtime flavour
+    SpawnStorm,
+
+    /// Resident and virtual memory per parked OS thread vs per pending task (tasks = units of each)
+    Footprint,
+
+    /// Weighted priority semaphore shared by large and small jobs (tasks = jobs per class, delay = large job duration)
+    PrioritySemaphore,
+
+    /// Two-stage service under overload, accepting everything vs answering 429 past bounded queues (tasks = requests, delay = store time)
+    Backpressure,
+
+    /// select! racing a computation against a timeout and a shutdown signal, showing the losing futures cancelled (tasks = rounds, delay = computation time)
+    Select,
+
+    /// A stream of sensor readings through zip-throttle, filter, map and chunks combinators (tasks = readings, delay = throttle period)
+    Stream,
+
+    /// A tree of tasks sharing CancellationTokens, a subtree then the root cancelled by a timer or Ctrl-C, every task cleaning up (tasks = workers per service, delay = run time)
+    Shutdown,
+
+    /// CPU-bound jobs run inline in tasks vs through spawn_blocking, with a heartbeat timer measuring how late the runtime fires it (tasks = jobs, delay = job duration)
+    Blocking,
+
+    /// Tasks holding a lock across .await: std::sync::Mutex deadlocking or losing updates vs tokio::sync::Mutex, and both without an await inside (tasks = tasks, delay = await under the lock)
+    AsyncMutex,
+
+    /// A small service using all four tokio channels: mpsc requests, oneshot replies, watch config and broadcast shutdown (tasks = clients, delay = pause between requests)
+    Channels,
+
+    /// Work items as a stream of futures, awaited sequentially, with buffered(n) and with buffer_unordered(n) (tasks = items, delay = base duration, --max-in-flight = n)
+    FanOut,
+
+    /// Concurrent HTTP requests with reqwest, retried and timed out, against --urls-file or a local demo server (tasks = demo requests, delay = slow endpoint time, --max-in-flight = limit)
+    Fetch,
+
+    /// TCP echo server with a task per connection, and a client opening many connections at once (tasks = connections, delay = pause between lines)
+    TcpEcho,
+
+    /// TCP chat server fanning messages out over a broadcast channel, with lagged receivers (tasks = clients, delay = pause between messages)
+    Chat,
+
+    /// Parent task spawning children through a scope that joins or cancels them all, vs detached spawns (tasks = children, delay = work per task)
+    StructuredConcurrency,
+
+    /// Hand-built single-threaded executor with a RawWaker, running hand-written futures, compared with Tokio (tasks = futures, delay = timer per future)
+    MiniExecutor,
+
+    /// Recursive async tree traversal with Box::pin, sequential, concurrent and spawned, with stack and task counts (tasks = tree depth, delay = wait per leaf)
+    AsyncRecursion,
+
+    /// Periodic jobs on tokio::time::interval, with an overrunning job under each MissedTickBehavior, for --duration (tasks = steady jobs, delay = base period)
+    Scheduler,
+
+    /// Files written and read back with tokio::fs sequentially and concurrently, and with spawn_blocking + std::fs (tasks = files, delay = file size in KiB, --max-in-flight = files at once)
+    FileIo,
+
+    /// Busy loops starving a heartbeat on a current-thread runtime, without awaits, awaiting ready futures, and with coop budgeting or yield_now (tasks = busy loops, delay = busy time of each in milliseconds)
+    Starvation,
+
+    /// Periodic tasks timed poll by poll, one of them secretly calling std::thread::sleep, flagged by its polls over a threshold (tasks = tasks, delay = wake-up period and blocking time in milliseconds)
+    BlockingDetection,
+
+    /// Wall time and peak memory of join_all, FuturesUnordered and JoinSet awaiting ten to tens of thousands of futures (tasks = largest number of futures)
+    JoinBenchmark,
+
+    /// Async counterpart of the thread pool: worker tasks sharing a bounded mpsc queue, submitters waiting on it when full, and a shutdown that drains it (tasks = jobs, delay = job duration, --max-in-flight = workers)
+    WorkerPool,
+
+    /// Concurrent requests carrying their ID through nested async calls in a task_local!, compared with a thread-local and plain spawns (tasks = requests, delay = request time)
+    TaskLocal,
+
+    /// A select! loop forwarding messages between channels, losing some with a future that is not cancellation safe, then with a reserved Permit or a pinned future (tasks = messages, delay = tick period)
+    CancelSafety,
+
+    /// A timer, a channel and a generated stream merged with select_all and handled in arrival order, with a tally per source (tasks = items per source, delay = timer period)
+    MergeStreams,
+
+    /// Decode, process and sink stages as pools of worker tasks joined by bounded channels, with per-stage throughput (tasks = items, delay = process time)
+    Pipeline,
+
+    /// TCP connect scan of a port range with a per-connection timeout, repeated at rising concurrency limits (tasks = largest limit, delay = connect timeout)
+    PortScan,
+
+    /// WebSocket echo server pushing ticks, and concurrent clients sending and reading on the same connections, with a round-trip latency histogram (tasks = clients, delay = pause between messages)
+    WebsocketEcho,
+
+    /// !Send futures holding an Rc run with spawn_local on a LocalSet, against tokio::spawn with Arc state and a LocalSet per thread (tasks = jobs, delay = job duration)
+    LocalSet,
+}
+
+// Runtime flavours compared by the async tasks --runtime benchmark
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RuntimeFlavor {
+    /// Every task polled on the thread calling block_on
+    CurrentThread,
+
+    /// Tasks spread over a pool of work-stealing worker threads
+    MultiThread,
+
+    /// Both flavours side by side, with the speedup of the multi-thread one
+    Both,
+}
+
+// Sides of the async tcp-echo scenario
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EchoRole {
+    /// Server and client in one process
+    Both,
+
+    /// Only the server, until Ctrl-C
+    Server,
+
+    /// Only the client, against a server already running
+    Client,
+}
+
+// Parser for the --ports range of the port-scan scenario
+fn parse_port_range(value: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = value.split_once('-').unwrap_or((value, value));
+    let parse = |port: &str| port.trim().parse::<u16>().map_err(|_| format!("`{}` is not a port number", port));
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start == 0 || start > end {
+        return Err(format!("`{}` is not a range of ports from 1 to 65535", value));
+    }
+    Ok(start..=end)
+}
+
+// Destinations for pipeline results
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum SinkKind {
+    /// Print each record as it arrives
+    Stdout,
+
+    /// Append records to a CSV file
+    File,
+
+    /// Keep records in memory and print a preview after the run
+    Memory,
+
+    /// Insert records into an SQLite database (requires the `sqlite` feature)
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+// Synchronization strategies for the shared counter
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CounterStrategy {
+    /// Counter protected by a Mutex
+    Mutex,
+
+    /// Lock-free counter using AtomicUsize::fetch_add
+    Atomic,
+
+    /// Counter split into per-thread Mutex shards, summed when read
+    Sharded,
+}
+
+impl CounterStrategy {
+    /// Every strategy, in the order they are compared
+    pub const ALL: [CounterStrategy; 3] = [CounterStrategy::Mutex, CounterStrategy::Atomic, CounterStrategy::Sharded];
+}
@@ -1,34 +1,490 @@
 
 // Project dependencies
-use multi_thread_rust::{common::print_header, Cli, Commands, tools::*};
-use clap::Parser;
+use multi_thread_rust::{audit, chaos, console, cpus, profile, runtime_metrics, common::{print_error, print_header, print_info, print_warning}, metrics, sink, trace, traced, virtual_time, AsyncTasksScenario, Cli, Commands, MessagePassingScenario, SharedStateScenario, SinkKind, ThreadPoolScenario, tools::*};
+use clap::{CommandFactory, FromArgMatches};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 fn main() {
 
     // Instantiate the CLI parser and match on the provided command
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    // The budget sizes rayon's global pool, so it goes before the audit baseline starts that pool
+    if let Some(budget) = cli.cpus {
+        cpus::limit(budget, cli.pin);
+        cpus::apply_to_defaults(&mut cli.command, &matches);
+    }
+
+    // The console runs its own thread, so start it before the audit baseline
+    if cli.console {
+        console::start();
+    }
+
+    // Take the audit baseline before any demo spawns a thread
+    if cli.audit || cli.assert {
+        audit::enable();
+    }
+    if let Some(seed) = cli.chaos {
+        chaos::enable(seed.unwrap_or_else(rand::random));
+    }
+    let profiler = cli.profile.as_deref().and_then(profile::start);
     
     // Match the subcommand ENUM
     match cli.command {
-        Commands::ThreadPool { threads, num_tasks } => {
-            print_header("Thread Pool Example");
-            thread_pool::run(threads, num_tasks);
-        }
-        Commands::MessagePassing { senders, messages } => {
-            print_header("Message Passing Example");
-            message_passing::run(senders, messages);
+        Commands::ThreadPool { threads, num_tasks, scenario, quota } => match scenario {
+            ThreadPoolScenario::Tasks => {
+                print_header("Thread Pool Example");
+                thread_pool::run(threads, num_tasks);
+            }
+            ThreadPoolScenario::ColdStart => {
+                print_header("Thread Pool Cold Start Example");
+                thread_pool::warmup::run(threads, num_tasks);
+            }
+            ThreadPoolScenario::Payload => {
+                print_header("Task Payload Example");
+                thread_pool::payload::run(threads, num_tasks);
+            }
+            ThreadPoolScenario::WorkStealing => {
+                print_header("Work-Stealing Pool Example");
+                thread_pool::work_stealing::run(threads, num_tasks);
+            }
+            ThreadPoolScenario::HealthCheck => {
+                print_header("Worker Health Check Example");
+                thread_pool::health::run(threads, num_tasks);
+            }
+            ThreadPoolScenario::CpuQuota => {
+                print_header("CPU Quota Example");
+                thread_pool::quota::run(threads, num_tasks, quota);
+            }
+            ThreadPoolScenario::FairShare => {
+                print_header("Multi-Tenant Fair Scheduling Example");
+                thread_pool::fair_share::run(threads, num_tasks);
+            }
+            ThreadPoolScenario::TimeSlice => {
+                print_header("Time-Sliced Tasks Example");
+                thread_pool::time_slice::run(threads, num_tasks);
+            }
+        },
+        Commands::MessagePassing { senders, messages, scenario, trace: trace_file, dot, metrics_out, sample_interval, channel_stats, sink: sink_kind, sink_path } => {
+
+            // Start recording channel events and backlogs before any thread is spawned
+            if trace_file.is_some() {
+                trace::enable();
+            }
+            if metrics_out.is_some() {
+                metrics::enable(Duration::from_millis(sample_interval.max(1)));
+            }
+            // Only the default scenario builds its channels with the traced wrappers
+            let traced_scenario = matches!(scenario, MessagePassingScenario::Channels);
+            if channel_stats && !traced_scenario {
+                print_warning("--channel-stats only applies to the channels scenario, ignoring it");
+            }
+            let channel_stats = channel_stats && traced_scenario;
+            if dot.is_some() && !matches!(scenario, MessagePassingScenario::HappensBefore) {
+                print_warning("--dot only applies to the happens-before scenario, ignoring it");
+            }
+            if channel_stats {
+                traced::enable();
+            }
+            let sink_path = sink_path.unwrap_or_else(|| PathBuf::from(if sink_kind == Some(SinkKind::File) { "pipeline.csv" } else { "pipeline.db" }));
+            if let Some(kind) = sink_kind {
+                if let Err(error) = sink::enable(kind, &sink_path) {
+                    print_warning(&format!("Could not open the sink at {}: {}", sink_path.display(), error));
+                }
+            }
+
+            match scenario {
+                MessagePassingScenario::Channels => {
+                    print_header("Message Passing Example");
+                    message_passing::run(senders, messages);
+                }
+                MessagePassingScenario::Gossip => {
+                    print_header("Gossip Dissemination Example");
+                    message_passing::gossip::run(senders, messages);
+                }
+                MessagePassingScenario::Batching => {
+                    print_header("Batching Consumer Example");
+                    message_passing::batching::run(senders, messages);
+                }
+                MessagePassingScenario::HappensBefore => {
+                    print_header("Happens-Before Example");
+                    message_passing::happens_before::run(senders, messages, dot.as_deref());
+                }
+                MessagePassingScenario::Rendezvous => {
+                    print_header("Rendezvous Channel Example");
+                    message_passing::rendezvous::run(messages);
+                }
+                MessagePassingScenario::ConfigBroadcast => {
+                    print_header("Configuration Broadcast Example");
+                    message_passing::watch::run(senders, messages);
+                }
+                MessagePassingScenario::PeriodicFlush => {
+                    print_header("Periodic Flush Example");
+                    message_passing::flush::run(senders, messages);
+                }
+                MessagePassingScenario::ConsumerGroups => {
+                    print_header("Consumer Groups Example");
+                    message_passing::consumer_groups::run(senders, messages);
+                }
+                MessagePassingScenario::DynamicTopology => {
+                    print_header("Dynamic Topology Example");
+                    message_passing::topology::run(senders, messages);
+                }
+                MessagePassingScenario::Pacing => {
+                    print_header("Pacing Accuracy Example");
+                    message_passing::pacing::run(messages);
+                }
+            }
+
+            // Report and write the recorded events once every thread has finished
+            if channel_stats {
+                traced::report();
+            }
+            if sink::is_enabled() {
+                match sink::finish() {
+                    Ok(records) => match sink_kind {
+                        Some(SinkKind::File) => print_info(&format!("Wrote {} records to {}", records, sink_path.display())),
+                        Some(SinkKind::Memory) => {
+                            print_info(&format!("Kept {} records in memory, first ones:", records));
+                            for record in sink::records().iter().take(5) {
+                                println!("  {:>8.1}ms [{}] {}", record.elapsed.as_secs_f64() * 1000.0, record.source, record.payload);
+                            }
+                        }
+                        Some(SinkKind::Stdout) | None => print_info(&format!("Printed {} records", records)),
+                        #[cfg(feature = "sqlite")]
+                        Some(SinkKind::Sqlite) => print_info(&format!("Inserted {} records into {}", records, sink_path.display())),
+                    },
+                    Err(error) => print_warning(&format!("Could not write to the sink: {}", error)),
+                }
+            }
+            if let Some(path) = trace_file {
+                match trace::write(&path) {
+                    Ok(events) => print_info(&format!("Wrote {} trace events to {}", events, path.display())),
+                    Err(error) => print_warning(&format!("Could not write trace to {}: {}", path.display(), error)),
+                }
+            }
+            if let Some(path) = metrics_out {
+                match metrics::write_csv(&path) {
+                    Ok(rows) => print_info(&format!("Wrote {} backlog samples to {}", rows, path.display())),
+                    Err(error) => print_warning(&format!("Could not write metrics to {}: {}", path.display(), error)),
+                }
+            }
         }
-        Commands::SharedState { threads, increments } => {
-            print_header("Shared State Example");
-            shared_state::run(threads, increments);
+        Commands::SharedState { threads, increments, scenario, strategy, accounts, scaling, benchmark, benchmark_out } => match scenario {
+            SharedStateScenario::Counter if benchmark => {
+                print_header("Synchronization Primitive Benchmark");
+                shared_state::benchmark::run(threads, increments, &benchmark_out);
+            }
+            SharedStateScenario::Counter if scaling => {
+                print_header("Contention Scaling Example");
+                shared_state::scaling::run(threads, increments);
+            }
+            SharedStateScenario::Counter => {
+                print_header("Shared State Example");
+                shared_state::run(threads, increments, strategy);
+            }
+            SharedStateScenario::Stm => {
+                print_header("Software Transactional Memory Example");
+                shared_state::stm::run(threads, increments, accounts);
+            }
+            SharedStateScenario::Bank => {
+                print_header("Bank Transfer Consistency Example");
+                shared_state::bank::run(threads, increments, accounts);
+            }
+            SharedStateScenario::FairBuffer => {
+                print_header("Fair Bounded Buffer Example");
+                shared_state::fair_queue::run(threads, increments);
+            }
+            SharedStateScenario::Contention => {
+                print_header("Contention Heatmap Example");
+                shared_state::contention::run(threads, increments);
+            }
+            SharedStateScenario::ConcurrentMap => {
+                print_header("Concurrent Hash Map Example");
+                shared_state::concurrent_map::run(threads, increments);
+            }
+            SharedStateScenario::ProducerConsumer => {
+                print_header("Condvar Producer/Consumer Example");
+                shared_state::producer_consumer::run(threads, increments);
+            }
+            SharedStateScenario::DeadlockDemo => {
+                print_header("Deadlock Example");
+                shared_state::deadlock::run(threads, increments);
+            }
+            SharedStateScenario::LockProfile => {
+                print_header("Lock Contention Profiler Example");
+                shared_state::profiler::run(threads, increments);
+            }
+            SharedStateScenario::MemoryOrdering => {
+                print_header("Memory Ordering Example");
+                shared_state::ordering::run(increments);
+            }
+            SharedStateScenario::TreiberStack => {
+                print_header("Treiber Stack Example");
+                shared_state::treiber::run(threads, increments);
+            }
+            SharedStateScenario::MsQueue => {
+                print_header("Michael-Scott Queue Example");
+                shared_state::ms_queue::run(threads, increments);
+            }
+            SharedStateScenario::FalseSharing => {
+                print_header("False Sharing Example");
+                shared_state::false_sharing::run(threads, increments);
+            }
+            SharedStateScenario::LazyInit => {
+                print_header("Lazy Initialization Example");
+                shared_state::lazy_init::run(threads);
+            }
+            SharedStateScenario::ThreadLocal => {
+                print_header("Thread-Local Accumulation Example");
+                shared_state::thread_local::run(threads, increments);
+            }
+            SharedStateScenario::ArcSwap => {
+                print_header("ArcSwap Read-Mostly Example");
+                shared_state::rcu::run(threads, increments);
+            }
+            SharedStateScenario::Seqlock => {
+                print_header("Seqlock Example");
+                shared_state::seqlock::run(threads, increments);
+            }
+            SharedStateScenario::Spinlock => {
+                print_header("Spinlock Example");
+                shared_state::spinlock::run(threads, increments);
+            }
+            SharedStateScenario::RwStarvation => {
+                print_header("Reader/Writer Starvation Example");
+                shared_state::rw_starvation::run(threads);
+            }
+            SharedStateScenario::CasLoop => {
+                print_header("CAS Retry Loop Example");
+                shared_state::cas_loop::run(threads, increments);
+            }
+            SharedStateScenario::Shutdown => {
+                print_header("Cooperative Shutdown Example");
+                shared_state::shutdown::run(threads, increments);
+            }
+            SharedStateScenario::Poisoning => {
+                print_header("Mutex Poisoning Example");
+                shared_state::poisoning::run(threads, increments);
+            }
+            SharedStateScenario::DoubleChecked => {
+                print_header("Double-Checked Locking Example");
+                shared_state::double_checked::run(threads, increments);
+            }
+            SharedStateScenario::Aba => {
+                print_header("ABA Problem Example");
+                shared_state::aba::run(threads, increments);
+            }
+            SharedStateScenario::Barrier => {
+                print_header("Barrier Phases Example");
+                shared_state::barrier::run(threads, increments);
+            }
+            SharedStateScenario::Scoped => {
+                print_header("Scoped Threads Example");
+                shared_state::scoped::run(threads, increments);
+            }
+            SharedStateScenario::Cow => {
+                print_header("Copy-on-Write Snapshots Example");
+                shared_state::cow::run(threads, increments);
+            }
+        },
+        Commands::AsyncTasks { tasks, delay, scenario, virtual_time: use_virtual_time, channel_stats, max_in_flight, concurrency_limit, rate, until_ctrl_c, runtime, worker_threads, urls_file, retries, request_timeout, echo_role, port, duration, stage_workers, host, ports } => {
+
+            // Timed demos build their runtime through virtual_time::runtime()
+            if use_virtual_time {
+                virtual_time::enable();
+            }
+
+            // Only the merge-streams scenario builds its channel with the traced wrapper
+            let traced_scenario = matches!(scenario, AsyncTasksScenario::MergeStreams);
+            if channel_stats && !traced_scenario {
+                print_warning("--channel-stats only applies to the merge-streams scenario, ignoring it");
+            }
+            let channel_stats = channel_stats && traced_scenario;
+            if channel_stats {
+                traced::enable();
+            }
+            let wall_clock = Instant::now();
+
+            match scenario {
+                AsyncTasksScenario::Examples if runtime.is_some() => {
+                    print_header("Runtime Flavor Benchmark");
+                    async_tasks::flavor::run(tasks, delay, runtime.unwrap(), worker_threads);
+                }
+                AsyncTasksScenario::Examples => {
+                    print_header("Async Tasks Example");
+                    async_tasks::run(tasks, delay, max_in_flight, concurrency_limit, rate, until_ctrl_c);
+                }
+                AsyncTasksScenario::SpawnStorm => {
+                    print_header("Spawn Storm Example");
+                    async_tasks::spawn_storm::run(tasks);
+                }
+                AsyncTasksScenario::Footprint => {
+                    print_header("Threads vs Tasks Memory Footprint Example");
+                    async_tasks::footprint::run(tasks);
+                }
+                AsyncTasksScenario::PrioritySemaphore => {
+                    print_header("Priority Semaphore Example");
+                    async_tasks::priority_semaphore::run(tasks, delay);
+                }
+                AsyncTasksScenario::Backpressure => {
+                    print_header("Backpressure Example");
+                    async_tasks::backpressure::run(tasks, delay);
+                }
+                AsyncTasksScenario::Select => {
+                    print_header("select! Cancellation Example");
+                    async_tasks::select::run(tasks, delay);
+                }
+                AsyncTasksScenario::Stream => {
+                    print_header("Async Stream Example");
+                    async_tasks::stream::run(tasks, delay);
+                }
+                AsyncTasksScenario::Shutdown => {
+                    print_header("Graceful Shutdown Example");
+                    async_tasks::shutdown::run(tasks, delay);
+                }
+                AsyncTasksScenario::Blocking => {
+                    print_header("spawn_blocking Example");
+                    async_tasks::blocking::run(tasks, delay);
+                }
+                AsyncTasksScenario::AsyncMutex => {
+                    print_header("Async vs Std Mutex Example");
+                    async_tasks::async_mutex::run(tasks, delay);
+                }
+                AsyncTasksScenario::Channels => {
+                    print_header("Tokio Channels Example");
+                    async_tasks::channels::run(tasks, delay);
+                }
+                AsyncTasksScenario::FanOut => {
+                    print_header("Stream Fan-Out Example");
+                    async_tasks::fan_out::run(tasks, delay, max_in_flight);
+                }
+                AsyncTasksScenario::Fetch => {
+                    print_header("Concurrent HTTP Fetcher Example");
+                    async_tasks::fetch::run(
+                        tasks,
+                        delay,
+                        urls_file.as_deref(),
+                        max_in_flight,
+                        retries,
+                        Duration::from_millis(request_timeout),
+                    );
+                }
+                AsyncTasksScenario::TcpEcho => {
+                    print_header("TCP Echo Example");
+                    async_tasks::tcp_echo::run(echo_role, port, tasks, delay);
+                }
+                AsyncTasksScenario::Chat => {
+                    print_header("Broadcast Chat Example");
+                    async_tasks::chat::run(tasks, delay);
+                }
+                AsyncTasksScenario::StructuredConcurrency => {
+                    print_header("Structured Concurrency Example");
+                    async_tasks::structured::run(tasks, delay);
+                }
+                AsyncTasksScenario::MiniExecutor => {
+                    print_header("Mini Executor Example");
+                    async_tasks::mini_executor::run(tasks, delay);
+                }
+                AsyncTasksScenario::AsyncRecursion => {
+                    print_header("Async Recursion Example");
+                    async_tasks::recursion::run(tasks, delay);
+                }
+                AsyncTasksScenario::Scheduler => {
+                    print_header("Periodic Scheduler Example");
+                    async_tasks::scheduler::run(tasks, delay, Duration::from_millis(duration));
+                }
+                AsyncTasksScenario::FileIo => {
+                    print_header("Async File I/O Example");
+                    async_tasks::file_io::run(tasks, delay, max_in_flight);
+                }
+                AsyncTasksScenario::Starvation => {
+                    print_header("Cooperative Yielding Example");
+                    async_tasks::starvation::run(tasks, delay);
+                }
+                AsyncTasksScenario::BlockingDetection => {
+                    print_header("Blocking Detection Example");
+                    async_tasks::blocking_detection::run(tasks, delay);
+                }
+                AsyncTasksScenario::JoinBenchmark => {
+                    print_header("join_all vs FuturesUnordered vs JoinSet Benchmark");
+                    async_tasks::join_benchmark::run(tasks);
+                }
+                AsyncTasksScenario::WorkerPool => {
+                    print_header("Async Worker Pool Example");
+                    async_tasks::worker_pool::run(tasks, delay, max_in_flight);
+                }
+                AsyncTasksScenario::TaskLocal => {
+                    print_header("Task-Local Request Context Example");
+                    async_tasks::task_local::run(tasks, delay);
+                }
+                AsyncTasksScenario::CancelSafety => {
+                    print_header("Cancellation Safety Example");
+                    async_tasks::cancel_safety::run(tasks, delay);
+                }
+                AsyncTasksScenario::MergeStreams => {
+                    print_header("Merged Streams Example");
+                    async_tasks::merge_streams::run(tasks, delay);
+                }
+                AsyncTasksScenario::Pipeline => {
+                    print_header("Async Pipeline Example");
+                    async_tasks::pipeline::run(tasks, delay, &stage_workers);
+                }
+                AsyncTasksScenario::PortScan => {
+                    print_header("Port Scan Example");
+                    async_tasks::port_scan::run(tasks, delay, &host, ports);
+                }
+                AsyncTasksScenario::WebsocketEcho => {
+                    print_header("WebSocket Echo Example");
+                    async_tasks::websocket_echo::run(tasks, delay);
+                }
+                AsyncTasksScenario::LocalSet => {
+                    print_header("LocalSet Example");
+                    async_tasks::local_set::run(tasks, delay);
+                }
+            }
+            runtime_metrics::report();
+            if channel_stats {
+                traced::report();
+            }
+
+            if use_virtual_time {
+                print_info(&format!(
+                    "Virtual time: the durations above are simulated, the run took {:?} of wall-clock time",
+                    wall_clock.elapsed()
+                ));
+            }
         }
-        Commands::AsyncTasks { tasks, delay } => {
-            print_header("Async Tasks Example");
-            async_tasks::run(tasks, delay);
+        Commands::ParallelIteration { size, threads, scaling: true, .. } => {
+            print_header("Parallel Iteration Scaling Example");
+            parallel_iteration::scaling::run(size, threads);
         }
-        Commands::ParallelIteration { size, benchmark } => {
+        Commands::ParallelIteration { size, benchmark, threads, .. } => {
             print_header("Parallel Iteration Example");
-            parallel_iteration::run(size, benchmark);
+            parallel_iteration::run(size, benchmark, threads);
+        }
+    }
+
+    chaos::report();
+    if let Some(profiler) = profiler {
+        match profiler.finish() {
+            Ok((flamegraph, folded)) => print_info(&format!(
+                "Profile written to {} (flamegraph) and {} (collapsed stacks)",
+                flamegraph.display(),
+                folded.display()
+            )),
+            Err(error) => print_warning(&format!("Could not write the profile: {}", error)),
+        }
+    }
+
+    // Every demo has returned, so anything still alive has leaked
+    if audit::is_enabled() {
+        let leaks = audit::report();
+        if cli.assert && leaks > 0 {
+            print_error(&format!("Audit failed with {} leak(s)", leaks));
+            std::process::exit(1);
         }
     }
 }
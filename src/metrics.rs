@@ -0,0 +1,128 @@
+/*
+    Channel backlog gauges sampled over time and exported as CSV
+*/
+
+// Base dependencies
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// One sampled backlog value
+struct Sample {
+    elapsed: Duration,
+    channel: String,
+    backlog: i64,
+}
+
+/// Registered gauges, the background sampler and what it has collected
+struct Sampler {
+    gauges: Mutex<Vec<(String, Weak<AtomicI64>)>>,
+    samples: Mutex<Vec<Sample>>,
+    stop: AtomicBool,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Global sampler, only initialized when metrics are enabled
+static SAMPLER: OnceLock<Sampler> = OnceLock::new();
+
+/// Number of messages sent on a channel but not yet received
+///
+/// Senders call `add` before sending and receivers call `sub` after receiving,
+/// so the backlog never goes negative.
+/// When metrics are disabled a gauge is a no-op.
+#[derive(Clone)]
+pub struct Gauge(Option<Arc<AtomicI64>>);
+
+impl Gauge {
+    /// Record `n` messages entering the channel
+    pub fn add(&self, n: usize) {
+        if let Some(backlog) = &self.0 {
+            backlog.fetch_add(n as i64, Ordering::Relaxed);
+        }
+    }
+
+    /// Record `n` messages leaving the channel
+    pub fn sub(&self, n: usize) {
+        if let Some(backlog) = &self.0 {
+            backlog.fetch_sub(n as i64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Register a backlog gauge for the named channel; it is sampled until every clone is dropped
+pub fn gauge(channel: &str) -> Gauge {
+    let Some(sampler) = SAMPLER.get() else {
+        return Gauge(None);
+    };
+
+    let backlog = Arc::new(AtomicI64::new(0));
+    sampler
+        .gauges
+        .lock()
+        .unwrap()
+        .push((channel.to_string(), Arc::downgrade(&backlog)));
+    Gauge(Some(backlog))
+}
+
+/// Start sampling every registered gauge at the given interval
+pub fn enable(interval: Duration) {
+    let sampler = SAMPLER.get_or_init(|| Sampler {
+        gauges: Mutex::new(vec![]),
+        samples: Mutex::new(vec![]),
+        stop: AtomicBool::new(false),
+        handle: Mutex::new(None),
+    });
+
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        while !sampler.stop.load(Ordering::Acquire) {
+            thread::sleep(interval);
+            let elapsed = start.elapsed();
+
+            // Sample live gauges and forget the ones whose channel is gone
+            let mut gauges = sampler.gauges.lock().unwrap();
+            gauges.retain(|(_, backlog)| backlog.strong_count() > 0);
+            let mut samples = sampler.samples.lock().unwrap();
+            for (channel, backlog) in gauges.iter() {
+                if let Some(backlog) = backlog.upgrade() {
+                    samples.push(Sample {
+                        elapsed,
+                        channel: channel.clone(),
+                        backlog: backlog.load(Ordering::Relaxed),
+                    });
+                }
+            }
+        }
+    });
+    *sampler.handle.lock().unwrap() = Some(handle);
+}
+
+/// Stop sampling and write every sample to `path` as CSV, returning how many rows were written
+pub fn write_csv(path: &Path) -> io::Result<usize> {
+    let Some(sampler) = SAMPLER.get() else {
+        return Ok(0);
+    };
+
+    sampler.stop.store(true, Ordering::Release);
+    if let Some(handle) = sampler.handle.lock().unwrap().take() {
+        handle.join().unwrap();
+    }
+
+    let samples = sampler.samples.lock().unwrap();
+    let mut csv = String::from("elapsed_ms,channel,backlog\n");
+    for sample in samples.iter() {
+        csv.push_str(&format!(
+            "{:.3},{},{}\n",
+            sample.elapsed.as_secs_f64() * 1000.0,
+            sample.channel,
+            sample.backlog
+        ));
+    }
+
+    fs::write(path, csv)?;
+    Ok(samples.len())
+}
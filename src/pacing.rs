@@ -0,0 +1,124 @@
+/*
+    Sub-millisecond pacing: sleeping, spinning and a hybrid of both
+*/
+
+// Base dependencies
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Sleeps measured to estimate how late the OS wakes a sleeping thread
+const CALIBRATION_SLEEPS: usize = 20;
+
+/// Short sleep requested during calibration
+const CALIBRATION_SLEEP: Duration = Duration::from_micros(50);
+
+/// How a thread waits for a deadline
+#[derive(Clone, Copy)]
+pub enum PacingStrategy {
+    /// `thread::sleep` for the remaining time: no CPU used, but woken late by the OS timer slack
+    Sleep,
+    /// Busy-wait on the clock: precise, but burns a whole core while waiting
+    Spin,
+    /// Sleep until just before the deadline, then spin for the last stretch
+    Hybrid,
+}
+
+impl PacingStrategy {
+    /// Every strategy, in the order they are compared
+    pub const ALL: [PacingStrategy; 3] = [PacingStrategy::Sleep, PacingStrategy::Spin, PacingStrategy::Hybrid];
+
+    /// Label of the strategy in reports
+    pub fn label(&self) -> &'static str {
+        match self {
+            PacingStrategy::Sleep => "sleep",
+            PacingStrategy::Spin => "spin",
+            PacingStrategy::Hybrid => "hybrid",
+        }
+    }
+}
+
+/// How late a short sleep returns on this platform, measured once
+///
+/// Linux usually overshoots by tens of microseconds, other platforms by up to
+/// a scheduler tick. The hybrid strategy stops sleeping this far from its deadline.
+pub fn sleep_overshoot() -> Duration {
+    static OVERSHOOT: OnceLock<Duration> = OnceLock::new();
+    *OVERSHOOT.get_or_init(|| {
+        let mut overshoots: Vec<Duration> = (0..CALIBRATION_SLEEPS)
+            .map(|_| {
+                let start = Instant::now();
+                thread::sleep(CALIBRATION_SLEEP);
+                start.elapsed().saturating_sub(CALIBRATION_SLEEP)
+            })
+            .collect();
+        overshoots.sort_unstable();
+
+        // Close to the worst case, so the hybrid sleep rarely runs past the deadline
+        overshoots[overshoots.len() * 9 / 10]
+    })
+}
+
+/// Busy-wait for the given duration, as a stand-in for CPU work or for sub-microsecond precision
+pub fn spin_for(duration: Duration) {
+    spin_until(Instant::now() + duration);
+}
+
+/// Busy-wait until the given instant
+pub fn spin_until(deadline: Instant) {
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Wait until the given instant with the given strategy
+pub fn wait_until(deadline: Instant, strategy: PacingStrategy) {
+    match strategy {
+        PacingStrategy::Sleep => {
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+        }
+        PacingStrategy::Spin => spin_until(deadline),
+        PacingStrategy::Hybrid => {
+            let margin = sleep_overshoot();
+            let now = Instant::now();
+            if deadline > now + margin {
+                thread::sleep(deadline - now - margin);
+            }
+            spin_until(deadline);
+        }
+    }
+}
+
+/// Fires at a fixed interval, scheduling every tick from the start so delays do not accumulate
+pub struct Pacer {
+    interval: Duration,
+    next: Instant,
+    strategy: PacingStrategy,
+}
+
+impl Pacer {
+    /// Pacer using the hybrid strategy, the most accurate one that leaves the CPU idle most of the time
+    pub fn new(interval: Duration) -> Self {
+        Pacer::with_strategy(interval, PacingStrategy::Hybrid)
+    }
+
+    /// Pacer using the given strategy; the first tick is one interval from now
+    pub fn with_strategy(interval: Duration, strategy: PacingStrategy) -> Self {
+        Pacer {
+            interval,
+            next: Instant::now() + interval,
+            strategy,
+        }
+    }
+
+    /// Wait for the next tick, returning the instant it was scheduled for
+    pub fn tick(&mut self) -> Instant {
+        let scheduled = self.next;
+        wait_until(scheduled, self.strategy);
+        self.next += self.interval;
+        scheduled
+    }
+}
@@ -0,0 +1,86 @@
+/*
+    Sampling profiler: a flamegraph and collapsed stacks of the demo (feature `profile`)
+*/
+
+// Base dependencies
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Project dependencies
+use crate::common;
+
+/// Samples per second taken from every thread; a prime, so sampling does not lock step with periodic work
+#[cfg(feature = "profile")]
+const FREQUENCY: i32 = 997;
+
+/// A profile being recorded, written out by `finish`
+pub struct Profile {
+    #[cfg(feature = "profile")]
+    guard: pprof::ProfilerGuard<'static>,
+    path: PathBuf,
+}
+
+/// Start sampling every thread of the process, or explain why it cannot be done
+pub fn start(path: &Path) -> Option<Profile> {
+    #[cfg(feature = "profile")]
+    {
+        // Frames of the C runtime and the signal trampoline only add noise at the root of every stack
+        match pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+        {
+            Ok(guard) => Some(Profile {
+                guard,
+                path: path.to_path_buf(),
+            }),
+            Err(error) => {
+                common::print_warning(&format!("Could not start the profiler: {}", error));
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "profile"))]
+    {
+        common::print_warning(&format!(
+            "Not profiling to {}: rebuild with `--features profile` to enable --profile",
+            path.display()
+        ));
+        None
+    }
+}
+
+impl Profile {
+    /// Stop sampling and write the flamegraph SVG to the profile path and the collapsed stacks next to it
+    ///
+    /// The collapsed stacks (`.folded`) hold one `thread;root;...;leaf count`
+    /// line per distinct stack, the input format of `inferno` and
+    /// `flamegraph.pl`, for diffing runs or rendering them differently.
+    pub fn finish(self) -> io::Result<(PathBuf, PathBuf)> {
+        let folded_path = self.path.with_extension("folded");
+
+        #[cfg(feature = "profile")]
+        {
+            use std::fmt::Write as _;
+            use std::fs::{self, File};
+
+            let report = self.guard.report().build().map_err(io::Error::other)?;
+            report.flamegraph(File::create(&self.path)?).map_err(io::Error::other)?;
+
+            let mut folded = String::new();
+            for (frames, count) in &report.data {
+                let mut line = frames.thread_name_or_id();
+                for frame in frames.frames.iter().rev() {
+                    for symbol in frame.iter().rev() {
+                        let _ = write!(line, ";{}", symbol);
+                    }
+                }
+                let _ = writeln!(folded, "{} {}", line, count);
+            }
+            fs::write(&folded_path, folded)?;
+        }
+
+        Ok((self.path, folded_path))
+    }
+}
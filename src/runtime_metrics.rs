@@ -0,0 +1,155 @@
+/*
+    Tokio scheduler metrics of the async demos' runtimes, reported after the run
+*/
+
+// Base dependencies
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Third-party dependencies
+use tokio::runtime::{Runtime, RuntimeFlavor};
+
+// Project dependencies
+use crate::common;
+
+/// Counters of one worker thread
+struct WorkerSnapshot {
+    busy: Duration,
+    parks: u64,
+    #[cfg(tokio_unstable)]
+    polls: u64,
+    #[cfg(tokio_unstable)]
+    steals: u64,
+    #[cfg(tokio_unstable)]
+    local_schedules: u64,
+    #[cfg(tokio_unstable)]
+    overflows: u64,
+    #[cfg(tokio_unstable)]
+    mean_poll: Duration,
+}
+
+/// Metrics of one runtime, taken when its demo was done with it
+struct Snapshot {
+    name: String,
+    flavor: &'static str,
+    alive_tasks: usize,
+    global_queue_depth: usize,
+    workers: Vec<WorkerSnapshot>,
+    #[cfg(tokio_unstable)]
+    spawned_tasks: u64,
+    #[cfg(tokio_unstable)]
+    remote_schedules: u64,
+    #[cfg(tokio_unstable)]
+    forced_yields: u64,
+    #[cfg(tokio_unstable)]
+    blocking_threads: usize,
+}
+
+/// Snapshots recorded since the start of the run, in order
+static SNAPSHOTS: Mutex<Vec<Snapshot>> = Mutex::new(Vec::new());
+
+/// Record the metrics of a runtime the demo is done with, to be printed by `report`
+///
+/// The counters are cumulative since the runtime was built, so this belongs
+/// right after `audit::runtime`, before the runtime is dropped.
+pub fn record(name: &str, runtime: &Runtime) {
+    let metrics = runtime.metrics();
+    let flavor = match runtime.handle().runtime_flavor() {
+        RuntimeFlavor::CurrentThread => "current_thread",
+        RuntimeFlavor::MultiThread => "multi_thread",
+        _ => "other",
+    };
+    let workers = (0..metrics.num_workers())
+        .map(|worker| WorkerSnapshot {
+            busy: metrics.worker_total_busy_duration(worker),
+            parks: metrics.worker_park_count(worker),
+            #[cfg(tokio_unstable)]
+            polls: metrics.worker_poll_count(worker),
+            #[cfg(tokio_unstable)]
+            steals: metrics.worker_steal_count(worker),
+            #[cfg(tokio_unstable)]
+            local_schedules: metrics.worker_local_schedule_count(worker),
+            #[cfg(tokio_unstable)]
+            overflows: metrics.worker_overflow_count(worker),
+            #[cfg(tokio_unstable)]
+            mean_poll: metrics.worker_mean_poll_time(worker),
+        })
+        .collect();
+
+    SNAPSHOTS.lock().unwrap().push(Snapshot {
+        name: name.to_string(),
+        flavor,
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+        workers,
+        #[cfg(tokio_unstable)]
+        spawned_tasks: metrics.spawned_tasks_count(),
+        #[cfg(tokio_unstable)]
+        remote_schedules: metrics.remote_schedule_count(),
+        #[cfg(tokio_unstable)]
+        forced_yields: metrics.budget_forced_yield_count(),
+        #[cfg(tokio_unstable)]
+        blocking_threads: metrics.num_blocking_threads(),
+    });
+}
+
+/// Print the per-worker metrics of every runtime recorded so far
+pub fn report() {
+    let snapshots = std::mem::take(&mut *SNAPSHOTS.lock().unwrap());
+    if snapshots.is_empty() {
+        return;
+    }
+
+    println!();
+    common::print_info("Runtime metrics:");
+    for snapshot in &snapshots {
+        println!();
+        common::print_info(&format!(
+            "{}: {} runtime, {} worker(s), {} task(s) alive, global queue depth {}",
+            snapshot.name,
+            snapshot.flavor,
+            snapshot.workers.len(),
+            snapshot.alive_tasks,
+            snapshot.global_queue_depth
+        ));
+
+        #[cfg(tokio_unstable)]
+        {
+            common::print_info(&format!(
+                "  {} tasks spawned, {} scheduled through the global queue, {} forced yields, {} blocking threads",
+                snapshot.spawned_tasks, snapshot.remote_schedules, snapshot.forced_yields, snapshot.blocking_threads
+            ));
+            println!(
+                "{:>8} {:>14} {:>8} {:>10} {:>8} {:>12} {:>10} {:>12}",
+                "worker", "busy", "parks", "polls", "steals", "local sched", "overflows", "mean poll"
+            );
+            for (index, worker) in snapshot.workers.iter().enumerate() {
+                println!(
+                    "{:>8} {:>14?} {:>8} {:>10} {:>8} {:>12} {:>10} {:>12?}",
+                    index,
+                    worker.busy,
+                    worker.parks,
+                    worker.polls,
+                    worker.steals,
+                    worker.local_schedules,
+                    worker.overflows,
+                    worker.mean_poll
+                );
+            }
+        }
+
+        #[cfg(not(tokio_unstable))]
+        {
+            println!("{:>8} {:>14} {:>8}", "worker", "busy", "parks");
+            for (index, worker) in snapshot.workers.iter().enumerate() {
+                println!("{:>8} {:>14?} {:>8}", index, worker.busy, worker.parks);
+            }
+        }
+    }
+
+    #[cfg(not(tokio_unstable))]
+    {
+        println!();
+        common::print_info("Polls, steals and queue overflows per worker need a build with RUSTFLAGS=\"--cfg tokio_unstable\"");
+    }
+}
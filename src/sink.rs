@@ -0,0 +1,253 @@
+/*
+    Pluggable output sinks for pipeline results, fed through a single writer thread
+*/
+
+// Base dependencies
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::channel::{self, Sender};
+
+// Project dependencies
+use crate::SinkKind;
+
+/// One result produced by a pipeline stage
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub elapsed: Duration,
+    pub source: &'static str,
+    pub payload: String,
+}
+
+/// Destination of pipeline records
+///
+/// A sink is owned by the writer thread, so it never sees two writes at once
+/// and needs no locking of its own.
+pub trait Sink: Send {
+    /// Store one record
+    fn write(&mut self, record: &Record) -> io::Result<()>;
+
+    /// Make every stored record durable; called once, after the last write
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Prints every record as it arrives
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        writeln!(
+            stdout,
+            "📝 {:>8.1}ms [{}] {}",
+            record.elapsed.as_secs_f64() * 1000.0,
+            record.source,
+            record.payload
+        )
+    }
+}
+
+/// Writes records to a CSV file
+pub struct FileSink {
+    file: BufWriter<File>,
+}
+
+impl FileSink {
+    /// Create (or truncate) the file and write the CSV header
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "elapsed_ms,source,payload")?;
+        Ok(FileSink { file })
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{:.3},{},\"{}\"",
+            record.elapsed.as_secs_f64() * 1000.0,
+            record.source,
+            record.payload.replace('"', "\"\"")
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Keeps records in memory, readable through a shared handle once the writer is done
+#[derive(Default)]
+pub struct MemorySink {
+    records: Arc<Mutex<Vec<Record>>>,
+}
+
+impl MemorySink {
+    /// Handle on the stored records
+    pub fn records(&self) -> Arc<Mutex<Vec<Record>>> {
+        Arc::clone(&self.records)
+    }
+}
+
+impl Sink for MemorySink {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        self.records.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+/// Stores records in a `records` table of an SQLite database, in one transaction
+#[cfg(feature = "sqlite")]
+pub struct SqliteSink {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSink {
+    /// Open (or create) the database and start the transaction holding every record
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let connection = rusqlite::Connection::open(path).map_err(io::Error::other)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS records (elapsed_ms REAL NOT NULL, source TEXT NOT NULL, payload TEXT NOT NULL);
+                 BEGIN;",
+            )
+            .map_err(io::Error::other)?;
+        Ok(SqliteSink { connection })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Sink for SqliteSink {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        self.connection
+            .prepare_cached("INSERT INTO records (elapsed_ms, source, payload) VALUES (?1, ?2, ?3)")
+            .and_then(|mut insert| {
+                insert.execute((record.elapsed.as_secs_f64() * 1000.0, record.source, &record.payload))
+            })
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.connection.execute_batch("COMMIT;").map_err(io::Error::other)
+    }
+}
+
+/// Message from the pipeline threads to the writer thread
+enum Command {
+    Write(Record),
+    Finish,
+}
+
+/// The writer thread, the channel feeding it and the memory sink's records if one is used
+struct Writer {
+    start: Instant,
+    sender: Sender<Command>,
+    handle: Mutex<Option<JoinHandle<io::Result<usize>>>>,
+    memory: Option<Arc<Mutex<Vec<Record>>>>,
+}
+
+/// Global writer, only initialized when a sink is enabled
+static WRITER: OnceLock<Writer> = OnceLock::new();
+
+/// Open the sink of the given kind; `path` is used by the file and SQLite sinks
+pub fn enable(kind: SinkKind, path: &Path) -> io::Result<()> {
+    let mut memory = None;
+    let sink: Box<dyn Sink> = match kind {
+        SinkKind::Stdout => Box::new(StdoutSink),
+        SinkKind::File => Box::new(FileSink::create(path)?),
+        SinkKind::Memory => {
+            let sink = MemorySink::default();
+            memory = Some(sink.records());
+            Box::new(sink)
+        }
+        #[cfg(feature = "sqlite")]
+        SinkKind::Sqlite => Box::new(SqliteSink::open(path)?),
+    };
+    start(sink, memory);
+    Ok(())
+}
+
+/// Send pipeline records to a custom sink
+pub fn enable_with(sink: Box<dyn Sink>) {
+    start(sink, None);
+}
+
+/// Start the writer thread, the only owner of the sink
+fn start(mut sink: Box<dyn Sink>, memory: Option<Arc<Mutex<Vec<Record>>>>) {
+    let (sender, receiver) = channel::unbounded();
+    let handle = thread::spawn(move || {
+        let mut written = 0;
+        for command in receiver {
+            match command {
+                Command::Write(record) => {
+                    sink.write(&record)?;
+                    written += 1;
+                }
+                Command::Finish => break,
+            }
+        }
+        sink.finish()?;
+        Ok(written)
+    });
+
+    WRITER.get_or_init(|| Writer {
+        start: Instant::now(),
+        sender,
+        handle: Mutex::new(Some(handle)),
+        memory,
+    });
+}
+
+/// Whether a sink was enabled
+pub fn is_enabled() -> bool {
+    WRITER.get().is_some()
+}
+
+/// Hand a record to the writer thread (no-op when no sink is enabled)
+///
+/// Any number of threads may call this at once: the channel serializes
+/// their records, and the sink itself is only touched by the writer thread.
+pub fn write(source: &'static str, payload: String) {
+    let Some(writer) = WRITER.get() else {
+        return;
+    };
+
+    // After `finish` the writer is gone and late records are dropped
+    let _ = writer.sender.send(Command::Write(Record {
+        elapsed: writer.start.elapsed(),
+        source,
+        payload,
+    }));
+}
+
+/// Write the records still queued, close the sink and return how many records it received
+pub fn finish() -> io::Result<usize> {
+    let Some(writer) = WRITER.get() else {
+        return Ok(0);
+    };
+
+    let _ = writer.sender.send(Command::Finish);
+    match writer.handle.lock().unwrap().take() {
+        Some(handle) => handle.join().unwrap(),
+        None => Ok(0),
+    }
+}
+
+/// Records kept by the memory sink, empty for any other sink
+pub fn records() -> Vec<Record> {
+    WRITER
+        .get()
+        .and_then(|writer| writer.memory.as_ref())
+        .map(|records| records.lock().unwrap().clone())
+        .unwrap_or_default()
+}
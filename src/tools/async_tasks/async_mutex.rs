@@ -0,0 +1,301 @@
+//! `tokio::sync::Mutex` vs `std::sync::Mutex` around `.await` points
+//!
+//! A task that must keep a value locked while it awaits, say to read it,
+//! call a service and write back, cannot do that with a std mutex. Its
+//! guard is not `Send`, so `tokio::spawn` refuses the task at compile time.
+//! On a single thread the task can be spawned locally, and then the next
+//! task to call `lock()` blocks the very thread the holder needs to resume
+//! on: a deadlock. Releasing the std lock before the await avoids that but
+//! splits the read-modify-write in two, and concurrent tasks lose updates.
+//! `tokio::sync::Mutex` suspends waiting tasks instead of blocking their
+//! thread, so its guard can be held across an await. When no await happens
+//! under the lock, the std mutex remains the faster of the two.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+
+// Third-party dependencies
+use tokio::runtime::Runtime;
+use tokio::task::{self, JoinSet, LocalSet};
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Updates each task makes in the variants awaiting under the lock
+const UPDATES_PER_TASK: u64 = 4;
+
+/// Updates each task makes in the variants without an await under the lock
+const HOT_UPDATES: u64 = 100_000;
+
+/// Shortest time without progress before the watchdog calls a wait off
+const WATCHDOG_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How the shared value ended up
+#[derive(Clone, Copy, PartialEq)]
+enum Verdict {
+    Correct,
+    LostUpdates,
+    Deadlock,
+}
+
+/// Result of one variant
+struct Outcome {
+    label: &'static str,
+    elapsed: Duration,
+    value: u64,
+    expected: u64,
+    verdict: Verdict,
+}
+
+impl Outcome {
+    fn new(label: &'static str, elapsed: Duration, value: u64, expected: u64, deadlocked: bool) -> Self {
+        let verdict = if deadlocked {
+            Verdict::Deadlock
+        } else if value == expected {
+            Verdict::Correct
+        } else {
+            Verdict::LostUpdates
+        };
+        Outcome {
+            label,
+            elapsed,
+            value,
+            expected,
+            verdict,
+        }
+    }
+}
+
+/// Block the thread on a std lock like `lock()` would, but let the watchdog call the wait off
+///
+/// A real `lock()` in this deadlock never returns; polling `try_lock` is
+/// only there so the demo can recover and report.
+fn blocking_lock<'a>(lock: &'a Mutex<u64>, give_up: &AtomicBool) -> Option<MutexGuard<'a, u64>> {
+    loop {
+        match lock.try_lock() {
+            Ok(guard) => return Some(guard),
+            Err(TryLockError::WouldBlock) if !give_up.load(Ordering::Relaxed) => thread::sleep(Duration::from_micros(100)),
+            Err(TryLockError::WouldBlock) => return None,
+            Err(TryLockError::Poisoned(error)) => panic!("lock poisoned: {}", error),
+        }
+    }
+}
+
+/// Set `give_up` once `progress` stood still for `timeout` of real time, until `done`
+fn watchdog(progress: Arc<AtomicU64>, give_up: Arc<AtomicBool>, done: Arc<AtomicBool>, timeout: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last = progress.load(Ordering::Relaxed);
+        let mut stalled_since = std::time::Instant::now();
+        while !done.load(Ordering::Acquire) {
+            thread::sleep(Duration::from_millis(10));
+            let current = progress.load(Ordering::Relaxed);
+            if current != last {
+                last = current;
+                stalled_since = std::time::Instant::now();
+            } else if stalled_since.elapsed() >= timeout {
+                common::print_warning(&format!(
+                    "  watchdog: no update for {:?}, the runtime thread is blocked in lock() while the holder waits to be polled",
+                    stalled_since.elapsed()
+                ));
+                give_up.store(true, Ordering::Relaxed);
+                return;
+            }
+        }
+    })
+}
+
+/// std mutex held across the await, with the tasks spawned locally since the guard is not `Send`
+// This is the mistake the variant demonstrates, and the one clippy's await_holding_lock lint catches
+#[allow(clippy::await_holding_lock)]
+fn std_held_across_await(runtime: &Runtime, num_tasks: usize, pause: Duration) -> Outcome {
+    let value = Arc::new(Mutex::new(0u64));
+    let progress = Arc::new(AtomicU64::new(0));
+    let (give_up, done) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+    let watchdog = watchdog(Arc::clone(&progress), Arc::clone(&give_up), Arc::clone(&done), WATCHDOG_TIMEOUT.max(pause * 5));
+
+    let start = Instant::now();
+    LocalSet::new().block_on(runtime, async {
+        let handles: Vec<_> = (0..num_tasks)
+            .map(|_| {
+                let (value, progress, give_up) = (Arc::clone(&value), Arc::clone(&progress), Arc::clone(&give_up));
+                task::spawn_local(async move {
+                    for _ in 0..UPDATES_PER_TASK {
+                        chaos::perturb_async(Point::TaskStart).await;
+                        let Some(mut guard) = blocking_lock(&value, &give_up) else { return };
+                        let current = *guard;
+                        sleep(pause).await;
+                        *guard = current + 1;
+                        drop(guard);
+                        progress.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::Release);
+    watchdog.join().unwrap();
+
+    let final_value = *value.lock().unwrap();
+    Outcome::new("std, held across .await", elapsed, final_value, num_tasks as u64 * UPDATES_PER_TASK, give_up.load(Ordering::Relaxed))
+}
+
+/// std mutex released before the await and locked again after it, splitting the update in two
+fn std_released_before_await(runtime: &Runtime, num_tasks: usize, pause: Duration) -> Outcome {
+    let value = Arc::new(Mutex::new(0u64));
+    audit::track("async mutex value (std, released)", &value);
+    let start = Instant::now();
+    runtime.block_on(async {
+        let mut set = JoinSet::new();
+        for _ in 0..num_tasks {
+            let value = Arc::clone(&value);
+            set.spawn(async move {
+                for _ in 0..UPDATES_PER_TASK {
+                    chaos::perturb_async(Point::TaskStart).await;
+                    let current = *value.lock().unwrap();
+                    sleep(pause).await;
+
+                    // Every task that read before this write overwrites it with the same stale value
+                    *value.lock().unwrap() = current + 1;
+                }
+            });
+        }
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+    });
+    let final_value = *value.lock().unwrap();
+    Outcome::new("std, released before", start.elapsed(), final_value, num_tasks as u64 * UPDATES_PER_TASK, false)
+}
+
+/// tokio mutex held across the await: waiting tasks are suspended, not their thread
+fn tokio_held_across_await(runtime: &Runtime, num_tasks: usize, pause: Duration) -> Outcome {
+    let value = Arc::new(tokio::sync::Mutex::new(0u64));
+    audit::track("async mutex value (tokio, held)", &value);
+    let start = Instant::now();
+    runtime.block_on(async {
+        let mut set = JoinSet::new();
+        for _ in 0..num_tasks {
+            let value = Arc::clone(&value);
+            set.spawn(async move {
+                for _ in 0..UPDATES_PER_TASK {
+                    chaos::perturb_async(Point::TaskStart).await;
+                    let mut guard = value.lock().await;
+                    let current = *guard;
+                    sleep(pause).await;
+                    *guard = current + 1;
+                }
+            });
+        }
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+    });
+    let final_value = *runtime.block_on(value.lock());
+    Outcome::new("tokio, held across", start.elapsed(), final_value, num_tasks as u64 * UPDATES_PER_TASK, false)
+}
+
+/// Short critical sections with no await inside, on a std or a tokio mutex
+fn no_await(runtime: &Runtime, num_tasks: usize, use_tokio: bool) -> Outcome {
+    let std_value = Arc::new(Mutex::new(0u64));
+    let tokio_value = Arc::new(tokio::sync::Mutex::new(0u64));
+    let start = Instant::now();
+    runtime.block_on(async {
+        let mut set = JoinSet::new();
+        for _ in 0..num_tasks {
+            let (std_value, tokio_value) = (Arc::clone(&std_value), Arc::clone(&tokio_value));
+            set.spawn(async move {
+                chaos::perturb_async(Point::TaskStart).await;
+                for _ in 0..HOT_UPDATES {
+                    if use_tokio {
+                        *tokio_value.lock().await += 1;
+                    } else {
+                        *std_value.lock().unwrap() += 1;
+                    }
+                }
+            });
+        }
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+    });
+    let elapsed = start.elapsed();
+    let final_value = if use_tokio { *runtime.block_on(tokio_value.lock()) } else { *std_value.lock().unwrap() };
+    let label = if use_tokio { "tokio, no .await inside" } else { "std, no .await inside" };
+    Outcome::new(label, elapsed, final_value, num_tasks as u64 * HOT_UPDATES, false)
+}
+
+/// Run the async mutex example: `num_tasks` tasks updating a shared value, awaiting `pause_ms` milliseconds under the lock
+pub fn run(num_tasks: usize, pause_ms: u64) {
+    let num_tasks = num_tasks.max(2);
+    let pause = Duration::from_millis(pause_ms.max(1));
+    common::print_info(&format!(
+        "{} tasks each make {} read-await-write updates of a shared value ({:?} await), then {} updates with nothing awaited under the lock",
+        num_tasks, UPDATES_PER_TASK, pause, HOT_UPDATES
+    ));
+
+    let runtime = virtual_time::runtime();
+    println!();
+    common::print_info("std::sync::Mutex held across .await, tasks spawned on a LocalSet because the guard is not Send:");
+    let outcomes = [
+        std_held_across_await(&runtime, num_tasks, pause),
+        std_released_before_await(&runtime, num_tasks, pause),
+        tokio_held_across_await(&runtime, num_tasks, pause),
+        no_await(&runtime, num_tasks, false),
+        no_await(&runtime, num_tasks, true),
+    ];
+    audit::runtime("async mutex", &runtime);
+    runtime_metrics::record("async mutex", &runtime);
+
+    println!();
+    println!(
+        "{:<26} {:>14} {:>14} {:>10} {:>10} {:>14}",
+        "variant", "time", "updates/s", "value", "expected", "result"
+    );
+    for outcome in &outcomes {
+        println!(
+            "{:<26} {:>14?} {:>14.0} {:>10} {:>10} {:>14}",
+            outcome.label,
+            outcome.elapsed,
+            outcome.value as f64 / outcome.elapsed.as_secs_f64().max(f64::EPSILON),
+            outcome.value,
+            outcome.expected,
+            match outcome.verdict {
+                Verdict::Correct => "correct",
+                Verdict::LostUpdates => "lost updates",
+                Verdict::Deadlock => "deadlock",
+            }
+        );
+    }
+
+    println!();
+    if outcomes[0].verdict == Verdict::Deadlock {
+        common::print_warning("Holding the std guard across .await deadlocked: the waiting task blocked the only thread the holder could resume on");
+    }
+    if outcomes[1].verdict == Verdict::LostUpdates {
+        common::print_warning(&format!(
+            "Releasing the std lock before .await lost {} updates: tasks wrote back values other tasks had already replaced",
+            outcomes[1].expected - outcomes[1].value
+        ));
+    }
+    if outcomes[2].verdict == Verdict::Correct {
+        common::print_success("tokio::sync::Mutex kept every update: lock().await suspends the waiting task and frees its thread");
+    }
+    let (std_hot, tokio_hot) = (&outcomes[3], &outcomes[4]);
+    common::print_info(&format!(
+        "With no .await under the lock, the std mutex was {:.1}x faster than tokio's: prefer it there, and tokio's only when the guard must live across an await",
+        tokio_hot.elapsed.as_secs_f64() / std_hot.elapsed.as_secs_f64().max(f64::EPSILON)
+    ));
+    common::print_info("On a multi-thread runtime, tokio::spawn rejects a task holding a std guard across .await at compile time, because the guard is not Send");
+    common::print_info("clippy's await_holding_lock lint flags the pattern in any case, LocalSet included");
+}
@@ -0,0 +1,285 @@
+//! Backpressure and admission control across a two-stage service
+//!
+//! This crate has no HTTP layer, so the service is modelled in process:
+//! requests enter through a front door, are parsed by one stage and stored
+//! by a pool of workers, and every reply carries an HTTP-like status. With
+//! bounded queues a full store queue blocks the parse stage, which fills its
+//! own queue, which makes the front door answer 429 Too Many Requests: the
+//! pressure travels back to the client. Accepting everything instead lets
+//! the queues, and the latency of every request, grow for as long as the
+//! overload lasts.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Third-party dependencies
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::time::{interval, sleep, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Workers in the store stage, the bottleneck of the service
+const STORE_WORKERS: usize = 4;
+
+/// Requests per second offered, relative to what the store stage can handle
+const OVERLOAD: f64 = 1.5;
+
+/// Queue length between stages when admission control is on
+const QUEUE_LIMIT: usize = 16;
+
+/// Queue length standing in for "unbounded" when everything is accepted
+const ACCEPT_ALL_CAPACITY: usize = 1 << 20;
+
+/// Reply sent back to the client
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    /// 200: the request went through both stages
+    Ok,
+    /// 429: the front door refused the request because the service is saturated
+    TooManyRequests,
+}
+
+/// A request travelling through the stages with the channel its reply goes to
+struct Request {
+    reply: oneshot::Sender<Status>,
+}
+
+/// How the service treats incoming requests
+struct Mode {
+    label: &'static str,
+    queue_limit: Option<usize>,
+}
+
+/// Entry point of the service, counting the requests admitted and not yet answered
+struct FrontDoor {
+    parse_queue: mpsc::Sender<Request>,
+    in_flight: Arc<AtomicUsize>,
+    peak_in_flight: AtomicUsize,
+}
+
+impl FrontDoor {
+    /// Queue the request for parsing, or answer 429 right away if the parse queue is full
+    fn admit(&self, request: Request) {
+        match self.parse_queue.try_send(request) {
+            Ok(()) => {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+                self.peak_in_flight.fetch_max(in_flight, Ordering::Relaxed);
+            }
+            Err(TrySendError::Full(request)) => {
+                let _ = request.reply.send(Status::TooManyRequests);
+            }
+            Err(TrySendError::Closed(_)) => unreachable!("the parse stage outlives the front door"),
+        }
+    }
+}
+
+/// Outcome of one load test
+struct LoadReport {
+    accepted: usize,
+    rejected: usize,
+    latencies: Vec<Duration>,
+    peak_in_flight: usize,
+    elapsed: Duration,
+}
+
+/// Offer `requests` requests at a fixed rate above capacity and wait for every reply
+async fn load_test(mode: &Mode, requests: usize, service_time: Duration) -> LoadReport {
+    let capacity = mode.queue_limit.unwrap_or(ACCEPT_ALL_CAPACITY);
+    let (parse_queue, mut parse_receiver) = mpsc::channel::<Request>(capacity);
+    let (store_queue, store_receiver) = mpsc::channel::<Request>(capacity);
+    let store_receiver = Arc::new(Mutex::new(store_receiver));
+    audit::track("backpressure store queue", &store_receiver);
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    // Parse stage: quick, but forwarding waits while the store queue is full
+    let parse_time = service_time / 10;
+    let parse = tokio::spawn(async move {
+        while let Some(request) = parse_receiver.recv().await {
+            sleep(parse_time).await;
+            if store_queue.send(request).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Store stage: the workers share one queue and set the pace of the whole service
+    let workers: Vec<_> = (0..STORE_WORKERS)
+        .map(|_| {
+            let store_receiver = Arc::clone(&store_receiver);
+            let in_flight = Arc::clone(&in_flight);
+            tokio::spawn(async move {
+                loop {
+                    let request = store_receiver.lock().await.recv().await;
+                    let Some(request) = request else { break };
+                    chaos::perturb_async(Point::TaskStart).await;
+                    sleep(service_time).await;
+                    in_flight.fetch_sub(1, Ordering::Relaxed);
+                    let _ = request.reply.send(Status::Ok);
+                }
+            })
+        })
+        .collect();
+    drop(store_receiver);
+
+    let front_door = FrontDoor {
+        parse_queue,
+        in_flight,
+        peak_in_flight: AtomicUsize::new(0),
+    };
+
+    // Open-loop clients: a new request arrives on every tick, whether earlier ones were answered or not
+    let rate = STORE_WORKERS as f64 / service_time.as_secs_f64() * OVERLOAD;
+    let mut arrivals = interval(Duration::from_secs_f64(1.0 / rate));
+    let start = Instant::now();
+    let mut clients = Vec::with_capacity(requests);
+    for _ in 0..requests {
+        arrivals.tick().await;
+        let (reply, response) = oneshot::channel();
+        let sent = Instant::now();
+        front_door.admit(Request { reply });
+        clients.push(tokio::spawn(async move {
+            let status = response.await.unwrap();
+            (status, sent.elapsed())
+        }));
+    }
+
+    let mut report = LoadReport {
+        accepted: 0,
+        rejected: 0,
+        latencies: Vec::with_capacity(requests),
+        peak_in_flight: 0,
+        elapsed: Duration::ZERO,
+    };
+    for client in clients {
+        match client.await.unwrap() {
+            (Status::Ok, latency) => {
+                report.accepted += 1;
+                report.latencies.push(latency);
+            }
+            (Status::TooManyRequests, _) => report.rejected += 1,
+        }
+    }
+    report.elapsed = start.elapsed();
+    report.peak_in_flight = front_door.peak_in_flight.load(Ordering::Relaxed);
+    report.latencies.sort_unstable();
+
+    // Closing the front door drains the stages one after the other
+    drop(front_door);
+    parse.await.unwrap();
+    for worker in workers {
+        worker.await.unwrap();
+    }
+    report
+}
+
+/// Longest an admitted request should wait with admission control on
+///
+/// Once the store queue is full both queues drain at the store rate: an
+/// admitted request waits behind at most two full queues served
+/// STORE_WORKERS at a time, plus its own service and parsing. Tokio timers
+/// round sleeps up to the next millisecond.
+fn latency_bound(service_time: Duration) -> Duration {
+    (service_time + Duration::from_millis(1)) * (2 * QUEUE_LIMIT / STORE_WORKERS + 2) as u32
+}
+
+/// Run the load test with and without admission control
+pub fn run(requests: usize, delay_ms: u64) {
+    let service_time = Duration::from_millis(delay_ms.max(1));
+    common::print_info(&format!(
+        "{} requests offered at {:.1}x what {} store workers taking {:?} each can serve",
+        requests, OVERLOAD, STORE_WORKERS, service_time
+    ));
+
+    let modes = [
+        Mode {
+            label: "accept all",
+            queue_limit: None,
+        },
+        Mode {
+            label: "admission",
+            queue_limit: Some(QUEUE_LIMIT),
+        },
+    ];
+
+    let runtime = virtual_time::runtime();
+    let reports: Vec<_> = runtime.block_on(async {
+        let mut reports = vec![];
+        for mode in &modes {
+            reports.push(load_test(mode, requests, service_time).await);
+        }
+        reports
+    });
+    audit::runtime("backpressure", &runtime);
+    runtime_metrics::record("backpressure", &runtime);
+
+    println!();
+    println!(
+        "{:<12} {:>8} {:>8} {:>12} {:>12} {:>12} {:>10} {:>12}",
+        "mode", "200", "429", "p50", "p99", "max", "in flight", "elapsed"
+    );
+    for (mode, report) in modes.iter().zip(reports.iter()) {
+        println!(
+            "{:<12} {:>8} {:>8} {:>12?} {:>12?} {:>12?} {:>10} {:>12?}",
+            mode.label,
+            report.accepted,
+            report.rejected,
+            common::percentile(&report.latencies, 50.0),
+            common::percentile(&report.latencies, 99.0),
+            report.latencies.last().copied().unwrap_or_default(),
+            report.peak_in_flight,
+            report.elapsed
+        );
+    }
+
+    let bound = latency_bound(service_time);
+
+    println!();
+    common::print_info("Latency is measured by the client from sending the request to receiving the 200");
+    common::print_info("Accepting everything queues the excess: each request waits behind all earlier ones, and the wait keeps growing while the overload lasts");
+    common::print_info(&format!(
+        "With bounded queues the store stage blocks the parse stage, whose full queue makes the front door answer 429; admitted requests wait at most about {:?}",
+        bound
+    ));
+    if reports[1].latencies.last().is_some_and(|max| *max <= bound * 2) {
+        common::print_success("Admission control kept the latency of accepted requests bounded, and told the rejected clients immediately");
+    } else {
+        common::print_warning("Admitted requests waited longer than the queue sizes allow; the machine may be overloaded");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // On a paused clock the load test takes no real time and gives the same numbers on every run
+    #[tokio::test(start_paused = true)]
+    async fn admission_control_bounds_latency() {
+        let service_time = Duration::from_millis(5);
+        let accept_all = Mode {
+            label: "accept all",
+            queue_limit: None,
+        };
+        let admission = Mode {
+            label: "admission",
+            queue_limit: Some(QUEUE_LIMIT),
+        };
+
+        let unbounded = load_test(&accept_all, 500, service_time).await;
+        assert_eq!(unbounded.rejected, 0);
+        assert!(*unbounded.latencies.last().unwrap() > latency_bound(service_time));
+
+        let bounded = load_test(&admission, 500, service_time).await;
+        assert!(bounded.rejected > 0);
+        assert_eq!(bounded.accepted + bounded.rejected, 500);
+        assert!(*bounded.latencies.last().unwrap() <= latency_bound(service_time));
+        assert!(bounded.peak_in_flight <= 2 * QUEUE_LIMIT + STORE_WORKERS + 1);
+    }
+}
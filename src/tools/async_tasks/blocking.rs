@@ -0,0 +1,180 @@
+//! CPU-bound work inside async code: inline vs `spawn_blocking`
+//!
+//! A Tokio worker thread only switches tasks at an `.await`. A task that
+//! computes for a long time without awaiting keeps its worker to itself,
+//! and once every worker is taken, nothing else on the runtime runs: timers
+//! fire late, I/O waits, new tasks queue up. `task::spawn_blocking` moves
+//! the computation to a separate pool of blocking threads instead, so the
+//! workers stay free to poll the rest. A heartbeat task that should tick
+//! at a fixed period measures the difference.
+
+// Base dependencies
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use tokio::sync::watch;
+use tokio::task::{self, JoinSet};
+use tokio::time::sleep;
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::tools::parallel_iteration::code::compute_intensive;
+use crate::virtual_time;
+
+/// Period the heartbeat expects to tick at
+const HEARTBEAT: Duration = Duration::from_millis(5);
+
+/// Input of every `compute_intensive` call a job makes
+const INPUT: u64 = 10_000;
+
+/// Calibration doubles the number of calls until a run takes at least this long
+const CALIBRATION_TIME: Duration = Duration::from_millis(20);
+
+/// Where the CPU-bound jobs run
+#[derive(Clone, Copy)]
+enum Placement {
+    Inline,
+    SpawnBlocking,
+}
+
+/// Heartbeat lateness collected during one run
+struct HeartbeatReport {
+    /// How late every tick was, sorted
+    lateness: Vec<Duration>,
+    elapsed: Duration,
+}
+
+/// A CPU-bound job: `calls` calls of `compute_intensive`, with no `.await` in between
+pub(crate) fn job(calls: u64) -> u64 {
+    (0..calls).fold(0, |acc, call| acc.wrapping_add(compute_intensive(black_box(INPUT + call % 8))))
+}
+
+/// Number of `compute_intensive` calls that keeps a CPU busy for about `target`
+pub(crate) fn calibrate(target: Duration) -> u64 {
+    // The optimizer may shorten a single call a lot, so the calls are timed by the batch
+    let mut calls = 1;
+    loop {
+        let start = Instant::now();
+        black_box(job(calls));
+        let elapsed = start.elapsed();
+        if elapsed >= CALIBRATION_TIME {
+            return ((calls as f64 * target.as_secs_f64() / elapsed.as_secs_f64()) as u64).max(1);
+        }
+        calls *= 2;
+    }
+}
+
+/// Run `jobs` jobs of `calls` calls placed as asked, while a heartbeat measures how late its ticks are
+async fn measure(placement: Placement, jobs: usize, calls: u64) -> HeartbeatReport {
+    let (stop, mut stopped) = watch::channel(false);
+
+    // Each tick records how far past its deadline it woke up
+    let heartbeat = tokio::spawn(async move {
+        let mut lateness = vec![];
+        while !*stopped.borrow() {
+            let deadline = Instant::now() + HEARTBEAT;
+            tokio::select! {
+                _ = sleep(HEARTBEAT) => {}
+                _ = stopped.changed() => {}
+            }
+
+            // A stalled timer may not have fired yet when the stop signal wakes the task, so any wake-up past the deadline counts
+            if let Some(late) = Instant::now().checked_duration_since(deadline) {
+                lateness.push(late);
+            }
+        }
+        lateness
+    });
+
+    // Let the heartbeat settle before the jobs arrive
+    sleep(HEARTBEAT * 2).await;
+    let start = Instant::now();
+    let mut set = JoinSet::new();
+    for _ in 0..jobs {
+        match placement {
+            Placement::Inline => {
+                set.spawn(async move { job(calls) });
+            }
+            Placement::SpawnBlocking => {
+                set.spawn(async move { task::spawn_blocking(move || job(calls)).await.unwrap() });
+            }
+        }
+    }
+    while let Some(result) = set.join_next().await {
+        result.unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    stop.send(true).unwrap();
+    let mut lateness = heartbeat.await.unwrap();
+    lateness.sort_unstable();
+    HeartbeatReport { lateness, elapsed }
+}
+
+/// Run the spawn_blocking example: `jobs` CPU-bound jobs of about `job_ms` milliseconds each
+pub fn run(jobs: usize, job_ms: u64) {
+    let workers = cpus::available();
+    let jobs = jobs.max(1);
+    let target = Duration::from_millis(job_ms.max(1));
+    let calls = calibrate(target);
+    common::print_info(&format!(
+        "{} jobs of {} compute_intensive({}) calls (~{:?} each) on a runtime with {} worker thread(s), while a heartbeat expects a tick every {:?}",
+        jobs, calls, INPUT, target, workers, HEARTBEAT
+    ));
+    if virtual_time::is_enabled() {
+        common::print_warning("This example measures real stalls, so it ignores --virtual-time");
+    }
+    if jobs < workers {
+        common::print_warning(&format!(
+            "With fewer jobs than the {} worker threads, some workers stay free and the inline case stalls little; try --tasks {}",
+            workers, workers
+        ));
+    }
+
+    // Real time on purpose: on a paused clock, computing takes no time at all
+    let runtime = cpus::multi_thread_runtime();
+    let reports = [
+        ("inline in a task", runtime.block_on(measure(Placement::Inline, jobs, calls))),
+        ("spawn_blocking", runtime.block_on(measure(Placement::SpawnBlocking, jobs, calls))),
+    ];
+    audit::runtime("blocking", &runtime);
+    runtime_metrics::record("blocking", &runtime);
+
+    println!();
+    println!(
+        "{:<18} {:>12} {:>8} {:>14} {:>14} {:>14}",
+        "placement", "jobs time", "ticks", "p50 late", "p99 late", "max late"
+    );
+    for (label, report) in &reports {
+        println!(
+            "{:<18} {:>12?} {:>8} {:>14?} {:>14?} {:>14?}",
+            label,
+            report.elapsed,
+            report.lateness.len(),
+            common::percentile(&report.lateness, 50.0),
+            common::percentile(&report.lateness, 99.0),
+            report.lateness.last().copied().unwrap_or_default()
+        );
+    }
+
+    println!();
+    let (inline, blocking) = (&reports[0].1, &reports[1].1);
+    let worst = |report: &HeartbeatReport| report.lateness.last().copied().unwrap_or_default();
+    if worst(inline) > worst(blocking) {
+        common::print_success(&format!(
+            "Inline jobs held the workers and the heartbeat fired up to {:?} late; with spawn_blocking it was at most {:?} late",
+            worst(inline),
+            worst(blocking)
+        ));
+    } else {
+        common::print_warning("The heartbeat was not delayed more by the inline jobs on this run");
+    }
+    common::print_info("An async task only yields at .await: CPU-bound work in it starves every other task sharing its worker");
+    if cpus::available() < 2 {
+        common::print_warning("With a single CPU the blocking threads still compete with the heartbeat for the processor, so some lateness remains");
+    }
+}
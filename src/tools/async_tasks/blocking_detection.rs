@@ -0,0 +1,226 @@
+//! Finding the task that blocks its worker
+//!
+//! A blocking call such as `std::thread::sleep` inside an async task does
+//! not yield: the worker thread polling it is stuck until it returns, and
+//! every task waiting for that worker is stuck with it. The victims notice,
+//! as timers that fire late, but they say nothing about who held the thread.
+//! Timing every poll does: a healthy poll takes microseconds, so any poll
+//! over a threshold points straight at the task that made it. Here every
+//! task runs inside such a wrapper, and one of them, picked at random,
+//! sleeps its thread from time to time.
+
+// Base dependencies
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use rand::Rng;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// A poll taking longer than this is reported as blocking
+const SLOW_POLL: Duration = Duration::from_millis(10);
+
+/// Wake-ups of every task
+const ROUNDS: usize = 8;
+
+/// The culprit blocks on every this many rounds
+const BLOCK_EVERY: usize = 3;
+
+/// Poll timings of one task, filled by its `Watched` wrapper
+#[derive(Default)]
+struct PollStats {
+    polls: AtomicUsize,
+    busy_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    slow_polls: AtomicUsize,
+}
+
+/// Times every poll of the future it wraps, and reports the slow ones as they happen
+struct Watched<F> {
+    name: String,
+    inner: Pin<Box<F>>,
+    stats: Arc<PollStats>,
+}
+
+impl<F: Future> Future for Watched<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let start = Instant::now();
+        let result = self.inner.as_mut().poll(cx);
+        let elapsed = start.elapsed();
+
+        let nanos = elapsed.as_nanos() as u64;
+        self.stats.polls.fetch_add(1, Ordering::Relaxed);
+        self.stats.busy_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.stats.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        if elapsed > SLOW_POLL {
+            self.stats.slow_polls.fetch_add(1, Ordering::Relaxed);
+            common::print_warning(&format!(
+                "{} held its worker ({:?}) for {:?} in a single poll",
+                self.name,
+                thread::current().name().unwrap_or("unnamed thread"),
+                elapsed
+            ));
+        }
+        result
+    }
+}
+
+/// What one task saw and did
+struct TaskReport {
+    stats: Arc<PollStats>,
+    /// How late every wake-up was, sorted
+    lateness: Vec<Duration>,
+}
+
+/// Wake up `ROUNDS` times, `period` apart, noting how late each wake-up is; the culprit also blocks its thread for `block`
+async fn periodic_task(culprit: bool, period: Duration, block: Duration) -> Vec<Duration> {
+    chaos::perturb_async(Point::TaskStart).await;
+    let mut lateness = Vec::with_capacity(ROUNDS);
+    for round in 1..=ROUNDS {
+        let deadline = Instant::now() + period;
+        sleep(period).await;
+        lateness.push(Instant::now().saturating_duration_since(deadline));
+
+        if culprit && round % BLOCK_EVERY == 0 {
+            // The bug: a blocking call in async code, e.g. a synchronous client or a std lock held too long
+            thread::sleep(block);
+        }
+    }
+    lateness.sort_unstable();
+    lateness
+}
+
+/// Run `num_tasks` watched tasks on `runtime`, task `culprit` being the one that blocks
+fn measure(runtime: &Runtime, num_tasks: usize, culprit: usize, period: Duration, block: Duration) -> Vec<TaskReport> {
+    let stats: Vec<Arc<PollStats>> = (0..num_tasks).map(|_| Arc::new(PollStats::default())).collect();
+    let lateness = Arc::new(Mutex::new(vec![vec![]; num_tasks]));
+    runtime.block_on(async {
+        let mut set = JoinSet::new();
+        for (id, stats) in stats.iter().enumerate() {
+            let lateness = Arc::clone(&lateness);
+            set.spawn(Watched {
+                name: format!("task {}", id),
+                inner: Box::pin(async move {
+                    let task_lateness = periodic_task(id == culprit, period, block).await;
+                    lateness.lock().unwrap()[id] = task_lateness;
+                }),
+                stats: Arc::clone(stats),
+            });
+        }
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+    });
+
+    let lateness = std::mem::take(&mut *lateness.lock().unwrap());
+    stats
+        .into_iter()
+        .zip(lateness)
+        .map(|(stats, lateness)| TaskReport { stats, lateness })
+        .collect()
+}
+
+/// Print one runtime's table, returning the tasks flagged by their slow polls
+fn print_reports(flavor: &str, reports: &[TaskReport], culprit: usize) -> Vec<usize> {
+    println!();
+    common::print_info(&format!("{} runtime:", flavor));
+    println!(
+        "{:<8} {:>7} {:>14} {:>14} {:>11} {:>14} {:>14}",
+        "task", "polls", "busy", "max poll", "slow polls", "p50 late", "max late"
+    );
+    for (id, report) in reports.iter().enumerate() {
+        let slow = report.stats.slow_polls.load(Ordering::Relaxed);
+        println!(
+            "{:<8} {:>7} {:>14?} {:>14?} {:>11} {:>14?} {:>14?}{}",
+            id,
+            report.stats.polls.load(Ordering::Relaxed),
+            Duration::from_nanos(report.stats.busy_nanos.load(Ordering::Relaxed)),
+            Duration::from_nanos(report.stats.max_nanos.load(Ordering::Relaxed)),
+            slow,
+            common::percentile(&report.lateness, 50.0),
+            report.lateness.last().copied().unwrap_or_default(),
+            if id == culprit { "  <- blocks" } else { "" }
+        );
+    }
+    reports
+        .iter()
+        .enumerate()
+        .filter(|(_, report)| report.stats.slow_polls.load(Ordering::Relaxed) > 0)
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Run the blocking detection example: `num_tasks` tasks waking every `period_ms`, one of which blocks its thread for `period_ms`
+pub fn run(num_tasks: usize, period_ms: u64) {
+    let num_tasks = num_tasks.max(2);
+    let period = Duration::from_millis(period_ms.max(1));
+    let block = period;
+    let culprit = rand::thread_rng().gen_range(0..num_tasks);
+    common::print_info(&format!(
+        "{} tasks wake up {} times, {:?} apart; one of them calls std::thread::sleep({:?}) on every {}rd wake-up",
+        num_tasks, ROUNDS, period, block, BLOCK_EVERY
+    ));
+    common::print_info(&format!("Every poll is timed, and polls over {:?} are reported as they happen", SLOW_POLL));
+    if virtual_time::is_enabled() {
+        common::print_warning("This example measures real stalls, so it ignores --virtual-time");
+    }
+    if block <= SLOW_POLL {
+        common::print_warning(&format!(
+            "Blocking calls of {:?} stay under the {:?} threshold; try --delay {}",
+            block,
+            SLOW_POLL,
+            SLOW_POLL.as_millis() * 5
+        ));
+    }
+
+    let runtimes = [
+        ("current_thread", Builder::new_current_thread().enable_all().build().unwrap()),
+        ("multi_thread", cpus::multi_thread_runtime()),
+    ];
+    let mut flagged = vec![];
+    for (flavor, runtime) in &runtimes {
+        println!();
+        common::print_info(&format!("Running on the {} runtime", flavor));
+        let reports = measure(runtime, num_tasks, culprit, period, block);
+        audit::runtime("blocking-detection", runtime);
+        runtime_metrics::record("blocking-detection", runtime);
+        flagged.push((*flavor, reports));
+    }
+
+    let mut caught = true;
+    for (flavor, reports) in &flagged {
+        let suspects = print_reports(flavor, reports, culprit);
+        caught &= suspects == [culprit];
+    }
+
+    println!();
+    if caught {
+        common::print_success(&format!("The slow polls pointed at task {}, the one calling std::thread::sleep, on both runtimes", culprit));
+    } else {
+        common::print_warning(&format!("The slow polls did not single out task {} on every runtime on this run", culprit));
+    }
+    common::print_info("On the current-thread runtime every task wakes up late: the victims show the stall, but not its cause");
+    if cpus::available() < 2 {
+        common::print_warning("With a single worker the multi-thread runtime stalls just like the current-thread one");
+    } else {
+        common::print_info("On the multi-thread runtime the other workers pick up most tasks, but the stalled worker's own queue still waits");
+    }
+    common::print_info("The fix: spawn_blocking or tokio's async equivalent for the blocking call; tokio-console (--console) flags the same busy polls");
+}
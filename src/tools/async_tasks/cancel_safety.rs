@@ -0,0 +1,209 @@
+//! Cancellation safety in a `select!` loop
+//!
+//! A `select!` in a loop drops the branches that lost on every iteration.
+//! `mpsc::Receiver::recv` is cancellation safe: dropped before it returns, it
+//! has taken nothing off the channel. A future that awaits twice is not: one
+//! that receives a message and then awaits room downstream holds that message
+//! in its state, and dropping it there drops the message too. Two ways out:
+//! make every await safe to drop, by reserving the downstream slot with a
+//! `Permit` before taking the message, or stop dropping the future, by
+//! creating it once, pinned outside the loop, and resuming it every time.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Third-party dependencies
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Slots of the downstream channel: kept small so the forwarder often waits for room
+const DOWNSTREAM_CAPACITY: usize = 1;
+
+/// Lost message IDs listed in the report
+const LOST_SHOWN: usize = 10;
+
+/// How the forwarder moves a message between the two channels
+#[derive(Clone, Copy)]
+enum Strategy {
+    Naive,
+    ReserveFirst,
+    Pinned,
+}
+
+impl Strategy {
+    fn label(&self) -> &'static str {
+        match self {
+            Strategy::Naive => "recv then send",
+            Strategy::ReserveFirst => "reserve, recv, send",
+            Strategy::Pinned => "pinned across ticks",
+        }
+    }
+}
+
+/// A message taken off the upstream channel and not handed downstream yet; counted if dropped that way
+struct InHand<'a> {
+    message: Option<u64>,
+    dropped: &'a AtomicUsize,
+}
+
+impl Drop for InHand<'_> {
+    fn drop(&mut self) {
+        if self.message.is_some() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Not cancellation safe: dropped while waiting for room downstream, it drops the message it received
+async fn forward_naive(upstream: &mut mpsc::Receiver<u64>, downstream: &mpsc::Sender<u64>, dropped: &AtomicUsize) -> bool {
+    let Some(message) = upstream.recv().await else { return false };
+    let mut hand = InHand { message: Some(message), dropped };
+    downstream.send(message).await.unwrap();
+    hand.message = None;
+    true
+}
+
+/// Cancellation safe: nothing is taken off the upstream channel until the downstream slot is held
+async fn forward_reserved(upstream: &mut mpsc::Receiver<u64>, downstream: &mpsc::Sender<u64>) -> bool {
+    // Dropped here, the reservation was never granted; dropped during recv, the permit just returns its slot
+    let permit = downstream.reserve().await.unwrap();
+    let Some(message) = upstream.recv().await else { return false };
+    permit.send(message);
+    true
+}
+
+/// What one forwarder run saw
+struct Report {
+    sent: usize,
+    delivered: Vec<u64>,
+    ticks: usize,
+    dropped_in_hand: usize,
+}
+
+impl Report {
+    fn lost(&self) -> Vec<u64> {
+        let mut delivered = self.delivered.clone();
+        delivered.sort_unstable();
+        (0..self.sent as u64).filter(|id| delivered.binary_search(id).is_err()).collect()
+    }
+}
+
+/// Forward `messages` messages to a consumer taking `consume` each, while a `tick` branch wins the select! now and then
+async fn run_forwarder(strategy: Strategy, messages: usize, tick: Duration, consume: Duration) -> Report {
+    let (producer, mut upstream) = mpsc::channel::<u64>(messages.max(1));
+    for id in 0..messages as u64 {
+        producer.send(id).await.unwrap();
+    }
+    drop(producer);
+
+    let (downstream, mut consumer_queue) = mpsc::channel::<u64>(DOWNSTREAM_CAPACITY);
+    let consumer = tokio::spawn(async move {
+        let mut delivered = vec![];
+        while let Some(message) = consumer_queue.recv().await {
+            chaos::perturb_async(Point::TaskStart).await;
+            delivered.push(message);
+            sleep(consume).await;
+        }
+        delivered
+    });
+
+    // The tick stands for any periodic duty of the loop: a flush, a health report, a shutdown check
+    let mut ticks = interval(tick);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticks.tick().await;
+    let mut tick_count = 0;
+    let dropped = AtomicUsize::new(0);
+
+    match strategy {
+        Strategy::Naive => loop {
+            tokio::select! {
+                more = forward_naive(&mut upstream, &downstream, &dropped) => if !more { break },
+                _ = ticks.tick() => tick_count += 1,
+            }
+        },
+        Strategy::ReserveFirst => loop {
+            tokio::select! {
+                more = forward_reserved(&mut upstream, &downstream) => if !more { break },
+                _ = ticks.tick() => tick_count += 1,
+            }
+        },
+        Strategy::Pinned => {
+            // The same unsafe forward, but created once and pinned: select! only borrows it, so a tick pauses it instead of dropping it
+            let forwarding = async { while forward_naive(&mut upstream, &downstream, &dropped).await {} };
+            tokio::pin!(forwarding);
+            loop {
+                tokio::select! {
+                    _ = &mut forwarding => break,
+                    _ = ticks.tick() => tick_count += 1,
+                }
+            }
+        }
+    }
+    drop(downstream);
+
+    Report {
+        sent: messages,
+        delivered: consumer.await.unwrap(),
+        ticks: tick_count,
+        dropped_in_hand: dropped.load(Ordering::Relaxed),
+    }
+}
+
+/// Run the cancellation-safety example: forward `messages` messages to a consumer taking `tick_ms` / 4 each, with a tick every `tick_ms`
+pub fn run(messages: usize, tick_ms: u64) {
+    let tick = Duration::from_millis(tick_ms.max(4));
+    let consume = tick / 4;
+    common::print_info(&format!(
+        "Forwarding {} messages into a {}-slot channel drained every {:?}, in a select! loop that also ticks every {:?}",
+        messages, DOWNSTREAM_CAPACITY, consume, tick
+    ));
+
+    let runtime = virtual_time::runtime();
+    let strategies = [Strategy::Naive, Strategy::ReserveFirst, Strategy::Pinned];
+    let reports: Vec<Report> = strategies
+        .iter()
+        .map(|strategy| runtime.block_on(run_forwarder(*strategy, messages, tick, consume)))
+        .collect();
+    audit::runtime("cancel-safety", &runtime);
+    runtime_metrics::record("cancel-safety", &runtime);
+
+    println!();
+    println!(
+        "{:<22} {:>6} {:>10} {:>6} {:>7} {:>16}",
+        "forwarder", "sent", "delivered", "lost", "ticks", "dropped in hand"
+    );
+    for (strategy, report) in strategies.iter().zip(&reports) {
+        println!(
+            "{:<22} {:>6} {:>10} {:>6} {:>7} {:>16}",
+            strategy.label(),
+            report.sent,
+            report.delivered.len(),
+            report.lost().len(),
+            report.ticks,
+            report.dropped_in_hand
+        );
+    }
+
+    println!();
+    let naive_lost = reports[0].lost();
+    if naive_lost.is_empty() {
+        common::print_warning("The naive forwarder lost nothing on this run; try more messages with --tasks");
+    } else {
+        common::print_warning(&format!(
+            "The naive forwarder lost {} message(s), e.g. {:?}: each one was in hand when a tick won the select!",
+            naive_lost.len(),
+            &naive_lost[..naive_lost.len().min(LOST_SHOWN)]
+        ));
+    }
+    if reports[1..].iter().all(|report| report.lost().is_empty()) {
+        common::print_success("Reserving the slot first, or resuming the pinned forward, delivered every message");
+    }
+    common::print_info("A future is cancellation safe when dropping it at any .await loses nothing; recv() and reserve() are, recv-then-send is not");
+}
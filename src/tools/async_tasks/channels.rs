@@ -0,0 +1,237 @@
+//! A tour of Tokio's four channels in one small service
+//!
+//! Each channel has its own shape, and a service usually needs all of them:
+//! - `mpsc`: many clients send requests to the one server task
+//! - `oneshot`: every request carries the sender its single reply goes back on
+//! - `watch`: the latest configuration, which readers look at whenever they need it
+//! - `broadcast`: one shutdown message, delivered to every subscriber
+//!
+//! Clients keep sending requests, the configuration changes halfway through,
+//! and a broadcast then stops the server and the clients together.
+
+// Base dependencies
+use std::collections::BTreeMap;
+
+// Third-party dependencies
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Requests the mpsc queue holds before clients wait to send
+const QUEUE_CAPACITY: usize = 8;
+
+/// Configuration published over the watch channel
+#[derive(Clone, Copy)]
+struct Config {
+    version: u32,
+    factor: u64,
+}
+
+/// A client's request, with the oneshot sender its reply goes to
+struct Request {
+    payload: u64,
+    reply: oneshot::Sender<Reply>,
+}
+
+/// The server's answer to one request
+struct Reply {
+    payload: u64,
+    result: u64,
+    config_version: u32,
+}
+
+/// What a client saw during the run
+struct ClientReport {
+    sent: usize,
+    /// Replies per configuration version
+    replies: BTreeMap<u32, usize>,
+    wrong: usize,
+}
+
+/// Server: takes requests off the mpsc queue, answers each with the current config, until the shutdown broadcast
+async fn server(
+    mut requests: mpsc::Receiver<Request>,
+    mut config: watch::Receiver<Config>,
+    mut shutdown: broadcast::Receiver<()>,
+    work: Duration,
+) -> (usize, usize) {
+    let (mut handled, mut config_changes) = (0, 0);
+    let mut handlers = JoinSet::new();
+    loop {
+        tokio::select! {
+            Some(request) = requests.recv() => {
+                handled += 1;
+
+                // borrow() reads the latest value; the handler keeps its own copy
+                let current = *config.borrow();
+                handlers.spawn(async move {
+                    chaos::perturb_async(Point::TaskStart).await;
+                    sleep(work).await;
+                    let reply = Reply {
+                        payload: request.payload,
+                        result: request.payload * current.factor,
+                        config_version: current.version,
+                    };
+
+                    // The client may have stopped waiting; a oneshot send then just fails
+                    let _ = request.reply.send(reply);
+                });
+            }
+            Ok(()) = config.changed() => {
+                config_changes += 1;
+                let current = *config.borrow_and_update();
+                common::print_info(&format!("  server: config v{} seen, factor {}", current.version, current.factor));
+            }
+            _ = shutdown.recv() => {
+                common::print_warning("  server: shutdown received, finishing the requests in progress");
+                break;
+            }
+        }
+    }
+
+    // Requests still queued are dropped with the receiver; their oneshot senders go with them
+    drop(requests);
+    while let Some(result) = handlers.join_next().await {
+        result.unwrap();
+    }
+    (handled, config_changes)
+}
+
+/// Client: sends a request every `pause` and awaits its reply, until the shutdown broadcast
+async fn client(
+    client: usize,
+    requests: mpsc::Sender<Request>,
+    mut shutdown: broadcast::Receiver<()>,
+    pause: Duration,
+) -> ClientReport {
+    let mut report = ClientReport {
+        sent: 0,
+        replies: BTreeMap::new(),
+        wrong: 0,
+    };
+    let mut payload = client as u64 * 1000;
+    loop {
+        payload += 1;
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        let request = Request {
+            payload,
+            reply: reply_sender,
+        };
+        tokio::select! {
+            sent = requests.send(request) => {
+                if sent.is_err() {
+                    break;
+                }
+                report.sent += 1;
+            }
+            _ = shutdown.recv() => break,
+        }
+
+        // A dropped sender, when the server shut down before answering, shows up as an error here
+        match reply_receiver.await {
+            Ok(reply) => {
+                *report.replies.entry(reply.config_version).or_default() += 1;
+                // Payloads are unique across clients, so a reply routed to the wrong request shows
+                if reply.payload != payload || reply.result % payload != 0 {
+                    report.wrong += 1;
+                }
+            }
+            Err(_) => break,
+        }
+
+        tokio::select! {
+            _ = sleep(pause) => {}
+            _ = shutdown.recv() => break,
+        }
+    }
+    report
+}
+
+/// Run the channel tour: `num_clients` clients sending a request every `delay_ms` milliseconds
+pub fn run(num_clients: usize, delay_ms: u64) {
+    let num_clients = num_clients.max(1);
+    let pause = Duration::from_millis(delay_ms.max(1));
+    let phase = pause * 5;
+    common::print_info(&format!(
+        "{} clients send a request every {:?}; the config changes after {:?} and a shutdown is broadcast after {:?}",
+        num_clients,
+        pause,
+        phase,
+        phase * 2
+    ));
+
+    let runtime = virtual_time::runtime();
+    let (reports, handled, config_changes, subscribers) = runtime.block_on(async {
+        let (request_sender, request_receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let (config_sender, config_receiver) = watch::channel(Config { version: 1, factor: 2 });
+        let (shutdown_sender, _) = broadcast::channel(1);
+        let start = Instant::now();
+
+        let server = tokio::spawn(server(request_receiver, config_receiver, shutdown_sender.subscribe(), pause / 2));
+        let mut clients = JoinSet::new();
+        for id in 0..num_clients {
+            clients.spawn(client(id, request_sender.clone(), shutdown_sender.subscribe(), pause));
+        }
+
+        // Only the clients hold senders now, so the queue closes once they are all gone
+        drop(request_sender);
+
+        sleep(phase).await;
+        common::print_info(&format!("Publishing config v2 at {:?}", start.elapsed()));
+        config_sender.send(Config { version: 2, factor: 3 }).unwrap();
+
+        sleep(phase).await;
+        let subscribers = shutdown_sender.receiver_count();
+        common::print_warning(&format!("Broadcasting shutdown to {} subscribers at {:?}", subscribers, start.elapsed()));
+        shutdown_sender.send(()).unwrap();
+
+        let mut reports = vec![];
+        while let Some(result) = clients.join_next().await {
+            reports.push(result.unwrap());
+        }
+        let (handled, config_changes) = server.await.unwrap();
+        (reports, handled, config_changes, subscribers)
+    });
+    audit::runtime("channels", &runtime);
+    runtime_metrics::record("channels", &runtime);
+
+    let sent: usize = reports.iter().map(|report| report.sent).sum();
+    let mut replies: BTreeMap<u32, usize> = BTreeMap::new();
+    for report in &reports {
+        for (version, count) in &report.replies {
+            *replies.entry(*version).or_default() += count;
+        }
+    }
+    let answered: usize = replies.values().sum();
+
+    println!();
+    println!("{:<10} {:<46} {:>10}", "channel", "role", "messages");
+    println!("{:<10} {:<46} {:>10}", "mpsc", "requests from every client to the server", sent);
+    println!("{:<10} {:<46} {:>10}", "oneshot", "one reply per request", answered);
+    println!("{:<10} {:<46} {:>10}", "watch", "config changes the server noticed", config_changes);
+    println!("{:<10} {:<46} {:>10}", "broadcast", "shutdown copies delivered, one per subscriber", subscribers);
+
+    println!();
+    for (version, count) in &replies {
+        common::print_info(&format!("{} replies computed with config v{}", count, version));
+    }
+    if reports.iter().all(|report| report.wrong == 0) {
+        common::print_success(&format!("The server handled {} requests and every reply matched its request", handled));
+    } else {
+        common::print_warning("A reply did not match its request");
+    }
+    if sent > answered {
+        common::print_info(&format!(
+            "{} request(s) were in flight at shutdown: their oneshot sender was dropped, and the client saw an error instead of a reply",
+            sent - answered
+        ));
+    }
+    common::print_info("watch keeps only the latest value, so a slow reader skips versions; broadcast queues every message for every subscriber");
+}
@@ -0,0 +1,281 @@
+//! Broadcast chat server over TCP
+//!
+//! Every client connection is a task holding its own receiver of one
+//! `tokio::sync::broadcast` channel: a line read from a client is sent once
+//! and every connection task forwards it to its own client. The channel keeps
+//! only the last `CHANNEL_CAPACITY` messages, so a receiver that falls behind
+//! does not slow the senders down; its next `recv()` instead returns
+//! `Lagged(n)` with the number of messages it missed, and it carries on from
+//! the oldest one still kept. A deliberately slow archiver subscribed next to
+//! the connections shows that happening.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Barrier;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, sleep_until, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Messages the broadcast channel keeps for receivers that have not read them yet
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Lines each client sends
+const MESSAGES_PER_CLIENT: usize = 5;
+
+/// One chat line, tagged with the connection it came from
+#[derive(Clone)]
+struct Message {
+    from: usize,
+    text: String,
+}
+
+/// Counts kept by the server and its archiver
+#[derive(Default)]
+struct ServerStats {
+    peak: AtomicUsize,
+    open: AtomicUsize,
+    lagged: AtomicUsize,
+    archived: AtomicUsize,
+    archive_skipped: AtomicUsize,
+}
+
+/// What a client saw during the run
+#[derive(Default)]
+struct ClientReport {
+    sent: usize,
+    received: usize,
+    notices: usize,
+    missed: usize,
+}
+
+/// One connection: publish the client's lines, and forward everyone else's until the client leaves
+async fn connection(
+    id: usize,
+    stream: TcpStream,
+    messages: broadcast::Sender<Message>,
+    stats: Arc<ServerStats>,
+) -> io::Result<()> {
+    // Subscribe before announcing, so the connection never misses what follows its own arrival
+    let mut inbox = messages.subscribe();
+    let _ = messages.send(Message { from: id, text: format!("*** user {} joined", id) });
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let result = loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(text)) => {
+                    chaos::perturb_async(Point::TaskStart).await;
+                    let _ = messages.send(Message { from: id, text: format!("[user {}] {}", id, text) });
+                }
+                Ok(None) => break Ok(()),
+                Err(error) => break Err(error),
+            },
+            message = inbox.recv() => {
+                let line = match message {
+                    Ok(message) if message.from == id => continue,
+                    Ok(message) => message.text,
+                    // Too far behind: the oldest messages were overwritten, so tell the client and carry on
+                    Err(RecvError::Lagged(missed)) => {
+                        stats.lagged.fetch_add(missed as usize, Ordering::Relaxed);
+                        format!("*** missed {} messages", missed)
+                    }
+                    Err(RecvError::Closed) => break Ok(()),
+                };
+                if let Err(error) = writer.write_all(format!("{}\n", line).as_bytes()).await {
+                    break Err(error);
+                }
+            }
+        }
+    };
+    let _ = messages.send(Message { from: id, text: format!("*** user {} left", id) });
+    result
+}
+
+/// A subscriber slower than the chat: it falls behind and skips what the channel dropped
+async fn archiver(mut inbox: broadcast::Receiver<Message>, cost: Duration, stats: Arc<ServerStats>, shutdown: CancellationToken) {
+    loop {
+        tokio::select! {
+            message = inbox.recv() => match message {
+                Ok(_) => {
+                    sleep(cost).await;
+                    stats.archived.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    stats.archive_skipped.fetch_add(missed as usize, Ordering::Relaxed);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = shutdown.cancelled() => break,
+        }
+    }
+}
+
+/// Accept connections until `shutdown`, each one handled by its own task
+async fn server(listener: TcpListener, messages: broadcast::Sender<Message>, stats: Arc<ServerStats>, shutdown: CancellationToken) {
+    let mut connections = JoinSet::new();
+    let mut next_id = 0;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else { continue };
+                let open = stats.open.fetch_add(1, Ordering::Relaxed) + 1;
+                stats.peak.fetch_max(open, Ordering::Relaxed);
+                let (messages, stats) = (messages.clone(), Arc::clone(&stats));
+                connections.spawn(async move {
+                    if let Err(error) = connection(next_id, stream, messages, Arc::clone(&stats)).await {
+                        common::print_warning(&format!("  user {}: {}", next_id, error));
+                    }
+                    stats.open.fetch_sub(1, Ordering::Relaxed);
+                });
+                next_id += 1;
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+    connections.shutdown().await;
+}
+
+/// One client: wait for everyone to join, send a line every `pause`, and read until the room goes quiet
+async fn client(id: usize, address: String, everyone: Arc<Barrier>, pause: Duration) -> io::Result<ClientReport> {
+    let stream = TcpStream::connect(&address).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut report = ClientReport::default();
+    everyone.wait().await;
+
+    let quiet = pause * 3 + Duration::from_millis(50);
+    let mut next_send = Instant::now();
+    loop {
+        tokio::select! {
+            _ = sleep_until(next_send), if report.sent < MESSAGES_PER_CLIENT => {
+                writer.write_all(format!("hello #{} from client {}\n", report.sent, id).as_bytes()).await?;
+                report.sent += 1;
+                next_send += pause;
+            }
+            line = lines.next_line() => match line? {
+                Some(line) if line.starts_with("*** missed ") => {
+                    report.missed += line.split_whitespace().nth(2).and_then(|count| count.parse::<usize>().ok()).unwrap_or(0);
+                }
+                Some(line) if line.starts_with("***") => report.notices += 1,
+                Some(_) => report.received += 1,
+                None => break,
+            },
+            // Done sending and nothing arrived for a while: leave the room
+            _ = sleep(quiet), if report.sent == MESSAGES_PER_CLIENT => break,
+        }
+    }
+    Ok(report)
+}
+
+/// Run the chat example: `num_clients` clients sending a line every `pause_ms` milliseconds
+pub fn run(num_clients: usize, pause_ms: u64) {
+    let num_clients = num_clients.max(2);
+    let pause = Duration::from_millis(pause_ms.max(1));
+    let archive_cost = pause / 2;
+    if virtual_time::is_enabled() {
+        common::print_warning("Real sockets need real time, so the chat example ignores --virtual-time");
+    }
+    common::print_info(&format!(
+        "{} clients each send {} lines {:?} apart through a broadcast channel of capacity {}; the archiver takes {:?} per message",
+        num_clients, MESSAGES_PER_CLIENT, pause, CHANNEL_CAPACITY, archive_cost
+    ));
+
+    let runtime = cpus::multi_thread_runtime();
+    let stats = Arc::new(ServerStats::default());
+    let (reports, failed, elapsed) = runtime.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (messages, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let shutdown = CancellationToken::new();
+        let archiver = tokio::spawn(archiver(messages.subscribe(), archive_cost, Arc::clone(&stats), shutdown.clone()));
+        let server = tokio::spawn(server(listener, messages, Arc::clone(&stats), shutdown.clone()));
+        common::print_info(&format!("Chat server listening on {}", address));
+
+        let start = Instant::now();
+        let everyone = Arc::new(Barrier::new(num_clients));
+        let mut clients = JoinSet::new();
+        for id in 0..num_clients {
+            clients.spawn(client(id, address.clone(), Arc::clone(&everyone), pause));
+        }
+        let (mut reports, mut failed) = (vec![], 0);
+        while let Some(result) = clients.join_next().await {
+            match result.unwrap() {
+                Ok(report) => reports.push(report),
+                Err(error) => {
+                    failed += 1;
+                    common::print_warning(&format!("  client failed: {}", error));
+                }
+            }
+        }
+        let elapsed = start.elapsed();
+
+        shutdown.cancel();
+        server.await.unwrap();
+        archiver.await.unwrap();
+        (reports, failed, elapsed)
+    });
+    audit::runtime("chat", &runtime);
+    runtime_metrics::record("chat", &runtime);
+
+    let sent: usize = reports.iter().map(|report| report.sent).sum();
+    let received: usize = reports.iter().map(|report| report.received).sum();
+    let missed: usize = reports.iter().map(|report| report.missed).sum();
+    let notices: usize = reports.iter().map(|report| report.notices).sum();
+    let expected = sent * (num_clients - 1);
+    let (archived, archive_skipped) = (stats.archived.load(Ordering::Relaxed), stats.archive_skipped.load(Ordering::Relaxed));
+
+    println!();
+    println!("{:<12} {:>10} {:>12} {:>12} {:>12}", "subscriber", "messages", "delivered", "missed", "notices");
+    println!("{:<12} {:>10} {:>12} {:>12} {:>12}", "clients", sent, format!("{}/{}", received, expected), missed, notices);
+    println!(
+        "{:<12} {:>10} {:>12} {:>12} {:>12}",
+        "archiver",
+        archived + archive_skipped,
+        archived,
+        archive_skipped,
+        "-"
+    );
+
+    println!();
+    common::print_info(&format!(
+        "{} clients connected, at most {} at once, in {:?}; {} failed",
+        reports.len() + failed,
+        stats.peak.load(Ordering::Relaxed),
+        elapsed,
+        failed
+    ));
+    if received == expected && missed == 0 {
+        common::print_success("Every client got every other client's lines: the connection tasks kept up with the channel");
+    } else if missed > 0 {
+        common::print_warning(&format!(
+            "Connection tasks fell behind and skipped {} messages; their clients were told how many",
+            stats.lagged.load(Ordering::Relaxed)
+        ));
+    }
+    if archive_skipped > 0 {
+        common::print_warning(&format!(
+            "The archiver lagged and skipped {} messages, join and leave notices included, without ever slowing the chat down",
+            archive_skipped
+        ));
+    }
+    common::print_info(&format!(
+        "broadcast keeps {} messages: a receiver further behind gets Lagged(n) and resumes at the oldest one left",
+        CHANNEL_CAPACITY
+    ));
+}
@@ -3,17 +3,27 @@
 //! This module demonstrates asynchronous programming in Rust using
 //! the Tokio runtime and async/await syntax.
 
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
 // Third-party dependencies
-use tokio::time::{sleep, Duration, Instant};
-use tokio::task;
+use tokio::sync::Semaphore;
+use tokio::time::{interval, sleep, Duration, Instant};
+use tokio::task::JoinSet;
 
 // Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
 use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
 
 /// Simulate an async task that takes some time to complete
 async fn async_task(id: usize, delay_ms: u64) -> String {
 
     // Log the task start
+    chaos::perturb_async(Point::TaskStart).await;
     common::print_info(&format!("Task {} started", id));
 
     // Simulate its execution
@@ -27,34 +37,87 @@ async fn async_task(id: usize, delay_ms: u64) -> String {
     result
 }
 
-/// Example of spawning multiple concurrent async tasks
-async fn spawn_concurrent_tasks(num_tasks: usize, delay_ms: u64) {
-    common::print_info(&format!("Spawning {} concurrent async tasks", num_tasks));
+/// Delay of task `id`: between half and one and a half times `delay_ms`, so tasks finish out of spawn order
+pub(crate) fn task_delay(id: usize, delay_ms: u64) -> u64 {
+    delay_ms * (5 + (id as u64 * 7) % 11) / 10
+}
+
+/// Example of spawning concurrent async tasks into a JoinSet, keeping at most `max_in_flight` of them running;
+/// with `until_ctrl_c`, Ctrl-C cancels the tasks left and the function returns false after printing the partial results
+async fn spawn_concurrent_tasks(num_tasks: usize, delay_ms: u64, max_in_flight: Option<usize>, until_ctrl_c: bool) -> bool {
+    let limit = max_in_flight.unwrap_or(num_tasks).clamp(1, num_tasks.max(1));
+    common::print_info(&format!(
+        "Spawning {} concurrent async tasks, at most {} in flight",
+        num_tasks, limit
+    ));
     let start = Instant::now();
 
-    let mut handles = vec![];
-    
-    for i in 0..num_tasks {
-        let handle = task::spawn(async_task(i, delay_ms));
-        handles.push(handle);
+    let mut set = JoinSet::new();
+    let mut next = 0;
+    let mut peak = 0;
+    let mut completion_order = vec![];
+
+    // Only polled with --until-ctrl-c, so the default Ctrl-C behaviour stays untouched otherwise
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut interrupted = None;
+    let mut cancelled = 0;
+
+    loop {
+        // Top the set up to the limit; a new task only starts once another one completed
+        while interrupted.is_none() && next < num_tasks && set.len() < limit {
+            let (id, delay) = (next, task_delay(next, delay_ms));
+            set.spawn(async move { (id, async_task(id, delay).await) });
+            next += 1;
+        }
+        peak = peak.max(set.len());
+
+        // join_next yields tasks in completion order, not in spawn order
+        tokio::select! {
+            biased;
+            _ = &mut ctrl_c, if until_ctrl_c && interrupted.is_none() => {
+                common::print_warning(&format!("Ctrl-C received: cancelling the {} task(s) in flight", set.len()));
+                set.abort_all();
+                interrupted = Some(start.elapsed());
+            }
+            result = set.join_next() => match result {
+                Some(Ok((id, _))) => completion_order.push(id),
+                // Draining the set after abort_all: each task was dropped at its await point
+                Some(Err(error)) if error.is_cancelled() => cancelled += 1,
+                Some(Err(error)) => std::panic::resume_unwind(error.into_panic()),
+                None => break,
+            },
+        }
     }
-    
-    // Wait for all tasks to complete
-    let mut results = vec![];
-    for handle in handles {
-        results.push(handle.await.unwrap());
+
+    if let Some(at) = interrupted {
+        println!();
+        common::print_warning(&format!("Interrupted after {:?}: partial results", at));
+        common::print_info(&format!("Completed: {} of {} tasks, in order {:?}", completion_order.len(), num_tasks, completion_order));
+        common::print_info(&format!("Cancelled in flight: {}", cancelled));
+        common::print_info(&format!("Never started: {}", num_tasks - next));
+        common::print_info(&format!("Tasks left in the set: {} - nothing outlives the interruption", set.len()));
+        return false;
     }
-    
+
     let duration = start.elapsed();
-    
+    let sequential: u64 = (0..num_tasks).map(|id| task_delay(id, delay_ms)).sum();
+
     println!();
     common::print_success(&format!("All {} tasks completed", num_tasks));
+    common::print_info(&format!("Completion order: {:?}", completion_order));
+    common::print_info(&format!("Peak tasks in flight: {} (limit {})", peak, limit));
     common::print_info(&format!("Total time: {:?}", duration));
     common::print_info(&format!(
-        "Tasks ran concurrently - total time (~{}ms) is much less than sequential time ({}ms)",
+        "Tasks ran concurrently - total time (~{}ms) vs sequential time ({}ms), bounded by the {} in flight",
         duration.as_millis(),
-        num_tasks as u64 * delay_ms
+        sequential,
+        limit
     ));
+    if until_ctrl_c {
+        common::print_info("Every task completed before Ctrl-C; try more tasks with --max-in-flight to have time to press it");
+    }
+    true
 }
 
 /// Example of using join! macro for concurrent execution
@@ -96,7 +159,43 @@ async fn sequential_example(num_tasks: usize, delay_ms: u64) {
     ));
 }
 
-/// Example of async task with timeout
+/// Value served when a task misses its deadline, standing in for a cache or a cheaper computation
+fn cached_result(id: usize) -> String {
+    format!("Task {} served from the cache", id)
+}
+
+/// Run task `id` under `timeout_duration`, falling back to the cached value if it takes longer;
+/// returns the result and the path that produced it
+async fn with_fallback(id: usize, delay_ms: u64, timeout_duration: Duration) -> (String, &'static str) {
+    match tokio::time::timeout(timeout_duration, async_task(id, delay_ms)).await {
+        Ok(result) => (result, "primary"),
+        Err(_) => {
+            common::print_warning(&format!("Task {} timed out after {:?}, falling back to the cache", id, timeout_duration));
+            (cached_result(id), "cache")
+        }
+    }
+}
+
+/// Hedged request: if task `id` is not done after `hedge_after`, start a duplicate and keep whichever finishes first
+async fn hedged(id: usize, primary_ms: u64, hedge_ms: u64, hedge_after: Duration) -> (String, &'static str) {
+    let primary = async_task(id, primary_ms);
+    tokio::pin!(primary);
+    tokio::select! {
+        result = &mut primary => return (result, "primary"),
+        _ = sleep(hedge_after) => {}
+    }
+
+    common::print_warning(&format!("Task {} still running after {:?}, sending a hedged duplicate", id, hedge_after));
+    let hedge = async_task(id + 1, hedge_ms);
+
+    // The loser of the race is dropped, which cancels it
+    tokio::select! {
+        result = &mut primary => (result, "primary"),
+        result = hedge => (result, "hedge"),
+    }
+}
+
+/// Example of async task with timeout, then the fallback and hedging patterns built on it
 async fn timeout_example(delay_ms: u64) {
     common::print_info("Running timeout example");
     
@@ -110,15 +209,214 @@ async fn timeout_example(delay_ms: u64) {
             timeout_duration
         )),
     }
+
+    println!();
+    common::print_info("Running timeout with fallback: a fast task, then a slow one");
+    let mut paths = vec![];
+    for (id, latency) in [(310, delay_ms / 4), (311, delay_ms)] {
+        let start = Instant::now();
+        let (result, path) = with_fallback(id, latency, timeout_duration).await;
+        paths.push((format!("fallback, task {}", id), path, start.elapsed(), result));
+    }
+
+    println!();
+    common::print_info("Running hedged requests: the duplicate is sent if the primary is not done in time");
+    let hedge_after = Duration::from_millis(delay_ms / 4);
+    for (id, primary_ms) in [(320, delay_ms / 8), (322, delay_ms * 2)] {
+        let start = Instant::now();
+        let (result, path) = hedged(id, primary_ms, delay_ms / 2, hedge_after).await;
+        paths.push((format!("hedged, task {}", id), path, start.elapsed(), result));
+    }
+
+    println!();
+    println!("{:<20} {:>10} {:>12}   result", "request", "path", "time");
+    for (request, path, elapsed, result) in &paths {
+        println!("{:<20} {:>10} {:>12?}   {}", request, path, elapsed, result);
+    }
+    println!();
+    common::print_info(&format!(
+        "A fallback bounds the wait at the timeout ({:?}) but serves an older or cheaper answer",
+        timeout_duration
+    ));
+    common::print_info(&format!(
+        "A hedge bounds the wait at about {:?} plus the duplicate's latency, at the cost of a second request for the slow ones",
+        hedge_after
+    ));
+}
+
+/// Spawn `num_tasks` tasks of `delay_ms` at once, each waiting for a permit of `semaphore` when there is one;
+/// returns the wall time and the most tasks seen running together
+async fn run_gated(num_tasks: usize, delay_ms: u64, semaphore: Option<Arc<Semaphore>>) -> (Duration, usize) {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+
+    let mut set = JoinSet::new();
+    for _ in 0..num_tasks {
+        let (semaphore, running, peak) = (semaphore.clone(), Arc::clone(&running), Arc::clone(&peak));
+        set.spawn(async move {
+            // Every task exists from the start; the permit decides when its work begins
+            let _permit = match semaphore {
+                Some(semaphore) => Some(semaphore.acquire_owned().await.unwrap()),
+                None => None,
+            };
+            chaos::perturb_async(Point::TaskStart).await;
+            peak.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            sleep(Duration::from_millis(delay_ms)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    while let Some(result) = set.join_next().await {
+        result.unwrap();
+    }
+
+    (start.elapsed(), peak.load(Ordering::SeqCst))
 }
 
-/// Run all async examples
-pub fn run(num_tasks: usize, delay_ms: u64) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
+/// Example of a semaphore limiting how many spawned tasks run at once
+async fn concurrency_limit_example(num_tasks: usize, delay_ms: u64, limit: usize) {
+    let limit = limit.max(1);
+    common::print_info(&format!(
+        "Running {} tasks of {}ms unbounded, then behind a semaphore of {} permits",
+        num_tasks, delay_ms, limit
+    ));
+
+    let (unbounded, unbounded_peak) = run_gated(num_tasks, delay_ms, None).await;
+    let (limited, limited_peak) = run_gated(num_tasks, delay_ms, Some(Arc::new(Semaphore::new(limit)))).await;
+
+    println!();
+    println!("{:<22} {:>14} {:>14}", "mode", "peak running", "wall time");
+    println!("{:<22} {:>14} {:>14?}", "unbounded", unbounded_peak, unbounded);
+    println!("{:<22} {:>14} {:>14?}", format!("limit {}", limit), limited_peak, limited);
+
+    println!();
+    let waves = num_tasks.div_ceil(limit);
+    common::print_info(&format!(
+        "With {} permits the tasks run in {} waves of {}ms (~{}ms expected), {:.1}x the unbounded time",
+        limit,
+        waves,
+        delay_ms,
+        waves as u64 * delay_ms,
+        limited.as_secs_f64() / unbounded.as_secs_f64().max(f64::EPSILON)
+    ));
+    common::print_info("The limit trades wall time for a cap on what runs at once: connections, memory, or load on a downstream service");
+}
+
+/// Tokens the rate limiter's bucket holds, and so the tasks that may start in one burst
+const BUCKET_CAPACITY: usize = 5;
+
+/// Token bucket: a semaphore holding the tokens, refilled by an interval at `rate` tokens per second
+struct TokenBucket {
+    tokens: Arc<Semaphore>,
+    refill: tokio::task::JoinHandle<()>,
+}
+
+impl TokenBucket {
+    /// A full bucket of `capacity` tokens, with one more added every `1 / rate` seconds
+    fn new(rate: u32, capacity: usize) -> Self {
+        let tokens = Arc::new(Semaphore::new(capacity));
+        let period = Duration::from_secs(1) / rate.max(1);
+        let refill = tokio::spawn({
+            let tokens = Arc::clone(&tokens);
+            async move {
+                let mut ticks = interval(period);
+                // The first tick completes at once; the bucket starts full anyway
+                ticks.tick().await;
+                loop {
+                    ticks.tick().await;
+                    // A full bucket drops the token, so idle time never saves up more than one burst
+                    if tokens.available_permits() < capacity {
+                        tokens.add_permits(1);
+                    }
+                }
+            }
+        });
+        TokenBucket { tokens, refill }
+    }
+
+    /// Wait for a token and spend it
+    async fn take(&self) {
+        // Forgetting the permit keeps it out of the bucket: only the refill puts tokens back
+        self.tokens.acquire().await.unwrap().forget();
+    }
+}
+
+impl Drop for TokenBucket {
+    fn drop(&mut self) {
+        self.refill.abort();
+    }
+}
+
+/// Example of a token bucket pacing task starts to `rate` per second
+async fn rate_limit_example(num_tasks: usize, delay_ms: u64, rate: u32) {
+    let rate = rate.max(1);
+    common::print_info(&format!(
+        "Starting {} tasks of {}ms through a token bucket of {} tokens refilled at {} per second",
+        num_tasks, delay_ms, BUCKET_CAPACITY, rate
+    ));
+
+    let bucket = Arc::new(TokenBucket::new(rate, BUCKET_CAPACITY));
+    let start = Instant::now();
+    let mut set = JoinSet::new();
+    for _ in 0..num_tasks {
+        let bucket = Arc::clone(&bucket);
+        set.spawn(async move {
+            // The token gates the start only; tasks that started may all be running together
+            bucket.take().await;
+            let started = start.elapsed();
+            chaos::perturb_async(Point::TaskStart).await;
+            sleep(Duration::from_millis(delay_ms)).await;
+            started
+        });
+    }
+    let mut starts = vec![];
+    while let Some(result) = set.join_next().await {
+        starts.push(result.unwrap());
+    }
+    let total = start.elapsed();
+    drop(bucket);
+    starts.sort_unstable();
+
+    // Starts before the first refill spent the initial tokens; the rest waited for the refill
+    let period = Duration::from_secs(1) / rate;
+    let burst = starts.iter().filter(|started| **started < period / 2).count();
+    let last = starts.last().copied().unwrap_or_default();
+    let overall = starts.len() as f64 / last.as_secs_f64().max(f64::EPSILON);
+    let paced = starts.len() - burst;
+    let sustained = match starts.get(burst.saturating_sub(1)) {
+        Some(first) if paced > 0 => paced as f64 / (last - *first).as_secs_f64().max(f64::EPSILON),
+        _ => 0.0,
+    };
+
+    println!();
+    println!("{:<26} {:>14}", "starts in the first burst", burst);
+    println!("{:<26} {:>14?}", "last start", last);
+    println!("{:<26} {:>14.1}", "overall starts/s", overall);
+    println!("{:<26} {:>14.1}", "starts/s after the burst", sustained);
+    println!("{:<26} {:>14?}", "wall time", total);
+
+    println!();
+    common::print_info(&format!(
+        "Target {} starts/s: the full bucket lets {} tasks start at once, then one starts per token every {:?}",
+        rate,
+        burst,
+        period
+    ));
+    common::print_info("A rate limit caps how often work starts, --concurrency-limit how much runs at once; a downstream API usually asks for the first");
+}
+
+/// Run all async examples, with at most `max_in_flight` of the concurrent tasks running at once,
+/// a semaphore comparison when `concurrency_limit` is set, and task starts paced to `rate` per second when set;
+/// with `until_ctrl_c`, Ctrl-C during the concurrent tasks cancels them and skips the other examples
+pub fn run(num_tasks: usize, delay_ms: u64, max_in_flight: Option<usize>, concurrency_limit: Option<usize>, rate: Option<u32>, until_ctrl_c: bool) {
+    let rt = virtual_time::runtime();
     
     rt.block_on(async {
-        // Concurrent execution
-        spawn_concurrent_tasks(num_tasks, delay_ms).await;
+        // Concurrent execution, the only part Ctrl-C can interrupt
+        if !spawn_concurrent_tasks(num_tasks, delay_ms, max_in_flight, until_ctrl_c).await {
+            common::print_warning("Skipping the remaining examples");
+            return;
+        }
         
         println!("\n{}", "=".repeat(60));
         
@@ -134,5 +432,55 @@ pub fn run(num_tasks: usize, delay_ms: u64) {
         
         // Timeout example
         timeout_example(delay_ms).await;
+
+        // Semaphore-limited concurrency
+        if let Some(limit) = concurrency_limit {
+            println!("\n{}", "=".repeat(60));
+            concurrency_limit_example(num_tasks, delay_ms, limit).await;
+        }
+
+        // Token-bucket rate limiting
+        if let Some(rate) = rate {
+            println!("\n{}", "=".repeat(60));
+            rate_limit_example(num_tasks, delay_ms, rate).await;
+        }
     });
+    audit::runtime("examples", &rt);
+    runtime_metrics::record("examples", &rt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time `future` on the clock of the runtime it runs on
+    async fn timed<T>(future: impl std::future::Future<Output = T>) -> (T, Duration) {
+        let start = Instant::now();
+        let output = future.await;
+        (output, start.elapsed())
+    }
+
+    #[test]
+    fn timeout_fallback_and_hedge_run_on_virtual_time() {
+        virtual_time::enable();
+        let runtime = virtual_time::runtime();
+        let wall_clock = std::time::Instant::now();
+
+        let ((fast, fast_time), (slow, slow_time), (hedge, hedge_time)) = runtime.block_on(async {
+            let timeout_duration = Duration::from_millis(500);
+            (
+                timed(with_fallback(1, 250, timeout_duration)).await,
+                timed(with_fallback(2, 5_000, timeout_duration)).await,
+                timed(hedged(3, 10_000, 1_000, Duration::from_millis(250))).await,
+            )
+        });
+
+        // The paused clock jumps straight to each deadline, so the simulated durations are exact
+        assert_eq!((fast.1, fast_time), ("primary", Duration::from_millis(250)));
+        assert_eq!((slow.1, slow_time), ("cache", Duration::from_millis(500)));
+        assert_eq!((hedge.1, hedge_time), ("hedge", Duration::from_millis(1_250)));
+
+        // Two simulated seconds of timers, none of them actually waited for
+        assert!(wall_clock.elapsed() < std::time::Duration::from_secs(1), "took {:?}", wall_clock.elapsed());
+    }
 }
@@ -0,0 +1,135 @@
+//! Concurrent stream fan-out with `buffered` and `buffer_unordered`
+//!
+//! Turning a list of work items into a stream of futures and awaiting them
+//! one by one runs them sequentially. `buffered(n)` polls up to `n` of them
+//! at once but yields the results in the original order, so a slow item
+//! holds back every result behind it even if those are ready. Its sibling
+//! `buffer_unordered(n)` yields each result as soon as it completes. Both
+//! keep at most `n` futures in flight, so they bound concurrency the way a
+//! semaphore would, without spawning anything.
+
+// Third-party dependencies
+use futures::stream::{self, StreamExt};
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Futures kept in flight when `--max-in-flight` is not given
+const DEFAULT_IN_FLIGHT: usize = 4;
+
+/// How the stream of work items is driven
+#[derive(Clone, Copy)]
+enum Mode {
+    Sequential,
+    Buffered,
+    BufferUnordered,
+}
+
+impl Mode {
+    fn label(&self, in_flight: usize) -> String {
+        match self {
+            Mode::Sequential => "sequential".to_string(),
+            Mode::Buffered => format!("buffered({})", in_flight),
+            Mode::BufferUnordered => format!("buffer_unordered({})", in_flight),
+        }
+    }
+}
+
+/// What the consumer saw in one mode
+struct FanOutReport {
+    total: Duration,
+    /// Item ids in the order the consumer received them
+    order: Vec<usize>,
+    /// When the consumer received each result
+    received: Vec<Duration>,
+    /// Time results spent done but not yet handed to the consumer
+    held_back: Duration,
+}
+
+/// One work item: wait for its duration, then report when it finished
+async fn work(id: usize, delay_ms: u64, start: Instant) -> (usize, Duration) {
+    chaos::perturb_async(Point::TaskStart).await;
+    sleep(Duration::from_millis(task_delay(id, delay_ms))).await;
+    (id, start.elapsed())
+}
+
+/// Drive `num_items` work items through the stream in `mode`
+async fn drive(mode: Mode, num_items: usize, delay_ms: u64, in_flight: usize) -> FanOutReport {
+    let start = Instant::now();
+
+    // The stream yields futures; nothing runs until the consumer below polls it
+    let items = stream::iter(0..num_items).map(|id| work(id, delay_ms, start));
+    let mut results = match mode {
+        Mode::Sequential => items.then(|item| item).boxed(),
+        Mode::Buffered => items.buffered(in_flight).boxed(),
+        Mode::BufferUnordered => items.buffer_unordered(in_flight).boxed(),
+    };
+
+    let mut report = FanOutReport {
+        total: Duration::ZERO,
+        order: vec![],
+        received: vec![],
+        held_back: Duration::ZERO,
+    };
+    while let Some((id, finished)) = results.next().await {
+        let received = start.elapsed();
+        report.order.push(id);
+        report.received.push(received);
+        report.held_back += received.saturating_sub(finished);
+    }
+    report.total = start.elapsed();
+    report
+}
+
+/// Run the fan-out example: `num_items` items of around `delay_ms` each, `in_flight` at a time
+pub fn run(num_items: usize, delay_ms: u64, in_flight: Option<usize>) {
+    let num_items = num_items.max(1);
+    let in_flight = in_flight.unwrap_or(DEFAULT_IN_FLIGHT).max(1);
+    common::print_info(&format!(
+        "{} work items taking between {}ms and {}ms, as a stream of futures with up to {} in flight",
+        num_items,
+        task_delay(0, delay_ms),
+        (0..num_items).map(|id| task_delay(id, delay_ms)).max().unwrap_or(0),
+        in_flight
+    ));
+
+    let runtime = virtual_time::runtime();
+    let modes = [Mode::Sequential, Mode::Buffered, Mode::BufferUnordered];
+    let reports: Vec<FanOutReport> = modes
+        .iter()
+        .map(|mode| runtime.block_on(drive(*mode, num_items, delay_ms, in_flight)))
+        .collect();
+    audit::runtime("fan-out", &runtime);
+    runtime_metrics::record("fan-out", &runtime);
+
+    println!();
+    println!(
+        "{:<22} {:>12} {:>14} {:>14} {:>14}",
+        "mode", "total", "first result", "mean result", "held back"
+    );
+    for (mode, report) in modes.iter().zip(&reports) {
+        println!(
+            "{:<22} {:>12?} {:>14?} {:>14?} {:>14?}",
+            mode.label(in_flight),
+            report.total,
+            report.received.first().copied().unwrap_or_default(),
+            report.received.iter().sum::<Duration>() / num_items as u32,
+            report.held_back
+        );
+    }
+
+    println!();
+    for (mode, report) in modes.iter().zip(&reports) {
+        common::print_info(&format!("{:<22} order: {:?}", mode.label(in_flight), report.order));
+    }
+    println!();
+    common::print_info("buffered keeps the input order: a slow item holds back results that are already done behind it");
+    common::print_info("buffer_unordered hands each result over as it completes, so results arrive sooner but out of order");
+    common::print_info("Both bound the futures in flight, like --concurrency-limit, without spawning a task per item");
+}
@@ -0,0 +1,294 @@
+//! Concurrent HTTP fetcher with a limit, retries and timeouts
+//!
+//! Sleeps stand in for I/O everywhere else in this module; here the tasks
+//! do real network requests with `reqwest`. The URLs come from a file, one
+//! per line, or else from a small HTTP server started on localhost whose
+//! endpoints answer fast, slowly, with errors now and then, with 404, or
+//! never. Requests run through `buffer_unordered` with a limit on those in
+//! flight. Each has a timeout, and timeouts, connection errors and 5xx
+//! answers are retried with exponential backoff.
+
+// Base dependencies
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use futures::stream::{self, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Requests in flight when `--max-in-flight` is not given
+const DEFAULT_IN_FLIGHT: usize = 8;
+
+/// Wait before the first retry; every further retry doubles it
+const BACKOFF: Duration = Duration::from_millis(50);
+
+/// Endpoints of the demo server, requested in turn
+const DEMO_PATHS: [&str; 6] = ["/fast", "/fast", "/slow", "/flaky", "/missing", "/hang"];
+
+/// How the last attempt for a URL ended
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Outcome {
+    Status(u16),
+    Timeout,
+    InvalidUrl,
+    Error(String),
+}
+
+impl Outcome {
+    fn label(&self) -> String {
+        match self {
+            Outcome::Status(code) => format!("HTTP {}", code),
+            Outcome::Timeout => "timeout".to_string(),
+            Outcome::InvalidUrl => "invalid URL".to_string(),
+            Outcome::Error(kind) => kind.clone(),
+        }
+    }
+
+    /// Timeouts, connection errors and server errors may succeed on another try
+    fn retryable(&self) -> bool {
+        match self {
+            Outcome::Status(code) => *code >= 500,
+            Outcome::Timeout | Outcome::Error(_) => true,
+            Outcome::InvalidUrl => false,
+        }
+    }
+}
+
+/// Result of fetching one URL, retries included
+struct Fetch {
+    outcome: Outcome,
+    attempts: u32,
+    latency: Duration,
+}
+
+/// Read the URLs of `path`, one per line, skipping blank lines and `#` comments
+fn read_urls(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Answer one request on the demo server according to its path
+async fn serve(mut stream: TcpStream, slow: Duration, flaky: Arc<AtomicUsize>) -> io::Result<()> {
+    let mut request = vec![0u8; 1024];
+    let mut read = 0;
+    while !request[..read].windows(4).any(|window| window == b"\r\n\r\n") && read < request.len() {
+        match stream.read(&mut request[read..]).await? {
+            0 => return Ok(()),
+            count => read += count,
+        }
+    }
+    let head = String::from_utf8_lossy(&request[..read]);
+    let path = head.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, reason) = match path {
+        "/fast" => (200, "OK"),
+        "/slow" => {
+            sleep(slow).await;
+            (200, "OK")
+        }
+        // Fails every other time, so a retry usually gets through
+        "/flaky" if flaky.fetch_add(1, Ordering::Relaxed).is_multiple_of(2) => (503, "Service Unavailable"),
+        "/flaky" => (200, "OK"),
+        "/hang" => {
+            // Never answers; the server aborts the connection when the run is over
+            std::future::pending::<()>().await;
+            unreachable!()
+        }
+        _ => (404, "Not Found"),
+    };
+    let body = format!("{}\n", reason);
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+/// Demo HTTP server on `listener`, serving every connection until `shutdown`
+async fn demo_server(listener: TcpListener, slow: Duration, shutdown: CancellationToken) {
+    let flaky = Arc::new(AtomicUsize::new(0));
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                if let Ok((stream, _)) = accepted {
+                    connections.spawn(serve(stream, slow, Arc::clone(&flaky)));
+                }
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    // Dropping the set aborts the connections still open, the hanging ones included
+    connections.shutdown().await;
+}
+
+/// Fetch `url`, retrying up to `retries` times with exponential backoff
+async fn fetch(client: &reqwest::Client, url: &str, retries: u32) -> Fetch {
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        chaos::perturb_async(Point::TaskStart).await;
+        let outcome = match client.get(url).send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+
+                // Read the body so the request really completes
+                let _ = response.bytes().await;
+                Outcome::Status(status)
+            }
+            Err(error) if error.is_timeout() => Outcome::Timeout,
+            Err(error) if error.is_connect() => Outcome::Error("connect error".to_string()),
+            Err(error) if error.is_builder() => Outcome::InvalidUrl,
+            Err(_) => Outcome::Error("request error".to_string()),
+        };
+        if !outcome.retryable() || attempts > retries {
+            return Fetch {
+                outcome,
+                attempts,
+                latency: start.elapsed(),
+            };
+        }
+        sleep(BACKOFF * 2u32.pow(attempts - 1)).await;
+    }
+}
+
+/// Run the fetcher over the URLs of `urls_file`, or `num_requests` requests to the demo server
+pub fn run(
+    num_requests: usize,
+    slow_ms: u64,
+    urls_file: Option<&Path>,
+    in_flight: Option<usize>,
+    retries: u32,
+    request_timeout: Duration,
+) {
+    let in_flight = in_flight.unwrap_or(DEFAULT_IN_FLIGHT).max(1);
+    if virtual_time::is_enabled() {
+        common::print_warning("Real sockets need real time, so the fetcher ignores --virtual-time");
+    }
+
+    let urls = match urls_file {
+        Some(path) => match read_urls(path) {
+            Ok(urls) if !urls.is_empty() => Some(urls),
+            Ok(_) => {
+                common::print_error(&format!("{} lists no URLs", path.display()));
+                return;
+            }
+            Err(error) => {
+                common::print_error(&format!("Could not read {}: {}", path.display(), error));
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let runtime = cpus::multi_thread_runtime();
+    let (fetches, elapsed) = runtime.block_on(async {
+        // Without a file, serve the demo endpoints on a free local port
+        let shutdown = CancellationToken::new();
+        let (urls, server) = match urls {
+            Some(urls) => (urls, None),
+            None => {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let address = listener.local_addr().unwrap();
+                let slow = Duration::from_millis(slow_ms);
+                let server = tokio::spawn(demo_server(listener, slow, shutdown.clone()));
+                let urls = (0..num_requests.max(1))
+                    .map(|index| format!("http://{}{}", address, DEMO_PATHS[index % DEMO_PATHS.len()]))
+                    .collect();
+                (urls, Some(server))
+            }
+        };
+        common::print_info(&format!(
+            "Fetching {} URLs, {} at a time, with a {:?} timeout per request and up to {} retries",
+            urls.len(),
+            in_flight,
+            request_timeout,
+            retries
+        ));
+
+        let client = reqwest::Client::builder().timeout(request_timeout).build().unwrap();
+        let start = Instant::now();
+        let fetches: Vec<Fetch> = stream::iter(&urls)
+            .map(|url| fetch(&client, url, retries))
+            .buffer_unordered(in_flight)
+            .collect()
+            .await;
+        let elapsed = start.elapsed();
+
+        // The client's pooled connections are tasks too; drop them with the client
+        drop(client);
+        shutdown.cancel();
+        if let Some(server) = server {
+            server.await.unwrap();
+        }
+        (fetches, elapsed)
+    });
+    audit::runtime("fetch", &runtime);
+    runtime_metrics::record("fetch", &runtime);
+
+    let mut outcomes: BTreeMap<Outcome, (usize, u32)> = BTreeMap::new();
+    for fetch in &fetches {
+        let entry = outcomes.entry(fetch.outcome.clone()).or_default();
+        entry.0 += 1;
+        entry.1 += fetch.attempts;
+    }
+
+    println!();
+    println!("{:<22} {:>8} {:>10}", "final outcome", "URLs", "attempts");
+    for (outcome, (count, attempts)) in &outcomes {
+        println!("{:<22} {:>8} {:>10}", outcome.label(), count, attempts);
+    }
+
+    let mut latencies: Vec<Duration> = fetches.iter().map(|fetch| fetch.latency).collect();
+    latencies.sort_unstable();
+    println!();
+    println!("{:>10} {:>10} {:>10} {:>10} {:>10}", "p50", "p90", "p99", "max", "total");
+    println!(
+        "{:>10?} {:>10?} {:>10?} {:>10?} {:>10?}",
+        common::percentile(&latencies, 50.0),
+        common::percentile(&latencies, 90.0),
+        common::percentile(&latencies, 99.0),
+        latencies.last().copied().unwrap_or_default(),
+        elapsed
+    );
+
+    println!();
+    let attempts: u32 = fetches.iter().map(|fetch| fetch.attempts).sum();
+    common::print_info(&format!(
+        "{} requests for {} URLs in {:?}; latencies include retries and their backoff",
+        attempts,
+        fetches.len(),
+        elapsed
+    ));
+    let retried = fetches.iter().filter(|fetch| fetch.attempts > 1 && fetch.outcome == Outcome::Status(200)).count();
+    if retried > 0 {
+        common::print_success(&format!("{} URL(s) succeeded on a retry", retried));
+    }
+    common::print_info("4xx answers and invalid URLs are not retried: asking again would get the same answer");
+}
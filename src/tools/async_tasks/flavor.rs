@@ -0,0 +1,149 @@
+//! Runtime flavour benchmark: `current_thread` vs `multi_thread`
+//!
+//! A current-thread runtime polls every task on the thread that called
+//! `block_on`; a multi-thread runtime spreads them over a pool of worker
+//! threads that steal work from each other. Tasks that mostly wait overlap
+//! their waits on either flavour, so the extra threads buy little. Tasks
+//! that mostly compute only overlap on separate threads, so the same
+//! workload scales with the worker count on the multi-thread runtime and
+//! not at all on the current-thread one.
+
+// Base dependencies
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::{self, JoinSet};
+use tokio::time::sleep;
+
+// Project dependencies
+use super::blocking::{calibrate, job};
+use crate::audit;
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+use crate::RuntimeFlavor;
+
+/// Slices each task's work is split into, with an `.await` between two slices
+const SLICES: u32 = 10;
+
+/// Task mixes run on each runtime
+#[derive(Clone, Copy)]
+enum Mix {
+    /// Every task waits
+    IoBound,
+    /// Every task computes
+    CpuBound,
+    /// Half the tasks wait, half compute
+    Mixed,
+}
+
+impl Mix {
+    const ALL: [Mix; 3] = [Mix::IoBound, Mix::CpuBound, Mix::Mixed];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Mix::IoBound => "IO-bound",
+            Mix::CpuBound => "CPU-bound",
+            Mix::Mixed => "mixed",
+        }
+    }
+
+    /// Whether task `id` of the mix computes rather than waits
+    fn computes(&self, id: usize) -> bool {
+        match self {
+            Mix::IoBound => false,
+            Mix::CpuBound => true,
+            Mix::Mixed => id % 2 == 1,
+        }
+    }
+}
+
+/// Run `num_tasks` tasks of `mix` on `runtime`, each busy for about `work` in `SLICES` slices
+fn time_mix(runtime: &Runtime, mix: Mix, num_tasks: usize, work: Duration, calls: u64) -> Duration {
+    runtime.block_on(async {
+        let start = Instant::now();
+        let mut set = JoinSet::new();
+        for id in 0..num_tasks {
+            let computes = mix.computes(id);
+            set.spawn(async move {
+                for _ in 0..SLICES {
+                    if computes {
+                        black_box(job(calls / SLICES as u64));
+                        task::yield_now().await;
+                    } else {
+                        sleep(work / SLICES).await;
+                    }
+                }
+            });
+        }
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+        start.elapsed()
+    })
+}
+
+/// Run the runtime flavour benchmark: `num_tasks` tasks of about `work_ms` each, on the flavours asked for
+pub fn run(num_tasks: usize, work_ms: u64, flavor: RuntimeFlavor, worker_threads: Option<usize>) {
+    let num_tasks = num_tasks.max(1);
+    let workers = worker_threads.unwrap_or_else(cpus::available).max(1);
+    let work = Duration::from_millis(work_ms.max(1));
+    let calls = calibrate(work);
+    common::print_info(&format!(
+        "{} tasks of ~{:?} each, waiting, computing or half of each, in {} slices separated by an .await",
+        num_tasks, work, SLICES
+    ));
+    if virtual_time::is_enabled() {
+        common::print_warning("The benchmark times real work on both runtimes, so it ignores --virtual-time");
+    }
+
+    let mut runtimes = vec![];
+    if matches!(flavor, RuntimeFlavor::CurrentThread | RuntimeFlavor::Both) {
+        runtimes.push(("current_thread".to_string(), Builder::new_current_thread().enable_all().build().unwrap()));
+    }
+    if matches!(flavor, RuntimeFlavor::MultiThread | RuntimeFlavor::Both) {
+        runtimes.push((
+            format!("multi_thread ({})", workers),
+            Builder::new_multi_thread().worker_threads(workers).enable_all().build().unwrap(),
+        ));
+    }
+
+    println!();
+    print!("{:<12}", "mix");
+    for (label, _) in &runtimes {
+        print!(" {:>20}", label);
+    }
+    if runtimes.len() == 2 {
+        print!(" {:>10}", "speedup");
+    }
+    println!();
+
+    for mix in Mix::ALL {
+        print!("{:<12}", mix.label());
+        let times: Vec<Duration> = runtimes
+            .iter()
+            .map(|(_, runtime)| time_mix(runtime, mix, num_tasks, work, calls))
+            .collect();
+        for time in &times {
+            print!(" {:>20?}", time);
+        }
+        if let [current, multi] = times[..] {
+            print!(" {:>9.2}x", current.as_secs_f64() / multi.as_secs_f64().max(f64::EPSILON));
+        }
+        println!();
+    }
+    for (label, runtime) in &runtimes {
+        audit::runtime(label, runtime);
+        runtime_metrics::record(label, runtime);
+    }
+
+    println!();
+    common::print_info("IO-bound tasks overlap their waits on a single thread, so both flavours take about one task's time");
+    common::print_info("CPU-bound tasks only overlap on separate threads: the current-thread runtime runs them one slice at a time");
+    if cpus::available() < 2 || workers < 2 {
+        common::print_warning("With a single CPU or worker thread, the multi-thread runtime has nothing to spread the CPU-bound tasks over");
+    }
+}
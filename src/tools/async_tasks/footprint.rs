@@ -0,0 +1,180 @@
+//! Memory footprint of parked OS threads vs pending Tokio tasks
+//!
+//! Threads are said to cost megabytes and tasks a few hundred bytes. This
+//! module measures it: it parks the same number of OS threads and of Tokio
+//! tasks, all blocked on a gate, and reports the resident and virtual memory
+//! each of them adds to the process.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+
+/// Stack size requested for the small-stack thread run
+const SMALL_STACK: usize = 64 * 1024;
+
+/// Memory readings taken before and while every unit is parked
+struct Footprint {
+    parked: usize,
+    spawn_time: Duration,
+    resident: Option<u64>,
+    virtual_size: Option<u64>,
+}
+
+/// Growth of a memory reading between two points, if both are available
+fn growth(before: Option<u64>, after: Option<u64>) -> Option<u64> {
+    before.zip(after).map(|(before, after)| after.saturating_sub(before))
+}
+
+/// Park `count` OS threads on a gate, measure, then release and join them
+fn park_threads(count: usize, stack_size: Option<usize>) -> Footprint {
+    common::release_free_memory();
+    let resident_before = common::resident_memory_bytes();
+    let virtual_before = common::virtual_memory_bytes();
+
+    // Threads block on the read side while we hold the write side
+    let gate = Arc::new(RwLock::new(()));
+    let closed = gate.write().unwrap();
+    let ready = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    let mut handles = vec![];
+    for _ in 0..count {
+        let gate = Arc::clone(&gate);
+        let ready = Arc::clone(&ready);
+        let mut builder = thread::Builder::new();
+        if let Some(stack_size) = stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let spawned = builder.spawn(move || {
+            ready.fetch_add(1, Ordering::Release);
+            let _open = gate.read().unwrap();
+        });
+
+        // The OS may refuse more threads (ulimit, max_map_count): measure what we got
+        match spawned {
+            Ok(handle) => handles.push(handle),
+            Err(error) => {
+                common::print_warning(&format!("Stopped after {} threads: {}", handles.len(), error));
+                break;
+            }
+        }
+    }
+    let spawn_time = start.elapsed();
+
+    // Wait until every thread is actually running before reading memory
+    while ready.load(Ordering::Acquire) < handles.len() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let footprint = Footprint {
+        parked: handles.len(),
+        spawn_time,
+        resident: growth(resident_before, common::resident_memory_bytes()),
+        virtual_size: growth(virtual_before, common::virtual_memory_bytes()),
+    };
+
+    drop(closed);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    footprint
+}
+
+/// Park `count` Tokio tasks on a gate, measure, then release and await them
+fn park_tasks(count: usize) -> Footprint {
+    let runtime = cpus::multi_thread_runtime();
+
+    let footprint = runtime.block_on(async {
+        common::release_free_memory();
+        let resident_before = common::resident_memory_bytes();
+        let virtual_before = common::virtual_memory_bytes();
+
+        let gate = Arc::new(tokio::sync::RwLock::new(()));
+        let closed = gate.write().await;
+        let ready = Arc::new(AtomicUsize::new(0));
+
+        let start = Instant::now();
+        let mut handles = Vec::with_capacity(count);
+        for _ in 0..count {
+            let gate = Arc::clone(&gate);
+            let ready = Arc::clone(&ready);
+            handles.push(tokio::spawn(async move {
+                ready.fetch_add(1, Ordering::Release);
+                let _open = gate.read().await;
+            }));
+        }
+        let spawn_time = start.elapsed();
+
+        // Yield until every task has been polled once and is waiting on the gate
+        while ready.load(Ordering::Acquire) < count {
+            tokio::task::yield_now().await;
+        }
+        let footprint = Footprint {
+            parked: count,
+            spawn_time,
+            resident: growth(resident_before, common::resident_memory_bytes()),
+            virtual_size: growth(virtual_before, common::virtual_memory_bytes()),
+        };
+
+        drop(closed);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        footprint
+    });
+    audit::runtime("footprint", &runtime);
+    runtime_metrics::record("footprint", &runtime);
+    footprint
+}
+
+/// Format a total and its per-unit share
+fn per_unit(total: Option<u64>, units: usize) -> (String, String) {
+    match total {
+        Some(bytes) => (
+            common::format_bytes(bytes),
+            common::format_bytes(bytes / units.max(1) as u64),
+        ),
+        None => ("n/a".to_string(), "n/a".to_string()),
+    }
+}
+
+/// Run the threads vs tasks memory footprint comparison
+pub fn run(count: usize) {
+    common::print_info(&format!(
+        "Parking {} OS threads and {} Tokio tasks on a gate, then measuring the memory they hold",
+        count, count
+    ));
+
+    println!();
+    println!(
+        "{:<22} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "unit", "parked", "spawn time", "RSS", "RSS/unit", "virtual", "virtual/unit"
+    );
+
+    let runs = [
+        ("thread (default stack)", park_threads(count, None)),
+        ("thread (64 KiB stack)", park_threads(count, Some(SMALL_STACK))),
+        ("tokio task", park_tasks(count)),
+    ];
+
+    for (label, footprint) in &runs {
+        let (resident, resident_per_unit) = per_unit(footprint.resident, footprint.parked);
+        let (virtual_size, virtual_per_unit) = per_unit(footprint.virtual_size, footprint.parked);
+        println!(
+            "{:<22} {:>8} {:>12?} {:>12} {:>12} {:>12} {:>12}",
+            label, footprint.parked, footprint.spawn_time, resident, resident_per_unit, virtual_size, virtual_per_unit
+        );
+    }
+
+    println!();
+    common::print_success("Every parked thread and task was released and joined");
+    common::print_info("A thread reserves its whole stack as virtual memory but only touched pages count towards RSS");
+    common::print_info("A task is a heap-allocated state machine: its footprint is the size of the future plus the scheduler's bookkeeping");
+}
@@ -0,0 +1,183 @@
+//! Awaiting many futures: `join_all` vs `FuturesUnordered` vs `JoinSet`
+//!
+//! Three common ways to wait for a pile of futures behave differently as the
+//! pile grows. `join_all` keeps every future inside one task and returns the
+//! outputs in input order; up to 30 futures it polls all of them on every
+//! wake-up, and beyond that it switches to a `FuturesOrdered`.
+//! `FuturesUnordered` also runs in one task, but polls only the futures that
+//! were woken and yields outputs as they complete. A `JoinSet` spawns every
+//! future as a task of its own: the runtime's workers can poll them in
+//! parallel, and each one carries a task header and a join handle. Each size
+//! is timed, and the memory held while every future is pending is sampled.
+
+// Base dependencies
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::runtime::Runtime;
+use tokio::sync::Barrier;
+use tokio::task::{self, JoinSet};
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Smallest number of futures benchmarked; sizes grow tenfold from here
+const FIRST_SIZE: usize = 10;
+
+/// Times every future yields after the barrier, so each one is woken and polled a few times
+const YIELDS: usize = 3;
+
+/// Ways of awaiting the futures
+#[derive(Clone, Copy)]
+enum Method {
+    JoinAll,
+    FuturesUnordered,
+    JoinSet,
+}
+
+impl Method {
+    fn label(&self) -> &'static str {
+        match self {
+            Method::JoinAll => "join_all",
+            Method::FuturesUnordered => "FuturesUnordered",
+            Method::JoinSet => "JoinSet",
+        }
+    }
+}
+
+/// One method at one size
+struct Measurement {
+    elapsed: Duration,
+    /// Growth of the resident memory while every future was pending
+    peak_memory: Option<u64>,
+}
+
+/// A future of the benchmark: waits until all of them exist, then yields a few times and returns its id
+async fn unit(id: usize, barrier: Arc<Barrier>, peak: Arc<AtomicU64>) -> usize {
+    // The last one to arrive sees every future pending at once, the moment their memory peaks
+    if barrier.wait().await.is_leader() {
+        if let Some(resident) = common::resident_memory_bytes() {
+            peak.store(resident, Ordering::Relaxed);
+        }
+    }
+    for _ in 0..YIELDS {
+        task::yield_now().await;
+    }
+    id
+}
+
+/// Await `size` futures with `method` on `runtime`
+fn measure(runtime: &Runtime, method: Method, size: usize) -> Measurement {
+    common::release_free_memory();
+    let baseline = common::resident_memory_bytes();
+    let barrier = Arc::new(Barrier::new(size));
+    let peak = Arc::new(AtomicU64::new(0));
+
+    let start = Instant::now();
+    let ids = (0..size).map(|id| unit(id, Arc::clone(&barrier), Arc::clone(&peak)));
+    let checksum: usize = runtime.block_on(async {
+        match method {
+            Method::JoinAll => join_all(ids).await.into_iter().sum(),
+            Method::FuturesUnordered => {
+                let mut pending: FuturesUnordered<_> = ids.collect();
+                let mut sum = 0;
+                while let Some(id) = pending.next().await {
+                    sum += id;
+                }
+                sum
+            }
+            Method::JoinSet => {
+                let mut set = JoinSet::new();
+                for future in ids {
+                    set.spawn(future);
+                }
+                let mut sum = 0;
+                while let Some(result) = set.join_next().await {
+                    sum += result.unwrap();
+                }
+                sum
+            }
+        }
+    });
+    let elapsed = start.elapsed();
+    assert_eq!(checksum, size * (size - 1) / 2);
+
+    let peak = Some(peak.load(Ordering::Relaxed)).filter(|peak| *peak > 0);
+    Measurement {
+        elapsed,
+        peak_memory: baseline.zip(peak).map(|(baseline, peak)| peak.saturating_sub(baseline)),
+    }
+}
+
+/// Sizes benchmarked: tenfold steps from `FIRST_SIZE`, ending at `largest`
+fn sizes(largest: usize) -> Vec<usize> {
+    let mut sizes: Vec<usize> = std::iter::successors(Some(FIRST_SIZE), |size| size.checked_mul(10))
+        .take_while(|size| *size < largest)
+        .collect();
+    sizes.push(largest);
+    sizes
+}
+
+/// Run the benchmark with up to `largest` futures
+pub fn run(largest: usize) {
+    let sizes = sizes(largest.max(1));
+    common::print_info(&format!(
+        "Awaiting {:?} futures with join_all, FuturesUnordered and JoinSet; each waits for all the others, then yields {} times",
+        sizes, YIELDS
+    ));
+    if virtual_time::is_enabled() {
+        common::print_warning("The benchmark times real work, so it ignores --virtual-time");
+    }
+    if largest < 10_000 {
+        common::print_warning("The methods only pull apart with many futures; try --tasks 50000");
+    }
+
+    let runtime = cpus::multi_thread_runtime();
+    let methods = [Method::JoinAll, Method::FuturesUnordered, Method::JoinSet];
+    println!();
+    println!(
+        "{:>8} {:<18} {:>14} {:>12} {:>14} {:>12}",
+        "futures", "method", "time", "per future", "peak memory", "per future"
+    );
+    let mut largest_run = vec![];
+    for size in &sizes {
+        for method in &methods {
+            let measurement = measure(&runtime, *method, *size);
+            let (memory, memory_per_future) = match measurement.peak_memory {
+                Some(bytes) => (common::format_bytes(bytes), format!("{} B", bytes / *size as u64)),
+                None => ("n/a".to_string(), "n/a".to_string()),
+            };
+            println!(
+                "{:>8} {:<18} {:>14?} {:>12?} {:>14} {:>12}",
+                size,
+                method.label(),
+                measurement.elapsed,
+                measurement.elapsed / *size as u32,
+                memory,
+                memory_per_future
+            );
+            if size == sizes.last().unwrap() {
+                largest_run.push((method.label(), measurement.elapsed));
+            }
+        }
+    }
+    audit::runtime("join-benchmark", &runtime);
+    runtime_metrics::record("join-benchmark", &runtime);
+
+    println!();
+    if let Some((fastest, elapsed)) = largest_run.iter().min_by_key(|(_, elapsed)| *elapsed) {
+        common::print_success(&format!("Fastest with {} futures: {} in {:?}", largest, fastest, elapsed));
+    }
+    common::print_info("join_all and FuturesUnordered keep every future inside one task, polled by one worker at a time");
+    common::print_info("JoinSet pays a task allocation and a schedule per future, in exchange for polling them on every worker");
+    common::print_info("join_all returns outputs in input order, holding early finishers; the other two hand them out as they complete");
+    common::print_info("Memory is read in pages from RSS: below a few thousand futures the per-future figures are mostly noise");
+}
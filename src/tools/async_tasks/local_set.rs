@@ -0,0 +1,225 @@
+//! `!Send` futures on a `LocalSet`
+//!
+//! `tokio::spawn` only takes `Send` futures: a multi-thread runtime may
+//! resume a task on another worker after any `.await`, so everything the
+//! task holds across an await has to be safe to move there. A future that
+//! keeps an `Rc`, or any type that is not thread safe, alive across an await
+//! is `!Send`, and spawning it does not compile. A `LocalSet` runs such
+//! futures: `spawn_local` queues them on the set, and the set polls them on
+//! the thread driving it, never moving them. Local tasks can then share
+//! state through `Rc<RefCell<..>>` without atomics or locks, but one set only
+//! ever uses one thread. To use more, each thread runs a `LocalSet` of its
+//! own, with its own state, and the work is sharded between them.
+
+// Base dependencies
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+// Third-party dependencies
+use tokio::runtime::Builder;
+use tokio::task::{self, LocalSet};
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// State shared by the local tasks of one thread: behind an `Rc`, it makes every future holding it `!Send`
+#[derive(Default)]
+struct LocalState {
+    active: Cell<usize>,
+    peak: Cell<usize>,
+    completed: RefCell<Vec<usize>>,
+    threads: RefCell<HashSet<ThreadId>>,
+}
+
+/// The same state for `tokio::spawn`: behind an `Arc`, with atomics and a mutex
+#[derive(Default)]
+struct SharedState {
+    active: AtomicUsize,
+    peak: AtomicUsize,
+    completed: Mutex<Vec<usize>>,
+    threads: Mutex<HashSet<ThreadId>>,
+}
+
+/// A local job: keeps its `Rc` across the sleep, so it can only run on a `LocalSet`
+async fn local_job(id: usize, delay: Duration, state: Rc<LocalState>) {
+    chaos::perturb_async(Point::TaskStart).await;
+    state.active.set(state.active.get() + 1);
+    state.peak.set(state.peak.get().max(state.active.get()));
+    state.threads.borrow_mut().insert(thread::current().id());
+
+    // A RefCell borrow must not be held across an .await: another local task could borrow it meanwhile and panic
+    sleep(delay).await;
+
+    state.threads.borrow_mut().insert(thread::current().id());
+    state.completed.borrow_mut().push(id);
+    state.active.set(state.active.get() - 1);
+}
+
+/// The same job for `tokio::spawn`: every access to the shared state is synchronized
+async fn send_job(id: usize, delay: Duration, state: Arc<SharedState>) {
+    chaos::perturb_async(Point::TaskStart).await;
+    let active = state.active.fetch_add(1, Ordering::Relaxed) + 1;
+    state.peak.fetch_max(active, Ordering::Relaxed);
+    state.threads.lock().unwrap().insert(thread::current().id());
+
+    sleep(delay).await;
+
+    state.threads.lock().unwrap().insert(thread::current().id());
+    state.completed.lock().unwrap().push(id);
+    state.active.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// One way of running the jobs
+struct Outcome {
+    label: &'static str,
+    state: &'static str,
+    completed: usize,
+    threads: usize,
+    peak: usize,
+    elapsed: Duration,
+}
+
+/// Run `ids` as local tasks on a fresh `LocalSet`, on a current-thread runtime of this thread
+fn run_local_shard(ids: Vec<usize>, delay_ms: u64) -> (Rc<LocalState>, Duration) {
+    let runtime = Builder::new_current_thread().enable_all().start_paused(virtual_time::is_enabled()).build().unwrap();
+    let local = LocalSet::new();
+    let state = Rc::new(LocalState::default());
+
+    // run_until drives the set while the future runs; the future itself may spawn_local
+    let elapsed = runtime.block_on(local.run_until(async {
+        let start = Instant::now();
+        let handles: Vec<_> = ids
+            .into_iter()
+            .map(|id| task::spawn_local(local_job(id, Duration::from_millis(task_delay(id, delay_ms)), Rc::clone(&state))))
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        start.elapsed()
+    }));
+    (state, elapsed)
+}
+
+/// Run the LocalSet example: `num_jobs` jobs of about `delay_ms`, spawned locally, with tokio::spawn, and sharded over threads
+pub fn run(num_jobs: usize, delay_ms: u64) {
+    common::print_info(&format!(
+        "Running {} jobs of ~{}ms that keep shared state across an .await: Rc on a LocalSet, Arc with tokio::spawn, then one LocalSet per thread",
+        num_jobs, delay_ms
+    ));
+    common::print_info("tokio::spawn(local_job(..)) does not compile: `Rc<LocalState>` cannot be sent between threads safely");
+    let mut outcomes = vec![];
+
+    // Local tasks: spawned on the set, polled only by the thread that drives it
+    let runtime = virtual_time::runtime();
+    let local = LocalSet::new();
+    let state = Rc::new(LocalState::default());
+    let elapsed = local.block_on(&runtime, async {
+        let start = Instant::now();
+        let handles: Vec<_> = (0..num_jobs)
+            .map(|id| task::spawn_local(local_job(id, Duration::from_millis(task_delay(id, delay_ms)), Rc::clone(&state))))
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        start.elapsed()
+    });
+    drop(local);
+    audit::runtime("local-set", &runtime);
+    runtime_metrics::record("local-set", &runtime);
+    outcomes.push(Outcome {
+        label: "spawn_local on a LocalSet",
+        state: "Rc<RefCell<..>>",
+        completed: state.completed.borrow().len(),
+        threads: state.threads.borrow().len(),
+        peak: state.peak.get(),
+        elapsed,
+    });
+    let references = Rc::strong_count(&state);
+
+    // Send tasks: any worker of the runtime may poll them, before and after each .await
+    let runtime = virtual_time::runtime();
+    let state = Arc::new(SharedState::default());
+    let elapsed = runtime.block_on(async {
+        let start = Instant::now();
+        let handles: Vec<_> = (0..num_jobs)
+            .map(|id| tokio::spawn(send_job(id, Duration::from_millis(task_delay(id, delay_ms)), Arc::clone(&state))))
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        start.elapsed()
+    });
+    audit::runtime("local-set spawn", &runtime);
+    runtime_metrics::record("local-set spawn", &runtime);
+    outcomes.push(Outcome {
+        label: "tokio::spawn",
+        state: "Arc<Mutex<..>>, atomics",
+        completed: state.completed.lock().unwrap().len(),
+        threads: state.threads.lock().unwrap().len(),
+        peak: state.peak.load(Ordering::Relaxed),
+        elapsed,
+    });
+
+    // A LocalSet per thread, each with its own Rc state: the jobs are sharded, the state is never shared between threads
+    let shards = cpus::available().max(2).min(num_jobs.max(1));
+    let results: Vec<(usize, usize, usize, Duration)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..shards)
+            .map(|shard| {
+                let ids: Vec<usize> = (shard..num_jobs).step_by(shards).collect();
+                scope.spawn(move || {
+                    let (state, elapsed) = run_local_shard(ids, delay_ms);
+                    let completed = state.completed.borrow().len();
+                    let threads = state.threads.borrow().len();
+                    (completed, threads, state.peak.get(), elapsed)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    outcomes.push(Outcome {
+        label: "a LocalSet per thread",
+        state: "one Rc<RefCell<..>> per thread",
+        completed: results.iter().map(|result| result.0).sum(),
+        threads: results.iter().map(|result| result.1).sum(),
+        peak: results.iter().map(|result| result.2).sum(),
+        elapsed: results.iter().map(|result| result.3).max().unwrap_or_default(),
+    });
+
+    println!();
+    println!(
+        "{:<26} {:<31} {:>6} {:>8} {:>12} {:>12}",
+        "tasks", "shared state", "jobs", "threads", "peak active", "time"
+    );
+    for outcome in &outcomes {
+        println!(
+            "{:<26} {:<31} {:>6} {:>8} {:>12} {:>12?}",
+            outcome.label, outcome.state, outcome.completed, outcome.threads, outcome.peak, outcome.elapsed
+        );
+    }
+
+    println!();
+    if outcomes.iter().all(|outcome| outcome.completed == num_jobs) && outcomes[0].threads == 1 {
+        common::print_success("Every job ran, and the local tasks never left the thread driving their LocalSet");
+    }
+    common::print_info(&format!(
+        "After the LocalSet was dropped, {} reference to its Rc state was left: the tasks and their clones were all gone",
+        references
+    ));
+    common::print_info(&format!(
+        "The {} shards each ran their jobs on their own thread, with {:?} jobs per thread",
+        shards,
+        results.iter().map(|result| result.0).collect::<Vec<_>>()
+    ));
+    common::print_info("spawn_local outside a LocalSet panics; a JoinHandle of a local task can be awaited like any other");
+}
@@ -0,0 +1,154 @@
+//! Merging several async sources into one stream with `select_all`
+//!
+//! A consumer often waits on more than one source: a timer, messages from
+//! other tasks, data it produces itself. `select_all` takes any number of
+//! streams of the same item type and returns one stream that yields each
+//! item as soon as its source has it, so the consumer handles events in the
+//! order they arrive with a single `while let Some(..)` loop. The merged
+//! stream only ends once every source has ended. Sources of different kinds
+//! are mapped to one event type and boxed to give them the same type.
+
+// Third-party dependencies
+use futures::stream::{self, select_all, BoxStream, StreamExt};
+use tokio::time::{interval, sleep, Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::traced::{self, TokioReceiver};
+use crate::virtual_time;
+
+/// The sources merged
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    Timer,
+    Channel,
+    Generator,
+}
+
+impl Source {
+    fn label(&self) -> &'static str {
+        match self {
+            Source::Timer => "timer",
+            Source::Channel => "channel",
+            Source::Generator => "generator",
+        }
+    }
+}
+
+/// One item of the merged stream, whichever source it came from
+struct Event {
+    source: Source,
+    description: String,
+}
+
+/// Every `period`, `ticks` times: an interval turned into a stream
+fn timer_stream(period: Duration, ticks: usize) -> BoxStream<'static, Event> {
+    stream::unfold((interval(period), 0), |(mut interval, tick)| async move {
+        interval.tick().await;
+        let event = Event { source: Source::Timer, description: format!("tick {}", tick) };
+        Some((event, (interval, tick + 1)))
+    })
+    .skip(1)
+    .take(ticks)
+    .boxed()
+}
+
+/// Messages sent by other tasks, until every sender is dropped: a receiver turned into a stream
+fn channel_stream(receiver: TokioReceiver<String>) -> BoxStream<'static, Event> {
+    stream::unfold(receiver, |mut receiver| async move {
+        let message = receiver.recv().await?;
+        Some((Event { source: Source::Channel, description: message }, receiver))
+    })
+    .boxed()
+}
+
+/// Values computed by the consumer's side itself, each taking `step` to produce
+fn generated_stream(step: Duration, count: usize) -> BoxStream<'static, Event> {
+    stream::iter(0..count as u64)
+        .scan((0u64, 1u64), |fibonacci, _| {
+            let value = fibonacci.0;
+            *fibonacci = (fibonacci.1, fibonacci.0 + fibonacci.1);
+            futures::future::ready(Some(value))
+        })
+        .then(move |value| async move {
+            sleep(step).await;
+            Event { source: Source::Generator, description: format!("fibonacci {}", value) }
+        })
+        .boxed()
+}
+
+/// Run the merge example: `count` items from each source, paced around `delay_ms`
+pub fn run(count: usize, delay_ms: u64) {
+    let delay = Duration::from_millis(delay_ms.max(1));
+    let sources = [Source::Timer, Source::Channel, Source::Generator];
+    common::print_info(&format!(
+        "Merging {} ticks every {:?}, {} messages from producer tasks and {} generated values every {:?}",
+        count,
+        delay,
+        count,
+        count,
+        delay * 3 / 2
+    ));
+    println!();
+
+    let runtime = virtual_time::runtime();
+    let (tally, order) = runtime.block_on(async {
+        // Producers send their messages at their own pace, then drop their sender
+        let (sender, receiver) = traced::tokio_unbounded::<String>("merge-streams");
+        for id in 0..count {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                chaos::perturb_async(Point::TaskStart).await;
+                sleep(Duration::from_millis(task_delay(id, delay_ms) * 2)).await;
+                let _ = sender.send(format!("message from producer {}", id));
+            });
+        }
+        drop(sender);
+
+        let mut merged = select_all([
+            timer_stream(delay, count),
+            channel_stream(receiver),
+            generated_stream(delay * 3 / 2, count),
+        ]);
+
+        let start = Instant::now();
+        let mut tally = [0usize; 3];
+        let mut order = vec![];
+        while let Some(event) = merged.next().await {
+            let index = sources.iter().position(|source| *source == event.source).unwrap();
+            tally[index] += 1;
+            order.push(event.source);
+            println!("[{:>8?}] {:<10} {}", start.elapsed(), event.source.label(), event.description);
+        }
+        (tally, order)
+    });
+    audit::runtime("merge-streams", &runtime);
+    runtime_metrics::record("merge-streams", &runtime);
+
+    println!();
+    println!("{:<10} {:>7} {:>12}", "source", "events", "first at");
+    for (source, events) in sources.iter().zip(tally) {
+        let first = order.iter().position(|arrived| arrived == source);
+        println!(
+            "{:<10} {:>7} {:>12}",
+            source.label(),
+            events,
+            first.map_or("-".to_string(), |position| format!("#{}", position + 1))
+        );
+    }
+
+    println!();
+    let switches = order.windows(2).filter(|pair| pair[0] != pair[1]).count();
+    common::print_success(&format!(
+        "{} events from {} sources in one loop, in arrival order, switching source {} times",
+        order.len(),
+        sources.len(),
+        switches
+    ));
+    common::print_info("select_all polls only the sources that were woken, and ends once every source has ended");
+    common::print_info("Each source was boxed into a BoxStream<Event>: select_all needs them all of the same type");
+}
@@ -0,0 +1,386 @@
+//! A tiny single-threaded executor, built by hand
+//!
+//! Tokio is one executor among many: anything that polls futures and
+//! honours their wakers can run them. This one fits in a page. A queue holds
+//! the tasks ready to be polled; the waker handed to each poll is built from
+//! a `RawWaker` whose vtable pushes the task back onto that queue, and the
+//! executor parks on a condition variable while the queue is empty. The
+//! futures are hand-written too: one that yields by waking itself, and a
+//! timer that wakes its task from a helper thread. Running the same futures
+//! on Tokio shows they only depend on the `Waker` contract, and Tokio's own
+//! timers show what a real reactor saves: a thread per pending timer.
+
+// Base dependencies
+use std::collections::VecDeque;
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Times each task yields back to its executor after its timer fired
+const YIELDS_PER_TASK: usize = 3;
+
+/// A task's future, boxed so tasks of any future type share one queue
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Helper threads the hand-written timers have spawned so far
+static TIMER_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// State shared by the executor and every waker of its tasks
+struct Shared {
+    ready: Mutex<VecDeque<Arc<Task>>>,
+    wakeup: Condvar,
+    wakes: AtomicUsize,
+}
+
+/// A spawned future, polled by the executor whenever its waker puts it back on the queue
+struct Task {
+    future: Mutex<Option<BoxFuture>>,
+    shared: Arc<Shared>,
+}
+
+impl Task {
+    fn schedule(self: &Arc<Self>) {
+        self.shared.wakes.fetch_add(1, Ordering::Relaxed);
+        self.shared.ready.lock().unwrap().push_back(Arc::clone(self));
+        self.shared.wakeup.notify_one();
+    }
+}
+
+/// The waker's vtable: its data pointer is an `Arc<Task>` turned into a raw pointer
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+/// Every clone of a waker owns one strong count of its task
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    Arc::increment_strong_count(data as *const Task);
+    RawWaker::new(data, &VTABLE)
+}
+
+/// `wake` consumes the waker, and with it its strong count
+unsafe fn wake(data: *const ()) {
+    let task = Arc::from_raw(data as *const Task);
+    task.schedule();
+}
+
+/// `wake_by_ref` leaves the waker, and its strong count, to its owner
+unsafe fn wake_by_ref(data: *const ()) {
+    let task = ManuallyDrop::new(Arc::from_raw(data as *const Task));
+    task.schedule();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    drop(Arc::from_raw(data as *const Task));
+}
+
+/// A waker that schedules `task` again when woken
+fn waker_for(task: &Arc<Task>) -> Waker {
+    let data = Arc::into_raw(Arc::clone(task)) as *const ();
+    // Safety: the vtable functions above keep the strong count in step with the wakers alive
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+/// What the executor did during a run
+struct ExecutorStats {
+    polls: usize,
+    wakes: usize,
+    parks: usize,
+}
+
+/// Single-threaded executor: a ready queue, polled until every task completed
+struct MiniExecutor {
+    shared: Arc<Shared>,
+    pending: usize,
+}
+
+impl MiniExecutor {
+    fn new() -> Self {
+        MiniExecutor {
+            shared: Arc::new(Shared {
+                ready: Mutex::new(VecDeque::new()),
+                wakeup: Condvar::new(),
+                wakes: AtomicUsize::new(0),
+            }),
+            pending: 0,
+        }
+    }
+
+    /// Queue `future` as a new task, to be polled for the first time once `run` starts
+    fn spawn(&mut self, future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            shared: Arc::clone(&self.shared),
+        });
+        self.shared.ready.lock().unwrap().push_back(task);
+        self.pending += 1;
+    }
+
+    /// Poll ready tasks until none is left pending, parking the thread while the queue is empty
+    fn run(mut self) -> ExecutorStats {
+        let (mut polls, mut parks) = (0, 0);
+        while self.pending > 0 {
+            let task = {
+                let mut ready = self.shared.ready.lock().unwrap();
+                loop {
+                    match ready.pop_front() {
+                        Some(task) => break task,
+                        None => {
+                            parks += 1;
+                            ready = self.shared.wakeup.wait(ready).unwrap();
+                        }
+                    }
+                }
+            };
+
+            // A task woken twice is queued twice; once completed its slot is empty and it is skipped
+            let mut slot = task.future.lock().unwrap();
+            let Some(mut future) = slot.take() else { continue };
+            let waker = waker_for(&task);
+            let mut context = Context::from_waker(&waker);
+            polls += 1;
+            match future.as_mut().poll(&mut context) {
+                Poll::Pending => *slot = Some(future),
+                Poll::Ready(()) => self.pending -= 1,
+            }
+        }
+        ExecutorStats {
+            polls,
+            wakes: self.shared.wakes.load(Ordering::Relaxed),
+            parks,
+        }
+    }
+}
+
+/// Hand-written yield: returns `Pending` `remaining` times, waking its own task each time
+struct YieldNow {
+    remaining: usize,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.remaining == 0 {
+            return Poll::Ready(());
+        }
+        self.remaining -= 1;
+
+        // Without the wake the task would never be polled again
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// State of a hand-written timer, shared with the thread that fires it
+#[derive(Default)]
+struct TimerState {
+    fired: bool,
+    waker: Option<Waker>,
+}
+
+/// Hand-written timer: a helper thread sleeps until the deadline and wakes the task
+struct ThreadTimer {
+    duration: Duration,
+    state: Option<Arc<Mutex<TimerState>>>,
+}
+
+impl ThreadTimer {
+    fn new(duration: Duration) -> Self {
+        ThreadTimer { duration, state: None }
+    }
+}
+
+impl Future for ThreadTimer {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let duration = self.duration;
+        let state = self.state.get_or_insert_with(|| {
+            // Started on the first poll: futures do nothing until polled
+            let state = Arc::new(Mutex::new(TimerState::default()));
+            let shared = Arc::clone(&state);
+            TIMER_THREADS.fetch_add(1, Ordering::Relaxed);
+            thread::spawn(move || {
+                thread::sleep(duration);
+                let mut state = shared.lock().unwrap();
+                state.fired = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+            state
+        });
+
+        let mut state = state.lock().unwrap();
+        if state.fired {
+            return Poll::Ready(());
+        }
+
+        // The task may have moved to another executor since the last poll: keep the latest waker
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Counts the polls of the future it wraps
+pub(crate) struct CountPolls<F> {
+    pub(crate) inner: Pin<Box<F>>,
+    pub(crate) polls: Arc<AtomicUsize>,
+}
+
+impl<F: Future> Future for CountPolls<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+/// One run's results
+struct RunReport {
+    label: &'static str,
+    elapsed: Duration,
+    polls: usize,
+    wakes: Option<usize>,
+    parks: Option<usize>,
+    timer_threads: usize,
+}
+
+/// The task every executor runs: wait out its delay on a hand-written timer, then yield a few times
+async fn hand_written_task(delay: Duration, done: Arc<AtomicUsize>) {
+    ThreadTimer::new(delay).await;
+    YieldNow { remaining: YIELDS_PER_TASK }.await;
+    done.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Run `num_tasks` tasks on the mini executor
+fn run_mini(num_tasks: usize, delay_ms: u64) -> (RunReport, usize) {
+    let done = Arc::new(AtomicUsize::new(0));
+    let threads_before = TIMER_THREADS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    let mut executor = MiniExecutor::new();
+    for id in 0..num_tasks {
+        executor.spawn(hand_written_task(Duration::from_millis(task_delay(id, delay_ms)), Arc::clone(&done)));
+    }
+    let stats = executor.run();
+
+    let report = RunReport {
+        label: "mini executor",
+        elapsed: start.elapsed(),
+        polls: stats.polls,
+        wakes: Some(stats.wakes),
+        parks: Some(stats.parks),
+        timer_threads: TIMER_THREADS.load(Ordering::Relaxed) - threads_before,
+    };
+    (report, done.load(Ordering::Relaxed))
+}
+
+/// Run `num_tasks` tasks on a current-thread Tokio runtime, with the hand-written timer or Tokio's own
+fn run_tokio(num_tasks: usize, delay_ms: u64, tokio_timers: bool) -> (RunReport, usize) {
+    let done = Arc::new(AtomicUsize::new(0));
+    let polls = Arc::new(AtomicUsize::new(0));
+    let threads_before = TIMER_THREADS.load(Ordering::Relaxed);
+
+    // Real threads fire the hand-written timers, so the clock has to be real too
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let start = Instant::now();
+    runtime.block_on(async {
+        let handles: Vec<_> = (0..num_tasks)
+            .map(|id| {
+                let (delay, done) = (Duration::from_millis(task_delay(id, delay_ms)), Arc::clone(&done));
+                let task: BoxFuture = if tokio_timers {
+                    Box::pin(async move {
+                        tokio::time::sleep(delay).await;
+                        YieldNow { remaining: YIELDS_PER_TASK }.await;
+                        done.fetch_add(1, Ordering::Relaxed);
+                    })
+                } else {
+                    Box::pin(hand_written_task(delay, done))
+                };
+                tokio::spawn(CountPolls { inner: Box::pin(task), polls: Arc::clone(&polls) })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
+    let elapsed = start.elapsed();
+    let label = if tokio_timers { "tokio, tokio timers" } else { "tokio, thread timers" };
+    audit::runtime(label, &runtime);
+    runtime_metrics::record(label, &runtime);
+
+    let report = RunReport {
+        label,
+        elapsed,
+        polls: polls.load(Ordering::Relaxed),
+        wakes: None,
+        parks: None,
+        timer_threads: TIMER_THREADS.load(Ordering::Relaxed) - threads_before,
+    };
+    (report, done.load(Ordering::Relaxed))
+}
+
+/// Run the mini executor example: `num_tasks` tasks, each waiting around `delay_ms` then yielding `YIELDS_PER_TASK` times
+pub fn run(num_tasks: usize, delay_ms: u64) {
+    let num_tasks = num_tasks.max(1);
+    if virtual_time::is_enabled() {
+        common::print_warning("The hand-written timers sleep real threads, so the mini executor example ignores --virtual-time");
+    }
+    common::print_info(&format!(
+        "{} tasks each await a timer of {}ms to {}ms, then yield {} times",
+        num_tasks,
+        task_delay(0, delay_ms),
+        (0..num_tasks).map(|id| task_delay(id, delay_ms)).max().unwrap_or(0),
+        YIELDS_PER_TASK
+    ));
+
+    let runs = [
+        run_mini(num_tasks, delay_ms),
+        run_tokio(num_tasks, delay_ms, false),
+        run_tokio(num_tasks, delay_ms, true),
+    ];
+
+    let optional = |value: Option<usize>| value.map_or("-".to_string(), |value| value.to_string());
+    println!();
+    println!(
+        "{:<22} {:>14} {:>8} {:>8} {:>8} {:>14} {:>12}",
+        "executor", "time", "polls", "wakes", "parks", "timer threads", "completed"
+    );
+    for (report, completed) in &runs {
+        println!(
+            "{:<22} {:>14?} {:>8} {:>8} {:>8} {:>14} {:>12}",
+            report.label,
+            report.elapsed,
+            report.polls,
+            optional(report.wakes),
+            optional(report.parks),
+            report.timer_threads,
+            format!("{}/{}", completed, num_tasks)
+        );
+    }
+
+    println!();
+    let expected_polls = num_tasks * (YIELDS_PER_TASK + 2);
+    common::print_info(&format!(
+        "Each task needs {} polls: the first starts its timer, one follows the timer's wake, and one follows each of the {} self-wakes",
+        YIELDS_PER_TASK + 2,
+        YIELDS_PER_TASK
+    ));
+    if runs[0].0.polls == expected_polls && runs.iter().all(|(_, completed)| *completed == num_tasks) {
+        common::print_success("The hand-written executor polled exactly that many times, and the same futures ran unchanged on Tokio");
+    }
+    common::print_info("The futures only know the Waker they are handed, so any executor that honours it can run them");
+    common::print_info("Tokio's timer wheel wakes every task from its own driver: no thread per pending timer, which is most of what a real runtime adds");
+}
@@ -1,6 +1,37 @@
 
 // Re-export the commands from this module
 pub mod code;
+pub mod footprint;
+pub mod spawn_storm;
+pub mod priority_semaphore;
+pub mod backpressure;
+pub mod select;
+pub mod stream;
+pub mod shutdown;
+pub mod blocking;
+pub mod flavor;
+pub mod async_mutex;
+pub mod channels;
+pub mod fan_out;
+pub mod fetch;
+pub mod tcp_echo;
+pub mod chat;
+pub mod structured;
+pub mod mini_executor;
+pub mod recursion;
+pub mod scheduler;
+pub mod file_io;
+pub mod starvation;
+pub mod blocking_detection;
+pub mod join_benchmark;
+pub mod worker_pool;
+pub mod task_local;
+pub mod cancel_safety;
+pub mod merge_streams;
+pub mod pipeline;
+pub mod port_scan;
+pub mod websocket_echo;
+pub mod local_set;
 
 // Re-export the run function for easier access from main.rs
 pub use code::run;
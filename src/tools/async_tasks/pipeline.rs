@@ -0,0 +1,266 @@
+//! An async pipeline of stages connected by bounded channels
+//!
+//! The async mirror of the threaded pipelines of the message passing
+//! examples: raw lines are decoded, processed and written to a sink, and
+//! each stage is a pool of worker tasks sharing the receiving end of the
+//! channel in front of it. The channels are bounded `tokio::sync::mpsc`
+//! channels, so a stage that falls behind fills its input channel and the
+//! stage feeding it waits on `send` until there is room. Every stage runs at
+//! most as fast as the slowest one, whose share of time waiting downstream
+//! is near zero while the others show how long they were held back. A
+//! stage ends when every worker of the stage before it has ended, so closing
+//! the source drains and stops the whole pipeline in order.
+
+// Base dependencies
+use std::future::Future;
+use std::sync::Arc;
+
+// Third-party dependencies
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Items each channel between two stages holds before the stage feeding it has to wait
+const CHANNEL_CAPACITY: usize = 8;
+
+/// Stage names, in pipeline order
+const STAGES: [&str; 3] = ["decode", "process", "sink"];
+
+/// A decoded input line
+struct Record {
+    id: usize,
+    value: u64,
+}
+
+/// A processed record, ready to be written
+struct Processed {
+    id: usize,
+    digest: u64,
+}
+
+/// What one worker of a stage did until its input closed
+#[derive(Default)]
+struct WorkerStats {
+    items: usize,
+    /// Time spent on the items themselves
+    busy: Duration,
+    /// Time spent waiting for room in the next stage's channel
+    blocked: Duration,
+    /// When, since the pipeline started, its last item was handed on
+    last_done: Duration,
+}
+
+/// Spawn `workers` tasks running `work` on each item of `input` and sending the results to `output`
+///
+/// The stage's end of `output` is dropped once all its workers are done, which closes the next stage's input.
+fn spawn_stage<I, O, F, Fut>(
+    name: &str,
+    workers: usize,
+    input: mpsc::Receiver<I>,
+    output: mpsc::Sender<O>,
+    start: Instant,
+    work: F,
+) -> Vec<JoinHandle<WorkerStats>>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: Fn(I) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = O> + Send,
+{
+    // The workers share the receiving end and take the next item whichever is free first
+    let input = Arc::new(Mutex::new(input));
+    audit::track(&format!("{} stage input", name), &input);
+    let work = Arc::new(work);
+
+    (0..workers)
+        .map(|_| {
+            let input = Arc::clone(&input);
+            let output = output.clone();
+            let work = Arc::clone(&work);
+            tokio::spawn(async move {
+                let mut stats = WorkerStats::default();
+                loop {
+                    let item = input.lock().await.recv().await;
+                    let Some(item) = item else { break };
+                    chaos::perturb_async(Point::TaskStart).await;
+                    let started = Instant::now();
+                    let result = work(item).await;
+                    let finished = Instant::now();
+                    stats.busy += finished - started;
+
+                    // The backpressure: a full channel means the next stage is behind
+                    if output.send(result).await.is_err() {
+                        break;
+                    }
+                    stats.blocked += finished.elapsed();
+                    stats.items += 1;
+                    stats.last_done = start.elapsed();
+                }
+                stats
+            })
+        })
+        .collect()
+}
+
+/// Totals of one stage
+struct StageReport {
+    workers: usize,
+    items: usize,
+    busy: Duration,
+    blocked: Duration,
+    finished: Duration,
+}
+
+impl StageReport {
+    fn new(stats: Vec<WorkerStats>) -> StageReport {
+        StageReport {
+            workers: stats.len(),
+            items: stats.iter().map(|worker| worker.items).sum(),
+            busy: stats.iter().map(|worker| worker.busy).sum(),
+            blocked: stats.iter().map(|worker| worker.blocked).sum(),
+            finished: stats.iter().map(|worker| worker.last_done).max().unwrap_or_default(),
+        }
+    }
+
+    /// Items per second the stage handed on over the run
+    fn throughput(&self) -> f64 {
+        self.items as f64 / self.finished.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Items per second the stage could handle with its workers always busy
+    fn capacity(&self) -> f64 {
+        let per_item = self.busy.as_secs_f64() / self.items.max(1) as f64;
+        self.workers as f64 / per_item.max(f64::EPSILON)
+    }
+
+    /// Share of the workers' time over the run spent on items
+    fn utilization(&self) -> f64 {
+        100.0 * self.busy.as_secs_f64() / (self.workers as f64 * self.finished.as_secs_f64()).max(f64::EPSILON)
+    }
+}
+
+async fn await_stage(workers: Vec<JoinHandle<WorkerStats>>) -> StageReport {
+    let mut stats = Vec::with_capacity(workers.len());
+    for worker in workers {
+        stats.push(worker.await.unwrap());
+    }
+    StageReport::new(stats)
+}
+
+/// Run the pipeline example: `num_items` lines through decode, process (about `delay_ms` each) and sink,
+/// with `stage_workers` workers per stage
+pub fn run(num_items: usize, delay_ms: u64, stage_workers: &[usize]) {
+    let [decoders, processors, writers] = match stage_workers {
+        [decoders, processors, writers] if stage_workers.iter().all(|workers| *workers > 0) => [*decoders, *processors, *writers],
+        _ => {
+            common::print_error("--stage-workers takes three worker counts above zero, for decode, process and sink: e.g. 1,4,1");
+            return;
+        }
+    };
+    let decode_time = Duration::from_millis(delay_ms / 5);
+    let sink_time = Duration::from_millis(delay_ms / 10);
+    common::print_info(&format!(
+        "{} lines through decode ({} worker(s), {:?} each), process ({} worker(s), ~{}ms each) and sink ({} worker(s), {:?} each), {}-item channels in between",
+        num_items, decoders, decode_time, processors, delay_ms, writers, sink_time, CHANNEL_CAPACITY
+    ));
+
+    let runtime = virtual_time::runtime();
+    let (reports, written, out_of_order, elapsed) = runtime.block_on(async {
+        let start = Instant::now();
+        let (source, decode_input) = mpsc::channel::<String>(CHANNEL_CAPACITY);
+        let (decoded, process_input) = mpsc::channel::<Record>(CHANNEL_CAPACITY);
+        let (processed, sink_input) = mpsc::channel::<Processed>(CHANNEL_CAPACITY);
+        let (written, mut done) = mpsc::channel::<usize>(CHANNEL_CAPACITY);
+
+        let decode = spawn_stage(STAGES[0], decoders, decode_input, decoded, start, move |line: String| async move {
+            sleep(decode_time).await;
+            let (id, value) = line.split_once(',').unwrap();
+            Record { id: id.parse().unwrap(), value: value.parse().unwrap() }
+        });
+        let process = spawn_stage(STAGES[1], processors, process_input, processed, start, move |record: Record| async move {
+            sleep(Duration::from_millis(task_delay(record.id, delay_ms))).await;
+            let digest = record.value.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(17);
+            Processed { id: record.id, digest }
+        });
+        let sink = spawn_stage(STAGES[2], writers, sink_input, written, start, move |processed: Processed| async move {
+            sleep(sink_time).await;
+            assert_ne!(processed.digest, 0);
+            processed.id
+        });
+
+        // The source waits on the decode stage like every stage waits on the next one; dropping it starts the shutdown
+        let feeder = tokio::spawn(async move {
+            for id in 0..num_items {
+                if source.send(format!("{},{}", id, id * id + 1)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut ids = vec![];
+        while let Some(id) = done.recv().await {
+            ids.push(id);
+        }
+        feeder.await.unwrap();
+        let out_of_order = ids.windows(2).filter(|pair| pair[1] < pair[0]).count();
+        let written = ids.len();
+        let reports = [await_stage(decode).await, await_stage(process).await, await_stage(sink).await];
+        (reports, written, out_of_order, start.elapsed())
+    });
+    audit::runtime("pipeline", &runtime);
+    runtime_metrics::record("pipeline", &runtime);
+
+    println!();
+    println!(
+        "{:<8} {:>8} {:>6} {:>12} {:>12} {:>12} {:>14}",
+        "stage", "workers", "items", "items/s", "capacity/s", "utilization", "waiting next"
+    );
+    for (stage, report) in STAGES.iter().zip(&reports) {
+        println!(
+            "{:<8} {:>8} {:>6} {:>12.1} {:>12.1} {:>11.0}% {:>14?}",
+            stage,
+            report.workers,
+            report.items,
+            report.throughput(),
+            report.capacity(),
+            report.utilization(),
+            report.blocked
+        );
+    }
+
+    println!();
+    common::print_info(&format!(
+        "Total time: {:?}, {:.1} items/s end to end",
+        elapsed,
+        written as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    ));
+    if written == num_items {
+        common::print_success(&format!(
+            "All {} items went through the three stages; {} arrived at the end before an earlier one",
+            written, out_of_order
+        ));
+    } else {
+        common::print_warning(&format!("Only {} of {} items reached the end of the pipeline", written, num_items));
+    }
+    let bottleneck = (0..STAGES.len())
+        .min_by(|a, b| reports[*a].capacity().total_cmp(&reports[*b].capacity()))
+        .unwrap();
+    common::print_info(&format!("The {} stage has the lowest capacity and sets the pace", STAGES[bottleneck]));
+    if bottleneck > 0 {
+        common::print_info("The stages before it spent their waiting time held back by its full input channel");
+    }
+    let mut more_workers = [decoders, processors, writers];
+    more_workers[bottleneck] *= 2;
+    common::print_info(&format!(
+        "Double its workers with --stage-workers {},{},{} and the pace moves to the next slowest stage",
+        more_workers[0], more_workers[1], more_workers[2]
+    ));
+}
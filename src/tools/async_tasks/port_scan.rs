@@ -0,0 +1,271 @@
+//! Concurrent TCP port scanner
+//!
+//! A connect scan tries to open a TCP connection to every port of a range:
+//! a port that accepts is open, one that answers with a reset is closed,
+//! and one that does not answer at all, usually behind a firewall dropping
+//! packets, is only given up on when the connect timeout fires. Closed
+//! ports cost a round trip, silent ones a full timeout, so a scan probing
+//! one port at a time is dominated by the silent ones. The probes run
+//! through `buffer_unordered` with a limit on those in flight, and the same
+//! scan is repeated at rising limits to show the duration falling with it.
+//! Without a port range, a local demo opens a few listening ports and a few
+//! silent ones, whose accept queue is already full, in a range of its own.
+
+// Base dependencies
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+
+// Third-party dependencies
+use futures::stream::{self, StreamExt};
+use tokio::net::{lookup_host, TcpListener, TcpSocket, TcpStream};
+use tokio::time::{timeout, Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Listening ports opened by the local demo
+const DEMO_OPEN: usize = 4;
+
+/// Ports of the local demo that leave connection attempts unanswered
+const DEMO_SILENT: usize = 8;
+
+/// Ports in the range of the local demo
+const DEMO_RANGE: u16 = 512;
+
+/// Open ports listed in the report
+const OPEN_SHOWN: usize = 20;
+
+/// What a connection attempt to one port ran into
+#[derive(Clone, Copy, PartialEq)]
+enum PortState {
+    Open,
+    Closed,
+    TimedOut,
+    Failed,
+}
+
+/// Try to connect to `port`, giving up after `connect_timeout`
+async fn probe(ip: IpAddr, port: u16, connect_timeout: Duration) -> (u16, PortState) {
+    chaos::perturb_async(Point::TaskStart).await;
+    let state = match timeout(connect_timeout, TcpStream::connect((ip, port))).await {
+        // The connection is dropped, and so closed, right away
+        Ok(Ok(_stream)) => PortState::Open,
+        Ok(Err(error)) if error.kind() == io::ErrorKind::ConnectionRefused => PortState::Closed,
+        Ok(Err(_)) => PortState::Failed,
+        Err(_) => PortState::TimedOut,
+    };
+    (port, state)
+}
+
+/// One scan of the whole range at one concurrency limit
+struct Scan {
+    limit: usize,
+    elapsed: Duration,
+    open: Vec<u16>,
+    closed: usize,
+    timed_out: usize,
+    failed: usize,
+}
+
+/// Probe every port of `ports` on `ip`, at most `limit` at a time
+async fn scan(ip: IpAddr, ports: RangeInclusive<u16>, limit: usize, connect_timeout: Duration) -> Scan {
+    let start = Instant::now();
+    let results: Vec<(u16, PortState)> = stream::iter(ports)
+        .map(|port| probe(ip, port, connect_timeout))
+        .buffer_unordered(limit)
+        .collect()
+        .await;
+    let elapsed = start.elapsed();
+
+    let count = |state: PortState| results.iter().filter(|(_, found)| *found == state).count();
+    let mut open: Vec<u16> = results.iter().filter(|(_, state)| *state == PortState::Open).map(|(port, _)| *port).collect();
+    open.sort_unstable();
+    Scan {
+        limit,
+        elapsed,
+        open,
+        closed: count(PortState::Closed),
+        timed_out: count(PortState::TimedOut),
+        failed: count(PortState::Failed),
+    }
+}
+
+/// Listeners of the local demo, kept alive until the scans are done
+struct Demo {
+    ports: RangeInclusive<u16>,
+    open: Vec<u16>,
+    silent: Vec<u16>,
+    _listeners: Vec<TcpListener>,
+    /// Connections filling the accept queue of the silent listeners
+    _fillers: Vec<TcpStream>,
+}
+
+/// Open the demo's listening and silent ports, spread over a range starting at a free port
+async fn demo() -> io::Result<Demo> {
+    let first = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let base = first.local_addr()?.port();
+    let start = base.min(u16::MAX - (DEMO_RANGE - 1));
+    let step = DEMO_RANGE / (DEMO_OPEN + DEMO_SILENT) as u16;
+    let mut demo = Demo {
+        ports: start..=start + (DEMO_RANGE - 1),
+        open: vec![base],
+        silent: vec![],
+        _listeners: vec![first],
+        _fillers: vec![],
+    };
+
+    // Ports already taken are skipped: the scan reports them as found anyway
+    for index in 1..DEMO_OPEN + DEMO_SILENT {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, start + step * index as u16));
+        if index < DEMO_OPEN {
+            if let Ok(listener) = TcpListener::bind(address).await {
+                demo.open.push(address.port());
+                demo._listeners.push(listener);
+            }
+            continue;
+        }
+
+        // With a backlog of 0 the accept queue holds one connection; once it is taken, and never
+        // accepted, Linux drops further connection attempts without an answer, like a firewall
+        let socket = TcpSocket::new_v4()?;
+        if socket.bind(address).is_err() {
+            continue;
+        }
+        let listener = socket.listen(0)?;
+        if let Ok(Ok(filler)) = timeout(Duration::from_millis(100), TcpStream::connect(address)).await {
+            demo._fillers.push(filler);
+        }
+        demo.silent.push(address.port());
+        demo._listeners.push(listener);
+    }
+    demo.open.sort_unstable();
+    Ok(demo)
+}
+
+/// Concurrency limits scanned with: tenfold steps from 1, ending at `largest`
+fn limits(largest: usize) -> Vec<usize> {
+    let mut limits: Vec<usize> = std::iter::successors(Some(1), |limit: &usize| limit.checked_mul(10))
+        .take_while(|limit| *limit < largest)
+        .collect();
+    limits.push(largest);
+    limits
+}
+
+/// Run the scanner: `ports` of `host`, or the local demo range, at limits up to `largest_limit`,
+/// with a `timeout_ms` connect timeout
+pub fn run(largest_limit: usize, timeout_ms: u64, host: &str, ports: Option<RangeInclusive<u16>>) {
+    let connect_timeout = Duration::from_millis(timeout_ms.max(1));
+    if virtual_time::is_enabled() {
+        common::print_warning("Real sockets need real time, so the port scanner ignores --virtual-time");
+    }
+
+    let runtime = cpus::multi_thread_runtime();
+    let outcome = runtime.block_on(async {
+        let (ip, ports, demo) = match ports {
+            Some(ports) => match lookup_host((host, 0)).await.map(|mut addresses| addresses.next()) {
+                Ok(Some(address)) => (address.ip(), ports, None),
+                Ok(None) => {
+                    common::print_error(&format!("{} has no address", host));
+                    return None;
+                }
+                Err(error) => {
+                    common::print_error(&format!("Could not resolve {}: {}", host, error));
+                    return None;
+                }
+            },
+            None => match demo().await {
+                Ok(demo) => {
+                    common::print_info(&format!(
+                        "No --ports given: scanning a local demo range with {} listening and {} silent ports",
+                        demo.open.len(),
+                        demo.silent.len()
+                    ));
+                    (IpAddr::V4(Ipv4Addr::LOCALHOST), demo.ports.clone(), Some(demo))
+                }
+                Err(error) => {
+                    common::print_error(&format!("Could not open the demo ports: {}", error));
+                    return None;
+                }
+            },
+        };
+
+        let port_count = ports.len();
+        let limits = limits(largest_limit.clamp(1, port_count));
+        common::print_info(&format!(
+            "Probing {} ports {}-{} of {} with a {:?} connect timeout, at most {:?} at a time",
+            port_count,
+            ports.start(),
+            ports.end(),
+            ip,
+            connect_timeout,
+            limits
+        ));
+
+        let mut scans = vec![];
+        for limit in limits {
+            scans.push(scan(ip, ports.clone(), limit, connect_timeout).await);
+        }
+        Some((scans, port_count, demo))
+    });
+    audit::runtime("port-scan", &runtime);
+    runtime_metrics::record("port-scan", &runtime);
+    let Some((scans, port_count, demo)) = outcome else { return };
+
+    println!();
+    println!(
+        "{:>7} {:>12} {:>10} {:>6} {:>8} {:>10} {:>8}",
+        "limit", "time", "ports/s", "open", "closed", "timed out", "failed"
+    );
+    for scan in &scans {
+        println!(
+            "{:>7} {:>12?} {:>10.0} {:>6} {:>8} {:>10} {:>8}",
+            scan.limit,
+            scan.elapsed,
+            port_count as f64 / scan.elapsed.as_secs_f64().max(f64::EPSILON),
+            scan.open.len(),
+            scan.closed,
+            scan.timed_out,
+            scan.failed
+        );
+    }
+
+    println!();
+    let last = scans.last().unwrap();
+    if last.open.is_empty() {
+        common::print_info("No open port found");
+    } else {
+        common::print_info(&format!(
+            "Open ports: {:?}{}",
+            &last.open[..last.open.len().min(OPEN_SHOWN)],
+            if last.open.len() > OPEN_SHOWN { ", ..." } else { "" }
+        ));
+    }
+    if scans.iter().any(|scan| scan.open != last.open) {
+        common::print_warning("The scans disagree on the open ports: some services opened or closed during the run");
+    }
+    if let Some(demo) = &demo {
+        if demo.open.iter().all(|port| last.open.contains(port)) && last.timed_out == demo.silent.len() {
+            common::print_success(&format!(
+                "Found every demo listener, and each of the {} silent ports ran into the timeout",
+                demo.silent.len()
+            ));
+        } else {
+            common::print_warning("The silent demo ports answered: the accept-queue trick they rely on works on Linux only");
+        }
+    }
+    if scans.len() > 1 {
+        let first = &scans[0];
+        common::print_info(&format!(
+            "{} probes at a time scanned {:.1}x faster than one at a time",
+            last.limit,
+            first.elapsed.as_secs_f64() / last.elapsed.as_secs_f64().max(f64::EPSILON)
+        ));
+    }
+    common::print_info("Each unanswered port holds a slot for the whole timeout: with k slots they cost about timeouts / k of the scan");
+}
@@ -0,0 +1,391 @@
+//! Async semaphore with weighted permits and priorities
+//!
+//! A plain semaphore hands out one permit per task. Here a request asks for
+//! as many permits as the resources it needs, and waits in a queue ordered
+//! by priority, then by arrival. Permits are only granted from the head of
+//! the queue: small requests never slip past a large one of the same
+//! priority, so large jobs cannot starve. Giving small, interactive jobs a
+//! higher priority keeps large batch jobs from starving them in turn.
+
+// Base dependencies
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Third-party dependencies
+use tokio::sync::oneshot;
+use tokio::time::{sleep, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Position of a waiter in the queue: highest priority first, then first come first served
+type QueueKey = (Reverse<u8>, u64);
+
+/// A request waiting for its permits
+struct Waiter {
+    permits: usize,
+    granted: oneshot::Sender<()>,
+}
+
+/// Free permits and the queue of waiting requests
+struct State {
+    available: usize,
+    next_seq: u64,
+    waiters: BTreeMap<QueueKey, Waiter>,
+}
+
+/// Shared by the semaphore handles and every outstanding permit
+struct Inner {
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+impl Inner {
+    /// Grant permits to the head of the queue for as long as it fits
+    fn dispatch(&self, state: &mut State) {
+        while let Some(entry) = state.waiters.first_entry() {
+            if entry.get().permits > state.available {
+                break;
+            }
+            let waiter = entry.remove();
+            state.available -= waiter.permits;
+            // The receiver is alive while the request is queued (see `Pending`)
+            let _ = waiter.granted.send(());
+        }
+    }
+
+    /// Give permits back and wake whoever now fits
+    fn release(&self, permits: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.available += permits;
+        self.dispatch(&mut state);
+    }
+}
+
+/// A queued request, withdrawn if the `acquire` future is dropped before completing
+struct Pending<'a> {
+    inner: &'a Inner,
+    key: QueueKey,
+    permits: usize,
+    receiver: oneshot::Receiver<()>,
+    claimed: bool,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if self.claimed {
+            return;
+        }
+        let mut state = self.inner.state.lock().unwrap();
+        if state.waiters.remove(&self.key).is_some() {
+            // Still queued: leaving may let the requests behind us through
+            self.inner.dispatch(&mut state);
+        } else if self.receiver.try_recv().is_ok() {
+            // Granted but never claimed: hand the permits back
+            state.available += self.permits;
+            self.inner.dispatch(&mut state);
+        }
+    }
+}
+
+/// Semaphore where each request takes several permits and higher priority requests queue ahead
+#[derive(Clone)]
+pub struct PrioritySemaphore {
+    inner: Arc<Inner>,
+}
+
+/// Permits held by a request, given back when dropped
+pub struct Permit {
+    inner: Arc<Inner>,
+    permits: usize,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.inner.release(self.permits);
+    }
+}
+
+impl PrioritySemaphore {
+    /// Create a semaphore with `capacity` permits
+    pub fn new(capacity: usize) -> Self {
+        PrioritySemaphore {
+            inner: Arc::new(Inner {
+                capacity,
+                state: Mutex::new(State {
+                    available: capacity,
+                    next_seq: 0,
+                    waiters: BTreeMap::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Number of permits not held by anyone
+    pub fn available_permits(&self) -> usize {
+        self.inner.state.lock().unwrap().available
+    }
+
+    /// Wait for `permits` permits; higher `priority` values are served first
+    ///
+    /// Dropping the returned future before it completes withdraws the request
+    /// without losing any permit.
+    pub async fn acquire(&self, permits: usize, priority: u8) -> Permit {
+        assert!(
+            permits <= self.inner.capacity,
+            "requested {} permits from a semaphore of {}",
+            permits,
+            self.inner.capacity
+        );
+
+        let (granted, receiver) = oneshot::channel();
+        let mut pending = {
+            let mut state = self.inner.state.lock().unwrap();
+            let key = (Reverse(priority), state.next_seq);
+            state.next_seq += 1;
+            state.waiters.insert(key, Waiter { permits, granted });
+            self.inner.dispatch(&mut state);
+            Pending {
+                inner: &self.inner,
+                key,
+                permits,
+                receiver,
+                claimed: false,
+            }
+        };
+
+        // The sender is only dropped after sending, so this cannot fail
+        (&mut pending.receiver).await.unwrap();
+        pending.claimed = true;
+        Permit {
+            inner: Arc::clone(&self.inner),
+            permits,
+        }
+    }
+}
+
+/// A kind of job competing for the shared resource
+#[derive(Clone, Copy)]
+struct JobClass {
+    label: &'static str,
+    permits: usize,
+    priority: u8,
+    duration: Duration,
+}
+
+/// Waiting times observed by one job class
+#[derive(Default)]
+struct ClassReport {
+    jobs: usize,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+/// Launch every job at once and record how long each class waited for its permits
+async fn simulate(semaphore: &PrioritySemaphore, classes: &[JobClass], jobs_per_class: usize) -> Vec<ClassReport> {
+    let mut handles = vec![];
+
+    // Interleave the classes so every one of them is queued early
+    for _ in 0..jobs_per_class {
+        for (index, class) in classes.iter().enumerate() {
+            let semaphore = semaphore.clone();
+            let class = *class;
+            handles.push(tokio::spawn(async move {
+                chaos::perturb_async(Point::TaskStart).await;
+                let requested = Instant::now();
+                let permit = semaphore.acquire(class.permits, class.priority).await;
+                let waited = requested.elapsed();
+                sleep(class.duration).await;
+                drop(permit);
+                (index, waited)
+            }));
+        }
+    }
+
+    let mut reports: Vec<ClassReport> = classes.iter().map(|_| ClassReport::default()).collect();
+    for handle in handles {
+        let (index, waited) = handle.await.unwrap();
+        let report = &mut reports[index];
+        report.jobs += 1;
+        report.total_wait += waited;
+        report.max_wait = report.max_wait.max(waited);
+    }
+    reports
+}
+
+/// Run the priority semaphore example
+pub fn run(jobs_per_class: usize, delay_ms: u64) {
+    let capacity = 8;
+    let large = JobClass {
+        label: "large batch",
+        permits: 6,
+        priority: 0,
+        duration: Duration::from_millis(delay_ms),
+    };
+    let small = JobClass {
+        label: "small query",
+        permits: 1,
+        priority: 1,
+        duration: Duration::from_millis((delay_ms / 10).max(1)),
+    };
+    common::print_info(&format!(
+        "{} permits shared by {} large jobs ({} permits, {:?}) and {} small jobs ({} permit, {:?})",
+        capacity, jobs_per_class, large.permits, large.duration, jobs_per_class, small.permits, small.duration
+    ));
+
+    let runtime = virtual_time::runtime();
+    runtime.block_on(async {
+        println!();
+        println!("{:<14} {:<14} {:>6} {:>14} {:>14}", "queueing", "class", "jobs", "mean wait", "max wait");
+
+        let runs = [
+            ("same priority", [JobClass { priority: 0, ..small }, large]),
+            ("small first", [small, large]),
+        ];
+        for (mode, classes) in runs {
+            let semaphore = PrioritySemaphore::new(capacity);
+            let reports = simulate(&semaphore, &classes, jobs_per_class).await;
+            for (class, report) in classes.iter().zip(reports.iter()) {
+                println!(
+                    "{:<14} {:<14} {:>6} {:>14?} {:>14?}",
+                    mode,
+                    class.label,
+                    report.jobs,
+                    report.total_wait / report.jobs.max(1) as u32,
+                    report.max_wait
+                );
+            }
+            assert_eq!(semaphore.available_permits(), capacity);
+        }
+    });
+    audit::runtime("priority semaphore", &runtime);
+    runtime_metrics::record("priority semaphore", &runtime);
+
+    println!();
+    common::print_success("Every job got its permits and every permit was returned");
+    common::print_info("With one queue, small queries wait behind each large batch; with a higher priority they are served as soon as a permit is free");
+    common::print_info("Large batches still run: permits are only granted from the head of the queue, so small jobs never keep them from accumulating");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn permits_are_returned_on_drop() {
+        let semaphore = PrioritySemaphore::new(4);
+        let first = semaphore.acquire(3, 0).await;
+        assert_eq!(semaphore.available_permits(), 1);
+        let second = semaphore.acquire(1, 0).await;
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(first);
+        drop(second);
+        assert_eq!(semaphore.available_permits(), 4);
+    }
+
+    #[tokio::test]
+    async fn higher_priority_is_served_first() {
+        let semaphore = PrioritySemaphore::new(1);
+        let held = semaphore.acquire(1, 0).await;
+        let order = Arc::new(Mutex::new(vec![]));
+
+        let mut handles = vec![];
+        for (name, priority) in [("low", 0), ("high", 5), ("medium", 2)] {
+            let semaphore = semaphore.clone();
+            let order = Arc::clone(&order);
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire(1, priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            // Let the task queue its request before the next one
+            tokio::task::yield_now().await;
+        }
+
+        drop(held);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["high", "medium", "low"]);
+    }
+
+    #[tokio::test]
+    async fn small_requests_do_not_overtake_a_large_one() {
+        let semaphore = PrioritySemaphore::new(4);
+        let held = semaphore.acquire(2, 0).await;
+
+        // The large request cannot fit yet, and the small one queued behind it must wait too
+        let large = tokio::spawn({
+            let semaphore = semaphore.clone();
+            async move { semaphore.acquire(4, 0).await }
+        });
+        tokio::task::yield_now().await;
+        let small = tokio::spawn({
+            let semaphore = semaphore.clone();
+            async move { semaphore.acquire(1, 0).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(semaphore.available_permits(), 2);
+        assert!(!small.is_finished());
+
+        drop(held);
+        let large = large.await.unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(large);
+        let small = small.await.unwrap();
+        assert_eq!(semaphore.available_permits(), 3);
+        drop(small);
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_does_not_leak_permits() {
+        let semaphore = PrioritySemaphore::new(2);
+        let held = semaphore.acquire(2, 0).await;
+
+        let waiting = tokio::spawn({
+            let semaphore = semaphore.clone();
+            async move { semaphore.acquire(2, 0).await }
+        });
+        tokio::task::yield_now().await;
+        waiting.abort();
+        assert!(matches!(waiting.await, Err(error) if error.is_cancelled()));
+
+        drop(held);
+        assert_eq!(semaphore.available_permits(), 2);
+        let _again = semaphore.acquire(2, 0).await;
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+
+    #[tokio::test]
+    async fn withdrawn_request_unblocks_the_queue() {
+        let semaphore = PrioritySemaphore::new(2);
+        let held = semaphore.acquire(1, 0).await;
+
+        // A large request blocks the head of the queue, with a small one behind it
+        let large = tokio::spawn({
+            let semaphore = semaphore.clone();
+            async move { semaphore.acquire(2, 0).await }
+        });
+        tokio::task::yield_now().await;
+        let small = tokio::spawn({
+            let semaphore = semaphore.clone();
+            async move { semaphore.acquire(1, 0).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!small.is_finished());
+
+        // Giving up on the large request lets the small one take the free permit
+        large.abort();
+        let _ = large.await;
+        let small = small.await.unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(small);
+        drop(held);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+}
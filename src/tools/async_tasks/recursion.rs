@@ -0,0 +1,275 @@
+//! Async recursion and why it needs `Box::pin`
+//!
+//! An `async fn` compiles to a state machine that stores the futures it
+//! awaits inline. A function awaiting itself would have to contain itself,
+//! a type of infinite size, so the compiler rejects it (E0733). Boxing the
+//! recursive call puts each level's state machine on the heap and leaves a
+//! pointer in its parent: either return `Pin<Box<dyn Future>>` from a plain
+//! function, or await `Box::pin(recurse(..))` inside the async fn. Either way
+//! every level is still polled from inside its parent's poll, so a deep tree
+//! gets a deep call stack even though its state lives on the heap. Spawning
+//! each level as a task instead resets the stack at every node.
+
+// Base dependencies
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use futures::future::{join_all, BoxFuture, FutureExt};
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Children of every inner node of the tree
+const BRANCHING: usize = 2;
+
+/// Deepest tree the example builds
+const MAX_DEPTH: usize = 12;
+
+/// A node of the tree being traversed
+struct Node {
+    value: u64,
+    children: Vec<Arc<Node>>,
+}
+
+/// A tree of `depth` levels below the root, with nodes numbered in depth-first order
+fn build(depth: usize, next: &mut u64) -> Arc<Node> {
+    *next += 1;
+    let value = *next;
+    let children = if depth == 0 { vec![] } else { (0..BRANCHING).map(|_| build(depth - 1, next)).collect() };
+    Arc::new(Node { value, children })
+}
+
+/// What the traversal saw, shared by every level
+#[derive(Default)]
+struct Probe {
+    /// Deepest level of nested polls inside a single task
+    max_nesting: AtomicUsize,
+    /// Lowest stack address reached, on the thread that polls the root
+    lowest_address: AtomicUsize,
+    spawned: AtomicUsize,
+    alive: AtomicUsize,
+    peak_alive: AtomicUsize,
+}
+
+impl Probe {
+    /// Note a node being polled `nesting` polls below its task's own
+    fn visit(&self, nesting: usize) {
+        self.max_nesting.fetch_max(nesting, Ordering::Relaxed);
+
+        // A local's address tells how deep the poll stack currently is
+        let marker = 0u8;
+        let address = black_box(&marker) as *const u8 as usize;
+        self.lowest_address.fetch_min(address, Ordering::Relaxed);
+    }
+}
+
+/// Leaves wait `leaf` like an I/O call would; inner nodes add up their children
+async fn own_value(node: &Node, leaf: Duration) -> u64 {
+    if node.children.is_empty() {
+        chaos::perturb_async(Point::TaskStart).await;
+        sleep(leaf).await;
+    }
+    node.value
+}
+
+/// Form 1: a plain function returning the boxed future, visiting the children one after the other
+///
+/// Written as `async fn visit_sequential(..) -> u64` with a bare
+/// `visit_sequential(child, ..).await` inside, this fails with E0733:
+/// "recursion in an async fn requires boxing".
+fn visit_sequential(node: Arc<Node>, leaf: Duration, nesting: usize, probe: Arc<Probe>) -> BoxFuture<'static, u64> {
+    async move {
+        probe.visit(nesting);
+        let mut sum = own_value(&node, leaf).await;
+        for child in &node.children {
+            sum += visit_sequential(Arc::clone(child), leaf, nesting + 1, Arc::clone(&probe)).await;
+        }
+        sum
+    }
+    .boxed()
+}
+
+/// Form 2: an `async fn` boxing its recursive calls, visiting the children concurrently with `join_all`
+async fn visit_concurrent(node: Arc<Node>, leaf: Duration, nesting: usize, probe: Arc<Probe>) -> u64 {
+    probe.visit(nesting);
+    let children = node
+        .children
+        .iter()
+        .map(|child| Box::pin(visit_concurrent(Arc::clone(child), leaf, nesting + 1, Arc::clone(&probe))));
+    let (own, sums) = tokio::join!(own_value(&node, leaf), join_all(children));
+    own + sums.iter().sum::<u64>()
+}
+
+/// Form 3: every child spawned as a task of its own, polled from the runtime instead of from its parent
+fn visit_spawned(node: Arc<Node>, leaf: Duration, probe: Arc<Probe>) -> BoxFuture<'static, u64> {
+    async move {
+        probe.visit(0);
+        let handles: Vec<_> = node
+            .children
+            .iter()
+            .map(|child| {
+                let (child, probe) = (Arc::clone(child), Arc::clone(&probe));
+                probe.spawned.fetch_add(1, Ordering::Relaxed);
+                let alive = probe.alive.fetch_add(1, Ordering::Relaxed) + 1;
+                probe.peak_alive.fetch_max(alive, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let sum = visit_spawned(child, leaf, Arc::clone(&probe)).await;
+                    probe.alive.fetch_sub(1, Ordering::Relaxed);
+                    sum
+                })
+            })
+            .collect();
+        let mut sum = own_value(&node, leaf).await;
+        for handle in handles {
+            sum += handle.await.unwrap();
+        }
+        sum
+    }
+    // As an async fn, the compiler cannot prove a future that spawns itself is Send; the boxed type states it
+    .boxed()
+}
+
+/// The three ways of walking the tree
+#[derive(Clone, Copy)]
+enum Form {
+    Sequential,
+    Concurrent,
+    Spawned,
+}
+
+impl Form {
+    fn label(&self) -> &'static str {
+        match self {
+            Form::Sequential => "BoxFuture, sequential",
+            Form::Concurrent => "Box::pin, join_all",
+            Form::Spawned => "tokio::spawn per node",
+        }
+    }
+}
+
+/// What one traversal reported
+struct Report {
+    sum: u64,
+    elapsed: Duration,
+    future_size: usize,
+    max_nesting: usize,
+    /// Bytes of stack below the root's poll at the deepest point, when all polls share one thread
+    stack: Option<usize>,
+    spawned: usize,
+    peak_alive: usize,
+}
+
+/// Walk `tree` in `form`, with leaves waiting `leaf`
+async fn traverse(form: Form, tree: Arc<Node>, leaf: Duration) -> Report {
+    let probe = Arc::new(Probe {
+        lowest_address: AtomicUsize::new(usize::MAX),
+        ..Probe::default()
+    });
+    let marker = 0u8;
+    let base = black_box(&marker) as *const u8 as usize;
+
+    let start = Instant::now();
+    let (sum, future_size) = match form {
+        Form::Sequential => {
+            let future = visit_sequential(tree, leaf, 0, Arc::clone(&probe));
+            // The size of one level's state machine, behind the Box the parent keeps
+            let size = std::mem::size_of_val(&*future);
+            (future.await, size)
+        }
+        Form::Concurrent => {
+            let future = visit_concurrent(tree, leaf, 0, Arc::clone(&probe));
+            let size = std::mem::size_of_val(&future);
+            (future.await, size)
+        }
+        Form::Spawned => {
+            let future = visit_spawned(tree, leaf, Arc::clone(&probe));
+            let size = std::mem::size_of_val(&*future);
+            (future.await, size)
+        }
+    };
+
+    // Spawned nodes are polled by whichever worker runs them, on that worker's stack
+    let stack = match form {
+        Form::Spawned => None,
+        _ => Some(base.saturating_sub(probe.lowest_address.load(Ordering::Relaxed))),
+    };
+    Report {
+        sum,
+        elapsed: start.elapsed(),
+        future_size,
+        max_nesting: probe.max_nesting.load(Ordering::Relaxed),
+        stack,
+        spawned: probe.spawned.load(Ordering::Relaxed),
+        peak_alive: probe.peak_alive.load(Ordering::Relaxed),
+    }
+}
+
+/// Run the async recursion example over a binary tree `depth` levels deep, its leaves waiting `leaf_ms` each
+pub fn run(depth: usize, leaf_ms: u64) {
+    let depth = depth.clamp(1, MAX_DEPTH);
+    let leaf = Duration::from_millis(leaf_ms);
+    let tree = build(depth, &mut 0);
+    let nodes = (0..=depth as u32).map(|level| BRANCHING.pow(level)).sum::<usize>();
+    let leaves = BRANCHING.pow(depth as u32);
+    let expected = (nodes * (nodes + 1) / 2) as u64;
+    common::print_info(&format!(
+        "Summing a tree of {} nodes, {} levels below the root, whose {} leaves each wait {:?}",
+        nodes, depth, leaves, leaf
+    ));
+
+    let runtime = virtual_time::runtime();
+    let forms = [Form::Sequential, Form::Concurrent, Form::Spawned];
+    let reports: Vec<Report> = forms
+        .iter()
+        .map(|form| runtime.block_on(traverse(*form, Arc::clone(&tree), leaf)))
+        .collect();
+    audit::runtime("recursion", &runtime);
+    runtime_metrics::record("recursion", &runtime);
+
+    println!();
+    println!(
+        "{:<24} {:>8} {:>12} {:>12} {:>9} {:>12} {:>9} {:>11}",
+        "form", "sum", "time", "future size", "nesting", "poll stack", "spawned", "peak tasks"
+    );
+    for (form, report) in forms.iter().zip(&reports) {
+        println!(
+            "{:<24} {:>8} {:>12?} {:>12} {:>9} {:>12} {:>9} {:>11}",
+            form.label(),
+            report.sum,
+            report.elapsed,
+            format!("{} B", report.future_size),
+            report.max_nesting,
+            report.stack.map_or("-".to_string(), |bytes| format!("{} B", bytes)),
+            report.spawned,
+            report.peak_alive + 1
+        );
+    }
+
+    println!();
+    if reports.iter().all(|report| report.sum == expected) {
+        common::print_success(&format!("Every form summed the {} nodes to {}", nodes, expected));
+    } else {
+        common::print_warning(&format!("A form did not sum the nodes to {}", expected));
+    }
+    common::print_info(&format!(
+        "Each level is a heap allocation of the size shown: {} boxes of up to {} B for the whole tree",
+        nodes,
+        reports.iter().map(|report| report.future_size).max().unwrap_or(0)
+    ));
+    common::print_info(&format!(
+        "Boxed levels are still polled inside their parent's poll: {} nested polls and the stack grows with the depth",
+        reports[0].max_nesting
+    ));
+    common::print_info("Spawning every node resets the nesting to 0, at the price of a task per node and results passed through JoinHandles");
+    common::print_info(&format!(
+        "Sequential visits wait for the {} leaves one at a time; the concurrent and spawned forms wait about one leaf",
+        leaves
+    ));
+}
@@ -0,0 +1,168 @@
+//! Periodic jobs with `tokio::time::interval` and `MissedTickBehavior`
+//!
+//! An interval hands out ticks at a fixed period, and `tick()` returns the
+//! instant each one was scheduled for. A job that runs past its period misses
+//! ticks, and `MissedTickBehavior` decides what happens next. `Burst`, the
+//! default, fires every missed tick at once to catch up with the original
+//! schedule. `Delay` fires one tick now and shifts the schedule to start from
+//! it. `Skip` drops the missed ticks and waits for the next multiple of the
+//! period. Steady jobs never notice; an overrunning job shows the difference.
+
+// Third-party dependencies
+use tokio::task::JoinSet;
+use tokio::time::{interval, sleep, sleep_until, Duration, Instant, MissedTickBehavior};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Every how many runs the overrunning jobs stall
+const STALL_EVERY: usize = 4;
+
+/// How many periods a stall lasts
+const STALL_PERIODS: f64 = 2.5;
+
+/// A periodic job and how it is scheduled
+struct Job {
+    name: String,
+    period: Duration,
+    work: Duration,
+    behavior: MissedTickBehavior,
+    /// Runs that take `STALL_PERIODS` periods instead of `work`
+    stalls: bool,
+}
+
+/// What a job saw over the run
+struct JobReport {
+    ticks: usize,
+    expected: usize,
+    /// How late each tick fired after the instant it was scheduled for
+    lateness: Vec<Duration>,
+    /// Shortest time between two consecutive ticks
+    min_gap: Duration,
+}
+
+fn behavior_label(behavior: MissedTickBehavior) -> &'static str {
+    match behavior {
+        MissedTickBehavior::Burst => "Burst",
+        MissedTickBehavior::Delay => "Delay",
+        MissedTickBehavior::Skip => "Skip",
+    }
+}
+
+/// Run `job` on its interval until `end`
+async fn run_job(job: Job, start: Instant, end: Instant) -> (Job, JobReport) {
+    let mut ticks = interval(job.period);
+    ticks.set_missed_tick_behavior(job.behavior);
+
+    let mut lateness = vec![];
+    let (mut last_tick, mut min_gap) = (None::<Instant>, Duration::MAX);
+    loop {
+        // A tick due right at the end belongs to the next run, so the end wins a tie
+        let scheduled = tokio::select! {
+            biased;
+            _ = sleep_until(end) => break,
+            scheduled = ticks.tick() => scheduled,
+        };
+        let now = Instant::now();
+        lateness.push(now - scheduled);
+        if let Some(last) = last_tick {
+            min_gap = min_gap.min(now - last);
+        }
+        last_tick = Some(now);
+
+        // The job's own work: while it runs, nobody calls tick() and ticks go missing
+        chaos::perturb_async(Point::TaskStart).await;
+        let stalled = job.stalls && lateness.len() % STALL_EVERY == 0;
+        sleep(if stalled { job.period.mul_f64(STALL_PERIODS) } else { job.work }).await;
+    }
+
+    // The first tick fires at once, then one per period until just before the end
+    let expected = ((end - start).as_nanos().saturating_sub(1) / job.period.as_nanos().max(1)) as usize + 1;
+    lateness.sort_unstable();
+    let report = JobReport {
+        ticks: lateness.len(),
+        expected,
+        lateness,
+        min_gap: if min_gap == Duration::MAX { Duration::ZERO } else { min_gap },
+    };
+    (job, report)
+}
+
+/// Run the scheduler example for `run_for`: `steady_jobs` jobs at multiples of `period_ms`, and one overrunning job per behavior
+pub fn run(steady_jobs: usize, period_ms: u64, run_for: Duration) {
+    let period = Duration::from_millis(period_ms.max(1));
+    let mut jobs: Vec<Job> = (0..steady_jobs)
+        .map(|index| Job {
+            name: format!("steady #{}", index),
+            period: period * (index as u32 + 1),
+            work: period / 10,
+            behavior: MissedTickBehavior::Burst,
+            stalls: false,
+        })
+        .collect();
+    for behavior in [MissedTickBehavior::Burst, MissedTickBehavior::Delay, MissedTickBehavior::Skip] {
+        jobs.push(Job {
+            name: format!("overrun, {}", behavior_label(behavior)),
+            period,
+            work: period / 10,
+            behavior,
+            stalls: true,
+        });
+    }
+    common::print_info(&format!(
+        "Running {} periodic jobs for {:?}; the overrunning ones take {} periods on every {}th run",
+        jobs.len(),
+        run_for,
+        STALL_PERIODS,
+        STALL_EVERY
+    ));
+
+    let runtime = virtual_time::runtime();
+    let mut reports = runtime.block_on(async {
+        let start = Instant::now();
+        let end = start + run_for;
+        let mut set = JoinSet::new();
+        for (index, job) in jobs.into_iter().enumerate() {
+            set.spawn(async move { (index, run_job(job, start, end).await) });
+        }
+        let mut reports = vec![];
+        while let Some(result) = set.join_next().await {
+            reports.push(result.unwrap());
+        }
+        reports
+    });
+    audit::runtime("scheduler", &runtime);
+    runtime_metrics::record("scheduler", &runtime);
+    reports.sort_by_key(|(index, _)| *index);
+
+    println!();
+    println!(
+        "{:<18} {:>10} {:>8} {:>7} {:>9} {:>8} {:>12} {:>12} {:>12} {:>10}",
+        "job", "period", "behavior", "ticks", "expected", "skipped", "p50 late", "p99 late", "max late", "min gap"
+    );
+    for (_, (job, report)) in &reports {
+        println!(
+            "{:<18} {:>10?} {:>8} {:>7} {:>9} {:>8} {:>12?} {:>12?} {:>12?} {:>10?}",
+            job.name,
+            job.period,
+            behavior_label(job.behavior),
+            report.ticks,
+            report.expected,
+            report.expected.saturating_sub(report.ticks),
+            common::percentile(&report.lateness, 50.0),
+            common::percentile(&report.lateness, 99.0),
+            report.lateness.last().copied().unwrap_or_default(),
+            report.min_gap
+        );
+    }
+
+    println!();
+    common::print_info("Lateness is measured against the instant tick() says the tick was scheduled for");
+    common::print_info("Burst fires the missed ticks back to back to keep the count: see its min gap near zero and its late ticks");
+    common::print_info("Delay restarts the schedule from the late tick: no bursts, but the ticks drift and the count falls behind");
+    common::print_info("Skip drops the missed ticks and stays on the original grid: the count falls behind by the stalls only");
+}
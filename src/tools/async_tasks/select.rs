@@ -0,0 +1,236 @@
+//! Racing futures with `tokio::select!` and cancelling the losers
+//!
+//! `select!` polls several futures in the same task and runs the arm of the
+//! first one to complete. The other futures are then dropped, and dropping
+//! a future is how Rust cancels it: it stops at the `.await` it was parked
+//! on, runs the destructors of its locals, and never resumes. Each branch
+//! here owns a guard that reports when it is dropped unfinished, so every
+//! round shows the winner and the cancelled losers. A spawned task is the
+//! exception: dropping its `JoinHandle` only detaches it, and it keeps
+//! running unless it is aborted.
+
+// Base dependencies
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use tokio::sync::oneshot;
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Steps the computation is split into; cancellation can only happen between them
+const STEPS: u32 = 10;
+
+/// Timeout and shutdown signal of one round, as multiples of the computation time
+#[derive(Clone, Copy)]
+struct Race {
+    timeout: f64,
+    shutdown: Option<f64>,
+}
+
+/// Rounds cycle through these, so each branch wins in turn
+const RACES: [Race; 3] = [
+    Race { timeout: 2.0, shutdown: None },
+    Race { timeout: 0.5, shutdown: None },
+    Race { timeout: 2.0, shutdown: Some(0.3) },
+];
+
+/// Completions and cancellations across every round
+#[derive(Default)]
+struct Tally {
+    completed: AtomicUsize,
+    cancelled: AtomicUsize,
+}
+
+/// Reports, when dropped before `finish`, that the future owning it was cancelled
+struct CancelGuard {
+    name: &'static str,
+    /// Steps done and total, for futures that make progress in steps
+    progress: Option<(u32, u32)>,
+    finished: bool,
+    tally: Arc<Tally>,
+}
+
+impl CancelGuard {
+    fn new(name: &'static str, progress: Option<(u32, u32)>, tally: &Arc<Tally>) -> Self {
+        CancelGuard {
+            name,
+            progress,
+            finished: false,
+            tally: Arc::clone(tally),
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.tally.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.tally.cancelled.fetch_add(1, Ordering::Relaxed);
+        match self.progress {
+            Some((done, total)) => common::print_warning(&format!("  {} dropped after {}/{} steps: cancelled", self.name, done, total)),
+            None => common::print_warning(&format!("  {} dropped before completing: cancelled", self.name)),
+        }
+    }
+}
+
+/// A computation made of `STEPS` awaited steps, cancellable between any two of them
+async fn computation(name: &'static str, duration: Duration, tally: Arc<Tally>) -> u64 {
+    let mut guard = CancelGuard::new(name, Some((0, STEPS)), &tally);
+    let mut result = 0;
+    for step in 1..=STEPS {
+        chaos::perturb_async(Point::TaskStart).await;
+        sleep(duration / STEPS).await;
+        result += step as u64;
+        guard.progress = Some((step, STEPS));
+    }
+    guard.finish();
+    result
+}
+
+/// Run `future`, reporting its cancellation if it is dropped first
+async fn tracked<F: Future>(name: &'static str, future: F, tally: Arc<Tally>) -> F::Output {
+    let mut guard = CancelGuard::new(name, None, &tally);
+    let output = future.await;
+    guard.finish();
+    output
+}
+
+/// Which branch of the select won a round
+#[derive(Clone, Copy, PartialEq)]
+enum Winner {
+    Computation,
+    Timeout,
+    Shutdown,
+}
+
+/// Race the computation against a timeout and, in some rounds, a shutdown signal
+async fn race(round: usize, race: Race, work: Duration, tally: &Arc<Tally>) -> Winner {
+    let (shutdown_sender, shutdown) = oneshot::channel::<()>();
+
+    // Without a shutdown the sender is kept until the end of the round, so the receiver stays pending
+    let (signal, idle_sender) = match race.shutdown {
+        Some(after) => {
+            let delay = work.mul_f64(after);
+            let signal = tokio::spawn(async move {
+                sleep(delay).await;
+                let _ = shutdown_sender.send(());
+            });
+            (Some(signal), None)
+        }
+        None => (None, Some(shutdown_sender)),
+    };
+
+    common::print_info(&format!(
+        "Round {}: computation of {:?} vs timeout of {:?}{}",
+        round,
+        work,
+        work.mul_f64(race.timeout),
+        race.shutdown.map_or(String::new(), |after| format!(" vs shutdown after {:?}", work.mul_f64(after)))
+    ));
+    let start = Instant::now();
+    let winner = tokio::select! {
+        result = computation("computation", work, Arc::clone(tally)) => {
+            common::print_success(&format!("  computation won after {:?} with result {}", start.elapsed(), result));
+            Winner::Computation
+        }
+        _ = tracked("timeout", sleep(work.mul_f64(race.timeout)), Arc::clone(tally)) => {
+            common::print_warning(&format!("  timeout won after {:?}", start.elapsed()));
+            Winner::Timeout
+        }
+        _ = tracked("shutdown signal", shutdown, Arc::clone(tally)) => {
+            common::print_warning(&format!("  shutdown won after {:?}", start.elapsed()));
+            Winner::Shutdown
+        }
+    };
+
+    // The losers were dropped when select! returned; the signal task may still be sleeping
+    drop(idle_sender);
+    if let Some(signal) = signal {
+        signal.abort();
+    }
+    winner
+}
+
+/// Race a spawned computation against a timeout, dropping or aborting its handle when the timeout wins
+async fn spawned_race(work: Duration, abort: bool, tally: &Arc<Tally>) -> bool {
+    let completed_before = tally.completed.load(Ordering::Relaxed);
+    let name = if abort { "aborted task" } else { "detached task" };
+    let mut handle = tokio::spawn(computation(name, work, Arc::clone(tally)));
+
+    tokio::select! {
+        _ = &mut handle => {}
+        _ = sleep(work / 2) => {
+            if abort {
+                handle.abort();
+            }
+        }
+    }
+    if abort {
+        let _ = (&mut handle).await;
+    } else {
+        drop(handle);
+
+        // Give the detached task the time it needs to finish on its own
+        sleep(work).await;
+    }
+    tally.completed.load(Ordering::Relaxed) > completed_before
+}
+
+/// Run `rounds` select! races against a computation taking `delay_ms` milliseconds
+pub fn run(rounds: usize, delay_ms: u64) {
+    let rounds = rounds.max(1);
+    let work = Duration::from_millis(delay_ms.max(STEPS as u64));
+    let tally = Arc::new(Tally::default());
+    audit::track("select tally", &tally);
+
+    let runtime = virtual_time::runtime();
+    let (winners, detached_finished, aborted_finished) = runtime.block_on(async {
+        let mut winners = vec![];
+        for round in 0..rounds {
+            winners.push(race(round, RACES[round % RACES.len()], work, &tally).await);
+        }
+
+        println!();
+        common::print_info("A spawned task raced through its JoinHandle, timeout after half the computation");
+        let detached = spawned_race(work, false, &tally).await;
+        let aborted = spawned_race(work, true, &tally).await;
+        (winners, detached, aborted)
+    });
+    audit::runtime("select", &runtime);
+    runtime_metrics::record("select", &runtime);
+
+    println!();
+    println!("{:<14} {:>6}", "winner", "rounds");
+    for (label, winner) in [("computation", Winner::Computation), ("timeout", Winner::Timeout), ("shutdown", Winner::Shutdown)] {
+        println!("{:<14} {:>6}", label, winners.iter().filter(|won| **won == winner).count());
+    }
+
+    println!();
+    common::print_info(&format!(
+        "{} futures completed and {} were cancelled by being dropped",
+        tally.completed.load(Ordering::Relaxed),
+        tally.cancelled.load(Ordering::Relaxed)
+    ));
+    if detached_finished {
+        common::print_warning("Dropping the JoinHandle did not cancel the spawned task: it ran to completion and its result was thrown away");
+    }
+    if !aborted_finished {
+        common::print_success("JoinHandle::abort cancelled the spawned task at its next await");
+    }
+    common::print_info("select! polls its branches in random order by default; add `biased;` to poll them top to bottom");
+    common::print_info("A losing future stops at whatever .await it was parked on, so work split across awaits can be left half done");
+}
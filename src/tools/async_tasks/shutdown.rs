@@ -0,0 +1,191 @@
+//! Graceful shutdown of a task tree with `CancellationToken`s
+//!
+//! Dropping a future cancels it on the spot, with no chance to run async
+//! cleanup. A `CancellationToken` asks instead: tasks watch it with
+//! `select!` between their work and `token.cancelled()`, and when it fires
+//! they leave their loop and flush, close or report whatever they hold,
+//! awaiting as they please. Tokens form a tree through `child_token()`:
+//! cancelling a token cancels every token below it, and never the ones
+//! above or beside it. Here the root is cancelled by a timer or Ctrl-C,
+//! after one service was already shut down on its own.
+
+// Base dependencies
+use std::sync::{Arc, Mutex};
+
+// Third-party dependencies
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Steps a worker's jobs are spread over, relative to the run time
+const STEPS_PER_RUN: u32 = 10;
+
+/// Time a task needs for its cleanup once cancelled
+const CLEANUP: Duration = Duration::from_millis(5);
+
+/// How a task ended
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Completed,
+    Cancelled,
+}
+
+/// Final report of one task of the tree
+struct TaskReport {
+    path: String,
+    outcome: Outcome,
+    jobs_done: u32,
+    jobs: u32,
+    finished_at: Duration,
+}
+
+/// Reports of every task, filled in as they exit
+type Reports = Arc<Mutex<Vec<TaskReport>>>;
+
+/// A worker processing `jobs` jobs of `step` each until done or cancelled, then cleaning up
+async fn worker(path: String, jobs: u32, step: Duration, token: CancellationToken, start: Instant, reports: Reports) {
+    let mut jobs_done = 0;
+    let outcome = loop {
+        if jobs_done == jobs {
+            break Outcome::Completed;
+        }
+        tokio::select! {
+            _ = token.cancelled() => break Outcome::Cancelled,
+            _ = async {
+                chaos::perturb_async(Point::TaskStart).await;
+                sleep(step).await;
+            } => jobs_done += 1,
+        }
+    };
+
+    // The cleanup can await: nothing is dropped until the worker returns
+    if outcome == Outcome::Cancelled {
+        sleep(CLEANUP).await;
+        common::print_warning(&format!(
+            "  {} cancelled after {}/{} jobs, flushed its partial results",
+            path, jobs_done, jobs
+        ));
+    } else {
+        common::print_success(&format!("  {} completed its {} jobs", path, jobs));
+    }
+    reports.lock().unwrap().push(TaskReport {
+        path,
+        outcome,
+        jobs_done,
+        jobs,
+        finished_at: start.elapsed(),
+    });
+}
+
+/// A service running `workers` workers on child tokens of its own, and closing once they all exited
+async fn service(name: &'static str, workers: usize, step: Duration, token: CancellationToken, start: Instant, reports: Reports) {
+    let mut set = JoinSet::new();
+    for id in 0..workers {
+        // Worker 0 has little to do and finishes before any cancellation; the others never run out of jobs in time
+        let jobs = if id == 0 { STEPS_PER_RUN / 4 } else { STEPS_PER_RUN * 2 * id as u32 };
+        set.spawn(worker(
+            format!("root/{}/worker-{}", name, id),
+            jobs,
+            step,
+            token.child_token(),
+            start,
+            Arc::clone(&reports),
+        ));
+    }
+    while let Some(result) = set.join_next().await {
+        result.unwrap();
+    }
+
+    // The service outlives its workers, so it can close what they shared
+    let outcome = if token.is_cancelled() {
+        sleep(CLEANUP).await;
+        common::print_warning(&format!("  root/{} closed after its workers exited", name));
+        Outcome::Cancelled
+    } else {
+        Outcome::Completed
+    };
+    reports.lock().unwrap().push(TaskReport {
+        path: format!("root/{}", name),
+        outcome,
+        jobs_done: 0,
+        jobs: 0,
+        finished_at: start.elapsed(),
+    });
+}
+
+/// Run the graceful shutdown example: services of `workers` workers each, the root cancelled after `run_ms` milliseconds or on Ctrl-C
+pub fn run(workers: usize, run_ms: u64) {
+    let workers = workers.max(2);
+    let run_time = Duration::from_millis(run_ms.max(STEPS_PER_RUN as u64));
+    let step = run_time / STEPS_PER_RUN;
+    common::print_info(&format!(
+        "Two services of {} workers each share a token tree; \"reports\" is cancelled alone after {:?}, the root after {:?} or on Ctrl-C",
+        workers,
+        run_time / 2,
+        run_time
+    ));
+
+    let reports: Reports = Arc::new(Mutex::new(vec![]));
+    audit::track("shutdown reports", &reports);
+    let runtime = virtual_time::runtime();
+    runtime.block_on(async {
+        let root = CancellationToken::new();
+        let start = Instant::now();
+
+        let mut services = JoinSet::new();
+        let ingest = root.child_token();
+        let reporting = root.child_token();
+        services.spawn(service("ingest", workers, step, ingest, start, Arc::clone(&reports)));
+        services.spawn(service("reports", workers, step, reporting.clone(), start, Arc::clone(&reports)));
+
+        // Cancelling a subtree leaves its parent and its sibling running
+        sleep(run_time / 2).await;
+        common::print_info(&format!("Cancelling root/reports at {:?}", start.elapsed()));
+        reporting.cancel();
+
+        tokio::select! {
+            _ = sleep(run_time / 2) => common::print_info(&format!("Run time over: cancelling the root at {:?}", start.elapsed())),
+            _ = tokio::signal::ctrl_c() => common::print_warning(&format!("Ctrl-C received: cancelling the root at {:?}", start.elapsed())),
+        }
+        root.cancel();
+
+        // Cancellation only asks: the root waits for every task to finish its cleanup
+        while let Some(result) = services.join_next().await {
+            result.unwrap();
+        }
+        common::print_info(&format!("Every task exited by {:?}", start.elapsed()));
+    });
+    audit::runtime("shutdown", &runtime);
+    runtime_metrics::record("shutdown", &runtime);
+
+    let mut reports = Arc::try_unwrap(reports).ok().unwrap().into_inner().unwrap();
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+
+    println!();
+    println!("{:<26} {:>10} {:>10} {:>12}", "task", "outcome", "jobs", "exited at");
+    for report in &reports {
+        println!(
+            "{:<26} {:>10} {:>10} {:>12?}",
+            report.path,
+            if report.outcome == Outcome::Completed { "completed" } else { "cancelled" },
+            if report.jobs == 0 { "-".to_string() } else { format!("{}/{}", report.jobs_done, report.jobs) },
+            report.finished_at
+        );
+    }
+
+    println!();
+    let cancelled = reports.iter().filter(|report| report.outcome == Outcome::Cancelled).count();
+    common::print_info(&format!(
+        "{} tasks completed and {} were cancelled; every cancelled task cleaned up before exiting",
+        reports.len() - cancelled,
+        cancelled
+    ));
+    common::print_info("Cancelling root/reports stopped only its workers; cancelling the root reached every token below it");
+}
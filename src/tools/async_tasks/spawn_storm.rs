@@ -0,0 +1,118 @@
+//! Spawn-storm stress benchmark for the Tokio runtime
+//!
+//! "Tasks are cheap" is easier to believe with numbers. This module spawns
+//! a very large number of trivial tasks, keeps them all alive behind a gate
+//! to measure the memory they hold, then releases them and times how long
+//! the runtime needs to drive every one of them to completion.
+
+// Base dependencies
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::RwLock;
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+
+/// Numbers collected for one runtime flavour
+struct StormReport {
+    spawn_time: Duration,
+    completion_time: Duration,
+    memory_growth: Option<u64>,
+}
+
+/// Spawn `num_tasks` gated tasks on the runtime, then release them and wait for all of them
+fn storm(runtime: &Runtime, num_tasks: usize) -> StormReport {
+    runtime.block_on(async {
+        common::release_free_memory();
+        let baseline = common::resident_memory_bytes();
+
+        // Every task waits on a read lock while we hold the write side, so they all stay alive at once
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().await;
+
+        let start = Instant::now();
+        let mut handles = Vec::with_capacity(num_tasks);
+        for i in 0..num_tasks {
+            let gate = Arc::clone(&gate);
+            handles.push(tokio::spawn(async move {
+                let _open = gate.read().await;
+                i
+            }));
+        }
+        let spawn_time = start.elapsed();
+
+        // Yield so the current-thread runtime polls every task once and they register on the gate
+        tokio::task::yield_now().await;
+        let peak = common::resident_memory_bytes();
+
+        // Open the gate and wait for every task to finish
+        let start = Instant::now();
+        drop(closed);
+        let mut checksum = 0;
+        for handle in handles {
+            checksum += handle.await.unwrap();
+        }
+        let completion_time = start.elapsed();
+        assert_eq!(checksum, num_tasks * num_tasks.saturating_sub(1) / 2);
+
+        StormReport {
+            spawn_time,
+            completion_time,
+            memory_growth: baseline.zip(peak).map(|(baseline, peak)| peak.saturating_sub(baseline)),
+        }
+    })
+}
+
+/// Run the spawn-storm benchmark on a multi-thread and a current-thread runtime
+pub fn run(num_tasks: usize) {
+    common::print_info(&format!(
+        "Spawning {} trivial tasks per runtime, all alive at once behind a gate, then releasing them",
+        num_tasks
+    ));
+
+    println!();
+    println!(
+        "{:<16} {:>12} {:>14} {:>14} {:>14} {:>10}",
+        "runtime", "spawn time", "spawns/s", "completion", "peak memory", "per task"
+    );
+
+    let runtimes = [
+        ("multi-thread", cpus::multi_thread_runtime()),
+        ("current-thread", Builder::new_current_thread().enable_all().build().unwrap()),
+    ];
+
+    for (label, runtime) in runtimes {
+        let report = storm(&runtime, num_tasks);
+        let (memory, per_task) = match report.memory_growth {
+            Some(bytes) => (
+                common::format_bytes(bytes),
+                format!("{} B", bytes / num_tasks.max(1) as u64),
+            ),
+            None => ("n/a".to_string(), "n/a".to_string()),
+        };
+        println!(
+            "{:<16} {:>12?} {:>14.0} {:>14?} {:>14} {:>10}",
+            label,
+            report.spawn_time,
+            num_tasks as f64 / report.spawn_time.as_secs_f64(),
+            report.completion_time,
+            memory,
+            per_task
+        );
+
+        // Shut the runtime down before measuring the next one
+        audit::runtime(label, &runtime);
+        runtime_metrics::record(label, &runtime);
+        drop(runtime);
+    }
+
+    println!();
+    common::print_success(&format!("Every one of the {} tasks ran to completion on both runtimes", num_tasks));
+    common::print_info("Peak memory is the growth of the resident set while every task was alive, including its JoinHandle and its place in the gate's wait queue");
+}
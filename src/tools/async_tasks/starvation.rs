@@ -0,0 +1,251 @@
+//! Cooperative yielding: starving a current-thread runtime and the fixes
+//!
+//! Tasks only give the thread back when a poll returns `Pending`. A loop
+//! without `.await` never does, but neither does a loop awaiting futures that
+//! are always ready: `.await` on a ready future carries on without leaving the
+//! task. On a current-thread runtime either one stops everything else until
+//! it finishes. Tokio's own resources share a per-task budget of operations,
+//! and once a task has spent it they return `Pending` to force it out, which
+//! `coop::consume_budget` extends to any loop. `yield_now` gives the thread
+//! back on every call. A heartbeat task measures who gets starved.
+
+// Base dependencies
+use std::future::ready;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use tokio::runtime::Builder;
+use tokio::sync::{mpsc, watch};
+use tokio::task::{self, coop, JoinSet};
+use tokio::time::sleep;
+
+// Project dependencies
+use super::blocking::{calibrate, job};
+use super::mini_executor::CountPolls;
+use crate::audit;
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Period the heartbeat expects to tick at
+const HEARTBEAT: Duration = Duration::from_millis(5);
+
+/// CPU time of one step of a busy loop
+const STEP: Duration = Duration::from_micros(20);
+
+/// How a busy loop behaves between its steps
+#[derive(Clone, Copy)]
+enum Strategy {
+    NoAwait,
+    AwaitReady,
+    ChannelRecv,
+    UnconstrainedRecv,
+    ConsumeBudget,
+    YieldNow,
+}
+
+impl Strategy {
+    fn label(&self) -> &'static str {
+        match self {
+            Strategy::NoAwait => "no .await",
+            Strategy::AwaitReady => "ready().await",
+            Strategy::ChannelRecv => "mpsc recv().await",
+            Strategy::UnconstrainedRecv => "recv() in unconstrained",
+            Strategy::ConsumeBudget => "coop::consume_budget()",
+            Strategy::YieldNow => "yield_now()",
+        }
+    }
+}
+
+/// What one strategy did to the heartbeat
+struct Report {
+    /// How late every tick was, sorted
+    lateness: Vec<Duration>,
+    /// Ticks the heartbeat managed while the busy loops ran
+    ticks_during: usize,
+    busy_time: Duration,
+    /// Polls of all busy tasks: one each, plus one per time they gave the thread back
+    polls: usize,
+}
+
+impl Report {
+    /// Ticks the heartbeat would have managed in the busy time if nothing held the thread
+    fn expected_ticks(&self) -> usize {
+        (self.busy_time.as_nanos() / HEARTBEAT.as_nanos()) as usize
+    }
+}
+
+/// A busy loop of `steps` steps of `calls` calls each, giving the thread back as `strategy` allows
+async fn busy_loop(strategy: Strategy, steps: usize, calls: u64) -> u64 {
+    let mut acc = 0u64;
+    match strategy {
+        Strategy::NoAwait => {
+            for _ in 0..steps {
+                acc = acc.wrapping_add(job(calls));
+            }
+        }
+        Strategy::AwaitReady => {
+            for _ in 0..steps {
+                // Looks like a suspension point, but a ready future never returns Pending
+                ready(()).await;
+                acc = acc.wrapping_add(job(calls));
+            }
+        }
+        Strategy::ChannelRecv | Strategy::UnconstrainedRecv => {
+            // Every message is already queued, so recv() could always answer at once
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            for step in 0..steps {
+                sender.send(step as u64).unwrap();
+            }
+            drop(sender);
+            let consume = async move {
+                let mut acc = 0u64;
+                while let Some(step) = receiver.recv().await {
+                    acc = acc.wrapping_add(job(calls) ^ step);
+                }
+                acc
+            };
+            acc = match strategy {
+                Strategy::UnconstrainedRecv => coop::unconstrained(consume).await,
+                _ => consume.await,
+            };
+        }
+        Strategy::ConsumeBudget => {
+            for _ in 0..steps {
+                coop::consume_budget().await;
+                acc = acc.wrapping_add(job(calls));
+            }
+        }
+        Strategy::YieldNow => {
+            for _ in 0..steps {
+                task::yield_now().await;
+                acc = acc.wrapping_add(job(calls));
+            }
+        }
+    }
+    black_box(acc)
+}
+
+/// Run `tasks` busy loops with `strategy` next to a heartbeat, on the current runtime
+async fn measure(strategy: Strategy, tasks: usize, steps: usize, calls: u64) -> Report {
+    let (stop, mut stopped) = watch::channel(false);
+    let ticks = Arc::new(AtomicUsize::new(0));
+
+    // Each tick records how far past its deadline it woke up
+    let heartbeat = {
+        let ticks = Arc::clone(&ticks);
+        tokio::spawn(async move {
+            let mut lateness = vec![];
+            while !*stopped.borrow() {
+                let deadline = Instant::now() + HEARTBEAT;
+                tokio::select! {
+                    _ = sleep(HEARTBEAT) => {}
+                    _ = stopped.changed() => {}
+                }
+                if let Some(late) = Instant::now().checked_duration_since(deadline) {
+                    lateness.push(late);
+                }
+                ticks.fetch_add(1, Ordering::Relaxed);
+            }
+            lateness
+        })
+    };
+
+    // Let the heartbeat settle before the busy loops arrive
+    sleep(HEARTBEAT * 2).await;
+    let ticks_before = ticks.load(Ordering::Relaxed);
+    let polls = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let mut set = JoinSet::new();
+    for _ in 0..tasks {
+        set.spawn(CountPolls {
+            inner: Box::pin(busy_loop(strategy, steps, calls)),
+            polls: Arc::clone(&polls),
+        });
+    }
+    while let Some(result) = set.join_next().await {
+        result.unwrap();
+    }
+    let busy_time = start.elapsed();
+    let ticks_during = ticks.load(Ordering::Relaxed) - ticks_before;
+
+    stop.send(true).unwrap();
+    let mut lateness = heartbeat.await.unwrap();
+    lateness.sort_unstable();
+    Report {
+        lateness,
+        ticks_during,
+        busy_time,
+        polls: polls.load(Ordering::Relaxed),
+    }
+}
+
+/// Run the starvation example: `tasks` busy loops of about `busy_ms` milliseconds each next to a heartbeat
+pub fn run(tasks: usize, busy_ms: u64) {
+    let tasks = tasks.max(1);
+    let busy = Duration::from_millis(busy_ms.max(1));
+    let calls = calibrate(STEP);
+    let steps = (busy.as_nanos() / STEP.as_nanos()).max(1) as usize;
+    common::print_info(&format!(
+        "{} busy loops of {} steps of ~{:?} (~{:?} each) on a current-thread runtime, while a heartbeat expects a tick every {:?}",
+        tasks, steps, STEP, busy, HEARTBEAT
+    ));
+    if virtual_time::is_enabled() {
+        common::print_warning("This example measures real stalls, so it ignores --virtual-time");
+    }
+
+    // One thread for everything: whoever does not give it back starves the rest
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let strategies = [
+        Strategy::NoAwait,
+        Strategy::AwaitReady,
+        Strategy::ChannelRecv,
+        Strategy::UnconstrainedRecv,
+        Strategy::ConsumeBudget,
+        Strategy::YieldNow,
+    ];
+    let reports: Vec<Report> = strategies
+        .iter()
+        .map(|strategy| runtime.block_on(measure(*strategy, tasks, steps, calls)))
+        .collect();
+    audit::runtime("starvation", &runtime);
+    runtime_metrics::record("starvation", &runtime);
+
+    println!();
+    println!(
+        "{:<26} {:>12} {:>8} {:>8} {:>8} {:>12} {:>12} {:>12}",
+        "busy loop", "busy time", "polls", "ticks", "of", "p50 late", "p99 late", "max late"
+    );
+    for (strategy, report) in strategies.iter().zip(&reports) {
+        println!(
+            "{:<26} {:>12?} {:>8} {:>8} {:>8} {:>12?} {:>12?} {:>12?}",
+            strategy.label(),
+            report.busy_time,
+            report.polls,
+            report.ticks_during,
+            report.expected_ticks(),
+            common::percentile(&report.lateness, 50.0),
+            common::percentile(&report.lateness, 99.0),
+            report.lateness.last().copied().unwrap_or_default()
+        );
+    }
+
+    println!();
+    let starved: Vec<&str> = strategies
+        .iter()
+        .zip(&reports)
+        .filter(|(_, report)| report.ticks_during * 4 < report.expected_ticks())
+        .map(|(strategy, _)| strategy.label())
+        .collect();
+    if starved.is_empty() {
+        common::print_warning("No busy loop kept the heartbeat under a quarter of its ticks on this run");
+    } else {
+        common::print_success(&format!("Starved the heartbeat to under a quarter of its ticks: {}", starved.join(", ")));
+    }
+    common::print_info("A task leaves the thread only when a poll returns Pending: one poll per task means it never did");
+    common::print_info("Awaiting a ready future is no yield; Tokio's resources count against a budget and force one, unless unconstrained");
+    common::print_info("consume_budget() gives any loop the same budget; yield_now() gives the thread back on every call, at a poll each");
+}
@@ -0,0 +1,131 @@
+//! Processing an async stream of items with combinators
+//!
+//! A `Stream` is the async counterpart of an `Iterator`: instead of a value
+//! at every `next()`, it hands out a future that resolves to the next item,
+//! or to `None` once the stream is over. Like iterators, streams are lazy
+//! and chain combinators (`map`, `filter`, `chunks`, …), and nothing happens
+//! until the consumer polls them with `while let Some(..) = stream.next().await`.
+//! Here a sensor produces readings, a ticking interval throttles them, and
+//! the chain validates, converts and batches them before they are consumed.
+
+// Base dependencies
+use std::future;
+use std::pin::pin;
+
+// Third-party dependencies
+use futures::stream::{self, Stream, StreamExt};
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Readings grouped into each batch handed to the consumer
+const BATCH_SIZE: usize = 4;
+
+/// One in this many readings is a sensor fault
+const FAULT_EVERY: usize = 7;
+
+/// A raw sensor reading, in tenths of a degree Fahrenheit
+#[derive(Clone, Copy)]
+struct Reading {
+    id: usize,
+    tenths_fahrenheit: i64,
+}
+
+/// Value the sensor reports when it fails to read
+const FAULT: i64 = i64::MIN;
+
+/// Sensor producing `count` readings, each after a short async read
+fn sensor(count: usize) -> impl Stream<Item = Reading> {
+    stream::unfold(0, move |id| async move {
+        if id == count {
+            return None;
+        }
+        chaos::perturb_async(Point::TaskStart).await;
+        sleep(Duration::from_millis(1)).await;
+
+        // A slow drift with some deterministic noise, and a fault now and then
+        let tenths_fahrenheit = if id % FAULT_EVERY == FAULT_EVERY - 1 {
+            FAULT
+        } else {
+            680 + (id as i64 * 37) % 50
+        };
+        Some((Reading { id, tenths_fahrenheit }, id + 1))
+    })
+}
+
+/// Stream yielding one item every `period`, so zipping with it throttles another stream
+fn ticks(period: Duration) -> impl Stream<Item = Instant> {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    stream::unfold(ticker, |mut ticker| async move {
+        let tick = ticker.tick().await;
+        Some((tick, ticker))
+    })
+}
+
+/// Run the stream example over `num_items` readings, throttled to one every `period_ms` milliseconds
+pub fn run(num_items: usize, period_ms: u64) {
+    let period = Duration::from_millis(period_ms.max(1));
+    common::print_info(&format!(
+        "A sensor streams {} readings, throttled to one every {:?}, validated, converted to Celsius and batched by {}",
+        num_items, period, BATCH_SIZE
+    ));
+
+    let runtime = virtual_time::runtime();
+    let (batches, accepted, faults, elapsed) = runtime.block_on(async {
+        let mut faults = 0;
+
+        // Building the chain does no work yet: every stage runs when the consumer polls it
+        let pipeline = sensor(num_items)
+            .zip(ticks(period))
+            .map(|(reading, _tick)| reading)
+            .filter(|reading| {
+                let valid = reading.tenths_fahrenheit != FAULT;
+                if !valid {
+                    faults += 1;
+                    common::print_warning(&format!("  reading {} is a sensor fault, filtered out", reading.id));
+                }
+                future::ready(valid)
+            })
+            .map(|reading| (reading.id, (reading.tenths_fahrenheit as f64 / 10.0 - 32.0) * 5.0 / 9.0))
+            .chunks(BATCH_SIZE);
+
+        // Combinators such as zip and unfold hold futures that must not move, so the chain is pinned
+        let mut pipeline = pin!(pipeline);
+        let start = Instant::now();
+        let (mut batches, mut accepted) = (0, 0);
+        while let Some(batch) = pipeline.next().await {
+            batches += 1;
+            accepted += batch.len();
+            let ids: Vec<usize> = batch.iter().map(|(id, _)| *id).collect();
+            let mean = batch.iter().map(|(_, celsius)| celsius).sum::<f64>() / batch.len() as f64;
+            common::print_success(&format!(
+                "  batch {} at {:>8.1?}: readings {:?}, mean {:.2} °C",
+                batches,
+                start.elapsed(),
+                ids,
+                mean
+            ));
+        }
+        (batches, accepted, faults, start.elapsed())
+    });
+    audit::runtime("stream", &runtime);
+    runtime_metrics::record("stream", &runtime);
+
+    println!();
+    common::print_info(&format!(
+        "{} readings in {} batches, {} faults filtered out, in {:?}",
+        accepted, batches, faults, elapsed
+    ));
+    common::print_info(&format!(
+        "The throttle spaced the readings {:?} apart, so the stream took {} periods however fast the sensor was (the first tick is immediate)",
+        period,
+        num_items.saturating_sub(1)
+    ));
+    common::print_info("chunks hands out full batches and a shorter last one when the stream ends; the consumer never sees a partial batch otherwise");
+}
@@ -0,0 +1,299 @@
+//! Structured concurrency: children never outlive their parent
+//!
+//! `tokio::spawn` detaches: the handle may be dropped, the parent may return
+//! early with an error, and the task keeps running regardless. Here a parent
+//! spawns its children through a `TaskScope` instead. Joining the scope
+//! awaits every child, and the first failure cancels and awaits the others
+//! before the error reaches the parent. If the parent leaves without joining,
+//! on a `?` for instance, dropping the scope aborts whatever still runs.
+//! Scopes nest, so a task tree is torn down from the branch that failed.
+
+// Base dependencies
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Grandchildren each child task spawns
+const GRANDCHILDREN: usize = 2;
+
+/// Child that fails in every variant
+const FAILING_CHILD: usize = 1;
+
+/// Children spawned by a parent, joined or cancelled together with it
+struct TaskScope<T> {
+    children: JoinSet<Result<T, String>>,
+}
+
+impl<T: Send + 'static> TaskScope<T> {
+    fn new() -> Self {
+        TaskScope { children: JoinSet::new() }
+    }
+
+    fn spawn(&mut self, child: impl Future<Output = Result<T, String>> + Send + 'static) {
+        self.children.spawn(child);
+    }
+
+    /// Await every child; on the first failure, cancel the others and wait until they are gone
+    async fn join(mut self) -> Result<Vec<T>, String> {
+        let mut results = vec![];
+        while let Some(joined) = self.children.join_next().await {
+            let failure = match joined {
+                Ok(Ok(result)) => {
+                    results.push(result);
+                    continue;
+                }
+                Ok(Err(error)) => error,
+                Err(error) => format!("child panicked: {}", error),
+            };
+
+            // join_next also returns the aborted children, so the loop ends once all are gone
+            self.children.abort_all();
+            while self.children.join_next().await.is_some() {}
+            return Err(failure);
+        }
+        Ok(results)
+    }
+}
+
+impl<T> Drop for TaskScope<T> {
+    fn drop(&mut self) {
+        // Dropping the set aborts its tasks; the count only makes the safety net visible
+        if !self.children.is_empty() {
+            common::print_warning(&format!("  scope dropped with {} children still running: aborting them", self.children.len()));
+        }
+    }
+}
+
+/// Tasks alive and leaves finished, shared by every task of a tree
+#[derive(Default)]
+struct Counters {
+    alive: AtomicUsize,
+    finished: AtomicUsize,
+}
+
+/// Counts a task as alive from its creation until it completes or is cancelled
+struct Alive(Arc<Counters>);
+
+impl Alive {
+    fn new(counters: &Arc<Counters>) -> Self {
+        counters.alive.fetch_add(1, Ordering::SeqCst);
+        Alive(Arc::clone(counters))
+    }
+}
+
+impl Drop for Alive {
+    fn drop(&mut self) {
+        self.0.alive.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A leaf task: `ms` milliseconds of work
+async fn leaf(ms: u64, counters: Arc<Counters>) -> Result<u64, String> {
+    let _alive = Alive::new(&counters);
+    chaos::perturb_async(Point::TaskStart).await;
+    sleep(Duration::from_millis(ms)).await;
+    counters.finished.fetch_add(1, Ordering::SeqCst);
+    Ok(ms)
+}
+
+/// A child task running grandchildren through its own scope; `FAILING_CHILD` fails after a third of `delay_ms`
+async fn scoped_child(id: usize, delay_ms: u64, counters: Arc<Counters>) -> Result<u64, String> {
+    let _alive = Alive::new(&counters);
+    let mut scope = TaskScope::new();
+    for grandchild in 0..GRANDCHILDREN {
+        scope.spawn(leaf(task_delay(id * GRANDCHILDREN + grandchild, delay_ms), Arc::clone(&counters)));
+    }
+    if id == FAILING_CHILD {
+        sleep(Duration::from_millis(delay_ms / 3)).await;
+
+        // Leaving early drops the scope, which aborts this child's own grandchildren
+        return Err(format!("child {} failed", id));
+    }
+    Ok(scope.join().await?.iter().sum())
+}
+
+/// The same child with detached spawns: its grandchildren outlive it when it fails
+async fn detached_child(id: usize, delay_ms: u64, counters: Arc<Counters>) -> Result<u64, String> {
+    let _alive = Alive::new(&counters);
+    let handles: Vec<_> = (0..GRANDCHILDREN)
+        .map(|grandchild| tokio::spawn(leaf(task_delay(id * GRANDCHILDREN + grandchild, delay_ms), Arc::clone(&counters))))
+        .collect();
+    if id == FAILING_CHILD {
+        sleep(Duration::from_millis(delay_ms / 3)).await;
+        return Err(format!("child {} failed", id));
+    }
+    let mut total = 0;
+    for handle in handles {
+        total += handle.await.unwrap()?;
+    }
+    Ok(total)
+}
+
+/// How the parent task treats its children
+#[derive(Clone, Copy)]
+enum Variant {
+    /// `tokio::spawn`, returning on the first error
+    Detached,
+    /// A scope, joined: the failing child cancels its siblings
+    ScopedJoin,
+    /// A scope the parent leaves early with its own error, before joining
+    ScopedEarlyExit,
+}
+
+impl Variant {
+    fn label(&self) -> &'static str {
+        match self {
+            Variant::Detached => "detached spawns",
+            Variant::ScopedJoin => "scope, child fails",
+            Variant::ScopedEarlyExit => "scope, parent fails",
+        }
+    }
+}
+
+/// Work the parent does itself while its children run, failing after a third of `delay_ms`
+async fn parent_step(delay_ms: u64) -> Result<(), String> {
+    sleep(Duration::from_millis(delay_ms / 3)).await;
+    Err("parent failed".to_string())
+}
+
+/// The parent task of one variant, spawning `num_children` children
+async fn parent(variant: Variant, num_children: usize, delay_ms: u64, counters: Arc<Counters>) -> Result<u64, String> {
+    match variant {
+        Variant::Detached => {
+            let handles: Vec<_> = (0..num_children)
+                .map(|id| tokio::spawn(detached_child(id, delay_ms, Arc::clone(&counters))))
+                .collect();
+            let mut total = 0;
+            for handle in handles {
+                // The `?` returns on the first error; the handles left are dropped, which detaches them
+                total += handle.await.unwrap()?;
+            }
+            Ok(total)
+        }
+        Variant::ScopedJoin => {
+            let mut scope = TaskScope::new();
+            for id in 0..num_children {
+                scope.spawn(scoped_child(id, delay_ms, Arc::clone(&counters)));
+            }
+            Ok(scope.join().await?.iter().sum())
+        }
+        Variant::ScopedEarlyExit => {
+            let mut scope = TaskScope::new();
+            for id in (0..num_children).filter(|id| *id != FAILING_CHILD) {
+                scope.spawn(scoped_child(id, delay_ms, Arc::clone(&counters)));
+            }
+            // The `?` leaves before the join, and the scope is dropped on the way out
+            parent_step(delay_ms).await?;
+            Ok(scope.join().await?.iter().sum())
+        }
+    }
+}
+
+/// What became of one variant's tree
+struct Report {
+    result: Result<u64, String>,
+    exit: Duration,
+    /// Tasks still alive shortly after the parent returned
+    alive_at_exit: usize,
+    /// Leaves that finished their work after the parent returned
+    finished_after_exit: usize,
+    last_gone: Duration,
+}
+
+/// Run one variant and follow its tasks until the last one is gone
+async fn run_variant(variant: Variant, num_children: usize, delay_ms: u64) -> Report {
+    let counters = Arc::new(Counters::default());
+    let start = Instant::now();
+    let result = parent(variant, num_children, delay_ms, Arc::clone(&counters)).await;
+    let exit = start.elapsed();
+
+    // An abort takes effect when the runtime next gets to the task, so let aborted ones settle first
+    sleep(Duration::from_millis(1)).await;
+    let alive_at_exit = counters.alive.load(Ordering::SeqCst);
+    let finished_at_exit = counters.finished.load(Ordering::SeqCst);
+    while counters.alive.load(Ordering::SeqCst) > 0 {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    Report {
+        result,
+        exit,
+        alive_at_exit,
+        finished_after_exit: counters.finished.load(Ordering::SeqCst) - finished_at_exit,
+        last_gone: start.elapsed(),
+    }
+}
+
+/// Run the structured concurrency example: a parent of `num_children` children, each with `GRANDCHILDREN` tasks of around `delay_ms`
+pub fn run(num_children: usize, delay_ms: u64) {
+    let num_children = num_children.max(FAILING_CHILD + 2);
+    let delay_ms = delay_ms.max(3);
+    common::print_info(&format!(
+        "A parent spawns {} children of {} grandchildren each ({}ms to {}ms of work); child {} fails after {}ms",
+        num_children,
+        GRANDCHILDREN,
+        task_delay(0, delay_ms),
+        (0..num_children * GRANDCHILDREN).map(|id| task_delay(id, delay_ms)).max().unwrap_or(0),
+        FAILING_CHILD,
+        delay_ms / 3
+    ));
+
+    let runtime = virtual_time::runtime();
+    let variants = [Variant::Detached, Variant::ScopedJoin, Variant::ScopedEarlyExit];
+    let reports: Vec<Report> = variants
+        .iter()
+        .map(|variant| {
+            println!();
+            common::print_info(&format!("Running {}:", variant.label()));
+            runtime.block_on(run_variant(*variant, num_children, delay_ms))
+        })
+        .collect();
+    audit::runtime("structured", &runtime);
+    runtime_metrics::record("structured", &runtime);
+
+    println!();
+    println!(
+        "{:<22} {:<22} {:>12} {:>14} {:>16} {:>14}",
+        "variant", "parent result", "parent exit", "alive after", "finished after", "last task gone"
+    );
+    for (variant, report) in variants.iter().zip(&reports) {
+        let result = match &report.result {
+            Ok(total) => format!("Ok({})", total),
+            Err(error) => format!("Err({})", error),
+        };
+        println!(
+            "{:<22} {:<22} {:>12?} {:>14} {:>16} {:>14?}",
+            variant.label(),
+            result,
+            report.exit,
+            report.alive_at_exit,
+            report.finished_after_exit,
+            report.last_gone
+        );
+    }
+
+    println!();
+    let detached = &reports[0];
+    if detached.alive_at_exit > 0 {
+        common::print_warning(&format!(
+            "Detached spawns left {} tasks running after the parent returned its error, and {} of them finished work nobody will read",
+            detached.alive_at_exit, detached.finished_after_exit
+        ));
+    }
+    if reports[1..].iter().all(|report| report.alive_at_exit == 0) {
+        common::print_success("With a scope, no task outlived its parent: joined children were cancelled and awaited, and a dropped scope aborted its own");
+    }
+    common::print_info("A scope turns the task tree into the call tree: an error unwinds through it and takes the siblings down with it");
+}
@@ -0,0 +1,148 @@
+//! Request context with `tokio::task_local!`
+//!
+//! Logging which request a line belongs to usually means passing a request
+//! ID through every function on the way down. A task-local holds it instead:
+//! `REQUEST_ID.scope(id, future)` sets it for everything that future runs,
+//! however deeply nested, and any function can read it back. A thread-local
+//! looks like the same thing but is not: tasks take turns on a thread at
+//! every `.await`, so a value stored per thread is overwritten by whichever
+//! request ran last. Task-locals are not inherited either: a spawned task
+//! starts without one, unless the spawned future is scoped again.
+
+// Base dependencies
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Third-party dependencies
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+tokio::task_local! {
+    /// ID of the request the current task is serving
+    static REQUEST_ID: u64;
+}
+
+thread_local! {
+    /// The same ID kept per thread instead: overwritten by every request the thread runs in between
+    static THREAD_REQUEST_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Log lines written with and without a request ID
+static TAGGED: AtomicUsize = AtomicUsize::new(0);
+static UNTAGGED: AtomicUsize = AtomicUsize::new(0);
+
+/// Times the thread-local disagreed with the task-local after an `.await`
+static THREAD_LOCAL_MISMATCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// First request ID handed out
+const FIRST_ID: u64 = 1000;
+
+/// Log a line tagged with the current request, if the task has one
+fn log(message: &str) {
+    match REQUEST_ID.try_with(|id| *id) {
+        Ok(id) => {
+            TAGGED.fetch_add(1, Ordering::Relaxed);
+            common::print_info(&format!("[req-{}] {}", id, message));
+        }
+        Err(_) => {
+            UNTAGGED.fetch_add(1, Ordering::Relaxed);
+            common::print_warning(&format!("[no request] {}", message));
+        }
+    }
+}
+
+/// Compare what the thread-local says with the task-local, after the task has been suspended
+fn check_thread_local() {
+    if THREAD_REQUEST_ID.get() != REQUEST_ID.get() {
+        THREAD_LOCAL_MISMATCHES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Innermost call: none of the functions above passed the request ID down
+async fn query_database(table: &str, delay: Duration) -> usize {
+    log(&format!("SELECT * FROM {}", table));
+    sleep(delay).await;
+    check_thread_local();
+    log(&format!("{} answered", table));
+    table.len()
+}
+
+async fn authenticate(delay: Duration) -> u64 {
+    log("checking the session token");
+    query_database("sessions", delay / 2).await;
+    let user = REQUEST_ID.get() % 7;
+    log(&format!("authenticated as user {}", user));
+    user
+}
+
+async fn load_dashboard(user: u64, delay: Duration) -> usize {
+    log(&format!("loading the dashboard of user {}", user));
+    query_database("orders", delay / 2).await + query_database("messages", delay / 4).await
+}
+
+/// Handle one request; every line it logs, at any depth, carries its ID
+async fn handle_request(path: &'static str, delay: Duration) {
+    chaos::perturb_async(Point::TaskStart).await;
+    THREAD_REQUEST_ID.set(REQUEST_ID.get());
+    log(&format!("GET {}", path));
+
+    let user = authenticate(delay).await;
+    let rows = load_dashboard(user, delay).await;
+
+    // A spawned task starts with no task-locals: the first audit line is untagged, the second is scoped again
+    let untagged = tokio::spawn(async { log("audit record written from a plain spawn") });
+    let id = REQUEST_ID.get();
+    let scoped = tokio::spawn(REQUEST_ID.scope(id, async { log("audit record written from a re-scoped spawn") }));
+    untagged.await.unwrap();
+    scoped.await.unwrap();
+
+    check_thread_local();
+    log(&format!("200 OK, {} rows", rows));
+}
+
+/// Run the task-local example: `num_requests` requests of about `delay_ms`, served concurrently
+pub fn run(num_requests: usize, delay_ms: u64) {
+    let paths = ["/dashboard", "/orders", "/inbox"];
+    common::print_info(&format!(
+        "Serving {} requests concurrently; each sets REQUEST_ID once and every nested call logs with it",
+        num_requests
+    ));
+    println!();
+
+    let runtime = virtual_time::runtime();
+    runtime.block_on(async {
+        let mut set = JoinSet::new();
+        for index in 0..num_requests {
+            let id = FIRST_ID + index as u64;
+            let delay = Duration::from_millis(task_delay(index, delay_ms));
+            set.spawn(REQUEST_ID.scope(id, handle_request(paths[index % paths.len()], delay)));
+        }
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+    });
+    audit::runtime("task-local", &runtime);
+    runtime_metrics::record("task-local", &runtime);
+
+    println!();
+    let (tagged, untagged) = (TAGGED.load(Ordering::Relaxed), UNTAGGED.load(Ordering::Relaxed));
+    let mismatches = THREAD_LOCAL_MISMATCHES.load(Ordering::Relaxed);
+    common::print_success(&format!(
+        "{} log lines carried their request ID without any function taking it as a parameter",
+        tagged
+    ));
+    common::print_info(&format!("{} lines came from plain spawns, which start without the task-local", untagged));
+    common::print_info(&format!(
+        "The thread-local disagreed with the task-local {} times after an .await: other requests ran on the thread meanwhile",
+        mismatches
+    ));
+    common::print_info("REQUEST_ID.get() panics outside a scope; try_with() returns an error instead, as log() uses here");
+}
@@ -0,0 +1,213 @@
+//! Async TCP echo server and client
+//!
+//! The server binds a port and accepts connections in a loop, spawning one
+//! task per connection that echoes every line back. Thousands of idle
+//! connections cost thousands of small tasks, not thousands of threads. The
+//! client opens many connections at once, sends lines on each, and checks
+//! and times every echo. Either side can run alone, against a server or a
+//! client elsewhere, or both together in one process.
+
+// Base dependencies
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::runtime_metrics;
+use crate::virtual_time;
+use crate::EchoRole;
+
+/// Lines each client connection sends
+const LINES_PER_CONNECTION: usize = 5;
+
+/// Connection counts kept by the server
+#[derive(Default)]
+struct ServerStats {
+    open: AtomicUsize,
+    peak: AtomicUsize,
+    accepted: AtomicUsize,
+    lines: AtomicUsize,
+}
+
+/// Echo every line of one connection back until the client closes it
+async fn handle(stream: TcpStream, stats: Arc<ServerStats>) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        chaos::perturb_async(Point::TaskStart).await;
+        writer.write_all(format!("{}\n", line).as_bytes()).await?;
+        stats.lines.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Accept connections on `listener` until `shutdown`, each one handled by its own task
+async fn server(listener: TcpListener, shutdown: CancellationToken, stats: Arc<ServerStats>, verbose: bool) {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, peer)) = accepted else { continue };
+                let open = stats.open.fetch_add(1, Ordering::Relaxed) + 1;
+                stats.peak.fetch_max(open, Ordering::Relaxed);
+                stats.accepted.fetch_add(1, Ordering::Relaxed);
+                if verbose {
+                    common::print_info(&format!("  {} connected, {} open", peer, open));
+                }
+
+                let stats = Arc::clone(&stats);
+                connections.spawn(async move {
+                    if let Err(error) = handle(stream, Arc::clone(&stats)).await {
+                        common::print_warning(&format!("  {}: {}", peer, error));
+                    }
+                    let open = stats.open.fetch_sub(1, Ordering::Relaxed) - 1;
+                    if verbose {
+                        common::print_info(&format!("  {} disconnected, {} open", peer, open));
+                    }
+                });
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+    connections.shutdown().await;
+}
+
+/// One client connection: send `LINES_PER_CONNECTION` lines, `pause` apart, and time each echo
+async fn client(id: usize, address: String, pause: Duration) -> io::Result<(Vec<Duration>, usize)> {
+    let stream = TcpStream::connect(&address).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut replies = BufReader::new(reader).lines();
+    let (mut round_trips, mut mismatches) = (vec![], 0);
+
+    for line in 0..LINES_PER_CONNECTION {
+        let message = format!("client {} line {}", id, line);
+        let start = Instant::now();
+        writer.write_all(format!("{}\n", message).as_bytes()).await?;
+        match replies.next_line().await? {
+            Some(reply) if reply == message => round_trips.push(start.elapsed()),
+            _ => mismatches += 1,
+        }
+        sleep(pause).await;
+    }
+    Ok((round_trips, mismatches))
+}
+
+/// Open `connections` client connections to `address` at once and report their echoes
+async fn run_clients(address: &str, connections: usize, pause: Duration) {
+    common::print_info(&format!(
+        "Opening {} connections to {}, each sending {} lines {:?} apart",
+        connections, address, LINES_PER_CONNECTION, pause
+    ));
+    let start = Instant::now();
+    let mut set = JoinSet::new();
+    for id in 0..connections {
+        set.spawn(client(id, address.to_string(), pause));
+    }
+
+    let (mut round_trips, mut mismatches, mut failed) = (vec![], 0, 0);
+    while let Some(result) = set.join_next().await {
+        match result.unwrap() {
+            Ok((times, wrong)) => {
+                round_trips.extend(times);
+                mismatches += wrong;
+            }
+            Err(error) => {
+                failed += 1;
+                if failed == 1 {
+                    common::print_warning(&format!("  connection failed: {}", error));
+                }
+            }
+        }
+    }
+    round_trips.sort_unstable();
+
+    println!();
+    println!(
+        "{:>12} {:>8} {:>11} {:>12} {:>12} {:>12} {:>12}",
+        "connections", "failed", "echoes", "p50 RTT", "p99 RTT", "max RTT", "total"
+    );
+    println!(
+        "{:>12} {:>8} {:>11} {:>12?} {:>12?} {:>12?} {:>12?}",
+        connections,
+        failed,
+        round_trips.len(),
+        common::percentile(&round_trips, 50.0),
+        common::percentile(&round_trips, 99.0),
+        round_trips.last().copied().unwrap_or_default(),
+        start.elapsed()
+    );
+    println!();
+    if failed == 0 && mismatches == 0 {
+        common::print_success("Every line came back unchanged on its own connection");
+    } else if mismatches > 0 {
+        common::print_warning(&format!("{} echoes did not match the line sent", mismatches));
+    }
+}
+
+/// Run the echo example in `role` on `port`: `connections` client connections sending a line every `pause_ms`
+pub fn run(role: EchoRole, port: u16, connections: usize, pause_ms: u64) {
+    let connections = connections.max(1);
+    let pause = Duration::from_millis(pause_ms);
+    let address = format!("127.0.0.1:{}", port);
+    if virtual_time::is_enabled() {
+        common::print_warning("Real sockets need real time, so the echo example ignores --virtual-time");
+    }
+
+    let runtime = cpus::multi_thread_runtime();
+    runtime.block_on(async {
+        if let EchoRole::Client = role {
+            run_clients(&address, connections, pause).await;
+            return;
+        }
+
+        let listener = match TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(error) => {
+                common::print_error(&format!("Could not bind {}: {}", address, error));
+                return;
+            }
+        };
+        let stats = Arc::new(ServerStats::default());
+        let shutdown = CancellationToken::new();
+        let verbose = matches!(role, EchoRole::Server);
+        let server = tokio::spawn(server(listener, shutdown.clone(), Arc::clone(&stats), verbose));
+
+        match role {
+            EchoRole::Server => {
+                common::print_info(&format!(
+                    "Echoing lines on {}; connect with `nc 127.0.0.1 {}` or `--echo-role client`, stop with Ctrl-C",
+                    address, port
+                ));
+                let _ = tokio::signal::ctrl_c().await;
+                common::print_warning("Ctrl-C received: closing the server");
+            }
+            _ => {
+                common::print_info(&format!("Echo server listening on {}", address));
+                run_clients(&address, connections, pause).await;
+            }
+        }
+        shutdown.cancel();
+        server.await.unwrap();
+
+        common::print_info(&format!(
+            "Server: {} connections accepted, at most {} open at once, {} lines echoed",
+            stats.accepted.load(Ordering::Relaxed),
+            stats.peak.load(Ordering::Relaxed),
+            stats.lines.load(Ordering::Relaxed)
+        ));
+    });
+    audit::runtime("tcp-echo", &runtime);
+    runtime_metrics::record("tcp-echo", &runtime);
+}
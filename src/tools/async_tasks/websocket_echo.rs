@@ -0,0 +1,263 @@
+//! WebSocket echo server and concurrent clients
+//!
+//! A WebSocket is a long-lived connection on which both sides send whenever
+//! they want. The server here accepts connections with `tokio-tungstenite`
+//! and gives each one a task that echoes every message back while it also
+//! pushes a tick of its own now and then, unprompted. Each client splits
+//! its socket into a sending and a receiving half and drives both at once:
+//! the writer sends timestamped messages, the reader takes the echoes and
+//! the pushes as they come, interleaved in any order. The timestamp travels
+//! to the server and back, so the reader times every round trip without
+//! sharing any state with the writer. The client then closes the connection
+//! with a close handshake.
+
+// Base dependencies
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Third-party dependencies
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{interval, sleep, Duration, Instant};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{accept_async, client_async};
+use tokio_util::sync::CancellationToken;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::histogram::Histogram;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Messages each client sends
+const MESSAGES_PER_CLIENT: usize = 20;
+
+/// Connection counts kept by the server
+#[derive(Default)]
+struct ServerStats {
+    open: AtomicUsize,
+    peak: AtomicUsize,
+    echoed: AtomicUsize,
+    pushed: AtomicUsize,
+}
+
+/// Serve one connection: echo every message back, and push a tick every `push_every`, until the client closes it
+async fn handle(stream: TcpStream, push_every: Duration, stats: &ServerStats) -> Result<(), WsError> {
+    // Small frames sent one by one: without TCP_NODELAY, Nagle's algorithm holds each one back waiting for an ACK
+    stream.set_nodelay(true)?;
+    let mut socket = accept_async(stream).await?;
+    let mut pushes = interval(push_every);
+    pushes.tick().await;
+    let (mut tick, mut closing) = (0, false);
+
+    loop {
+        tokio::select! {
+            message = socket.next() => match message {
+                Some(Ok(message)) if message.is_text() || message.is_binary() => {
+                    chaos::perturb_async(Point::TaskStart).await;
+                    socket.send(message).await?;
+                    stats.echoed.fetch_add(1, Ordering::Relaxed);
+                }
+                // tungstenite queues the close reply itself; the next read sends it and ends the stream
+                Some(Ok(Message::Close(_))) => closing = true,
+                // Pings are answered by tungstenite too
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(error),
+                None => return Ok(()),
+            },
+            _ = pushes.tick(), if !closing => {
+                socket.send(Message::text(format!("tick {}", tick))).await?;
+                stats.pushed.fetch_add(1, Ordering::Relaxed);
+                tick += 1;
+            }
+        }
+    }
+}
+
+/// Accept connections on `listener` until `shutdown`, each one served by its own task
+async fn server(listener: TcpListener, push_every: Duration, shutdown: CancellationToken, stats: Arc<ServerStats>) {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, peer)) = accepted else { continue };
+                let open = stats.open.fetch_add(1, Ordering::Relaxed) + 1;
+                stats.peak.fetch_max(open, Ordering::Relaxed);
+
+                let stats = Arc::clone(&stats);
+                connections.spawn(async move {
+                    if let Err(error) = handle(stream, push_every, &stats).await {
+                        common::print_warning(&format!("  {}: {}", peer, error));
+                    }
+                    stats.open.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+    connections.shutdown().await;
+}
+
+/// What one client saw on its connection
+#[derive(Default)]
+struct ClientReport {
+    round_trips: Histogram,
+    mismatches: usize,
+    pushes: usize,
+    connected: Duration,
+}
+
+/// One client: send `MESSAGES_PER_CLIENT` messages `pause` apart while reading echoes and pushes, then close
+async fn client(id: usize, address: SocketAddr, pause: Duration) -> Result<ClientReport, WsError> {
+    let start = Instant::now();
+    let stream = TcpStream::connect(address).await?;
+    stream.set_nodelay(true)?;
+    let (socket, _response) = client_async(format!("ws://{}/echo", address), stream).await?;
+    let (mut outgoing, mut incoming) = socket.split();
+
+    // Each message carries the time it was sent, relative to `start`, and comes back with it
+    let writer = async {
+        for sequence in 0..MESSAGES_PER_CLIENT {
+            let sent = start.elapsed().as_nanos();
+            outgoing.send(Message::text(format!("{} {} {}", id, sequence, sent))).await?;
+            sleep(pause).await;
+        }
+        Ok::<_, WsError>(())
+    };
+    let reader = async {
+        let mut report = ClientReport::default();
+        while (report.round_trips.count() as usize) + report.mismatches < MESSAGES_PER_CLIENT {
+            let Some(message) = incoming.next().await else { break };
+            let Message::Text(text) = message? else { continue };
+            if text.starts_with("tick") {
+                report.pushes += 1;
+                continue;
+            }
+            let fields: Vec<&str> = text.split(' ').collect();
+            match (fields.as_slice(), fields.last().and_then(|sent| sent.parse::<u64>().ok())) {
+                ([owner, _, _], Some(sent)) if *owner == id.to_string() => {
+                    report.round_trips.record(start.elapsed().saturating_sub(Duration::from_nanos(sent)));
+                }
+                _ => report.mismatches += 1,
+            }
+        }
+        Ok::<_, WsError>(report)
+    };
+    let ((), mut report) = tokio::try_join!(writer, reader)?;
+
+    // Close handshake: pushes may still arrive until the server's close reply does
+    outgoing.send(Message::Close(None)).await?;
+    while let Some(message) = incoming.next().await {
+        if matches!(message?, Message::Text(_)) {
+            report.pushes += 1;
+        }
+    }
+    report.connected = start.elapsed();
+    Ok(report)
+}
+
+/// Run the WebSocket example: `num_clients` concurrent clients, each sending a message every `pause_ms`
+pub fn run(num_clients: usize, pause_ms: u64) {
+    let num_clients = num_clients.max(1);
+    let pause = Duration::from_millis(pause_ms);
+    let push_every = Duration::from_millis(pause_ms.max(1) * 3);
+    if virtual_time::is_enabled() {
+        common::print_warning("Real sockets need real time, so the WebSocket example ignores --virtual-time");
+    }
+
+    let runtime = cpus::multi_thread_runtime();
+    let (reports, failed, stats, elapsed) = runtime.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let stats = Arc::new(ServerStats::default());
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(server(listener, push_every, shutdown.clone(), Arc::clone(&stats)));
+        common::print_info(&format!(
+            "WebSocket echo server on ws://{}, pushing a tick every {:?} on each connection",
+            address, push_every
+        ));
+        common::print_info(&format!(
+            "{} clients connect at once and send {} messages each, {:?} apart, while reading echoes and pushes",
+            num_clients, MESSAGES_PER_CLIENT, pause
+        ));
+
+        let start = Instant::now();
+        let mut clients = JoinSet::new();
+        for id in 0..num_clients {
+            clients.spawn(client(id, address, pause));
+        }
+        let (mut reports, mut failed) = (vec![], 0);
+        while let Some(result) = clients.join_next().await {
+            match result.unwrap() {
+                Ok(report) => reports.push(report),
+                Err(error) => {
+                    failed += 1;
+                    if failed == 1 {
+                        common::print_warning(&format!("  client failed: {}", error));
+                    }
+                }
+            }
+        }
+        let elapsed = start.elapsed();
+
+        shutdown.cancel();
+        server.await.unwrap();
+        (reports, failed, stats, elapsed)
+    });
+    audit::runtime("websocket-echo", &runtime);
+    runtime_metrics::record("websocket-echo", &runtime);
+
+    let mut round_trips = Histogram::new();
+    for report in &reports {
+        round_trips.merge(&report.round_trips);
+    }
+    let mismatches: usize = reports.iter().map(|report| report.mismatches).sum();
+    let pushes: usize = reports.iter().map(|report| report.pushes).sum();
+    let mut connected: Vec<Duration> = reports.iter().map(|report| report.connected).collect();
+    connected.sort_unstable();
+
+    println!();
+    println!(
+        "{:>8} {:>8} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "clients", "failed", "echoes", "mean RTT", "p50 RTT", "p99 RTT", "max RTT", "total"
+    );
+    println!(
+        "{:>8} {:>8} {:>8} {:>12?} {:>12?} {:>12?} {:>12?} {:>12?}",
+        num_clients,
+        failed,
+        round_trips.count(),
+        round_trips.mean(),
+        round_trips.percentile(50.0),
+        round_trips.percentile(99.0),
+        round_trips.max(),
+        elapsed
+    );
+    println!();
+    println!("Round-trip times:");
+    round_trips.print();
+
+    println!();
+    common::print_info(&format!(
+        "Server: at most {} connections open at once, {} messages echoed, {} ticks pushed",
+        stats.peak.load(Ordering::Relaxed),
+        stats.echoed.load(Ordering::Relaxed),
+        stats.pushed.load(Ordering::Relaxed)
+    ));
+    common::print_info(&format!(
+        "Clients received {} pushes between their echoes, and stayed connected {:?} at the median",
+        pushes,
+        common::percentile(&connected, 50.0)
+    ));
+    if failed == 0 && mismatches == 0 {
+        common::print_success("Every message came back to the client that sent it, on a connection used both ways at once");
+    } else if mismatches > 0 {
+        common::print_warning(&format!("{} echoes did not match a message of their client", mismatches));
+    }
+    common::print_info("Percentiles come from power-of-two buckets: each is the upper bound of its bucket");
+}
@@ -0,0 +1,233 @@
+//! An async worker pool on a bounded queue
+//!
+//! The async counterpart of the thread pool: a fixed set of worker tasks
+//! takes jobs from one shared channel, whichever is free first. Two things
+//! change with async. The queue is a bounded `tokio::sync::mpsc` channel, so
+//! a submitter facing a full queue awaits a free slot instead of piling up
+//! jobs without limit, and that wait is the backpressure. And shutting down
+//! is a matter of closing the queue: every worker finishes the jobs already
+//! queued, sees the channel closed and returns its statistics.
+
+// Base dependencies
+use std::future::Future;
+use std::sync::Arc;
+
+// Third-party dependencies
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep, Duration, Instant};
+
+// Project dependencies
+use super::code::task_delay;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::runtime_metrics;
+use crate::virtual_time;
+
+/// Workers when `--max-in-flight` is not given
+const DEFAULT_WORKERS: usize = 4;
+
+/// Jobs the queue holds before submitters have to wait
+const QUEUE_CAPACITY: usize = 8;
+
+/// Tasks submitting jobs to the pool at the same time
+const SUBMITTERS: usize = 3;
+
+/// A job in the queue, with the time it was queued at
+struct Job {
+    queued_at: Instant,
+    work: BoxFuture<'static, ()>,
+}
+
+/// What one worker did until the queue closed
+pub struct WorkerStats {
+    pub jobs: usize,
+    pub busy: Duration,
+    /// Time each of its jobs spent in the queue before it took them
+    pub queue_waits: Vec<Duration>,
+}
+
+/// A fixed set of worker tasks consuming jobs from a bounded queue
+pub struct AsyncPool {
+    submitter: Submitter,
+    workers: Vec<JoinHandle<WorkerStats>>,
+}
+
+impl AsyncPool {
+    /// Spawn `size` workers sharing a queue of `capacity` jobs; must be called inside a runtime
+    pub fn new(size: usize, capacity: usize) -> AsyncPool {
+        assert!(size > 0);
+
+        // The workers share the receiving end, as the thread pool's workers do
+        let (sender, receiver) = mpsc::channel::<Job>(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        audit::track("async pool receiver", &receiver);
+
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                tokio::spawn(async move {
+                    let mut stats = WorkerStats { jobs: 0, busy: Duration::ZERO, queue_waits: vec![] };
+                    loop {
+                        // The lock is only held while waiting for the next job, never while running one
+                        let job = receiver.lock().await.recv().await;
+                        let Some(job) = job else { break };
+                        let start = Instant::now();
+                        stats.queue_waits.push(start - job.queued_at);
+                        chaos::perturb_async(Point::TaskStart).await;
+                        job.work.await;
+                        stats.busy += start.elapsed();
+                        stats.jobs += 1;
+                    }
+                    stats
+                })
+            })
+            .collect();
+
+        AsyncPool { submitter: Submitter { sender }, workers }
+    }
+
+    /// Queue a job, waiting for a free slot while the queue is full; returns how long it waited
+    pub async fn submit<F>(&self, work: F) -> Duration
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.submitter.submit(work).await
+    }
+
+    /// A handle to queue jobs from other tasks
+    pub fn submitter(&self) -> Submitter {
+        self.submitter.clone()
+    }
+
+    /// Jobs waiting in the queue right now
+    pub fn queued(&self) -> usize {
+        let sender = &self.submitter.sender;
+        sender.max_capacity() - sender.capacity()
+    }
+
+    /// Close the queue, let the workers drain the jobs left in it, and return their statistics
+    ///
+    /// Submitters still holding a `Submitter` keep the queue open, so they must be done first.
+    pub async fn shutdown(self) -> Vec<WorkerStats> {
+        drop(self.submitter);
+        let mut stats = Vec::with_capacity(self.workers.len());
+        for worker in self.workers {
+            stats.push(worker.await.unwrap());
+        }
+        stats
+    }
+}
+
+/// A cloneable handle to the queue of an `AsyncPool`
+#[derive(Clone)]
+pub struct Submitter {
+    sender: mpsc::Sender<Job>,
+}
+
+impl Submitter {
+    /// Queue a job, waiting for a free slot while the queue is full; returns how long it waited
+    pub async fn submit<F>(&self, work: F) -> Duration
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let start = Instant::now();
+        let job = Job { queued_at: start, work: work.boxed() };
+        match self.sender.try_send(job) {
+            Ok(()) => Duration::ZERO,
+            Err(TrySendError::Full(job)) => {
+                // The backpressure: no slot until a worker takes a job off the queue
+                if self.sender.send(job).await.is_err() {
+                    unreachable!("the workers keep the queue open until shutdown");
+                }
+                start.elapsed()
+            }
+            Err(TrySendError::Closed(_)) => unreachable!("the workers keep the queue open until shutdown"),
+        }
+    }
+}
+
+/// Run the async worker pool example: `num_jobs` jobs of about `delay_ms` on `workers` workers
+pub fn run(num_jobs: usize, delay_ms: u64, workers: Option<usize>) {
+    let workers = workers.unwrap_or(DEFAULT_WORKERS).max(1);
+    common::print_info(&format!(
+        "{} submitters push {} jobs of ~{}ms into a queue of {} slots, drained by {} worker tasks",
+        SUBMITTERS, num_jobs, delay_ms, QUEUE_CAPACITY, workers
+    ));
+
+    let runtime = virtual_time::runtime();
+    let (stats, submit_waits, queued_at_shutdown, elapsed) = runtime.block_on(async {
+        let start = Instant::now();
+        let pool = AsyncPool::new(workers, QUEUE_CAPACITY);
+
+        // Each submitter awaits its turn on a full queue, and times every wait
+        let mut submitters = JoinSet::new();
+        for first in 0..SUBMITTERS {
+            let submitter = pool.submitter();
+            submitters.spawn(async move {
+                let mut waits = vec![];
+                for id in (first..num_jobs).step_by(SUBMITTERS) {
+                    let delay = Duration::from_millis(task_delay(id, delay_ms));
+                    // sleep() fixes its deadline when called, so the job calls it once a worker runs it
+                    waits.push(submitter.submit(async move { sleep(delay).await }).await);
+                }
+                waits
+            });
+        }
+        let mut submit_waits = vec![];
+        while let Some(result) = submitters.join_next().await {
+            submit_waits.extend(result.unwrap());
+        }
+
+        // Every job is queued or running: closing the queue now must still run them all
+        let queued_at_shutdown = pool.queued();
+        let stats = pool.shutdown().await;
+        (stats, submit_waits, queued_at_shutdown, start.elapsed())
+    });
+    audit::runtime("worker-pool", &runtime);
+    runtime_metrics::record("worker-pool", &runtime);
+
+    println!();
+    println!("{:>8} {:>6} {:>14} {:>12} {:>14} {:>14}", "worker", "jobs", "busy", "utilization", "p50 queued", "max queued");
+    for (id, worker) in stats.iter().enumerate() {
+        let mut waits = worker.queue_waits.clone();
+        waits.sort_unstable();
+        println!(
+            "{:>8} {:>6} {:>14?} {:>11.0}% {:>14?} {:>14?}",
+            id,
+            worker.jobs,
+            worker.busy,
+            100.0 * worker.busy.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON),
+            common::percentile(&waits, 50.0),
+            waits.last().copied().unwrap_or_default()
+        );
+    }
+
+    let mut submit_waits = submit_waits;
+    submit_waits.sort_unstable();
+    let blocked = submit_waits.iter().filter(|wait| **wait > Duration::ZERO).count();
+    let done: usize = stats.iter().map(|worker| worker.jobs).sum();
+    println!();
+    common::print_info(&format!(
+        "Backpressure: {} of {} submissions waited for a slot, p50 {:?}, p99 {:?}, max {:?}",
+        blocked,
+        submit_waits.len(),
+        common::percentile(&submit_waits, 50.0),
+        common::percentile(&submit_waits, 99.0),
+        submit_waits.last().copied().unwrap_or_default()
+    ));
+    common::print_info(&format!("Total time: {:?}", elapsed));
+    if done == num_jobs {
+        common::print_success(&format!(
+            "Shutdown drained the queue: {} jobs were still queued when it closed, and all {} jobs ran",
+            queued_at_shutdown, done
+        ));
+    } else {
+        common::print_warning(&format!("Only {} of {} jobs ran before the workers stopped", done, num_jobs));
+    }
+    common::print_info("Like the thread pool, free workers race for the next job; unlike it, a full queue makes submitters wait instead of growing");
+}
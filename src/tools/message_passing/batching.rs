@@ -0,0 +1,205 @@
+//! Batching consumer with throughput/latency tradeoff
+//!
+//! A single consumer pays a fixed cost per wakeup (think of a syscall, a
+//! disk flush or a network round trip) plus a small cost per message.
+//! Draining several messages per wakeup amortizes the fixed cost, raising
+//! throughput, at the price of messages waiting for their batch to fill.
+
+// Base dependencies
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::channel::{self, Receiver};
+
+// Project dependencies
+use crate::common;
+use crate::metrics;
+use crate::pacing::{self, Pacer};
+
+/// Fixed cost paid by the consumer every time it processes a batch
+const BATCH_OVERHEAD: Duration = Duration::from_micros(200);
+
+/// Cost paid by the consumer for every message inside a batch
+const PER_MESSAGE_COST: Duration = Duration::from_micros(10);
+
+/// Interval between two messages of the same producer
+const SEND_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Upper bound on messages drained per wakeup in time-window mode
+const MAX_WINDOW_BATCH: usize = 1024;
+
+/// How the consumer decides when a batch is complete
+#[derive(Clone, Copy)]
+enum BatchPolicy {
+    /// Drain up to N messages already waiting in the channel
+    Size(usize),
+    /// Keep collecting messages until the window elapses
+    Window(Duration),
+}
+
+impl BatchPolicy {
+    fn label(&self) -> String {
+        match self {
+            BatchPolicy::Size(1) => "one-at-a-time".to_string(),
+            BatchPolicy::Size(n) => format!("size <= {}", n),
+            BatchPolicy::Window(window) => format!("window {:?}", window),
+        }
+    }
+}
+
+/// Summary of a single consumer run
+struct BatchReport {
+    batches: usize,
+    messages: usize,
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+/// Collect the next batch according to the policy, blocking for its first message
+fn next_batch(rx: &Receiver<Instant>, policy: BatchPolicy) -> Vec<Instant> {
+    let mut batch = vec![];
+
+    // Block until at least one message is available (or every sender is gone)
+    match rx.recv() {
+        Ok(sent_at) => batch.push(sent_at),
+        Err(_) => return batch,
+    }
+
+    match policy {
+        BatchPolicy::Size(max) => {
+            while batch.len() < max {
+                match rx.try_recv() {
+                    Ok(sent_at) => batch.push(sent_at),
+                    Err(_) => break,
+                }
+            }
+        }
+        BatchPolicy::Window(window) => {
+            let deadline = Instant::now() + window;
+            while batch.len() < MAX_WINDOW_BATCH {
+                match rx.recv_deadline(deadline) {
+                    Ok(sent_at) => batch.push(sent_at),
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    batch
+}
+
+/// Run producers against a batching consumer using the given policy
+fn run_policy(policy: BatchPolicy, num_senders: usize, messages_per_sender: usize) -> BatchReport {
+    let (tx, rx) = channel::unbounded::<Instant>();
+    let backlog = metrics::gauge(&format!("batching {}", policy.label()));
+    let start = Instant::now();
+
+    // Producers send a timestamp at a steady pace
+    let mut handles = vec![];
+    for _ in 0..num_senders {
+        let tx_clone = tx.clone();
+        let backlog = backlog.clone();
+        let handle = thread::spawn(move || {
+            // thread::sleep is too coarse for this interval, the pacer is not
+            let mut pacer = Pacer::new(SEND_INTERVAL);
+            for _ in 0..messages_per_sender {
+                backlog.add(1);
+                tx_clone.send(Instant::now()).unwrap();
+                pacer.tick();
+            }
+        });
+        handles.push(handle);
+    }
+    drop(tx);
+
+    // The consumer processes batches until every producer has finished
+    let consumer = thread::spawn(move || {
+        let mut batches = 0;
+        let mut latencies = vec![];
+
+        loop {
+            let batch = next_batch(&rx, policy);
+            if batch.is_empty() {
+                break;
+            }
+            backlog.sub(batch.len());
+
+            // Pay the fixed cost once and the per-message cost for every item
+            pacing::spin_for(BATCH_OVERHEAD + PER_MESSAGE_COST * batch.len() as u32);
+
+            let processed_at = Instant::now();
+            latencies.extend(batch.iter().map(|sent_at| processed_at - *sent_at));
+            batches += 1;
+        }
+
+        (batches, latencies)
+    });
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let (batches, mut latencies) = consumer.join().unwrap();
+    latencies.sort_unstable();
+
+    BatchReport {
+        batches,
+        messages: latencies.len(),
+        elapsed: start.elapsed(),
+        latencies,
+    }
+}
+
+/// Run the batching consumer example for several batching policies
+pub fn run(num_senders: usize, messages_per_sender: usize) {
+    common::print_info(&format!(
+        "{} producers send {} messages each, one every {:?}",
+        num_senders, messages_per_sender, SEND_INTERVAL
+    ));
+    common::print_info(&format!(
+        "Consumer cost: {:?} per batch + {:?} per message",
+        BATCH_OVERHEAD, PER_MESSAGE_COST
+    ));
+
+    let policies = [
+        BatchPolicy::Size(1),
+        BatchPolicy::Size(8),
+        BatchPolicy::Size(32),
+        BatchPolicy::Size(128),
+        BatchPolicy::Window(Duration::from_millis(1)),
+        BatchPolicy::Window(Duration::from_millis(5)),
+    ];
+
+    println!();
+    println!(
+        "{:<16} {:>8} {:>10} {:>14} {:>12} {:>12}",
+        "policy", "batches", "avg batch", "throughput/s", "p50", "p99"
+    );
+
+    let expected = num_senders * messages_per_sender;
+    let mut incomplete = vec![];
+    for policy in policies {
+        let report = run_policy(policy, num_senders, messages_per_sender);
+        if report.messages != expected {
+            incomplete.push(format!("{} ({}/{})", policy.label(), report.messages, expected));
+        }
+        println!(
+            "{:<16} {:>8} {:>10.1} {:>14.0} {:>12?} {:>12?}",
+            policy.label(),
+            report.batches,
+            report.messages as f64 / report.batches.max(1) as f64,
+            report.messages as f64 / report.elapsed.as_secs_f64(),
+            common::percentile(&report.latencies, 50.0),
+            common::percentile(&report.latencies, 99.0),
+        );
+    }
+
+    println!();
+    if incomplete.is_empty() {
+        common::print_success("All batching policies processed every message");
+    } else {
+        common::print_warning(&format!("Messages missing for: {}", incomplete.join(", ")));
+    }
+    common::print_info("One-at-a-time pays the fixed cost per message and falls behind, so queueing inflates latency");
+    common::print_info("Size-capped batches adapt to the backlog; time windows add up to one window of latency per message");
+}
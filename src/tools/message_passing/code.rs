@@ -8,21 +8,21 @@
 */
 
 // Base dependencies
-use std::sync::mpsc;
 use std::{thread, thread::JoinHandle};
 use std::time::Duration;
 
 // Third-party dependencies
-use crossbeam::channel;
+use crossbeam::channel::{self, after, select};
 
 // Project dependencies
 use crate::common;
+use crate::traced;
 
 /// Example using standard library mpsc channels
 fn run_mpsc(num_senders: usize, messages_per_sender: usize) {
 
-    // Instantiate a channel for communication between threads
-    let (tx, rx) = mpsc::channel();
+    // Instantiate a channel for communication between threads (a std mpsc channel, wrapped to measure it)
+    let (tx, rx) = traced::std_channel::<String>("mpsc");
 
     // Vector to hold the sender thread handles
     let mut handles: Vec<JoinHandle<()>> = vec![];
@@ -53,7 +53,7 @@ fn run_mpsc(num_senders: usize, messages_per_sender: usize) {
     // Spawn receiver thread
     let receiver_handle = thread::spawn(move || {
         let mut count = 0;
-        for received in rx {
+        while let Ok(received) = rx.recv() {
             println!("📨 Received: {}", received);
             count += 1;
         }
@@ -71,7 +71,7 @@ fn run_mpsc(num_senders: usize, messages_per_sender: usize) {
 
 /// Example using crossbeam channels (supports multiple consumers)
 fn run_crossbeam(num_senders: usize, messages_per_sender: usize) {
-    let (tx, rx) = channel::unbounded();
+    let (tx, rx) = traced::crossbeam_unbounded::<String>("crossbeam");
     let mut handles = vec![];
 
     // Multiple senders
@@ -120,6 +120,75 @@ fn run_crossbeam(num_senders: usize, messages_per_sender: usize) {
     }
 }
 
+/// Spawn crossbeam senders that pace their messages, returning the receiver
+fn spawn_paced_senders(num_senders: usize, messages_per_sender: usize) -> (channel::Receiver<String>, Vec<JoinHandle<()>>) {
+    let (tx, rx) = channel::unbounded();
+    let mut handles = vec![];
+
+    for sender_id in 0..num_senders {
+        let tx_clone = tx.clone();
+        let handle = thread::spawn(move || {
+            for msg_num in 0..messages_per_sender {
+                tx_clone.send(format!("Select message {} from sender {}", msg_num, sender_id)).unwrap();
+                thread::sleep(Duration::from_millis(30));
+            }
+        });
+        handles.push(handle);
+    }
+
+    (rx, handles)
+}
+
+/// Example using crossbeam select! with `default` (polling) and `after` (timed wait) arms
+fn run_crossbeam_select(num_senders: usize, messages_per_sender: usize) {
+
+    // Non-blocking polling: the default arm fires whenever no message is ready
+    let (rx, handles) = spawn_paced_senders(num_senders, messages_per_sender);
+    let mut received = 0;
+    let mut idle_polls = 0;
+    loop {
+        select! {
+            recv(rx) -> message => match message {
+                Ok(_) => received += 1,
+                Err(_) => break,
+            },
+            default => {
+                idle_polls += 1;
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    common::print_success(&format!(
+        "Polling with default: recv arm fired {} times, default arm fired {} times",
+        received, idle_polls
+    ));
+
+    // Timed wait: block for a message, but give up on each wait after a timeout
+    let timeout = Duration::from_millis(20);
+    let (rx, handles) = spawn_paced_senders(num_senders, messages_per_sender);
+    let mut received = 0;
+    let mut timeouts = 0;
+    loop {
+        select! {
+            recv(rx) -> message => match message {
+                Ok(_) => received += 1,
+                Err(_) => break,
+            },
+            recv(after(timeout)) -> _ => timeouts += 1,
+        }
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    common::print_success(&format!(
+        "Timed wait with after({:?}): recv arm fired {} times, timeout arm fired {} times",
+        timeout, received, timeouts
+    ));
+}
+
 /// Run the message passing example with standard library channels
 pub fn run(num_senders: usize, messages_per_sender: usize) {
     common::print_info("Running standard library mpsc channel example");
@@ -129,5 +198,10 @@ pub fn run(num_senders: usize, messages_per_sender: usize) {
     
     common::print_info("Running crossbeam channel example");
     run_crossbeam(num_senders, messages_per_sender);
+
+    println!();
+
+    common::print_info("Running crossbeam select! example with default and timeout arms");
+    run_crossbeam_select(num_senders, messages_per_sender);
 }
 
@@ -0,0 +1,158 @@
+//! Kafka-like consumer groups over crossbeam channels
+//!
+//! Every consumer group receives the whole stream, but inside a group each
+//! message goes to exactly one consumer, spreading the load. A broker thread
+//! fans every published message out to one MPMC channel per group, and the
+//! consumers of a group compete on that channel.
+
+// Base dependencies
+use std::thread;
+use std::time::Duration;
+
+// Third-party dependencies
+use crossbeam::channel::{self, Sender};
+
+// Project dependencies
+use crate::common;
+use crate::sink;
+
+/// Consumer groups subscribed to the stream: (name, number of consumers)
+const GROUPS: [(&str, usize); 3] = [("analytics", 3), ("billing", 2), ("audit", 1)];
+
+/// Time a consumer spends on each message, so the load has to be shared
+const PROCESSING_TIME: Duration = Duration::from_millis(2);
+
+/// A published record: producer and sequence number within that producer
+#[derive(Clone, Copy)]
+struct Record {
+    producer: usize,
+    offset: usize,
+}
+
+/// Delivery results for one group, checked against the published stream
+struct GroupReport {
+    name: &'static str,
+    per_consumer: Vec<usize>,
+    duplicates: usize,
+    missing: usize,
+}
+
+/// Run the consumer groups example
+pub fn run(num_producers: usize, messages_per_producer: usize) {
+    let total = num_producers * messages_per_producer;
+    common::print_info(&format!(
+        "{} producers publish {} records each to {} consumer groups",
+        num_producers,
+        messages_per_producer,
+        GROUPS.len()
+    ));
+
+    // One MPMC channel per group: consumers of the same group compete for its records
+    let mut group_senders: Vec<Sender<Record>> = vec![];
+    let mut consumers = vec![];
+    for (group_id, (name, size)) in GROUPS.iter().enumerate() {
+        let (tx, rx) = channel::unbounded::<Record>();
+        group_senders.push(tx);
+
+        for consumer_id in 0..*size {
+            let rx = rx.clone();
+            let handle = thread::spawn(move || {
+                let mut received = vec![];
+                for record in rx {
+                    thread::sleep(PROCESSING_TIME);
+                    sink::write(
+                        "consumer-groups",
+                        format!("group={} consumer={} producer={} offset={}", name, consumer_id, record.producer, record.offset),
+                    );
+                    received.push(record);
+                }
+                common::print_info(&format!(
+                    "{} consumer {} processed {} records",
+                    name,
+                    consumer_id,
+                    received.len()
+                ));
+                (group_id, received)
+            });
+            consumers.push(handle);
+        }
+    }
+
+    // The broker delivers a copy of every published record to each group
+    let (publish, inbox) = channel::unbounded::<Record>();
+    let broker = thread::spawn(move || {
+        for record in inbox {
+            for group in &group_senders {
+                group.send(record).unwrap();
+            }
+        }
+    });
+
+    let mut producers = vec![];
+    for producer in 0..num_producers {
+        let publish = publish.clone();
+        let handle = thread::spawn(move || {
+            for offset in 0..messages_per_producer {
+                publish.send(Record { producer, offset }).unwrap();
+            }
+        });
+        producers.push(handle);
+    }
+    drop(publish);
+
+    for handle in producers {
+        handle.join().unwrap();
+    }
+    broker.join().unwrap();
+
+    // Count how often each record was delivered inside each group
+    let mut reports: Vec<GroupReport> = GROUPS
+        .iter()
+        .map(|(name, _)| GroupReport {
+            name,
+            per_consumer: vec![],
+            duplicates: 0,
+            missing: 0,
+        })
+        .collect();
+    let mut deliveries = vec![vec![0usize; total]; GROUPS.len()];
+    for handle in consumers {
+        let (group_id, received) = handle.join().unwrap();
+        reports[group_id].per_consumer.push(received.len());
+        for record in received {
+            deliveries[group_id][record.producer * messages_per_producer + record.offset] += 1;
+        }
+    }
+    for (report, counts) in reports.iter_mut().zip(&deliveries) {
+        report.duplicates = counts.iter().filter(|count| **count > 1).count();
+        report.missing = counts.iter().filter(|count| **count == 0).count();
+    }
+
+    println!();
+    println!(
+        "{:<12} {:>10} {:>10} {:>11} {:>8}   {:<}",
+        "group", "consumers", "delivered", "duplicates", "missing", "per consumer"
+    );
+    let mut exactly_once = true;
+    for report in &reports {
+        let split: Vec<String> = report.per_consumer.iter().map(|count| count.to_string()).collect();
+        println!(
+            "{:<12} {:>10} {:>10} {:>11} {:>8}   {}",
+            report.name,
+            report.per_consumer.len(),
+            report.per_consumer.iter().sum::<usize>(),
+            report.duplicates,
+            report.missing,
+            split.join(" / ")
+        );
+        exactly_once &= report.duplicates == 0 && report.missing == 0;
+    }
+
+    println!();
+    if exactly_once {
+        common::print_success(&format!("Every group received all {} records exactly once", total));
+    } else {
+        common::print_warning("Some group saw a record twice or never!");
+    }
+    common::print_info("Groups are independent subscribers; consumers inside a group share the work of a single subscription");
+}
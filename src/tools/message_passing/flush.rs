@@ -0,0 +1,140 @@
+//! Periodic flushing of accumulated messages with a timer channel
+//!
+//! Writing every message as soon as it arrives is expensive, but holding
+//! messages until a buffer fills up can delay them forever on a quiet
+//! channel. Combining the data channel with `channel::tick` in a `select!`
+//! loop flushes whatever has accumulated at a regular interval, while a
+//! size cap still flushes early under heavy load.
+
+// Base dependencies
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::channel::{self, select};
+use rand::Rng;
+
+// Project dependencies
+use crate::common;
+use crate::sink;
+
+/// Interval between two periodic flushes
+const FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Buffered messages that trigger an early flush
+const MAX_BUFFERED: usize = 16;
+
+/// Why a buffer was flushed
+#[derive(Clone, Copy)]
+enum Trigger {
+    Tick,
+    Full,
+    Disconnected,
+}
+
+/// Flush statistics gathered by the consumer
+#[derive(Default)]
+struct FlushStats {
+    flushes: [usize; 3],
+    messages: usize,
+    max_age: Duration,
+}
+
+impl FlushStats {
+    /// "Write" the buffered messages, recording why and how long the oldest one waited
+    fn flush(&mut self, buffer: &mut Vec<(usize, Instant)>, trigger: Trigger, start: Instant) {
+        if buffer.is_empty() {
+            return;
+        }
+
+        let oldest = buffer.iter().map(|(_, received_at)| *received_at).min().unwrap();
+        let age = oldest.elapsed();
+        self.max_age = self.max_age.max(age);
+        self.messages += buffer.len();
+        self.flushes[trigger as usize] += 1;
+
+        let label = match trigger {
+            Trigger::Tick => "tick",
+            Trigger::Full => "full",
+            Trigger::Disconnected => "final",
+        };
+        for (id, _) in buffer.iter() {
+            sink::write("periodic-flush", format!("message={} trigger={}", id, label));
+        }
+        println!(
+            "💾 {:>6.1}ms flushed {:>2} messages ({:<5}), oldest waited {:?}",
+            start.elapsed().as_secs_f64() * 1000.0,
+            buffer.len(),
+            label,
+            age
+        );
+        buffer.clear();
+    }
+}
+
+/// Run the periodic flush example
+pub fn run(num_senders: usize, messages_per_sender: usize) {
+    common::print_info(&format!(
+        "{} producers send {} messages each in bursts; the consumer flushes every {:?} or at {} buffered messages",
+        num_senders, messages_per_sender, FLUSH_INTERVAL, MAX_BUFFERED
+    ));
+
+    let (tx, rx) = channel::unbounded::<usize>();
+    let start = Instant::now();
+
+    // Producers alternate quick bursts with quiet periods
+    let mut handles = vec![];
+    for sender_id in 0..num_senders {
+        let tx_clone = tx.clone();
+        let handle = thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            for msg_num in 0..messages_per_sender {
+                tx_clone.send(sender_id * messages_per_sender + msg_num).unwrap();
+                let pause = if rng.gen_bool(0.8) { 2 } else { 120 };
+                thread::sleep(Duration::from_millis(pause));
+            }
+        });
+        handles.push(handle);
+    }
+    drop(tx);
+
+    // The consumer waits on both the data channel and the ticker
+    let ticker = channel::tick(FLUSH_INTERVAL);
+    let mut buffer = Vec::with_capacity(MAX_BUFFERED);
+    let mut stats = FlushStats::default();
+    loop {
+        select! {
+            recv(rx) -> message => match message {
+                Ok(id) => {
+                    buffer.push((id, Instant::now()));
+                    if buffer.len() >= MAX_BUFFERED {
+                        stats.flush(&mut buffer, Trigger::Full, start);
+                    }
+                }
+                Err(_) => {
+                    stats.flush(&mut buffer, Trigger::Disconnected, start);
+                    break;
+                }
+            },
+            recv(ticker) -> _ => stats.flush(&mut buffer, Trigger::Tick, start),
+        }
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    println!();
+    common::print_success(&format!(
+        "Flushed {} messages: {} on tick, {} when full, {} on shutdown",
+        stats.messages,
+        stats.flushes[Trigger::Tick as usize],
+        stats.flushes[Trigger::Full as usize],
+        stats.flushes[Trigger::Disconnected as usize]
+    ));
+    common::print_info(&format!(
+        "No message waited more than {:?} to be flushed (flush interval: {:?})",
+        stats.max_age, FLUSH_INTERVAL
+    ));
+    common::print_info("The ticker bounds how long a message can sit in the buffer; the size cap bounds how much can pile up");
+}
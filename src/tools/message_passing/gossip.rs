@@ -0,0 +1,179 @@
+//! Gossip-style state dissemination between threads
+//!
+//! Each thread plays the role of a node in a small cluster. Nodes hold a
+//! version vector, apply local updates and periodically push their view to
+//! a random peer over a channel, merging whatever they receive. The example
+//! measures how many gossip rounds it takes for every node to converge.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+// Third-party dependencies
+use rand::Rng;
+
+// Project dependencies
+use crate::common;
+
+/// Upper bound on gossip rounds, guarding against a run that never converges
+const MAX_ROUNDS: usize = 1_000;
+
+/// Version vector holding the latest known update counter of every node
+type VersionVector = Vec<u64>;
+
+/// Outcome of a single gossip simulation
+struct GossipResult {
+    rounds: usize,
+    messages_sent: usize,
+}
+
+/// Merge a received version vector into the local one (element-wise max)
+fn merge(local: &mut VersionVector, remote: &VersionVector) {
+    for (mine, theirs) in local.iter_mut().zip(remote) {
+        *mine = (*mine).max(*theirs);
+    }
+}
+
+/// Run a gossip simulation with the given number of nodes
+fn simulate(num_nodes: usize, updates_per_node: usize) -> GossipResult {
+
+    // Every node owns a receiver and knows the senders of all its peers
+    let (senders, receivers): (Vec<Sender<VersionVector>>, Vec<Receiver<VersionVector>>) =
+        (0..num_nodes).map(|_| mpsc::channel()).unzip();
+
+    // Rounds are delimited by barriers so that "rounds to convergence" is well defined
+    let barrier = Arc::new(Barrier::new(num_nodes));
+
+    // Number of nodes whose view already matches the final state of the cluster
+    let converged = Arc::new(AtomicUsize::new(0));
+    let messages_sent = Arc::new(AtomicUsize::new(0));
+
+    // The state every node must eventually observe
+    let target: VersionVector = vec![updates_per_node as u64; num_nodes];
+
+    let mut handles = vec![];
+
+    for (node_id, inbox) in receivers.into_iter().enumerate() {
+        let peers = senders.clone();
+        let barrier = Arc::clone(&barrier);
+        let converged = Arc::clone(&converged);
+        let messages_sent = Arc::clone(&messages_sent);
+        let target = target.clone();
+
+        let handle = thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            let mut state: VersionVector = vec![0; num_nodes];
+            let mut is_converged = false;
+            let mut round = 0;
+
+            while round < MAX_ROUNDS {
+
+                // Apply one local update per round until the node is done updating
+                if round < updates_per_node {
+                    state[node_id] += 1;
+                }
+
+                // Push the current view to a random peer other than ourselves
+                let mut peer = rng.gen_range(0..num_nodes - 1);
+                if peer >= node_id {
+                    peer += 1;
+                }
+                peers[peer].send(state.clone()).unwrap();
+                messages_sent.fetch_add(1, Ordering::Relaxed);
+
+                // Wait until every node has sent its message for this round
+                barrier.wait();
+
+                // Merge everything received during the round
+                while let Ok(remote) = inbox.try_recv() {
+                    merge(&mut state, &remote);
+                }
+
+                // Convergence is monotonic: once a node has the full view it keeps it
+                if !is_converged && state == target {
+                    is_converged = true;
+                    converged.fetch_add(1, Ordering::SeqCst);
+                }
+
+                // Wait until every node has merged before checking for global convergence
+                barrier.wait();
+                round += 1;
+
+                if converged.load(Ordering::SeqCst) == num_nodes {
+                    break;
+                }
+            }
+
+            round
+        });
+
+        handles.push(handle);
+    }
+
+    // All nodes stop on the same round, so any of them reports the total
+    let rounds = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .max()
+        .unwrap_or(0);
+
+    GossipResult {
+        rounds,
+        messages_sent: messages_sent.load(Ordering::Relaxed),
+    }
+}
+
+/// Run the gossip example for a growing number of nodes
+pub fn run(max_nodes: usize, updates_per_node: usize) {
+
+    // Gossip requires at least two nodes and one update to disseminate
+    let max_nodes = max_nodes.max(2);
+    let updates_per_node = updates_per_node.max(1);
+
+    common::print_info(&format!(
+        "Each node applies {} local update(s) and pushes its version vector to one random peer per round",
+        updates_per_node
+    ));
+
+    // Double the cluster size on every run, always including the requested maximum
+    let mut cluster_sizes = vec![];
+    let mut size = 2;
+    while size < max_nodes {
+        cluster_sizes.push(size);
+        size *= 2;
+    }
+    cluster_sizes.push(max_nodes);
+
+    println!();
+    println!("{:>8} {:>8} {:>10} {:>12}", "nodes", "rounds", "messages", "log2(nodes)");
+
+    let mut all_converged = true;
+    for num_nodes in cluster_sizes {
+        let result = simulate(num_nodes, updates_per_node);
+        println!(
+            "{:>8} {:>8} {:>10} {:>12.2}",
+            num_nodes,
+            result.rounds,
+            result.messages_sent,
+            (num_nodes as f64).log2()
+        );
+
+        if result.rounds >= MAX_ROUNDS {
+            all_converged = false;
+            common::print_warning(&format!(
+                "{} nodes did not converge within {} rounds",
+                num_nodes, MAX_ROUNDS
+            ));
+        }
+    }
+
+    println!();
+    if all_converged {
+        common::print_success("All clusters converged to the same version vector");
+    }
+    common::print_info(
+        "Rounds past the last local update grow roughly with log2(nodes): information spreads epidemically",
+    );
+}
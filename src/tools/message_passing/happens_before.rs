@@ -0,0 +1,269 @@
+//! Vector clock / happens-before visualization of a run
+//!
+//! Every thread keeps a vector clock that is attached to the messages it
+//! sends and to the lock it releases. Receivers and lock acquirers merge
+//! the clocks they observe, so after the run every recorded event can be
+//! compared: either one happened-before the other, or they are concurrent.
+
+// Base dependencies
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Project dependencies
+use crate::common;
+
+/// Maximum number of concurrent event pairs listed in the summary
+const MAX_LISTED_PAIRS: usize = 5;
+
+/// A vector clock with one logical counter per thread
+#[derive(Clone, Debug, PartialEq)]
+struct VectorClock(Vec<u64>);
+
+impl VectorClock {
+    // Structure constructor
+    fn new(num_processes: usize) -> Self {
+        VectorClock(vec![0; num_processes])
+    }
+
+    // Advance the local component before recording an event
+    fn tick(&mut self, process: usize) {
+        self.0[process] += 1;
+    }
+
+    // Merge an observed clock into the local one (element-wise max)
+    fn merge(&mut self, other: &VectorClock) {
+        for (mine, theirs) in self.0.iter_mut().zip(&other.0) {
+            *mine = (*mine).max(*theirs);
+        }
+    }
+
+    // `self` happened-before `other` when it is <= on every component and differs on one
+    fn happened_before(&self, other: &VectorClock) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a <= b) && self != other
+    }
+}
+
+/// What a recorded event represents, used to draw causal edges
+#[derive(Clone)]
+enum EventKind {
+    LockAcquire { after: Option<String> },
+    Send { message_id: usize },
+    Receive { message_id: usize },
+}
+
+/// An event recorded by a thread together with its vector clock
+#[derive(Clone)]
+struct Event {
+    id: String,
+    process: usize,
+    kind: EventKind,
+    clock: VectorClock,
+}
+
+impl Event {
+    // Short human-readable description of the event
+    fn label(&self) -> String {
+        match &self.kind {
+            EventKind::LockAcquire { .. } => "lock".to_string(),
+            EventKind::Send { message_id } => format!("send m{}", message_id),
+            EventKind::Receive { message_id } => format!("recv m{}", message_id),
+        }
+    }
+}
+
+/// Message carrying the sender's clock along with its payload
+struct Stamped {
+    message_id: usize,
+    clock: VectorClock,
+}
+
+/// Clock stored inside the shared lock, plus the id of the event that last released it
+struct LockState {
+    clock: VectorClock,
+    last_event: Option<String>,
+}
+
+/// Run the instrumented message-passing workload and collect every event
+fn record_run(num_senders: usize, messages_per_sender: usize) -> Vec<Event> {
+
+    // Senders are processes 0..n, the receiver is process n
+    let num_processes = num_senders + 1;
+    let receiver_process = num_senders;
+
+    let (tx, rx) = mpsc::channel::<Stamped>();
+    let lock = Arc::new(Mutex::new(LockState {
+        clock: VectorClock::new(num_processes),
+        last_event: None,
+    }));
+
+    let mut handles = vec![];
+    for sender_id in 0..num_senders {
+        let tx_clone = tx.clone();
+        let lock = Arc::clone(&lock);
+
+        let handle = thread::spawn(move || {
+            let mut clock = VectorClock::new(num_processes);
+            let mut events = vec![];
+
+            for msg_num in 0..messages_per_sender {
+
+                // Acquiring the lock observes the clock of whoever released it last
+                {
+                    let mut state = lock.lock().unwrap();
+                    clock.merge(&state.clock);
+                    clock.tick(sender_id);
+
+                    let id = format!("p{}_{}", sender_id, events.len());
+                    events.push(Event {
+                        id: id.clone(),
+                        process: sender_id,
+                        kind: EventKind::LockAcquire { after: state.last_event.take() },
+                        clock: clock.clone(),
+                    });
+
+                    // Releasing the lock publishes our clock to the next acquirer
+                    state.clock = clock.clone();
+                    state.last_event = Some(id);
+                }
+
+                // Sending a message attaches the current clock to it
+                clock.tick(sender_id);
+                let message_id = sender_id * messages_per_sender + msg_num;
+                events.push(Event {
+                    id: format!("p{}_{}", sender_id, events.len()),
+                    process: sender_id,
+                    kind: EventKind::Send { message_id },
+                    clock: clock.clone(),
+                });
+                tx_clone.send(Stamped { message_id, clock: clock.clone() }).unwrap();
+            }
+
+            events
+        });
+
+        handles.push(handle);
+    }
+    drop(tx);
+
+    // The receiver merges the clock of every message it gets
+    let receiver = thread::spawn(move || {
+        let mut clock = VectorClock::new(num_processes);
+        let mut events = vec![];
+
+        for stamped in rx {
+            clock.merge(&stamped.clock);
+            clock.tick(receiver_process);
+            events.push(Event {
+                id: format!("p{}_{}", receiver_process, events.len()),
+                process: receiver_process,
+                kind: EventKind::Receive { message_id: stamped.message_id },
+                clock: clock.clone(),
+            });
+        }
+
+        events
+    });
+
+    let mut events: Vec<Event> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+    events.extend(receiver.join().unwrap());
+    events
+}
+
+/// Render the recorded events as a Graphviz DOT graph
+fn to_dot(events: &[Event], num_processes: usize) -> String {
+    let mut dot = String::from("digraph happens_before {\n    rankdir=LR;\n");
+
+    // One cluster per thread, with program-order edges between its events
+    for process in 0..num_processes {
+        let local: Vec<&Event> = events.iter().filter(|event| event.process == process).collect();
+        dot.push_str(&format!("    subgraph cluster_p{} {{\n        label=\"thread {}\";\n", process, process));
+        for event in &local {
+            dot.push_str(&format!("        {} [label=\"{}\\n{:?}\"];\n", event.id, event.label(), event.clock.0));
+        }
+        for pair in local.windows(2) {
+            dot.push_str(&format!("        {} -> {};\n", pair[0].id, pair[1].id));
+        }
+        dot.push_str("    }\n");
+    }
+
+    // Cross-thread causality: message delivery and lock hand-off
+    for event in events {
+        match &event.kind {
+            EventKind::Receive { message_id } => {
+                let send = events.iter().find(|candidate| {
+                    matches!(candidate.kind, EventKind::Send { message_id: id } if id == *message_id)
+                });
+                if let Some(send) = send {
+                    dot.push_str(&format!("    {} -> {} [style=dashed];\n", send.id, event.id));
+                }
+            }
+            EventKind::LockAcquire { after: Some(previous) } => {
+                dot.push_str(&format!("    {} -> {} [style=dotted];\n", previous, event.id));
+            }
+            _ => {}
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Run the happens-before instrumentation example, writing the DOT graph to `dot_file` if given
+pub fn run(num_senders: usize, messages_per_sender: usize, dot_file: Option<&Path>) {
+    common::print_info(&format!(
+        "Instrumenting {} senders x {} messages; every send is preceded by a shared lock acquisition",
+        num_senders, messages_per_sender
+    ));
+
+    let events = record_run(num_senders, messages_per_sender);
+
+    // Print every event with its clock, grouped by thread
+    println!();
+    for event in &events {
+        println!("{:<8} thread {:<3} {:<10} {:?}", event.id, event.process, event.label(), event.clock.0);
+    }
+
+    // Classify every pair of events from different threads
+    let mut ordered = 0;
+    let mut concurrent = vec![];
+    for (i, a) in events.iter().enumerate() {
+        for b in &events[i + 1..] {
+            if a.process == b.process {
+                continue;
+            }
+            if a.clock.happened_before(&b.clock) || b.clock.happened_before(&a.clock) {
+                ordered += 1;
+            } else {
+                concurrent.push((a, b));
+            }
+        }
+    }
+
+    println!();
+    common::print_success(&format!("Recorded {} events", events.len()));
+    common::print_info(&format!("Cross-thread pairs ordered by happens-before: {}", ordered));
+    common::print_info(&format!("Cross-thread pairs that are concurrent: {}", concurrent.len()));
+    for (a, b) in concurrent.iter().take(MAX_LISTED_PAIRS) {
+        common::print_info(&format!("  {} || {} ({:?} vs {:?})", a.id, b.id, a.clock.0, b.clock.0));
+    }
+
+    // Write the DOT graph to its own file, so it can be fed to `dot -Tsvg` as is
+    println!();
+    let Some(path) = dot_file else {
+        common::print_info("Pass --dot FILE to write the happens-before graph as Graphviz DOT");
+        return;
+    };
+    match fs::write(path, to_dot(&events, num_senders + 1)) {
+        Ok(()) => {
+            common::print_info(&format!("Wrote the happens-before graph to {}: solid = program order, dashed = message, dotted = lock hand-off", path.display()));
+            common::print_info(&format!("Render it with: dot -Tsvg {} -o happens-before.svg", path.display()));
+        }
+        Err(error) => common::print_warning(&format!("Could not write the DOT graph to {}: {}", path.display(), error)),
+    }
+}
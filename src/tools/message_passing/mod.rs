@@ -1,6 +1,15 @@
 
 // Re-export the commands from this module
 pub mod code;
+pub mod gossip;
+pub mod batching;
+pub mod happens_before;
+pub mod rendezvous;
+pub mod watch;
+pub mod flush;
+pub mod consumer_groups;
+pub mod topology;
+pub mod pacing;
 
 // Re-export the run function for easier access from main.rs
 pub use code::run;
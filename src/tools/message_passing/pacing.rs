@@ -0,0 +1,142 @@
+//! Pacing accuracy at sub-millisecond intervals
+//!
+//! Producers in the other examples send at a fixed rate, and at intervals
+//! below a millisecond `thread::sleep` is not precise enough: the OS wakes
+//! the thread late. This module ticks a `Pacer` with each strategy and
+//! reports how late the ticks fire, the rate actually achieved and how much
+//! CPU the waiting cost.
+
+// Base dependencies
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::common;
+use crate::pacing::{self, Pacer, PacingStrategy};
+
+/// Tick intervals compared, from well below to right at a millisecond
+const INTERVALS: [Duration; 5] = [
+    Duration::from_micros(50),
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+];
+
+/// Accuracy of one strategy at one interval
+struct PacingReport {
+    mean_late: Duration,
+    p99_late: Duration,
+    achieved: f64,
+    cpu: Option<f64>,
+}
+
+/// Fire `ticks` ticks and measure how late each one was observed
+fn measure(interval: Duration, strategy: PacingStrategy, ticks: usize) -> PacingReport {
+    let mut lateness = Vec::with_capacity(ticks);
+    let cpu_before = common::thread_cpu_time();
+    let start = Instant::now();
+
+    let mut pacer = Pacer::with_strategy(interval, strategy);
+    for _ in 0..ticks {
+        let scheduled = pacer.tick();
+        lateness.push(Instant::now() - scheduled);
+    }
+
+    let elapsed = start.elapsed();
+    let cpu = match (cpu_before, common::thread_cpu_time()) {
+        (Some(before), Some(after)) => Some((after - before).as_secs_f64() / elapsed.as_secs_f64()),
+        _ => None,
+    };
+    lateness.sort_unstable();
+
+    PacingReport {
+        mean_late: lateness.iter().sum::<Duration>() / ticks.max(1) as u32,
+        p99_late: common::percentile(&lateness, 99.0),
+        achieved: ticks as f64 / elapsed.as_secs_f64() * interval.as_secs_f64(),
+        cpu,
+    }
+}
+
+/// Run the pacing comparison with `ticks` ticks per interval and strategy
+pub fn run(ticks: usize) {
+    let ticks = ticks.max(1);
+    common::print_info(&format!(
+        "Measured sleep overshoot on this platform: {:?} (the hybrid strategy spins for that long before each deadline)",
+        pacing::sleep_overshoot()
+    ));
+    common::print_info(&format!("{} ticks per interval and strategy", ticks));
+
+    println!();
+    println!(
+        "{:<10} {:<8} {:>12} {:>12} {:>10} {:>8}",
+        "interval", "strategy", "mean late", "p99 late", "achieved", "cpu"
+    );
+    // Mean lateness and CPU share per strategy, summed over the intervals longer than the overshoot
+    let overshoot = pacing::sleep_overshoot();
+    let mut late_totals = [Duration::ZERO; 3];
+    let mut cpu_totals = [Some(0.0); 3];
+    let mut compared = 0;
+
+    for interval in INTERVALS {
+        if interval > overshoot {
+            compared += 1;
+        }
+        for strategy in PacingStrategy::ALL {
+            let report = measure(interval, strategy, ticks);
+            if interval > overshoot {
+                let index = strategy as usize;
+                late_totals[index] += report.mean_late;
+                cpu_totals[index] = cpu_totals[index].zip(report.cpu).map(|(total, cpu)| total + cpu);
+            }
+            let cpu = match report.cpu {
+                Some(cpu) => format!("{:.0}%", cpu * 100.0),
+                None => "n/a".to_string(),
+            };
+            println!(
+                "{:<10} {:<8} {:>12?} {:>12?} {:>9.1}% {:>8}",
+                format!("{:?}", interval),
+                strategy.label(),
+                report.mean_late,
+                report.p99_late,
+                report.achieved * 100.0,
+                cpu
+            );
+        }
+    }
+
+    println!();
+    common::print_info("Achieved is the tick rate reached as a share of the requested one; ticks are scheduled from the start, so a late tick does not delay the next");
+    report_hybrid(compared, late_totals, cpu_totals);
+}
+
+/// Compare the hybrid strategy with sleep and spin over the intervals longer than the overshoot
+fn report_hybrid(compared: u32, late_totals: [Duration; 3], cpu_totals: [Option<f64>; 3]) {
+    if compared == 0 {
+        common::print_info("No interval is longer than the sleep overshoot, so hybrid spun through every interval just like spin");
+        return;
+    }
+
+    let late = |strategy: PacingStrategy| late_totals[strategy as usize] / compared;
+    let cpu = |strategy: PacingStrategy| cpu_totals[strategy as usize].map(|total| total / compared as f64);
+    let (sleep_late, spin_late, hybrid_late) = (late(PacingStrategy::Sleep), late(PacingStrategy::Spin), late(PacingStrategy::Hybrid));
+
+    // Hybrid counts as accurate when its lateness is closer to spin's than to sleep's
+    let accurate = hybrid_late.saturating_sub(spin_late) <= sleep_late.saturating_sub(hybrid_late);
+    let accuracy = format!(
+        "over the {} intervals longer than the overshoot, hybrid was {:?} late on average (spin {:?}, sleep {:?})",
+        compared, hybrid_late, spin_late, sleep_late
+    );
+
+    match (cpu(PacingStrategy::Hybrid), cpu(PacingStrategy::Spin)) {
+        (Some(hybrid_cpu), Some(spin_cpu)) => {
+            let usage = format!("used {:.0}% of a CPU against {:.0}% for spin", hybrid_cpu * 100.0, spin_cpu * 100.0);
+            if accurate && hybrid_cpu < spin_cpu / 2.0 {
+                common::print_success(&format!("Hybrid kept close to spin accuracy and {}: {}; it is what Pacer::new uses", usage, accuracy));
+            } else {
+                common::print_warning(&format!("Hybrid did not beat both sleep's accuracy and spin's CPU cost here: {} and it {}", accuracy, usage));
+            }
+        }
+        _ if accurate => common::print_success(&format!("Hybrid kept close to spin accuracy ({}); CPU time is not available on this platform", accuracy)),
+        _ => common::print_warning(&format!("Hybrid was not closer to spin than to sleep: {}", accuracy)),
+    }
+}
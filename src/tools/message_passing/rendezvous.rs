@@ -0,0 +1,109 @@
+//! Zero-capacity rendezvous channel examples
+//!
+//! A channel with capacity zero has no buffer at all: a send only completes
+//! when a receiver is there to take the message. This module times both
+//! sides to show that the sender blocks until the receiver arrives.
+
+// Base dependencies
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::channel;
+
+// Project dependencies
+use crate::common;
+use crate::trace::{self, TraceKind};
+
+/// How long the receiver makes the sender wait before every receive
+const RECEIVER_DELAY: Duration = Duration::from_millis(50);
+
+/// Time a sequence of blocking sends against a slow receiver
+///
+/// `send` and `recv` abstract over the channel flavour so both std and
+/// crossbeam channels share the same timing logic; `channel` labels trace events.
+fn time_handoffs<S, R>(channel: &'static str, num_messages: usize, send: S, recv: R) -> Vec<Duration>
+where
+    S: Fn(usize) + Send + 'static,
+    R: Fn() -> Option<usize> + Send + 'static,
+{
+    let start = Instant::now();
+
+    // The receiver deliberately shows up late for every message
+    let receiver = thread::spawn(move || {
+        for _ in 0..num_messages {
+            thread::sleep(RECEIVER_DELAY);
+            if let Some(msg) = recv() {
+                trace::record(TraceKind::Receive, channel, &msg.to_string());
+                println!(
+                    "📥 Receiver took message {} at {:>6.1}ms",
+                    msg,
+                    start.elapsed().as_secs_f64() * 1000.0
+                );
+            }
+        }
+    });
+
+    // Every send blocks until the receiver is ready to take the message
+    let mut blocked = vec![];
+    for msg in 0..num_messages {
+        let before = Instant::now();
+        trace::record(TraceKind::Send, channel, &msg.to_string());
+        send(msg);
+        let waited = before.elapsed();
+        println!(
+            "📤 Sender handed off message {} at {:>6.1}ms after blocking {:?}",
+            msg,
+            start.elapsed().as_secs_f64() * 1000.0,
+            waited
+        );
+        blocked.push(waited);
+    }
+
+    receiver.join().unwrap();
+    blocked
+}
+
+/// Summarize how long the sender was blocked on each handoff
+fn report(blocked: &[Duration]) {
+    let total: Duration = blocked.iter().sum();
+    let average = total / blocked.len().max(1) as u32;
+    common::print_success(&format!(
+        "Sender blocked {:?} on average per message (receiver delay: {:?})",
+        average, RECEIVER_DELAY
+    ));
+}
+
+/// Run the rendezvous channel examples
+pub fn run(num_messages: usize) {
+    common::print_info(&format!(
+        "Handing off {} messages through zero-capacity channels; the receiver waits {:?} before each receive",
+        num_messages, RECEIVER_DELAY
+    ));
+
+    println!();
+    common::print_info("Standard library mpsc::sync_channel(0)");
+    let (tx, rx) = mpsc::sync_channel::<usize>(0);
+    let blocked = time_handoffs(
+        "sync_channel(0)",
+        num_messages,
+        move |msg| tx.send(msg).unwrap(),
+        move || rx.recv().ok(),
+    );
+    report(&blocked);
+
+    println!();
+    common::print_info("Crossbeam channel::bounded(0)");
+    let (tx, rx) = channel::bounded::<usize>(0);
+    let blocked = time_handoffs(
+        "bounded(0)",
+        num_messages,
+        move |msg| tx.send(msg).unwrap(),
+        move || rx.recv().ok(),
+    );
+    report(&blocked);
+
+    println!();
+    common::print_info("Each send only returns once the receiver arrives: the channel is a synchronous handoff point");
+}
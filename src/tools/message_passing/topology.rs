@@ -0,0 +1,195 @@
+//! Dynamic pipeline topology with a channel of channels
+//!
+//! Channel endpoints are ordinary values, so they can be sent over other
+//! channels. A router receives new pipeline inputs over a control channel
+//! while it is running, spreads the data stream over whatever pipelines are
+//! attached, and tears a pipeline down by simply dropping its sender.
+
+// Base dependencies
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Third-party dependencies
+use crossbeam::channel::{self, select, Sender};
+
+// Project dependencies
+use crate::common;
+
+/// Pause between two items of the data stream
+const SEND_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Messages the supervisor sends to the router
+enum Control {
+    /// Start routing to a new pipeline through its input endpoint
+    Attach { id: usize, input: Sender<u64> },
+    /// Stop routing to a pipeline and drop its input, letting it drain and shut down
+    Detach { id: usize },
+    /// Ask how many items were routed to each attached pipeline, answering on the given channel
+    Stats { reply: Sender<Vec<(usize, usize)>> },
+}
+
+/// Pipelines attached to the router: id, input endpoint and items routed so far
+type Attached = Vec<(usize, Sender<u64>, usize)>;
+
+/// Apply a control message to the router's set of pipelines
+fn apply(control: Control, pipelines: &mut Attached) {
+    match control {
+        Control::Attach { id, input } => {
+            pipelines.push((id, input, 0));
+            println!("🔌 Router attached pipeline {} ({} attached)", id, pipelines.len());
+        }
+        Control::Detach { id } => {
+            pipelines.retain(|(attached, _, _)| *attached != id);
+            println!("✂️  Router detached pipeline {} ({} attached)", id, pipelines.len());
+        }
+        Control::Stats { reply } => {
+            let stats = pipelines.iter().map(|(id, _, routed)| (*id, *routed)).collect();
+            reply.send(stats).unwrap();
+        }
+    }
+}
+
+/// Spawn a two-stage pipeline (square -> sum), returning its input endpoint and its sink
+fn spawn_pipeline(id: usize) -> (Sender<u64>, JoinHandle<(usize, u64)>) {
+    let (input, stage_rx) = channel::unbounded::<u64>();
+    let (stage_tx, sink_rx) = channel::unbounded::<u64>();
+
+    // Each stage ends when its input disconnects, so dropping `input` tears down the whole pipeline
+    thread::spawn(move || {
+        for value in stage_rx {
+            stage_tx.send(value * value).unwrap();
+        }
+    });
+    let sink = thread::spawn(move || {
+        let mut count = 0;
+        let mut sum = 0;
+        for value in sink_rx {
+            count += 1;
+            sum += value;
+        }
+        common::print_info(&format!("Pipeline {} drained and shut down after {} items", id, count));
+        (count, sum)
+    });
+
+    (input, sink)
+}
+
+/// Run the dynamic topology example
+pub fn run(num_pipelines: usize, items_per_pipeline: usize) {
+    let num_pipelines = num_pipelines.max(2);
+    let total = num_pipelines * items_per_pipeline;
+    common::print_info(&format!(
+        "Streaming {} items while {} pipelines are attached and detached at runtime",
+        total, num_pipelines
+    ));
+
+    let (control_tx, control_rx) = channel::unbounded::<Control>();
+    let (data_tx, data_rx) = channel::unbounded::<u64>();
+
+    // The router listens to both the control channel and the data stream
+    let router = thread::spawn(move || {
+        let mut pipelines: Attached = vec![];
+        let mut next = 0;
+
+        // select! picks randomly among ready arms, so wait for a pipeline before consuming any data
+        match control_rx.recv() {
+            Ok(control) => apply(control, &mut pipelines),
+            Err(_) => return 0,
+        }
+
+        // Keep going until both the control channel and the data stream are closed;
+        // a closed side is swapped for `never()` so select! stops waking up on it
+        let (mut control_rx, mut data_rx) = (control_rx, data_rx);
+        let (mut control_open, mut data_open) = (true, true);
+        while control_open || data_open {
+            select! {
+                recv(control_rx) -> message => match message {
+                    Ok(control) => apply(control, &mut pipelines),
+                    Err(_) => {
+                        control_open = false;
+                        control_rx = channel::never();
+                    }
+                },
+                recv(data_rx) -> value => match value {
+                    Ok(value) => {
+                        // Round-robin over the pipelines attached right now
+                        let index = next % pipelines.len();
+                        next += 1;
+                        let (_, input, routed) = &mut pipelines[index];
+                        input.send(value).unwrap();
+                        *routed += 1;
+                    }
+                    Err(_) => {
+                        data_open = false;
+                        data_rx = channel::never();
+                    }
+                },
+            }
+        }
+
+        // Dropping the remaining inputs tears down the pipelines still attached
+        pipelines.len()
+    });
+
+    // Attach the first pipeline before any data flows
+    let mut sinks = vec![];
+    let (input, sink) = spawn_pipeline(0);
+    control_tx.send(Control::Attach { id: 0, input }).unwrap();
+    sinks.push(sink);
+
+    let producer = thread::spawn(move || {
+        for value in 0..total as u64 {
+            data_tx.send(value).unwrap();
+            thread::sleep(SEND_INTERVAL);
+        }
+    });
+
+    // The supervisor grows the topology one pipeline at a time, then retires the oldest ones
+    let phase = SEND_INTERVAL * (total / (2 * num_pipelines)).max(1) as u32;
+    for id in 1..num_pipelines {
+        thread::sleep(phase);
+        let (input, sink) = spawn_pipeline(id);
+        control_tx.send(Control::Attach { id, input }).unwrap();
+        sinks.push(sink);
+    }
+    for id in 0..num_pipelines - 1 {
+        thread::sleep(phase);
+
+        // Ask the router for its stats through a reply channel sent over the control channel
+        let (reply, stats_rx) = channel::bounded(1);
+        control_tx.send(Control::Stats { reply }).unwrap();
+        let stats: Vec<String> = stats_rx
+            .recv()
+            .unwrap()
+            .iter()
+            .map(|(id, routed)| format!("#{}: {}", id, routed))
+            .collect();
+        common::print_info(&format!("Routed so far: {}", stats.join(", ")));
+
+        control_tx.send(Control::Detach { id }).unwrap();
+    }
+
+    producer.join().unwrap();
+    drop(control_tx);
+    let still_attached = router.join().unwrap();
+
+    // Every item must have reached exactly one pipeline
+    let results: Vec<(usize, u64)> = sinks.into_iter().map(|sink| sink.join().unwrap()).collect();
+    let processed: usize = results.iter().map(|(count, _)| count).sum();
+    let sum: u64 = results.iter().map(|(_, sum)| sum).sum();
+    let expected_sum: u64 = (0..total as u64).map(|value| value * value).sum();
+
+    println!();
+    for (id, (count, _)) in results.iter().enumerate() {
+        common::print_info(&format!("Pipeline {} processed {} items", id, count));
+    }
+    if processed == total && sum == expected_sum {
+        common::print_success(&format!(
+            "All {} items processed exactly once across a changing topology ({} pipeline(s) attached at the end)",
+            total, still_attached
+        ));
+    } else {
+        common::print_warning(&format!("Processed {} of {} items!", processed, total));
+    }
+    common::print_info("Sending a Sender attaches a pipeline; dropping it is all it takes to tear one down");
+}
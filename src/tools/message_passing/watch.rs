@@ -0,0 +1,166 @@
+//! Configuration broadcast to workers via a watch-style channel
+//!
+//! A watch channel only keeps the latest value: publishers overwrite it and
+//! every subscriber can cheaply check whether a newer version exists. Here
+//! a control thread keeps changing the processing delay while workers pick
+//! up the new value between work items, without ever stopping.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+
+/// Interval between two configuration updates published by the control thread
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(60);
+
+/// Latest value plus a version number, shared by the publisher and every subscriber
+pub struct Watch<T> {
+    version: AtomicU64,
+    value: RwLock<Arc<T>>,
+}
+
+impl<T> Watch<T> {
+    /// Create a watch cell holding an initial value (version 0)
+    pub fn new(value: T) -> Self {
+        Watch {
+            version: AtomicU64::new(0),
+            value: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    /// Replace the current value, making it visible to every subscriber
+    pub fn publish(&self, value: T) {
+        let mut current = self.value.write().unwrap();
+        *current = Arc::new(value);
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Create a subscriber starting from the current value
+    pub fn subscribe(self: &Arc<Self>) -> Subscriber<T> {
+        let seen = self.version.load(Ordering::Acquire);
+        let current = Arc::clone(&self.value.read().unwrap());
+        Subscriber {
+            watch: Arc::clone(self),
+            seen,
+            current,
+        }
+    }
+}
+
+/// Receiving side of a watch cell, remembering the last version it saw
+pub struct Subscriber<T> {
+    watch: Arc<Watch<T>>,
+    seen: u64,
+    current: Arc<T>,
+}
+
+impl<T> Subscriber<T> {
+    /// Refresh the local snapshot if a newer version was published, returning whether it changed
+    pub fn changed(&mut self) -> bool {
+
+        // Checking the version is a single atomic load, cheap enough to do on every item
+        let latest = self.watch.version.load(Ordering::Acquire);
+        if latest == self.seen {
+            return false;
+        }
+
+        self.current = Arc::clone(&self.watch.value.read().unwrap());
+        self.seen = latest;
+        true
+    }
+
+    /// The latest value this subscriber has observed
+    pub fn get(&self) -> &T {
+        &self.current
+    }
+}
+
+/// Configuration broadcast to the workers
+struct Config {
+    revision: u64,
+    delay: Duration,
+}
+
+/// Run the configuration broadcast example
+pub fn run(num_workers: usize, items_per_worker: usize) {
+    common::print_info(&format!(
+        "{} workers process {} items each while the control thread publishes a new delay every {:?}",
+        num_workers, items_per_worker, PUBLISH_INTERVAL
+    ));
+
+    let watch = Arc::new(Watch::new(Config {
+        revision: 0,
+        delay: Duration::from_millis(10),
+    }));
+    audit::track("config watch", &watch);
+    let workers_done = Arc::new(AtomicBool::new(false));
+
+    // Control thread: cycles through delays until every worker has finished
+    let control = {
+        let watch = Arc::clone(&watch);
+        let workers_done = Arc::clone(&workers_done);
+        thread::spawn(move || {
+            let delays = [30, 5, 20, 1, 15];
+            let mut revision = 0;
+            loop {
+                thread::sleep(PUBLISH_INTERVAL);
+                if workers_done.load(Ordering::Acquire) {
+                    break;
+                }
+                revision += 1;
+                let delay = Duration::from_millis(delays[(revision as usize - 1) % delays.len()]);
+                watch.publish(Config { revision, delay });
+                common::print_info(&format!("Control published revision {} (delay {:?})", revision, delay));
+            }
+            revision
+        })
+    };
+
+    // Workers check for a new configuration before every item, without ever pausing
+    let start = Instant::now();
+    let mut handles = vec![];
+    for worker_id in 0..num_workers {
+        let mut config = watch.subscribe();
+
+        let handle = thread::spawn(move || {
+            let mut updates_seen = 0;
+            for _ in 0..items_per_worker {
+                if config.changed() {
+                    updates_seen += 1;
+                    println!(
+                        "🔄 Worker {} switched to revision {} (delay {:?})",
+                        worker_id,
+                        config.get().revision,
+                        config.get().delay
+                    );
+                }
+                thread::sleep(config.get().delay);
+            }
+            (updates_seen, config.get().revision)
+        });
+        handles.push(handle);
+    }
+
+    let results: Vec<(usize, u64)> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    workers_done.store(true, Ordering::Release);
+    let published = control.join().unwrap();
+
+    println!();
+    for (worker_id, (updates_seen, last_revision)) in results.iter().enumerate() {
+        common::print_success(&format!(
+            "Worker {} observed {} updates, finishing on revision {}",
+            worker_id, updates_seen, last_revision
+        ));
+    }
+    common::print_info(&format!(
+        "Control thread published {} revisions in {:?}",
+        published,
+        start.elapsed()
+    ));
+    common::print_info("Workers only ever see the latest value: intermediate revisions may be skipped, never queued");
+}
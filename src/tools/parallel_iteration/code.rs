@@ -4,31 +4,32 @@
 //! which makes it easy to convert sequential computations into parallel ones.
 
 // Base dependencies
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 // Third-party dependencies
 use rayon::prelude::*;
 
 // Project dependencies
 use crate::common;
+use crate::cpus;
 
 /// A simple CPU-intensive function for benchmarking
-fn compute_intensive(n: u64) -> u64 {
+pub(crate) fn compute_intensive(n: u64) -> u64 {
     (0..n).fold(0, |acc, x| acc.wrapping_add(x * x))
 }
 
 /// Sequential map example
-fn sequential_map(data: &[u64]) -> Vec<u64> {
+pub(crate) fn sequential_map(data: &[u64]) -> Vec<u64> {
     data.iter().map(|&x| compute_intensive(x)).collect()
 }
 
 /// Parallel map example using Rayon
-fn parallel_map(data: &[u64]) -> Vec<u64> {
+pub(crate) fn parallel_map(data: &[u64]) -> Vec<u64> {
     data.par_iter().map(|&x| compute_intensive(x)).collect()
 }
 
 /// Sequential filter and sum
-fn sequential_filter_sum(data: &[u64]) -> u64 {
+pub(crate) fn sequential_filter_sum(data: &[u64]) -> u64 {
     data.iter()
         .filter(|&&x| x % 2 == 0)
         .map(|&x| x * x)
@@ -36,7 +37,7 @@ fn sequential_filter_sum(data: &[u64]) -> u64 {
 }
 
 /// Parallel filter and sum
-fn parallel_filter_sum(data: &[u64]) -> u64 {
+pub(crate) fn parallel_filter_sum(data: &[u64]) -> u64 {
     data.par_iter()
         .filter(|&&x| x % 2 == 0)
         .map(|&x| x * x)
@@ -44,24 +45,50 @@ fn parallel_filter_sum(data: &[u64]) -> u64 {
 }
 
 /// Parallel sorting
-fn parallel_sort(data: &mut [u64]) {
+pub(crate) fn parallel_sort(data: &mut [u64]) {
     data.par_sort_unstable();
 }
 
-/// Run the parallel iteration examples
-pub fn run(size: usize, benchmark: bool) {
+/// Run the parallel iteration examples, in a pool of `threads` threads if given, else in the global pool
+pub fn run(size: usize, benchmark: bool, threads: Option<usize>) {
     common::print_info(&format!("Collection size: {}", size));
-    common::print_info(&format!("Number of CPUs: {}", num_cpus::get()));
+    common::print_info(&format!("Number of CPUs: {}", cpus::available()));
+
+    // A pool of its own rather than the global one: the global pool is sized once, and --cpus may have done it already
+    let pool = threads.map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap());
+    let threads = pool.as_ref().map_or_else(rayon::current_num_threads, |pool| pool.current_num_threads());
+    common::print_info(&format!("Rayon threads: {}", threads));
+    if threads > cpus::available() {
+        common::print_warning("More threads than CPUs: the extra threads time-share the CPUs instead of adding speedup");
+    }
     
     println!();
     
-    if benchmark {
-        run_benchmark(size);
-    } else {
-        run_examples(size);
+    let examples = || {
+        if benchmark {
+            run_benchmark(size, threads);
+        } else {
+            run_examples(size);
+        }
+    };
+    match &pool {
+        // Every par_iter called inside install runs on this pool
+        Some(pool) => pool.install(examples),
+        None => examples(),
     }
 }
 
+/// Print the speedup of the parallel run over the sequential one, and how much of the ideal `threads`x it reaches
+fn print_speedup(sequential: Duration, parallel: Duration, threads: usize) {
+    let speedup = sequential.as_secs_f64() / parallel.as_secs_f64();
+    common::print_success(&format!(
+        "Speedup: {:.2}x on {} thread(s), {:.0}% parallel efficiency",
+        speedup,
+        threads,
+        100.0 * speedup / threads as f64
+    ));
+}
+
 fn run_examples(size: usize) {
     // Create test data
     let data: Vec<u64> = (0..size as u64).collect();
@@ -100,7 +127,7 @@ fn run_examples(size: usize) {
     
     common::print_info("Example 4: Parallel iteration with custom thread pool");
     let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(4)
+        .num_threads(cpus::clamp(4))
         .build()
         .unwrap();
     
@@ -113,7 +140,7 @@ fn run_examples(size: usize) {
     common::print_success(&format!("Sum of numbers divisible by 3: {}", sum));
 }
 
-fn run_benchmark(size: usize) {
+fn run_benchmark(size: usize, threads: usize) {
     common::print_header("Benchmark Mode: Sequential vs Parallel");
     
     // Create test data
@@ -133,8 +160,7 @@ fn run_benchmark(size: usize) {
     let par_duration = start.elapsed();
     common::print_info(&format!("Parallel:   {:?}", par_duration));
     
-    let speedup = seq_duration.as_secs_f64() / par_duration.as_secs_f64();
-    common::print_success(&format!("Speedup: {:.2}x", speedup));
+    print_speedup(seq_duration, par_duration, threads);
     
     // Verify results match
     assert_eq!(seq_result, par_result);
@@ -153,8 +179,7 @@ fn run_benchmark(size: usize) {
     let par_duration = start.elapsed();
     common::print_info(&format!("Parallel:   {:?}", par_duration));
     
-    let speedup = seq_duration.as_secs_f64() / par_duration.as_secs_f64();
-    common::print_success(&format!("Speedup: {:.2}x", speedup));
+    print_speedup(seq_duration, par_duration, threads);
     
     // Verify results match
     assert_eq!(seq_sum, par_sum);
@@ -175,8 +200,7 @@ fn run_benchmark(size: usize) {
     let par_duration = start.elapsed();
     common::print_info(&format!("Parallel:   {:?}", par_duration));
     
-    let speedup = seq_duration.as_secs_f64() / par_duration.as_secs_f64();
-    common::print_success(&format!("Speedup: {:.2}x", speedup));
+    print_speedup(seq_duration, par_duration, threads);
     
     // Verify results match
     assert_eq!(seq_data, par_data);
@@ -1,6 +1,7 @@
 
 // Re-export the commands from this module
 pub mod code;
+pub mod scaling;
 
 // Re-export the run function for easier access from main.rs
 pub use code::run;
@@ -0,0 +1,166 @@
+//! Speedup of the parallel iteration benchmarks as the Rayon thread count grows
+//!
+//! One sequential-vs-parallel comparison gives a single number for whatever
+//! the machine happens to have. Rerunning the same map, filter-and-sum and
+//! sort benchmarks on pools of 1, 2, 4, … N threads shows how the speedup
+//! grows with the threads, and the parallel efficiency, the speedup divided
+//! by the thread count, shows how much of each added thread is put to use.
+//! Work too small to split, memory bandwidth and the parts of an algorithm
+//! that stay sequential all make the efficiency fall as threads are added.
+
+// Base dependencies
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::code::{parallel_filter_sum, parallel_map, parallel_sort, sequential_filter_sum, sequential_map};
+use crate::common;
+use crate::cpus;
+use crate::tools::shared_state::false_sharing::thread_counts;
+
+/// Runs per cell, keeping the fastest to hide scheduling hiccups
+const REPEATS: usize = 3;
+
+/// Items of the map benchmark, as in the sequential-vs-parallel benchmark
+const MAP_ITEMS: usize = 10_000;
+
+/// The benchmarks of the sweep
+#[derive(Clone, Copy)]
+enum Benchmark {
+    Map,
+    FilterSum,
+    Sort,
+}
+
+impl Benchmark {
+    const ALL: [Benchmark; 3] = [Benchmark::Map, Benchmark::FilterSum, Benchmark::Sort];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Benchmark::Map => "map",
+            Benchmark::FilterSum => "filter+sum",
+            Benchmark::Sort => "sort",
+        }
+    }
+
+    /// Time one run, sequential or on the current Rayon pool; the sort's copy of the data is made before the clock starts
+    fn time(&self, data: &[u64], parallel: bool) -> Duration {
+        match self {
+            Benchmark::Map => {
+                let data = &data[..data.len().min(MAP_ITEMS)];
+                let start = Instant::now();
+                let result = if parallel { parallel_map(data) } else { sequential_map(data) };
+                let elapsed = start.elapsed();
+                assert_eq!(result.len(), data.len());
+                elapsed
+            }
+            Benchmark::FilterSum => {
+                let start = Instant::now();
+                let sum = if parallel { parallel_filter_sum(data) } else { sequential_filter_sum(data) };
+                let elapsed = start.elapsed();
+                assert!(sum > 0 || data.len() < 3);
+                elapsed
+            }
+            Benchmark::Sort => {
+                let mut copy = data.to_vec();
+                let start = Instant::now();
+                if parallel {
+                    parallel_sort(&mut copy);
+                } else {
+                    copy.sort_unstable();
+                }
+                let elapsed = start.elapsed();
+                assert!(copy.windows(2).all(|pair| pair[0] <= pair[1]));
+                elapsed
+            }
+        }
+    }
+
+    /// Fastest of `REPEATS` runs
+    fn best(&self, data: &[u64], parallel: bool) -> Duration {
+        (0..REPEATS).map(|_| self.time(data, parallel)).min().unwrap()
+    }
+}
+
+/// Run the benchmarks sequentially, then on pools of 1, 2, 4, … `max_threads` threads (one per CPU by default)
+pub fn run(size: usize, max_threads: Option<usize>) {
+    let max_threads = max_threads.unwrap_or_else(cpus::available).max(1);
+    let counts = thread_counts(max_threads);
+    let data: Vec<u64> = (0..size as u64).map(|x| x % 1000).collect();
+    common::print_info(&format!(
+        "Collection size: {} ({} items for map), at {:?} Rayon threads, best of {} runs per cell",
+        size,
+        size.min(MAP_ITEMS),
+        counts,
+        REPEATS
+    ));
+
+    let sequential: Vec<Duration> = Benchmark::ALL.iter().map(|benchmark| benchmark.best(&data, false)).collect();
+
+    println!();
+    print!("{:<8}", "threads");
+    for benchmark in Benchmark::ALL {
+        print!(" {:>30}", format!("{} (speedup, efficiency)", benchmark.label()));
+    }
+    println!();
+    print!("{:<8}", "seq");
+    for elapsed in &sequential {
+        print!(" {:>30}", format!("{:.3}ms", elapsed.as_secs_f64() * 1e3));
+    }
+    println!();
+
+    // speedups[benchmark][count]
+    let mut speedups: Vec<Vec<f64>> = vec![vec![]; Benchmark::ALL.len()];
+    for threads in &counts {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(*threads).build().unwrap();
+        print!("{:<8}", threads);
+        for (index, benchmark) in Benchmark::ALL.iter().enumerate() {
+            let elapsed = pool.install(|| benchmark.best(&data, true));
+            let speedup = sequential[index].as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+            speedups[index].push(speedup);
+            print!(
+                " {:>30}",
+                format!("{:.3}ms ({:.2}x, {:.0}%)", elapsed.as_secs_f64() * 1e3, speedup, 100.0 * speedup / *threads as f64)
+            );
+        }
+        println!();
+    }
+
+    println!();
+    for (benchmark, speedups) in Benchmark::ALL.iter().zip(&speedups) {
+        let (peak, best) = speedups
+            .iter()
+            .enumerate()
+            .fold((0, 0.0), |(peak, best), (index, speedup)| if *speedup > best { (index, *speedup) } else { (peak, best) });
+        let threads = *counts.last().unwrap();
+        let last = *speedups.last().unwrap();
+
+        // Karp-Flatt metric: the serial fraction that would explain the measured speedup under Amdahl's law
+        let serial = if threads > 1 && last >= 1.0 {
+            format!(
+                ", behaving as if {:.0}% of it ran sequentially",
+                (100.0 * (1.0 / last - 1.0 / threads as f64) / (1.0 - 1.0 / threads as f64)).max(0.0)
+            )
+        } else if last < 1.0 {
+            ", slower than the sequential loop".to_string()
+        } else {
+            String::new()
+        };
+        common::print_info(&format!(
+            "{}: best speedup {:.2}x at {} thread(s); {:.2}x at {} thread(s) is {:.0}% efficient{}",
+            benchmark.label(),
+            best,
+            counts[peak],
+            last,
+            threads,
+            100.0 * last / threads as f64,
+            serial
+        ));
+    }
+    common::print_info("The 1-thread row against the sequential one shows Rayon's own cost: splitting the work and stealing it back");
+    if cpus::available() < max_threads {
+        common::print_warning(&format!(
+            "Only {} CPU(s) available: beyond that the threads take turns instead of running in parallel",
+            cpus::available()
+        ));
+    }
+}
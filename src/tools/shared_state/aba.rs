@@ -0,0 +1,297 @@
+//! The ABA problem on a compare-and-swap stack, and two fixes
+//!
+//! A pop reads the head, reads the head's successor, and swaps the head for
+//! the successor if the head has not changed. "Not changed" only means the
+//! head holds the same value: if other threads pop that node, pop another
+//! and push the first one back in the meantime, the head is A again, the
+//! compare-and-swap succeeds, and the stale successor it installs may be a
+//! node that is no longer on the stack. The stack here is a pool of buffer
+//! indices, so a reused index stands for a reused pointer without making
+//! the corruption undefined behaviour. Tagging the head with a counter that
+//! every successful swap bumps makes the second A differ from the first;
+//! epoch-based reclamation (the Treiber stack) never reuses a node that a
+//! pinned thread may still be looking at.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::thread;
+
+// Project dependencies
+use super::treiber::{ConcurrentStack, TreiberStack};
+use crate::common;
+use crate::cpus;
+
+/// Index meaning "no node"
+const NIL: u32 = u32::MAX;
+
+/// Buffers in the pool of the stress run
+const POOL: usize = 8;
+
+/// Lock-free stack of buffer indices, with the head as one 64-bit word: tag in the high half, index in the low half
+pub(crate) struct IndexStack {
+    head: AtomicU64,
+    next: Vec<AtomicU32>,
+    /// Whether successful swaps bump the tag; without it the tag stays 0 and the head is just the index
+    tagged: bool,
+    retries: AtomicUsize,
+}
+
+fn pack(tag: u32, index: u32) -> u64 {
+    ((tag as u64) << 32) | index as u64
+}
+
+fn unpack(word: u64) -> (u32, u32) {
+    ((word >> 32) as u32, word as u32)
+}
+
+impl IndexStack {
+    /// Empty stack able to hold the indices `0..capacity`
+    pub(crate) fn new(capacity: usize, tagged: bool) -> Self {
+        IndexStack {
+            head: AtomicU64::new(pack(0, NIL)),
+            next: (0..capacity).map(|_| AtomicU32::new(NIL)).collect(),
+            tagged,
+            retries: AtomicUsize::new(0),
+        }
+    }
+
+    /// Head word that replaces `current` and points at `index`
+    fn successor(&self, current: u64, index: u32) -> u64 {
+        let (tag, _) = unpack(current);
+        pack(if self.tagged { tag.wrapping_add(1) } else { tag }, index)
+    }
+
+    pub(crate) fn push_index(&self, index: u32) {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            self.next[index as usize].store(unpack(head).1, Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange(head, self.successor(head, index), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Pop, calling `window` between reading the successor and swapping the head, where ABA strikes
+    pub(crate) fn pop_with(&self, mut window: impl FnMut()) -> Option<u32> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let (_, index) = unpack(head);
+            if index == NIL {
+                return None;
+            }
+            let next = self.next[index as usize].load(Ordering::Relaxed);
+            window();
+            if self
+                .head
+                .compare_exchange(head, self.successor(head, next), Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(index);
+            }
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Indices reachable from the head, stopping after `limit` in case the list loops
+    fn contents(&self, limit: usize) -> Vec<u32> {
+        let mut contents = vec![];
+        let mut index = unpack(self.head.load(Ordering::Acquire)).1;
+        while index != NIL && contents.len() < limit {
+            contents.push(index);
+            index = self.next[index as usize].load(Ordering::Relaxed);
+        }
+        contents
+    }
+}
+
+impl ConcurrentStack<u32> for IndexStack {
+    fn push(&self, value: u32) {
+        self.push_index(value);
+    }
+
+    // Yield in the window, so another thread is likely to run between the read and the swap
+    fn pop(&self) -> Option<u32> {
+        self.pop_with(thread::yield_now)
+    }
+
+    fn cas_retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
+
+/// Letter of the buffer at `index` in the scripted run
+fn name(index: u32) -> char {
+    (b'A' + index as u8) as char
+}
+
+/// What the scripted interleaving left behind
+struct Script {
+    /// Buffer the paused pop returned
+    slow: Option<u32>,
+    /// Buffer the other thread popped and still uses
+    kept: u32,
+    stack: Vec<u32>,
+}
+
+/// Stack A, B, C; pause one pop after it read A and its successor B, then pop A and B and push A back
+fn script(tagged: bool) -> Script {
+    let stack = IndexStack::new(3, tagged);
+    for index in [2, 1, 0] {
+        stack.push_index(index);
+    }
+    let read = Barrier::new(2);
+    let resume = Barrier::new(2);
+
+    thread::scope(|scope| {
+        let slow = scope.spawn(|| {
+            let mut paused = false;
+            stack.pop_with(|| {
+                if !paused {
+                    paused = true;
+                    read.wait();
+                    resume.wait();
+                }
+            })
+        });
+
+        read.wait();
+        let a = stack.pop_with(|| {}).unwrap();
+        let kept = stack.pop_with(|| {}).unwrap();
+        stack.push_index(a);
+        resume.wait();
+
+        Script {
+            slow: slow.join().unwrap(),
+            kept,
+            stack: stack.contents(3),
+        }
+    })
+}
+
+/// Outcome of one pool under the stress run
+struct PoolReport {
+    /// Times a thread popped a buffer another thread was still using
+    conflicts: usize,
+    /// Buffers the pool held at the end, counted with repeats
+    returned: usize,
+    /// Buffers found more than once in the pool at the end
+    duplicates: usize,
+    retries: usize,
+}
+
+/// Let `num_threads` threads take a buffer from the pool, use it and give it back, `rounds` times each
+fn stress<S: ConcurrentStack<u32>>(pool: S, num_threads: usize, rounds: usize) -> PoolReport {
+    for index in 0..POOL as u32 {
+        pool.push(index);
+    }
+    let in_use: Vec<AtomicBool> = (0..POOL).map(|_| AtomicBool::new(false)).collect();
+    let conflicts = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| {
+                for _ in 0..rounds {
+                    let Some(index) = pool.pop() else {
+                        thread::yield_now();
+                        continue;
+                    };
+                    // Owning a buffer is exclusive: finding it already taken means the pool handed it out twice
+                    if in_use[index as usize].swap(true, Ordering::AcqRel) {
+                        conflicts.fetch_add(1, Ordering::Relaxed);
+                    }
+                    thread::yield_now();
+                    in_use[index as usize].store(false, Ordering::Release);
+                    pool.push(index);
+                }
+            });
+        }
+    });
+
+    // Drain with a bound: a corrupted stack can loop back on itself
+    let mut seen = [0usize; POOL];
+    let mut returned = 0;
+    while returned <= 2 * POOL {
+        let Some(index) = pool.pop() else { break };
+        seen[index as usize] += 1;
+        returned += 1;
+    }
+    PoolReport {
+        conflicts: conflicts.load(Ordering::Relaxed),
+        returned,
+        duplicates: seen.iter().filter(|count| **count > 1).count(),
+        retries: pool.cas_retries(),
+    }
+}
+
+/// Run the ABA example: the scripted interleaving, then a stress run of `num_threads` threads making `rounds` rounds each
+pub fn run(num_threads: usize, rounds: usize) {
+    common::print_info("Stack A -> B -> C. Thread 1 starts a pop: it reads head A and successor B, then pauses");
+    common::print_info("Thread 2 pops A, pops B and keeps using it, then pushes A back: the head is A again");
+    println!();
+    for tagged in [false, true] {
+        let outcome = script(tagged);
+        let stack: Vec<String> = outcome.stack.iter().map(|index| name(*index).to_string()).collect();
+        common::print_info(&format!(
+            "{}: thread 1 popped {}, thread 2 holds {}, the stack is now [{}]",
+            if tagged { "Tagged head" } else { "Plain head" },
+            outcome.slow.map_or('-', name),
+            name(outcome.kept),
+            stack.join(" -> ")
+        ));
+        if outcome.stack.contains(&outcome.kept) {
+            common::print_warning(&format!(
+                "  ABA: thread 1's swap saw A and installed its stale successor {}, which thread 2 still uses; the next pop hands {} out twice",
+                name(outcome.kept),
+                name(outcome.kept)
+            ));
+        } else {
+            common::print_success("  The tag changed with every swap, so thread 1's swap failed, and its retry read the real successor");
+        }
+    }
+
+    let num_threads = num_threads.max(2);
+    println!();
+    common::print_info(&format!(
+        "{} threads take a buffer from a pool of {}, use it and give it back, {} times each",
+        num_threads, POOL, rounds
+    ));
+    println!(
+        "{:<18} {:>12} {:>12} {:>12} {:>14} {:>8}",
+        "pool", "conflicts", "returned", "duplicates", "cas retries", "intact"
+    );
+    let reports = [
+        ("plain index", stress(IndexStack::new(POOL, false), num_threads, rounds)),
+        ("tagged index", stress(IndexStack::new(POOL, true), num_threads, rounds)),
+        ("epoch (Treiber)", stress(TreiberStack::new(), num_threads, rounds)),
+    ];
+    for (label, report) in &reports {
+        println!(
+            "{:<18} {:>12} {:>12} {:>12} {:>14} {:>8}",
+            label,
+            report.conflicts,
+            format!("{}/{}", report.returned, POOL),
+            report.duplicates,
+            report.retries,
+            report.conflicts == 0 && report.returned == POOL && report.duplicates == 0
+        );
+    }
+
+    println!();
+    let (plain, tagged, epoch) = (&reports[0].1, &reports[1].1, &reports[2].1);
+    if plain.conflicts > 0 || plain.returned != POOL || plain.duplicates > 0 {
+        common::print_warning("The plain stack handed buffers out twice or lost them: ABA corrupted it under real contention");
+    }
+    if [tagged, epoch].iter().all(|report| report.conflicts == 0 && report.returned == POOL && report.duplicates == 0) {
+        common::print_success("The tagged and epoch-based stacks never handed a buffer out twice and got every buffer back");
+    }
+    common::print_info("A tag makes every head value unique, up to 2^32 swaps; epochs make a node's address unique while any thread might still compare against it");
+    if cpus::available() < 2 {
+        common::print_info("With a single CPU the stress run relies on the yield between a pop's read and its swap to interleave threads");
+    }
+}
@@ -0,0 +1,187 @@
+//! Phased computation separated by `std::sync::Barrier` waits
+//!
+//! Threads smooth a shared row of cells together, one phase at a time: each
+//! thread owns a chunk of the row, and every new value is the average of a
+//! cell and its two neighbours in the previous phase. The cells at the edge
+//! of a chunk need values from the neighbouring chunks, so no thread may
+//! start a phase before every thread finished the previous one. A `Barrier`
+//! enforces exactly that, and it also means the phase lasts as long as its
+//! slowest thread: one thread per phase is made a straggler, and everybody
+//! else's wait at the barrier shows what it costs.
+
+// Base dependencies
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Barrier;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::common;
+use crate::pacing;
+
+/// Number of phases the row is smoothed for
+const PHASES: usize = 8;
+
+/// Extra work the straggler of each phase does
+const STRAGGLER_DELAY: Duration = Duration::from_millis(2);
+
+/// Row of cells stored as f64 bits, so that threads can share it without locks
+struct Row {
+    cells: Vec<AtomicU64>,
+}
+
+impl Row {
+    fn new(values: &[f64]) -> Self {
+        Row {
+            cells: values.iter().map(|value| AtomicU64::new(value.to_bits())).collect(),
+        }
+    }
+
+    fn get(&self, index: usize) -> f64 {
+        f64::from_bits(self.cells[index].load(Ordering::Relaxed))
+    }
+
+    fn set(&self, index: usize, value: f64) {
+        self.cells[index].store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// New value of cell `index`: the average of the cell and its neighbours, the row wrapping around
+fn smooth(len: usize, index: usize, get: impl Fn(usize) -> f64) -> f64 {
+    (get((index + len - 1) % len) + get(index) + get((index + 1) % len)) / 3.0
+}
+
+/// Starting row: a single spike of heat
+fn initial(len: usize) -> Vec<f64> {
+    let mut values = vec![0.0; len];
+    values[len / 2] = 1000.0;
+    values
+}
+
+/// The same smoothing on one thread, to check the phased result against
+fn sequential(len: usize) -> Vec<f64> {
+    let mut values = initial(len);
+    for _ in 0..PHASES {
+        values = (0..len).map(|index| smooth(len, index, |at| values[at])).collect();
+    }
+    values
+}
+
+/// How one thread spent one phase
+#[derive(Clone, Copy)]
+struct PhaseTiming {
+    started: Instant,
+    arrived: Instant,
+    released: Instant,
+}
+
+impl PhaseTiming {
+    /// From the start of the phase to arriving at the barrier
+    fn work(&self) -> Duration {
+        self.arrived - self.started
+    }
+
+    /// From arriving at the barrier to being released
+    fn wait(&self) -> Duration {
+        self.released - self.arrived
+    }
+}
+
+/// Thread that straggles in `phase`
+fn straggler(phase: usize, num_threads: usize) -> usize {
+    phase % num_threads
+}
+
+/// Run the barrier example: `num_threads` threads smoothing `cells_per_thread` cells each over `PHASES` phases
+pub fn run(num_threads: usize, cells_per_thread: usize) {
+    let num_threads = num_threads.max(2);
+    let cells_per_thread = cells_per_thread.max(1);
+    let len = num_threads * cells_per_thread;
+    common::print_info(&format!(
+        "{} threads smooth a row of {} cells over {} phases; each phase reads the previous one's neighbours, and one thread per phase straggles for {:?}",
+        num_threads, len, PHASES, STRAGGLER_DELAY
+    ));
+
+    // Double buffering: a phase reads one row and writes the other, then they swap roles
+    let rows = [Row::new(&initial(len)), Row::new(&vec![0.0; len])];
+    let barrier = Barrier::new(num_threads);
+    let start = Instant::now();
+
+    let timings: Vec<Vec<PhaseTiming>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|thread_id| {
+                let (rows, barrier) = (&rows, &barrier);
+                scope.spawn(move || {
+                    let chunk = thread_id * cells_per_thread..(thread_id + 1) * cells_per_thread;
+                    let mut timings = Vec::with_capacity(PHASES);
+                    for phase in 0..PHASES {
+                        let started = Instant::now();
+                        let (current, next) = (&rows[phase % 2], &rows[(phase + 1) % 2]);
+                        for index in chunk.clone() {
+                            next.set(index, smooth(len, index, |at| current.get(at)));
+                        }
+                        if thread_id == straggler(phase, num_threads) {
+                            pacing::spin_for(STRAGGLER_DELAY);
+                        }
+
+                        // Nobody reads `next` as the current row before every chunk of it is written
+                        let arrived = Instant::now();
+                        barrier.wait();
+                        timings.push(PhaseTiming {
+                            started,
+                            arrived,
+                            released: Instant::now(),
+                        });
+                    }
+                    timings
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let elapsed = start.elapsed();
+
+    println!();
+    println!(
+        "{:<7} {:>10} {:>16} {:>16} {:>14} {:>14}",
+        "phase", "straggler", "straggler work", "others max work", "others wait", "phase time"
+    );
+    let mut total_wait = Duration::ZERO;
+    for phase in 0..PHASES {
+        let slow = straggler(phase, num_threads);
+        let all: Vec<&PhaseTiming> = timings.iter().map(|thread| &thread[phase]).collect();
+        let others: Vec<&PhaseTiming> = (0..num_threads).filter(|id| *id != slow).map(|id| all[id]).collect();
+        let others_wait: Duration = others.iter().map(|timing| timing.wait()).sum();
+        total_wait += all.iter().map(|timing| timing.wait()).sum::<Duration>();
+
+        // The phase runs from its first thread starting to its last thread arriving at the barrier
+        let first_start = all.iter().map(|timing| timing.started).min().unwrap();
+        let last_arrival = all.iter().map(|timing| timing.arrived).max().unwrap();
+        println!(
+            "{:<7} {:>10} {:>16?} {:>16?} {:>14?} {:>14?}",
+            phase,
+            format!("thread {}", slow),
+            all[slow].work(),
+            others.iter().map(|timing| timing.work()).max().unwrap_or_default(),
+            others_wait / others.len() as u32,
+            last_arrival - first_start
+        );
+    }
+
+    println!();
+    let final_row = &rows[PHASES % 2];
+    let expected = sequential(len);
+    if (0..len).all(|index| (final_row.get(index) - expected[index]).abs() < 1e-9) {
+        common::print_success("The phased row matches the sequential one: no thread ever read a neighbour's chunk from the wrong phase");
+    } else {
+        common::print_warning("The phased row differs from the sequential one");
+    }
+    common::print_info(&format!(
+        "Total {:?}; the threads spent {:?} waiting at barriers, {:.0}% of their combined time",
+        elapsed,
+        total_wait,
+        100.0 * total_wait.as_secs_f64() / (elapsed.as_secs_f64() * num_threads as f64)
+    ));
+    common::print_info("Every phase lasts as long as its slowest thread: the others finish early and wait, so one straggler idles the whole group");
+    common::print_info("Balancing the work per thread, or fewer phases with more work each, cuts that waiting; a barrier cannot");
+}
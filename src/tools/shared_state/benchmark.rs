@@ -0,0 +1,193 @@
+//! Benchmark matrix of synchronization primitives, exported as CSV
+//!
+//! The counter workload, every thread incrementing one shared counter, runs
+//! for each primitive at 1, 2, 4, … threads. Each cell is repeated, the
+//! median is printed as a throughput table, and the whole matrix is written
+//! to a CSV file with one row per primitive and thread count, ready to plot.
+
+// Base dependencies
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Project dependencies
+use super::code::{time_counter, AtomicCounter, Counter, SharedCounter, ShardedCounter};
+use super::false_sharing::thread_counts;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+
+/// Runs per cell; the median is reported
+const REPEATS: usize = 5;
+
+impl SharedCounter for parking_lot::Mutex<usize> {
+    fn increment(&self) {
+        chaos::perturb(Point::Lock);
+        *self.lock() += 1;
+    }
+
+    fn get_value(&self) -> usize {
+        *self.lock()
+    }
+}
+
+impl SharedCounter for RwLock<usize> {
+    fn increment(&self) {
+        chaos::perturb(Point::Lock);
+        *self.write().unwrap() += 1;
+    }
+
+    fn get_value(&self) -> usize {
+        *self.read().unwrap()
+    }
+}
+
+/// Synchronization primitive guarding the counter
+#[derive(Clone, Copy)]
+enum Primitive {
+    StdMutex,
+    ParkingLot,
+    RwLock,
+    Atomic,
+    Sharded,
+}
+
+impl Primitive {
+    const ALL: [Primitive; 5] = [
+        Primitive::StdMutex,
+        Primitive::ParkingLot,
+        Primitive::RwLock,
+        Primitive::Atomic,
+        Primitive::Sharded,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Primitive::StdMutex => "std_mutex",
+            Primitive::ParkingLot => "parking_lot",
+            Primitive::RwLock => "rwlock",
+            Primitive::Atomic => "atomic",
+            Primitive::Sharded => "sharded",
+        }
+    }
+
+    fn counter(&self) -> Arc<dyn SharedCounter> {
+        let counter: Arc<dyn SharedCounter> = match self {
+            Primitive::StdMutex => Arc::new(Counter::new()),
+            Primitive::ParkingLot => Arc::new(parking_lot::Mutex::new(0usize)),
+            Primitive::RwLock => Arc::new(RwLock::new(0usize)),
+            Primitive::Atomic => Arc::new(AtomicCounter::new()),
+            Primitive::Sharded => Arc::new(ShardedCounter::new()),
+        };
+        audit::track(&format!("{} benchmark counter", self.label()), &counter);
+        counter
+    }
+}
+
+/// Timings of one cell of the matrix
+struct Cell {
+    primitive: Primitive,
+    threads: usize,
+    /// Every run, sorted
+    runs: Vec<Duration>,
+    correct: bool,
+}
+
+impl Cell {
+    fn median(&self) -> Duration {
+        common::percentile(&self.runs, 50.0)
+    }
+
+    /// Millions of increments per second at the median run
+    fn mops(&self, increments: usize) -> f64 {
+        (self.threads * increments) as f64 / self.median().as_secs_f64().max(f64::EPSILON) / 1e6
+    }
+}
+
+/// Run `REPEATS` timings of `primitive` at `threads` threads
+fn measure(primitive: Primitive, threads: usize, increments: usize) -> Cell {
+    let mut correct = true;
+    let mut runs: Vec<Duration> = (0..REPEATS)
+        .map(|_| {
+            let (elapsed, value) = time_counter(primitive.counter(), threads, increments);
+            correct &= value == threads * increments;
+            elapsed
+        })
+        .collect();
+    runs.sort_unstable();
+    Cell {
+        primitive,
+        threads,
+        runs,
+        correct,
+    }
+}
+
+/// Write the matrix to `path`, one row per cell
+fn write_csv(path: &Path, cells: &[Cell], increments: usize) -> io::Result<()> {
+    let mut csv = String::from("primitive,threads,increments_per_thread,repeats,median_ns,min_ns,max_ns,ns_per_increment,mops_per_second,correct\n");
+    for cell in cells {
+        let total = (cell.threads * increments).max(1);
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.3},{:.3},{}\n",
+            cell.primitive.label(),
+            cell.threads,
+            increments,
+            cell.runs.len(),
+            cell.median().as_nanos(),
+            cell.runs[0].as_nanos(),
+            cell.runs[cell.runs.len() - 1].as_nanos(),
+            cell.median().as_nanos() as f64 / total as f64,
+            cell.mops(increments),
+            cell.correct
+        ));
+    }
+    fs::write(path, csv)
+}
+
+/// Run the benchmark matrix up to `num_threads` threads and write it to `path`
+pub fn run(num_threads: usize, increments: usize, path: &Path) {
+    let counts = thread_counts(num_threads.max(1));
+    common::print_info(&format!(
+        "Every thread makes {} increments of one shared counter, at {:?} threads, median of {} runs per cell",
+        increments, counts, REPEATS
+    ));
+
+    println!();
+    print!("{:<8}", "threads");
+    for primitive in Primitive::ALL {
+        print!(" {:>12}", primitive.label());
+    }
+    println!("   (M increments/s)");
+
+    let mut cells = vec![];
+    for threads in &counts {
+        print!("{:<8}", threads);
+        for primitive in Primitive::ALL {
+            let cell = measure(primitive, *threads, increments);
+            print!(" {:>12.2}", cell.mops(increments));
+            cells.push(cell);
+        }
+        println!();
+    }
+
+    println!();
+    if cells.iter().all(|cell| cell.correct) {
+        common::print_success("Every run counted every increment");
+    } else {
+        common::print_warning("A counter lost increments");
+    }
+    match write_csv(path, &cells, increments) {
+        Ok(()) => common::print_info(&format!("Wrote {} cells to {}", cells.len(), path.display())),
+        Err(error) => common::print_warning(&format!("Could not write the benchmark to {}: {}", path.display(), error)),
+    }
+    if cpus::available() < counts.last().copied().unwrap_or(1) {
+        common::print_warning(&format!(
+            "Only {} CPU(s) available: beyond that the threads take turns, so the matrix shows scheduling rather than contention",
+            cpus::available()
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+//! Compare-and-swap retry loops with per-thread failure counts
+//!
+//! Read-modify-write operations the hardware has no instruction for are
+//! built from a loop: read the current value, compute the new one, and
+//! `compare_exchange` it in, which only succeeds if nobody changed the value
+//! in between. A failed exchange hands back the value that won, and the loop
+//! retries from there. Every failure is work thrown away, and the more
+//! threads update the same variable, the more often the exchange fails.
+//! Here an increment and a max-update are written as such loops, next to the
+//! single-instruction `fetch_add`, and the failures are counted at growing
+//! thread counts.
+
+// Base dependencies
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::false_sharing::thread_counts;
+use crate::common;
+use crate::cpus;
+
+/// Retry counts of one thread's updates
+#[derive(Clone, Copy, Default)]
+struct Attempts {
+    /// Exchanges that failed because another thread changed the value first
+    failed: u64,
+    /// Max-updates that found a larger value already there and wrote nothing
+    skipped: u64,
+}
+
+/// An update every thread applies to the shared value
+#[derive(Clone, Copy)]
+enum Update {
+    /// `fetch_add`: one instruction, never retried
+    FetchAdd,
+    /// Increment as a `compare_exchange` loop
+    CasIncrement,
+    /// Raise the value to a candidate as a `compare_exchange` loop
+    CasMax,
+}
+
+impl Update {
+    const ALL: [Update; 3] = [Update::FetchAdd, Update::CasIncrement, Update::CasMax];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Update::FetchAdd => "fetch_add",
+            Update::CasIncrement => "CAS increment",
+            Update::CasMax => "CAS max",
+        }
+    }
+}
+
+/// Add one to `value`, returning how many exchanges failed on the way
+fn cas_increment(value: &AtomicU64) -> u64 {
+    let mut failed = 0;
+    let mut current = value.load(Ordering::Relaxed);
+    loop {
+        match value.compare_exchange_weak(current, current + 1, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return failed,
+            Err(actual) => {
+                failed += 1;
+                current = actual;
+            }
+        }
+    }
+}
+
+/// Raise `value` to `candidate` unless it is already larger, updating the counts
+fn cas_max(value: &AtomicU64, candidate: u64, attempts: &mut Attempts) {
+    let mut current = value.load(Ordering::Relaxed);
+    loop {
+        // Nothing to write: a larger value is already there, so the loop ends without any exchange
+        if current >= candidate {
+            attempts.skipped += 1;
+            return;
+        }
+        match value.compare_exchange_weak(current, candidate, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return,
+            Err(actual) => {
+                attempts.failed += 1;
+                current = actual;
+            }
+        }
+    }
+}
+
+/// Let `num_threads` threads apply `update` `updates` times each, returning the time, the final value and each thread's attempts
+fn hammer(update: Update, num_threads: usize, updates: usize) -> (Duration, u64, Vec<Attempts>) {
+    let value = AtomicU64::new(0);
+    let start = Instant::now();
+    let attempts = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|thread_id| {
+                let value = &value;
+                scope.spawn(move || {
+                    let mut attempts = Attempts::default();
+                    for item in 0..updates {
+                        match update {
+                            Update::FetchAdd => {
+                                value.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Update::CasIncrement => attempts.failed += cas_increment(value),
+                            Update::CasMax => {
+                                // Candidates rise over time, interleaved between threads, like timestamps
+                                let candidate = (item * num_threads + thread_id) as u64;
+                                cas_max(value, candidate, &mut attempts);
+                            }
+                        }
+                    }
+                    attempts
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    (start.elapsed(), value.load(Ordering::Relaxed), attempts)
+}
+
+/// Run the CAS retry loop example with up to `num_threads` threads
+pub fn run(num_threads: usize, updates: usize) {
+    let max_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "Each thread applies {} updates to one shared AtomicU64, at 1 to {} threads",
+        updates, max_threads
+    ));
+
+    println!();
+    println!(
+        "{:<8} {:<14} {:>12} {:>14} {:>12} {:>12} {:>16} {:>10}",
+        "threads", "update", "time", "updates/s", "failed CAS", "per 1k", "per thread max", "skipped"
+    );
+    let mut correct = true;
+    for threads in thread_counts(max_threads) {
+        for update in Update::ALL {
+            let (elapsed, value, attempts) = hammer(update, threads, updates);
+            let expected = match update {
+                Update::FetchAdd | Update::CasIncrement => (threads * updates) as u64,
+                Update::CasMax => (updates * threads).saturating_sub(1) as u64,
+            };
+            correct &= value == expected;
+
+            let total = (threads * updates) as f64;
+            let failed: u64 = attempts.iter().map(|attempt| attempt.failed).sum();
+            println!(
+                "{:<8} {:<14} {:>12?} {:>14.0} {:>12} {:>12.3} {:>16} {:>10}",
+                threads,
+                update.label(),
+                elapsed,
+                total / elapsed.as_secs_f64(),
+                failed,
+                1000.0 * failed as f64 / total,
+                attempts.iter().map(|attempt| attempt.failed).max().unwrap_or(0),
+                attempts.iter().map(|attempt| attempt.skipped).sum::<u64>()
+            );
+        }
+    }
+
+    println!();
+    if correct {
+        common::print_success("Every loop ended with the exact count or maximum: a failed exchange is retried, never lost");
+    } else {
+        common::print_warning("A final value does not match the updates applied");
+    }
+    common::print_info("fetch_add never fails; a CAS loop fails whenever another thread wrote between its load and its exchange, and each failure costs another round trip to the cache line");
+    common::print_info("A max-update can stop as soon as it sees a larger value, so it skips the write entirely once the maximum outruns its candidates");
+    if cpus::available() < 2 {
+        common::print_warning("With a single CPU a loop only fails when its thread is preempted between the load and the exchange, so failures stay rare");
+    }
+}
@@ -4,30 +4,70 @@
 //! using Arc (Atomic Reference Counting) and Mutex (Mutual Exclusion).
 
 // Base dependencies
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LockResult, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::utils::CachePadded;
 
 // Project dependencies 
+use crate::audit;
+use crate::chaos::{self, Point};
 use crate::common;
+use crate::cpus;
+use crate::CounterStrategy;
+
+/// A counter that can be incremented concurrently from many threads
+pub(crate) trait SharedCounter: Send + Sync {
+    fn increment(&self);
+    fn get_value(&self) -> usize;
+}
 
 /// A simple counter protected by a Mutex
-struct Counter {
+pub(crate) struct Counter {
     value: Mutex<usize>,
 }
 
 // Base method implementations for Counter
 impl Counter {
     // Structure constructor
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Counter {
             value: Mutex::new(0),
         }
     }
 
+    // Lock the value directly, leaving a poisoned lock for the caller to handle
+    pub(crate) fn lock(&self) -> LockResult<MutexGuard<'_, usize>> {
+        self.value.lock()
+    }
+
+    // Mark the value as consistent again after a panic poisoned the lock
+    pub(crate) fn clear_poison(&self) {
+        self.value.clear_poison();
+    }
+
+    // Increment the counter, returning how long we waited for the lock and how long we held it
+    pub(crate) fn increment_timed(&self) -> (Duration, Duration) {
+        let requested = Instant::now();
+        let mut num = self.value.lock().unwrap();
+        let acquired = Instant::now();
+        *num += 1;
+        drop(num);
+        (acquired - requested, acquired.elapsed())
+    }
+
+}
+
+// Counter operations shared with the other strategies
+impl SharedCounter for Counter {
     // Increment the counter safely using the Mutex
     fn increment(&self) {
 
         // Get the safe lock on the counter value
+        chaos::perturb(Point::Lock);
         let mut num = self.value.lock().unwrap();
 
         // Increment the counter
@@ -40,17 +80,131 @@ impl Counter {
     }
 }
 
+/// A counter updated with a single atomic instruction, without any lock
+pub(crate) struct AtomicCounter {
+    value: AtomicUsize,
+}
+
+impl AtomicCounter {
+    // Structure constructor
+    pub(crate) fn new() -> Self {
+        AtomicCounter {
+            value: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SharedCounter for AtomicCounter {
+    // Relaxed is enough: no other memory is published through the counter
+    fn increment(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get_value(&self) -> usize {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Shards per available CPU, so threads rarely share a shard even when oversubscribed
+const SHARDS_PER_CPU: usize = 4;
+
+/// Source of shard indices, handed out round-robin to threads on their first increment
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Shard index of the current thread, taken modulo the shard count of each counter
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A counter split into Mutex-protected shards, one per thread, summed when read
+///
+/// Each thread only ever locks its own shard, so increments from different
+/// threads no longer wait on each other. Shards are cache-line padded so two
+/// threads updating neighbouring shards do not fight over the same line.
+pub(crate) struct ShardedCounter {
+    shards: Vec<CachePadded<Mutex<usize>>>,
+}
+
+impl ShardedCounter {
+    // Structure constructor
+    pub(crate) fn new() -> Self {
+        let shards = (cpus::available() * SHARDS_PER_CPU).max(1);
+        ShardedCounter {
+            shards: (0..shards).map(|_| CachePadded::new(Mutex::new(0))).collect(),
+        }
+    }
+}
+
+impl SharedCounter for ShardedCounter {
+    fn increment(&self) {
+        let shard = SHARD.with(|shard| *shard) % self.shards.len();
+        chaos::perturb(Point::Lock);
+        *self.shards[shard].lock().unwrap() += 1;
+    }
+
+    // Aggregate every shard; only exact once the writers have finished
+    fn get_value(&self) -> usize {
+        self.shards.iter().map(|shard| *shard.lock().unwrap()).sum()
+    }
+}
+
+/// Create a counter for the given strategy
+pub(crate) fn new_counter(strategy: CounterStrategy) -> Arc<dyn SharedCounter> {
+    let counter: Arc<dyn SharedCounter> = match strategy {
+        CounterStrategy::Mutex => Arc::new(Counter::new()),
+        CounterStrategy::Atomic => Arc::new(AtomicCounter::new()),
+        CounterStrategy::Sharded => Arc::new(ShardedCounter::new()),
+    };
+    audit::track(&format!("{} counter", strategy_label(strategy)), &counter);
+    counter
+}
+
+/// Label of a counter strategy in reports
+pub(crate) fn strategy_label(strategy: CounterStrategy) -> &'static str {
+    match strategy {
+        CounterStrategy::Mutex => "mutex",
+        CounterStrategy::Atomic => "atomic",
+        CounterStrategy::Sharded => "sharded",
+    }
+}
+
+/// Time `num_threads` threads incrementing a fresh counter, without progress output
+pub(crate) fn time_strategy(strategy: CounterStrategy, num_threads: usize, increments_per_thread: usize) -> (Duration, usize) {
+    time_counter(new_counter(strategy), num_threads, increments_per_thread)
+}
+
+/// Time `num_threads` threads incrementing `counter`, returning the time and the final value
+pub(crate) fn time_counter(counter: Arc<dyn SharedCounter>, num_threads: usize, increments_per_thread: usize) -> (Duration, usize) {
+    let start = Instant::now();
+    let handles: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || {
+                for _ in 0..increments_per_thread {
+                    counter.increment();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (start.elapsed(), counter.get_value())
+}
+
 /// Run the shared state example
-pub fn run(num_threads: usize, increments_per_thread: usize) {
+pub fn run(num_threads: usize, increments_per_thread: usize, strategy: CounterStrategy) {
 
     // Log the parameters of the test
     common::print_info(&format!(
-        "Creating {} threads, each incrementing a counter {} times",
-        num_threads, increments_per_thread
+        "Creating {} threads, each incrementing a {} counter {} times",
+        num_threads,
+        strategy_label(strategy),
+        increments_per_thread
     ));
 
     // Create a shared counter wrapped in an Arc to allow multiple ownership across threads
-    let counter = Arc::new(Counter::new());
+    let counter = new_counter(strategy);
 
     // Vector to hold the thread handles so we can wait for them to finish
     let mut handles = vec![];
@@ -100,10 +254,31 @@ pub fn run(num_threads: usize, increments_per_thread: usize) {
     if final_value == expected_value {
         common::print_success("✅ Counter is correct! No race conditions detected.");
     } else {
-        common::print_warning("⚠️  Counter mismatch! This should not happen with Mutex.");
+        common::print_warning(&format!("⚠️  Counter mismatch! This should not happen with the {} strategy.", strategy_label(strategy)));
     }
 
     // Log the total time taken for the increments
     common::print_info(&format!("Total time: {:?}", duration));
 
+    // Benchmark every strategy under the same configuration, without progress output
+    println!();
+    common::print_info("Comparing counter strategies with the same threads and increments");
+    println!("{:<10} {:>14} {:>14} {:>10} {:>10}", "strategy", "time", "ns/increment", "vs mutex", "correct");
+    let total = (num_threads * increments_per_thread).max(1);
+    let mut mutex_time = None;
+    for strategy in CounterStrategy::ALL {
+        let (elapsed, value) = time_strategy(strategy, num_threads, increments_per_thread);
+        let baseline = *mutex_time.get_or_insert(elapsed);
+        println!(
+            "{:<10} {:>14?} {:>14.1} {:>9.1}x {:>10}",
+            strategy_label(strategy),
+            elapsed,
+            elapsed.as_nanos() as f64 / total as f64,
+            baseline.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON),
+            value == expected_value
+        );
+    }
+    common::print_info("A Mutex pays for lock and unlock around a one-instruction critical section; fetch_add is that instruction alone");
+    common::print_info("Sharding keeps the lock but gives every thread its own, so the shared cache line and the waiting disappear");
+
 }
@@ -0,0 +1,194 @@
+//! Concurrent hash map comparison
+//!
+//! Threads insert fresh keys and look up existing ones in a shared map.
+//! A `Mutex<HashMap>` serializes every operation, a `RwLock<HashMap>` lets
+//! lookups run side by side but still serializes inserts, and `DashMap`
+//! splits the map into independently locked shards.
+
+// Base dependencies
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use dashmap::DashMap;
+use rand::Rng;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Keys inserted before the timed run, which every lookup targets
+const PRELOADED_KEYS: u64 = 10_000;
+
+/// Lookups performed for every insert
+const READS_PER_WRITE: usize = 4;
+
+/// A map that can be read and written concurrently from many threads
+trait ConcurrentMap: Send + Sync {
+    fn insert(&self, key: u64, value: u64);
+    fn get(&self, key: u64) -> Option<u64>;
+    fn len(&self) -> usize;
+}
+
+impl ConcurrentMap for Mutex<HashMap<u64, u64>> {
+    fn insert(&self, key: u64, value: u64) {
+        chaos::perturb(Point::Lock);
+        self.lock().unwrap().insert(key, value);
+    }
+
+    fn get(&self, key: u64) -> Option<u64> {
+        chaos::perturb(Point::Lock);
+        self.lock().unwrap().get(&key).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.lock().unwrap().len()
+    }
+}
+
+impl ConcurrentMap for RwLock<HashMap<u64, u64>> {
+    fn insert(&self, key: u64, value: u64) {
+        chaos::perturb(Point::Lock);
+        self.write().unwrap().insert(key, value);
+    }
+
+    fn get(&self, key: u64) -> Option<u64> {
+        chaos::perturb(Point::Lock);
+        self.read().unwrap().get(&key).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.read().unwrap().len()
+    }
+}
+
+// DashMap locks only the shard holding the key
+impl ConcurrentMap for DashMap<u64, u64> {
+    fn insert(&self, key: u64, value: u64) {
+        chaos::perturb(Point::Lock);
+        DashMap::insert(self, key, value);
+    }
+
+    fn get(&self, key: u64) -> Option<u64> {
+        chaos::perturb(Point::Lock);
+        DashMap::get(self, &key).map(|entry| *entry)
+    }
+
+    fn len(&self) -> usize {
+        DashMap::len(self)
+    }
+}
+
+/// Outcome of one map under the workload
+struct MapReport {
+    elapsed: Duration,
+    operations: usize,
+    entries: usize,
+    hits: usize,
+    lookups: usize,
+}
+
+/// Value stored for a key, so lookups can check they read what was written
+fn value_of(key: u64) -> u64 {
+    key.wrapping_mul(31).wrapping_add(7)
+}
+
+/// Preload the map, then let every thread mix inserts of its own keys with lookups of preloaded ones
+fn run_map<M: ConcurrentMap + 'static>(name: &str, map: M, num_threads: usize, operations_per_thread: usize) -> MapReport {
+    for key in 0..PRELOADED_KEYS {
+        map.insert(key, value_of(key));
+    }
+    let map = Arc::new(map);
+    audit::track(name, &map);
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..num_threads)
+        .map(|thread_id| {
+            let map = Arc::clone(&map);
+            thread::spawn(move || {
+                let mut rng = rand::thread_rng();
+                let mut next_key = PRELOADED_KEYS + (thread_id * operations_per_thread) as u64;
+                let (mut hits, mut lookups) = (0, 0);
+                for operation in 0..operations_per_thread {
+                    if operation % (READS_PER_WRITE + 1) == 0 {
+                        map.insert(next_key, value_of(next_key));
+                        next_key += 1;
+                    } else {
+                        let key = rng.gen_range(0..PRELOADED_KEYS);
+                        lookups += 1;
+                        if map.get(key) == Some(value_of(key)) {
+                            hits += 1;
+                        }
+                    }
+                }
+                (hits, lookups)
+            })
+        })
+        .collect();
+
+    let (mut hits, mut lookups) = (0, 0);
+    for handle in handles {
+        let (thread_hits, thread_lookups) = handle.join().unwrap();
+        hits += thread_hits;
+        lookups += thread_lookups;
+    }
+
+    MapReport {
+        elapsed: start.elapsed(),
+        operations: num_threads * operations_per_thread,
+        entries: map.len(),
+        hits,
+        lookups,
+    }
+}
+
+/// Run the concurrent map comparison
+pub fn run(num_threads: usize, operations_per_thread: usize) {
+    let inserts_per_thread = operations_per_thread.div_ceil(READS_PER_WRITE + 1);
+    let expected_entries = PRELOADED_KEYS as usize + num_threads * inserts_per_thread;
+    common::print_info(&format!(
+        "{} threads perform {} operations each on a map preloaded with {} keys ({} lookups per insert)",
+        num_threads, operations_per_thread, PRELOADED_KEYS, READS_PER_WRITE
+    ));
+
+    println!();
+    println!(
+        "{:<20} {:>12} {:>14} {:>10} {:>16} {:>8}",
+        "map", "time", "ops/s", "entries", "hits", "correct"
+    );
+
+    let reports = [
+        ("Mutex<HashMap>", run_map("Mutex<HashMap>", Mutex::new(HashMap::new()), num_threads, operations_per_thread)),
+        ("RwLock<HashMap>", run_map("RwLock<HashMap>", RwLock::new(HashMap::new()), num_threads, operations_per_thread)),
+        ("DashMap", run_map("DashMap", DashMap::new(), num_threads, operations_per_thread)),
+    ];
+
+    let mut all_correct = true;
+    for (name, report) in &reports {
+        let correct = report.entries == expected_entries && report.hits == report.lookups;
+        all_correct &= correct;
+        println!(
+            "{:<20} {:>12?} {:>14.0} {:>10} {:>16} {:>8}",
+            name,
+            report.elapsed,
+            report.operations as f64 / report.elapsed.as_secs_f64(),
+            report.entries,
+            format!("{}/{}", report.hits, report.lookups),
+            correct
+        );
+    }
+
+    println!();
+    if all_correct {
+        common::print_success(&format!(
+            "Every map ended with {} entries and every lookup found the value that was written",
+            expected_entries
+        ));
+    } else {
+        common::print_warning(&format!("A map lost an insert or returned a wrong value (expected {} entries)", expected_entries));
+    }
+    common::print_info("RwLock only helps while nobody writes; DashMap's sharded locks let inserts and lookups on different shards proceed together");
+}
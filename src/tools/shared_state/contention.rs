@@ -0,0 +1,176 @@
+//! Lock contention heatmap over time
+//!
+//! A single end-of-run number hides how contention evolves. Here threads
+//! come online one after the other, each timing how long it waits for and
+//! holds the counter's Mutex, and the samples are bucketed over time to
+//! print a heatmap where contention visibly ramps up with every new thread.
+
+// Base dependencies
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::code::{Counter, SharedCounter};
+use crate::common;
+use crate::pacing::{self, PacingStrategy};
+
+/// Maximum number of time columns printed in the heatmap
+const COLUMNS: usize = 48;
+
+/// Number of raw buckets recorded during the time one thread needs alone
+const BUCKETS_PER_SOLO_RUN: u32 = 16;
+
+/// Shades from no contention to the worst bucket of the run
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// Lock timings accumulated over one time bucket
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    wait: Duration,
+    hold: Duration,
+    acquisitions: u32,
+}
+
+impl Bucket {
+    fn merge(&mut self, other: &Bucket) {
+        self.wait += other.wait;
+        self.hold += other.hold;
+        self.acquisitions += other.acquisitions;
+    }
+
+    fn average_wait(&self) -> Option<Duration> {
+        (self.acquisitions > 0).then(|| self.wait / self.acquisitions)
+    }
+}
+
+/// Merge raw buckets into at most `columns` columns
+fn rebucket(raw: &[Bucket], raw_per_column: usize, columns: usize) -> Vec<Bucket> {
+    let mut merged = vec![Bucket::default(); columns];
+    for (index, bucket) in raw.iter().enumerate() {
+        merged[(index / raw_per_column).min(columns - 1)].merge(bucket);
+    }
+    merged
+}
+
+/// Pick the shade of a cell on a log scale between the best and worst average waits of the run
+fn shade(bucket: &Bucket, best: Duration, worst: Duration) -> char {
+    let Some(wait) = bucket.average_wait() else {
+        return ' ';
+    };
+    if worst <= best {
+        return SHADES[0];
+    }
+
+    // Waits span orders of magnitude, so a linear scale would leave everything but the worst cell blank
+    let ratio = (wait.as_secs_f64() / best.as_secs_f64()).ln() / (worst.as_secs_f64() / best.as_secs_f64()).ln();
+    SHADES[((ratio.max(0.0) * (SHADES.len() - 1) as f64).round() as usize).min(SHADES.len() - 1)]
+}
+
+/// Run the contention heatmap example
+pub fn run(num_threads: usize, increments_per_thread: usize) {
+    let num_threads = num_threads.max(2);
+
+    // Calibrate: how long does one thread need for its increments with no contention?
+    let solo = Counter::new();
+    let start = Instant::now();
+    for _ in 0..increments_per_thread {
+        solo.increment_timed();
+    }
+    let solo_time = start.elapsed().max(Duration::from_micros(1));
+    let ramp_step = solo_time / 2;
+    let bucket_width = (solo_time / BUCKETS_PER_SOLO_RUN).max(Duration::from_nanos(100));
+
+    common::print_info(&format!(
+        "{} threads increment the counter {} times each; one more thread comes online every {:?}",
+        num_threads, increments_per_thread, ramp_step
+    ));
+
+    // Each thread waits for its turn, then records lock timings into its own time buckets
+    let counter = Arc::new(Counter::new());
+    let start = Instant::now() + Duration::from_millis(1);
+    let mut handles = vec![];
+    for thread_id in 0..num_threads {
+        let counter = Arc::clone(&counter);
+        let handle = thread::spawn(move || {
+            pacing::wait_until(start + ramp_step * thread_id as u32, PacingStrategy::Hybrid);
+            let mut buckets: Vec<Bucket> = vec![];
+            for _ in 0..increments_per_thread {
+                let (wait, hold) = counter.increment_timed();
+                let index = (start.elapsed().as_nanos() / bucket_width.as_nanos()) as usize;
+                if buckets.len() <= index {
+                    buckets.resize(index + 1, Bucket::default());
+                }
+                let bucket = &mut buckets[index];
+                bucket.wait += wait;
+                bucket.hold += hold;
+                bucket.acquisitions += 1;
+            }
+            buckets
+        });
+        handles.push(handle);
+    }
+
+    let raw: Vec<Vec<Bucket>> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    let elapsed = start.elapsed();
+
+    // Squeeze the raw buckets into the printable width
+    let raw_len = raw.iter().map(Vec::len).max().unwrap_or(1);
+    let raw_per_column = raw_len.div_ceil(COLUMNS).max(1);
+    let columns = raw_len.div_ceil(raw_per_column);
+    let rows: Vec<Vec<Bucket>> = raw.iter().map(|buckets| rebucket(buckets, raw_per_column, columns)).collect();
+    let mut total = vec![Bucket::default(); columns];
+    for row in &rows {
+        for (column, bucket) in row.iter().enumerate() {
+            total[column].merge(bucket);
+        }
+    }
+    let averages: Vec<Duration> = rows.iter().flatten().filter_map(Bucket::average_wait).collect();
+    let best = averages.iter().min().copied().unwrap_or_default().max(Duration::from_nanos(1));
+    let worst = averages.iter().max().copied().unwrap_or_default();
+
+    println!();
+    common::print_info(&format!(
+        "Average lock wait per {:?} column (darker = longer wait, blank = thread not running)",
+        bucket_width * raw_per_column as u32
+    ));
+    println!();
+    for (thread_id, row) in rows.iter().enumerate() {
+        let cells: String = row.iter().map(|bucket| shade(bucket, best, worst)).collect();
+        println!("thread {:>3} |{}|", thread_id, cells);
+    }
+    let cells: String = total.iter().map(|bucket| shade(bucket, best, worst)).collect();
+    println!("{:>10} |{}|", "all", cells);
+
+    // Per-column numbers for the combined row, every few columns to keep it readable
+    println!();
+    println!("{:>10} {:>8} {:>14} {:>12} {:>12}", "t", "active", "acquisitions", "avg wait", "avg hold");
+    let step = (columns / 8).max(1);
+    for column in (0..columns).step_by(step) {
+        let bucket = &total[column];
+        let active = rows.iter().filter(|row| row[column].acquisitions > 0).count();
+        println!(
+            "{:>10?} {:>8} {:>14} {:>12?} {:>12?}",
+            bucket_width * (column * raw_per_column) as u32,
+            active,
+            bucket.acquisitions,
+            bucket.average_wait().unwrap_or_default(),
+            bucket.hold / bucket.acquisitions.max(1)
+        );
+    }
+
+    println!();
+    common::print_success(&format!(
+        "Counter reached {} in {:?} (expected {})",
+        counter.get_value(),
+        elapsed,
+        num_threads * increments_per_thread
+    ));
+    common::print_info(&format!(
+        "Legend: {} log scale from the best ({:?}) to the worst ({:?}) column average",
+        SHADES.iter().collect::<String>(),
+        best,
+        worst
+    ));
+    common::print_info("Hold time barely moves while wait time grows with every thread that comes online");
+}
@@ -0,0 +1,272 @@
+//! Copy-on-write versions of a large structure behind an `Arc`
+//!
+//! A writer that changes a large structure in place has to hold the lock for
+//! the whole change, and every reader arriving meanwhile waits for it: most
+//! reads are fast, and a few take as long as a full update. With
+//! copy-on-write the current version sits in an `Arc`. A reader clones the
+//! `Arc`, which only holds a lock for a reference count increment, and then
+//! reads its snapshot at leisure. The writer clones the structure with
+//! `Arc::make_mut`, changes the copy without any lock, and publishes it by
+//! swapping the `Arc` in one short critical section. Readers that still hold
+//! the previous version keep it alive until they drop it.
+
+// Base dependencies
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+
+/// Entries in the structure, large enough that an update takes a while
+const ENTRIES: usize = 1 << 18;
+
+/// Pause between two updates
+const UPDATE_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Entries each read looks up
+const LOOKUPS: usize = 16;
+
+/// A read slower than this waited for something other than its own lookups
+const SLOW_READ: Duration = Duration::from_micros(100);
+
+/// A large versioned structure; every entry holds the version, so a torn read is easy to spot
+#[derive(Clone)]
+struct Catalog {
+    version: u64,
+    prices: Vec<u64>,
+}
+
+impl Catalog {
+    fn new() -> Self {
+        Catalog {
+            version: 0,
+            prices: vec![0; ENTRIES],
+        }
+    }
+
+    /// Move to the next version, rewriting every entry
+    fn reprice(&mut self) {
+        self.version += 1;
+        for price in self.prices.iter_mut() {
+            *price = self.version;
+        }
+    }
+
+    /// Look up `LOOKUPS` entries from `seed`, returning whether they all belong to the catalog's version
+    fn lookup(&self, seed: &mut u64) -> bool {
+        (0..LOOKUPS).all(|_| {
+            // xorshift: cheap pseudo-random positions, so reads touch the whole structure
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            self.prices[*seed as usize % ENTRIES] == self.version
+        })
+    }
+}
+
+/// Shared catalog that readers look into and one writer updates
+trait VersionedStore: Send + Sync {
+    fn read<R>(&self, read: impl FnOnce(&Catalog) -> R) -> R;
+
+    /// Publish the next version
+    fn update(&self);
+
+    /// Most readers seen still holding the previous version when a new one was published
+    fn retained(&self) -> usize {
+        0
+    }
+}
+
+/// In-place mutation: the writer holds the write lock while it rewrites the catalog
+impl VersionedStore for RwLock<Catalog> {
+    fn read<R>(&self, read: impl FnOnce(&Catalog) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        read(&self.read().unwrap())
+    }
+
+    fn update(&self) {
+        chaos::perturb(Point::Lock);
+        self.write().unwrap().reprice();
+    }
+}
+
+/// Copy-on-write: readers clone the current `Arc`, the writer publishes a modified copy
+struct CopyOnWrite {
+    current: Mutex<Arc<Catalog>>,
+    retained: AtomicUsize,
+}
+
+impl VersionedStore for CopyOnWrite {
+    fn read<R>(&self, read: impl FnOnce(&Catalog) -> R) -> R {
+        chaos::perturb(Point::Lock);
+
+        // The lock is only held to bump the reference count; the read itself runs on the snapshot
+        let snapshot = Arc::clone(&self.current.lock().unwrap());
+        read(&snapshot)
+    }
+
+    fn update(&self) {
+        let mut next = Arc::clone(&self.current.lock().unwrap());
+
+        // The published version is shared, so make_mut copies it, without holding any lock
+        Arc::make_mut(&mut next).reprice();
+
+        chaos::perturb(Point::Lock);
+        let previous = mem::replace(&mut *self.current.lock().unwrap(), next);
+        self.retained.fetch_max(Arc::strong_count(&previous) - 1, Ordering::Relaxed);
+    }
+
+    fn retained(&self) -> usize {
+        self.retained.load(Ordering::Relaxed)
+    }
+}
+
+/// Outcome of one store under the workload
+struct CowReport {
+    /// Latency of every read, sorted
+    latencies: Vec<Duration>,
+    reads_per_second: f64,
+    updates: usize,
+    /// Average time the writer needed per update
+    update_time: Duration,
+    retained: usize,
+    torn: usize,
+}
+
+/// Let `num_readers` readers do `reads_per_reader` reads each while a writer keeps updating the catalog
+fn run_store<S: VersionedStore + 'static>(name: &str, store: S, num_readers: usize, reads_per_reader: usize) -> CowReport {
+    let store = Arc::new(store);
+    audit::track(name, &store);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writer = {
+        let (store, done) = (Arc::clone(&store), Arc::clone(&done));
+        thread::spawn(move || {
+            let (mut updates, mut busy) = (0, Duration::ZERO);
+            while !done.load(Ordering::Relaxed) {
+                thread::sleep(UPDATE_INTERVAL);
+                let start = Instant::now();
+                store.update();
+                busy += start.elapsed();
+                updates += 1;
+            }
+            (updates, busy)
+        })
+    };
+
+    let start = Instant::now();
+    let readers: Vec<_> = (0..num_readers)
+        .map(|reader| {
+            let store = Arc::clone(&store);
+            thread::spawn(move || {
+                let mut seed = (reader as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15).wrapping_add(1);
+                let mut latencies = Vec::with_capacity(reads_per_reader);
+                let mut torn = 0;
+                for _ in 0..reads_per_reader {
+                    let start = Instant::now();
+                    if !store.read(|catalog| catalog.lookup(&mut seed)) {
+                        torn += 1;
+                    }
+                    latencies.push(start.elapsed());
+                }
+                (latencies, torn)
+            })
+        })
+        .collect();
+
+    let mut latencies = vec![];
+    let mut torn = 0;
+    for reader in readers {
+        let (reader_latencies, reader_torn) = reader.join().unwrap();
+        latencies.extend(reader_latencies);
+        torn += reader_torn;
+    }
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::Relaxed);
+    let (updates, busy) = writer.join().unwrap();
+    latencies.sort_unstable();
+
+    CowReport {
+        reads_per_second: latencies.len() as f64 / elapsed.as_secs_f64(),
+        latencies,
+        updates,
+        update_time: busy / updates.max(1) as u32,
+        retained: store.retained(),
+        torn,
+    }
+}
+
+/// Run the copy-on-write example with `num_threads` readers doing `reads_per_reader` reads each
+pub fn run(num_threads: usize, reads_per_reader: usize) {
+    let num_readers = num_threads.max(1);
+    common::print_info(&format!(
+        "{} readers look up {} entries of a {}-entry catalog {} times each, while a writer rewrites every entry every {:?}",
+        num_readers, LOOKUPS, ENTRIES, reads_per_reader, UPDATE_INTERVAL
+    ));
+
+    let reports = [
+        ("in place (RwLock)", run_store("in-place catalog", RwLock::new(Catalog::new()), num_readers, reads_per_reader)),
+        (
+            "copy-on-write",
+            run_store(
+                "copy-on-write catalog",
+                CopyOnWrite {
+                    current: Mutex::new(Arc::new(Catalog::new())),
+                    retained: AtomicUsize::new(0),
+                },
+                num_readers,
+                reads_per_reader,
+            ),
+        ),
+    ];
+
+    println!();
+    println!(
+        "{:<18} {:>12} {:>10} {:>10} {:>10} {:>12} {:>11} {:>9} {:>12} {:>6}",
+        "store", "reads/s", "p50", "p99", "p99.9", "max", "slow reads", "updates", "update time", "torn"
+    );
+    for (name, report) in &reports {
+        println!(
+            "{:<18} {:>12.0} {:>10?} {:>10?} {:>10?} {:>12?} {:>11} {:>9} {:>12?} {:>6}",
+            name,
+            report.reads_per_second,
+            common::percentile(&report.latencies, 50.0),
+            common::percentile(&report.latencies, 99.0),
+            common::percentile(&report.latencies, 99.9),
+            report.latencies.last().copied().unwrap_or_default(),
+            report.latencies.iter().filter(|latency| **latency > SLOW_READ).count(),
+            report.updates,
+            report.update_time,
+            report.torn
+        );
+    }
+
+    println!();
+    if reports.iter().all(|(_, report)| report.torn == 0) {
+        common::print_success("Every read saw a single version of the catalog");
+    } else {
+        common::print_warning("A read saw entries from two different versions");
+    }
+    let (in_place, cow) = (&reports[0].1, &reports[1].1);
+    let tail = |report: &CowReport| common::percentile(&report.latencies, 99.9).as_secs_f64() / common::percentile(&report.latencies, 50.0).as_secs_f64().max(f64::EPSILON);
+    common::print_info(&format!(
+        "Jitter (p99.9 / p50): {:.0}x in place, {:.0}x with copy-on-write",
+        tail(in_place),
+        tail(cow)
+    ));
+    common::print_info(&format!(
+        "Up to {} reader(s) still held the previous version when a new one was published; it was freed when the last of them let go",
+        cow.retained
+    ));
+    common::print_info("In place, a reader arriving during an update waits for the whole rewrite; with copy-on-write it only waits for a reference count bump");
+    common::print_info("The writer pays instead: every update copies the whole catalog, and old versions stay in memory while readers hold them");
+    if cpus::available() < 2 {
+        common::print_warning("With a single CPU no reader runs while the writer does, so the slow reads are preemptions rather than waits for the lock");
+    }
+}
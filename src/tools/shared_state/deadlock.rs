@@ -0,0 +1,281 @@
+//! Deadlock from inconsistent lock ordering, detected by a watchdog
+//!
+//! Two threads each take one lock and then want the other's: neither can
+//! ever proceed. A watchdog notices that nothing progresses, rebuilds the
+//! wait-for cycle from what each thread holds and wants, and explains it.
+//! The same workload then runs correctly with a consistent lock order, and
+//! with `try_lock` plus backoff, both checked by the lock-order validator,
+//! which also catches the opposite orders on a run that happens not to hang.
+
+// Base dependencies
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::lock_order::{self, OrderedMutex};
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// How long nothing may progress before the watchdog declares a deadlock
+const WATCHDOG_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often the watchdog checks for progress
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The two locks every thread needs, and what each thread holds or waits for
+struct Resources {
+    locks: [(&'static str, Mutex<u64>); 2],
+    holding: Mutex<HashMap<String, &'static str>>,
+    waiting: Mutex<HashMap<String, &'static str>>,
+    progress: AtomicUsize,
+    give_up: AtomicBool,
+}
+
+impl Resources {
+    fn new() -> Self {
+        Resources {
+            locks: [("A", Mutex::new(0)), ("B", Mutex::new(0))],
+            holding: Mutex::new(HashMap::new()),
+            waiting: Mutex::new(HashMap::new()),
+            progress: AtomicUsize::new(0),
+            give_up: AtomicBool::new(false),
+        }
+    }
+
+    /// Block on a lock like `lock()` would, but let the watchdog call the wait off
+    ///
+    /// A real `lock()` in a deadlock never returns; polling `try_lock` is only
+    /// there so the demo threads can be released and joined afterwards.
+    fn lock(&self, index: usize) -> Option<MutexGuard<'_, u64>> {
+        let (name, lock) = &self.locks[index];
+        let me = thread::current().name().unwrap_or("?").to_string();
+        self.waiting.lock().unwrap().insert(me.clone(), name);
+        loop {
+            match lock.try_lock() {
+                Ok(guard) => {
+                    self.waiting.lock().unwrap().remove(&me);
+                    self.holding.lock().unwrap().insert(me, name);
+                    self.progress.fetch_add(1, Ordering::Relaxed);
+                    return Some(guard);
+                }
+                Err(TryLockError::WouldBlock) if !self.give_up.load(Ordering::Relaxed) => {
+                    thread::sleep(Duration::from_micros(100));
+                }
+                Err(TryLockError::WouldBlock) => return None,
+                Err(TryLockError::Poisoned(error)) => panic!("lock {} poisoned: {}", name, error),
+            }
+        }
+    }
+
+    /// Follow "waits for a lock held by" edges from every waiting thread to find a cycle
+    fn find_cycle(&self) -> Option<Vec<(String, &'static str)>> {
+        let holding = self.holding.lock().unwrap();
+        let waiting = self.waiting.lock().unwrap();
+        let owner_of = |lock: &str| holding.iter().find(|(_, held)| **held == lock).map(|(thread, _)| thread.clone());
+
+        for start in waiting.keys() {
+            let mut path: Vec<(String, &'static str)> = vec![];
+            let mut current = start.clone();
+            while let Some(wanted) = waiting.get(&current) {
+                if let Some(index) = path.iter().position(|(thread, _)| *thread == current) {
+                    return Some(path.split_off(index));
+                }
+                path.push((current.clone(), wanted));
+                match owner_of(wanted) {
+                    Some(owner) => current = owner,
+                    None => break,
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Watch the progress counter until `done`, explaining the deadlock if progress stalls
+fn watchdog(resources: Arc<Resources>, done: Arc<AtomicBool>) -> thread::JoinHandle<bool> {
+    thread::spawn(move || {
+        let mut last_progress = resources.progress.load(Ordering::Relaxed);
+        let mut stalled_since = Instant::now();
+        while !done.load(Ordering::Acquire) {
+            thread::sleep(WATCHDOG_INTERVAL);
+            let progress = resources.progress.load(Ordering::Relaxed);
+            if progress != last_progress {
+                last_progress = progress;
+                stalled_since = Instant::now();
+                continue;
+            }
+            if stalled_since.elapsed() < WATCHDOG_TIMEOUT {
+                continue;
+            }
+
+            // No lock was acquired for a while: look for a cycle in the wait-for graph
+            if let Some(cycle) = resources.find_cycle() {
+                common::print_warning(&format!("Watchdog: no progress for {:?}, deadlock detected", stalled_since.elapsed()));
+                let holding = resources.holding.lock().unwrap();
+                for (thread, wanted) in &cycle {
+                    common::print_warning(&format!(
+                        "  {} holds lock {} and waits for lock {}",
+                        thread,
+                        holding.get(thread).copied().unwrap_or("-"),
+                        wanted
+                    ));
+                }
+                common::print_info("Each thread waits for a lock the next one holds, so none of them can ever continue");
+                resources.give_up.store(true, Ordering::Relaxed);
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Two threads take the locks in opposite orders, and both grab their first lock before the second
+fn run_deadlock() {
+    let resources = Arc::new(Resources::new());
+    let done = Arc::new(AtomicBool::new(false));
+    let both_hold_one = Arc::new(Barrier::new(2));
+    let watchdog = watchdog(Arc::clone(&resources), Arc::clone(&done));
+
+    let handles: Vec<_> = [(0, 1), (1, 0)]
+        .into_iter()
+        .enumerate()
+        .map(|(id, (first, second))| {
+            let resources = Arc::clone(&resources);
+            let both_hold_one = Arc::clone(&both_hold_one);
+            thread::Builder::new()
+                .name(format!("thread-{}", id))
+                .spawn(move || {
+                    let Some(mut first_guard) = resources.lock(first) else { return false };
+                    both_hold_one.wait();
+                    let Some(mut second_guard) = resources.lock(second) else { return false };
+                    *first_guard += 1;
+                    *second_guard += 1;
+                    true
+                })
+                .unwrap()
+        })
+        .collect();
+
+    let finished = handles.into_iter().map(|handle| handle.join().unwrap()).filter(|ok| *ok).count();
+    done.store(true, Ordering::Release);
+    let detected = watchdog.join().unwrap();
+
+    if detected {
+        common::print_info(&format!("The watchdog called off the waits; {} of 2 threads finished their work", finished));
+    } else {
+        common::print_success("Both threads finished: the interleaving avoided the deadlock this time");
+    }
+}
+
+/// Run the workload with a fixed global order, or with try-lock and backoff, returning the retries
+fn run_fixed(num_threads: usize, iterations: usize, use_try_lock: bool) -> (Duration, usize, u64) {
+    let locks = Arc::new([OrderedMutex::new("A", 0u64), OrderedMutex::new("B", 0u64)]);
+    let retries = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+
+    let handles: Vec<_> = (0..num_threads)
+        .map(|id| {
+            let locks = Arc::clone(&locks);
+            let retries = Arc::clone(&retries);
+            thread::Builder::new()
+                .name(format!("worker-{}", id))
+                .spawn(move || {
+                    // Half of the threads still want the locks in the opposite order
+                    let (first, second) = if id % 2 == 0 { (0, 1) } else { (1, 0) };
+                    for _ in 0..iterations {
+                        if use_try_lock {
+                            loop {
+                                chaos::perturb(Point::Lock);
+                                let mut first_guard = locks[first].lock();
+                                chaos::perturb(Point::Lock);
+                                match locks[second].try_lock() {
+                                    Some(mut second_guard) => {
+                                        *first_guard += 1;
+                                        *second_guard += 1;
+                                        break;
+                                    }
+                                    None => {
+                                        // Back off: release what we hold so the other thread can finish
+                                        drop(first_guard);
+                                        retries.fetch_add(1, Ordering::Relaxed);
+                                        thread::yield_now();
+                                    }
+                                }
+                            }
+                        } else {
+                            // Every thread locks the lower index first, whatever order it wanted
+                            let (low, high) = (first.min(second), first.max(second));
+                            chaos::perturb(Point::Lock);
+                            let mut low_guard = locks[low].lock();
+                            chaos::perturb(Point::Lock);
+                            let mut high_guard = locks[high].lock();
+                            *low_guard += 1;
+                            *high_guard += 1;
+                        }
+                    }
+                })
+                .unwrap()
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let total = *locks[0].lock() + *locks[1].lock();
+    (start.elapsed(), retries.load(Ordering::Relaxed), total)
+}
+
+/// Take the locks in opposite orders again, but one thread after the other, so the run cannot hang
+fn run_lucky() {
+    let locks = Arc::new([OrderedMutex::new("A", 0u64), OrderedMutex::new("B", 0u64)]);
+    for (id, (first, second)) in [(0, 1), (1, 0)].into_iter().enumerate() {
+        let locks = Arc::clone(&locks);
+        thread::Builder::new()
+            .name(format!("thread-{}", id))
+            .spawn(move || {
+                let mut first_guard = locks[first].lock();
+                let mut second_guard = locks[second].lock();
+                *first_guard += 1;
+                *second_guard += 1;
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
+
+/// Run the deadlock demonstration, then both fixes
+pub fn run(num_threads: usize, iterations: usize) {
+    let num_threads = num_threads.max(2);
+
+    common::print_info("Thread 0 locks A then B, thread 1 locks B then A, and both take their first lock before the second");
+    run_deadlock();
+
+    println!();
+    common::print_info(&format!(
+        "Same opposite-order workload with {} threads and {} iterations each, fixed two ways",
+        num_threads, iterations
+    ));
+    println!("{:<16} {:>14} {:>10} {:>12} {:>14}", "fix", "time", "retries", "correct", "order cycles");
+    let expected = 2 * (num_threads * iterations) as u64;
+    for (label, use_try_lock) in [("lock ordering", false), ("try_lock+backoff", true)] {
+        lock_order::reset();
+        let (elapsed, retries, total) = run_fixed(num_threads, iterations, use_try_lock);
+        let cycles = lock_order::cycles().len();
+        println!("{:<16} {:>14?} {:>10} {:>12} {:>14}", label, elapsed, retries, total == expected, cycles);
+    }
+
+    println!();
+    common::print_info("Lock ordering removes the cycle by construction; try_lock breaks it at runtime by releasing and retrying");
+
+    println!();
+    common::print_info("Opposite orders once more, but thread 1 only starts after thread 0 is done: nothing hangs this time");
+    lock_order::reset();
+    run_lucky();
+    if lock_order::report() > 0 {
+        common::print_info("The validator flags the inconsistent order from a run that completed, before an unlucky interleaving turns it into a hang");
+    }
+}
@@ -0,0 +1,208 @@
+//! Double-checked locking for a lazily computed cached value
+//!
+//! A value that is expensive to compute should be computed once, on first
+//! use, and then read many times. Taking a `Mutex` on every read is correct
+//! but makes the hot path pay for the lock long after the value exists. The
+//! classic fix checks a flag first and only takes the lock while the value
+//! is missing, checking the flag again under the lock. In C++ or Java the
+//! pattern was famously broken by plain flags; in Rust the flag is an
+//! atomic, stored with Release after the value is written and loaded with
+//! Acquire before it is read, so a thread that sees the flag also sees the
+//! value. `OnceLock` packages exactly this pattern.
+
+// Base dependencies
+use std::cell::UnsafeCell;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Barrier, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Time it takes to compute the cached value
+const COMPUTE_COST: Duration = Duration::from_millis(10);
+
+/// Value computed on first use, with a flag telling readers it is there
+pub(crate) struct DoubleChecked<T> {
+    ready: AtomicBool,
+    lock: Mutex<()>,
+    value: UnsafeCell<Option<T>>,
+}
+
+// Safety: the value is written once under the lock before `ready` is set, and only read after
+unsafe impl<T: Send + Sync> Sync for DoubleChecked<T> {}
+
+impl<T> DoubleChecked<T> {
+    pub(crate) fn new() -> Self {
+        DoubleChecked {
+            ready: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// Return the value, computing it with `init` if no thread has yet
+    pub(crate) fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        // First check, without the lock: the hot path once the value exists
+        if !self.ready.load(Ordering::Acquire) {
+            chaos::perturb(Point::Lock);
+            let _guard = self.lock.lock().unwrap();
+
+            // Second check, under the lock: another thread may have computed it while we waited
+            if !self.ready.load(Ordering::Relaxed) {
+                // Safety: the lock is held and no reader gets past `ready` before it is set
+                unsafe { *self.value.get() = Some(init()) };
+                self.ready.store(true, Ordering::Release);
+            }
+        }
+
+        // Safety: `ready` was seen set with Acquire, so the write of the value is visible and final
+        unsafe { (*self.value.get()).as_ref().unwrap() }
+    }
+}
+
+/// How each lookup reaches the cached value
+#[derive(Clone, Copy)]
+enum Cache {
+    /// Lock a `Mutex<Option<u64>>` on every lookup
+    MutexOnly,
+    /// Atomic flag first, `Mutex` only while the value is missing
+    DoubleChecked,
+    /// `OnceLock::get_or_init`
+    OnceLock,
+}
+
+impl Cache {
+    const ALL: [Cache; 3] = [Cache::MutexOnly, Cache::DoubleChecked, Cache::OnceLock];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Cache::MutexOnly => "mutex every time",
+            Cache::DoubleChecked => "double-checked",
+            Cache::OnceLock => "OnceLock",
+        }
+    }
+}
+
+/// One cache of each kind
+enum Slot {
+    Mutex(Mutex<Option<u64>>),
+    DoubleChecked(DoubleChecked<u64>),
+    Once(OnceLock<u64>),
+}
+
+impl Slot {
+    fn new(cache: Cache) -> Self {
+        match cache {
+            Cache::MutexOnly => Slot::Mutex(Mutex::new(None)),
+            Cache::DoubleChecked => Slot::DoubleChecked(DoubleChecked::new()),
+            Cache::OnceLock => Slot::Once(OnceLock::new()),
+        }
+    }
+
+    fn get(&self, computations: &AtomicUsize) -> u64 {
+        let compute = || {
+            computations.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(COMPUTE_COST);
+            42
+        };
+        match self {
+            Slot::Mutex(mutex) => {
+                chaos::perturb(Point::Lock);
+                *mutex.lock().unwrap().get_or_insert_with(compute)
+            }
+            Slot::DoubleChecked(cache) => *cache.get_or_init(compute),
+            Slot::Once(cache) => *cache.get_or_init(compute),
+        }
+    }
+}
+
+/// Outcome of one cache
+struct CacheReport {
+    computations: usize,
+    /// Slowest first lookup, which waited for the value to be computed
+    first_lookup: Duration,
+    /// Time per lookup once the value exists, averaged over every thread
+    warm_lookup: Duration,
+    correct: bool,
+}
+
+/// Release `num_threads` threads onto a fresh cache at once, then let each make `lookups` more lookups
+fn measure(cache: Cache, num_threads: usize, lookups: usize) -> CacheReport {
+    let slot = Slot::new(cache);
+    let computations = AtomicUsize::new(0);
+    let barrier = Barrier::new(num_threads);
+
+    let results: Vec<(Duration, Duration, bool)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    barrier.wait();
+                    let start = Instant::now();
+                    let mut correct = slot.get(&computations) == 42;
+                    let first_lookup = start.elapsed();
+
+                    // Everyone has the value: from here on only the lookup itself is timed
+                    barrier.wait();
+                    let start = Instant::now();
+                    for _ in 0..lookups {
+                        correct &= black_box(slot.get(&computations)) == 42;
+                    }
+                    (first_lookup, start.elapsed(), correct)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    let warm: Duration = results.iter().map(|(_, warm, _)| *warm).sum();
+    CacheReport {
+        computations: computations.load(Ordering::Relaxed),
+        first_lookup: results.iter().map(|(first, _, _)| *first).max().unwrap_or_default(),
+        warm_lookup: warm / (num_threads * lookups).max(1) as u32,
+        correct: results.iter().all(|(_, _, correct)| *correct),
+    }
+}
+
+/// Run the double-checked locking example with `num_threads` threads making `lookups` lookups each
+pub fn run(num_threads: usize, lookups: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads look up a value that takes {:?} to compute, at once on an empty cache, then {} more times each",
+        num_threads, COMPUTE_COST, lookups
+    ));
+
+    println!();
+    println!(
+        "{:<18} {:>14} {:>16} {:>14} {:>10}",
+        "cache", "computations", "first lookup", "warm lookup", "correct"
+    );
+    let reports: Vec<(Cache, CacheReport)> = Cache::ALL.iter().map(|cache| (*cache, measure(*cache, num_threads, lookups))).collect();
+    for (cache, report) in &reports {
+        println!(
+            "{:<18} {:>14} {:>16?} {:>14?} {:>10}",
+            cache.label(),
+            report.computations,
+            report.first_lookup,
+            report.warm_lookup,
+            report.correct
+        );
+    }
+
+    println!();
+    if reports.iter().all(|(_, report)| report.computations == 1 && report.correct) {
+        common::print_success("Every cache computed the value once: the threads that found it missing waited on the lock instead of computing their own");
+    } else {
+        common::print_warning("A cache computed the value more than once or returned a wrong value");
+    }
+    let (mutex, checked) = (&reports[0].1, &reports[1].1);
+    common::print_info(&format!(
+        "Once the value exists, a lookup costs {:?} through the Mutex and {:?} through the atomic flag",
+        mutex.warm_lookup, checked.warm_lookup
+    ));
+    common::print_info("Before initialization every approach waits for the one computation; after it, only the Mutex keeps making readers take turns");
+    common::print_info("The flag must be stored with Release after the value and loaded with Acquire before it: with Relaxed, a reader could see the flag and not the value");
+}
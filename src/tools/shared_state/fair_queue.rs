@@ -0,0 +1,328 @@
+//! Fair FIFO condition queue and bounded buffer
+//!
+//! `std::sync::Condvar` does not specify which waiter `notify_one` wakes up.
+//! This module builds a condition queue with guaranteed FIFO wakeup on top
+//! of a queue of parked threads, uses it in a bounded buffer and measures
+//! how often a consumer overtakes an older waiter with each primitive.
+
+// Base dependencies
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::pacing::{self, PacingStrategy};
+
+/// Capacity of the bounded buffers used in the comparison
+const BUFFER_CAPACITY: usize = 4;
+
+/// Pause between two items pushed by the producer, so consumers queue up
+const PRODUCER_PACE: Duration = Duration::from_micros(50);
+
+/// Something threads can wait on while releasing a mutex, and be woken from
+pub trait WaitQueue: Send + Sync {
+    /// Whether wakeups happen in arrival order, which lets a buffer serve waiters by ticket
+    const FIFO: bool;
+
+    /// Release the guard and block while `condition` holds, re-acquiring the mutex to check it
+    fn wait_while<'a, T, F>(&self, mutex: &'a Mutex<T>, guard: MutexGuard<'a, T>, condition: F) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool;
+
+    /// Wake up one waiting thread, if any
+    fn notify_one(&self);
+}
+
+// The standard Condvar: the woken waiter is chosen by the OS
+impl WaitQueue for Condvar {
+    const FIFO: bool = false;
+
+    fn wait_while<'a, T, F>(&self, _mutex: &'a Mutex<T>, guard: MutexGuard<'a, T>, condition: F) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        Condvar::wait_while(self, guard, condition).unwrap()
+    }
+
+    fn notify_one(&self) {
+        Condvar::notify_one(self);
+    }
+}
+
+/// A parked thread waiting in a FIFO condition queue
+struct Waiter {
+    seq: u64,
+    thread: Thread,
+    notified: AtomicBool,
+}
+
+/// Waiting threads ordered by the sequence number of their first wait
+#[derive(Default)]
+struct Queue {
+    next_seq: u64,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+/// Condition queue that always wakes up the thread that has waited the longest
+#[derive(Default)]
+pub struct FifoCondition {
+    queue: Mutex<Queue>,
+}
+
+impl FifoCondition {
+    /// Create an empty condition queue
+    pub fn new() -> Self {
+        FifoCondition::default()
+    }
+}
+
+impl WaitQueue for FifoCondition {
+    const FIFO: bool = true;
+
+    fn wait_while<'a, T, F>(&self, mutex: &'a Mutex<T>, mut guard: MutexGuard<'a, T>, mut condition: F) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut seq = None;
+        while condition(&mut guard) {
+
+            // Enqueue before releasing the guard, so a notification cannot be missed.
+            // A thread woken too early keeps its original sequence number, and so its place in line.
+            let waiter = {
+                let mut queue = self.queue.lock().unwrap();
+                let waiter_seq = *seq.get_or_insert_with(|| {
+                    queue.next_seq += 1;
+                    queue.next_seq
+                });
+                let waiter = Arc::new(Waiter {
+                    seq: waiter_seq,
+                    thread: thread::current(),
+                    notified: AtomicBool::new(false),
+                });
+                let position = queue
+                    .waiters
+                    .iter()
+                    .position(|queued| queued.seq > waiter_seq)
+                    .unwrap_or(queue.waiters.len());
+                queue.waiters.insert(position, Arc::clone(&waiter));
+                waiter
+            };
+            drop(guard);
+
+            // park() may return spuriously, so only the notified flag ends the wait
+            while !waiter.notified.load(Ordering::Acquire) {
+                thread::park();
+            }
+
+            guard = mutex.lock().unwrap();
+        }
+        guard
+    }
+
+    fn notify_one(&self) {
+        if let Some(waiter) = self.queue.lock().unwrap().waiters.pop_front() {
+            waiter.notified.store(true, Ordering::Release);
+            waiter.thread.unpark();
+        }
+    }
+}
+
+/// Buffer contents plus the bookkeeping needed to measure wakeup fairness
+struct State<T> {
+    items: VecDeque<T>,
+    waiting: BTreeSet<u64>,
+    next_ticket: u64,
+    in_order: usize,
+    overtakes: usize,
+}
+
+/// Bounded buffer generic over the condition primitive used to block
+pub struct BoundedBuffer<T, C: WaitQueue> {
+    state: Mutex<State<T>>,
+    not_empty: C,
+    not_full: C,
+    capacity: usize,
+}
+
+impl<T, C: WaitQueue> BoundedBuffer<T, C> {
+    /// Create a buffer with the given capacity and condition queues
+    pub fn new(capacity: usize, not_empty: C, not_full: C) -> Self {
+        BoundedBuffer {
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                waiting: BTreeSet::new(),
+                next_ticket: 0,
+                in_order: 0,
+                overtakes: 0,
+            }),
+            not_empty,
+            not_full,
+            capacity,
+        }
+    }
+
+    /// Push an item, blocking while the buffer is full
+    pub fn push(&self, item: T) {
+        let state = self.state.lock().unwrap();
+        let mut state = self
+            .not_full
+            .wait_while(&self.state, state, |state| state.items.len() == self.capacity);
+        state.items.push_back(item);
+        drop(state);
+        self.not_empty.notify_one();
+    }
+
+    /// Pop an item, blocking while the buffer is empty
+    ///
+    /// With a FIFO wait queue, items are handed out strictly by arrival ticket:
+    /// a consumer may only take an item once every older consumer was served.
+    pub fn pop(&self) -> T {
+        let mut state = self.state.lock().unwrap();
+
+        // Take a ticket so we know which consumers arrived before us
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.insert(ticket);
+
+        let must_wait = |state: &mut State<T>| {
+            state.items.is_empty() || (C::FIFO && state.waiting.first() != Some(&ticket))
+        };
+        let waited = must_wait(&mut state);
+        let mut state = self.not_empty.wait_while(&self.state, state, must_wait);
+
+        // Being served while an older consumer is still waiting is an overtake
+        if waited {
+            if state.waiting.first() == Some(&ticket) {
+                state.in_order += 1;
+            } else {
+                state.overtakes += 1;
+            }
+        }
+        state.waiting.remove(&ticket);
+
+        // In FIFO mode, pass the turn on if the next consumer in line can be served right away
+        let item = state.items.pop_front().unwrap();
+        let pass_turn = C::FIFO && !state.items.is_empty() && !state.waiting.is_empty();
+        drop(state);
+
+        self.not_full.notify_one();
+        if pass_turn {
+            self.not_empty.notify_one();
+        }
+        item
+    }
+
+    /// Number of woken consumers that were (in order, overtaking an older waiter)
+    fn fairness(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.in_order, state.overtakes)
+    }
+}
+
+/// Run a single producer against many consumers, returning the items each consumer received and fairness
+fn run_buffer<C: WaitQueue + 'static>(
+    buffer: BoundedBuffer<Option<usize>, C>,
+    num_consumers: usize,
+    num_items: usize,
+) -> (Duration, Vec<Vec<usize>>, usize, usize) {
+    let buffer = Arc::new(buffer);
+    audit::track("bounded buffer", &buffer);
+    let start = Instant::now();
+
+    let mut handles = vec![];
+    for _ in 0..num_consumers {
+        let buffer = Arc::clone(&buffer);
+        let handle = thread::spawn(move || {
+            let mut received = vec![];
+            while let Some(item) = buffer.pop() {
+                received.push(item);
+            }
+            received
+        });
+        handles.push(handle);
+    }
+
+    // The producer paces its items so consumers pile up waiting on the buffer
+    for item in 0..num_items {
+        buffer.push(Some(item));
+        pacing::wait_until(Instant::now() + PRODUCER_PACE, PacingStrategy::Hybrid);
+    }
+
+    // One end-of-stream marker per consumer
+    for _ in 0..num_consumers {
+        buffer.push(None);
+    }
+
+    let received: Vec<Vec<usize>> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    let (in_order, overtakes) = buffer.fairness();
+    (start.elapsed(), received, in_order, overtakes)
+}
+
+/// Run the fair bounded buffer comparison
+pub fn run(num_consumers: usize, items_per_consumer: usize) {
+    let num_consumers = num_consumers.max(2);
+    let num_items = num_consumers * items_per_consumer;
+
+    common::print_info(&format!(
+        "1 producer pushes {} items into a buffer of capacity {} drained by {} consumers",
+        num_items, BUFFER_CAPACITY, num_consumers
+    ));
+
+    println!();
+    println!(
+        "{:<16} {:>12} {:>10} {:>10} {:>12} {:>14}",
+        "primitive", "time", "in order", "overtakes", "overtake %", "served min/max"
+    );
+
+    let results = [
+        (
+            "Condvar",
+            run_buffer(
+                BoundedBuffer::new(BUFFER_CAPACITY, Condvar::new(), Condvar::new()),
+                num_consumers,
+                num_items,
+            ),
+        ),
+        (
+            "FifoCondition",
+            run_buffer(
+                BoundedBuffer::new(BUFFER_CAPACITY, FifoCondition::new(), FifoCondition::new()),
+                num_consumers,
+                num_items,
+            ),
+        ),
+    ];
+
+    let mut faulty = vec![];
+    for (label, (elapsed, received, in_order, overtakes)) in results {
+        let served: Vec<usize> = received.iter().map(Vec::len).collect();
+        let distinct: BTreeSet<usize> = received.iter().flatten().copied().collect();
+        if served.iter().sum::<usize>() != num_items || distinct.len() != num_items {
+            faulty.push(label);
+        }
+
+        let woken = (in_order + overtakes).max(1);
+        println!(
+            "{:<16} {:>12?} {:>10} {:>10} {:>11.1}% {:>14}",
+            label,
+            elapsed,
+            in_order,
+            overtakes,
+            overtakes as f64 / woken as f64 * 100.0,
+            format!("{}/{}", served.iter().min().unwrap(), served.iter().max().unwrap())
+        );
+    }
+
+    println!();
+    if faulty.is_empty() {
+        common::print_success("Both buffers delivered every item exactly once");
+    } else {
+        common::print_warning(&format!("Items lost or delivered twice by: {}", faulty.join(", ")));
+    }
+    common::print_info("Condvar makes no ordering promise: any waiter may be woken, and woken threads race newcomers for the mutex");
+    common::print_info("FifoCondition wakes the longest waiter first, so the buffer can serve consumers strictly in arrival order");
+}
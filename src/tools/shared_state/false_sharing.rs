@@ -0,0 +1,109 @@
+//! False sharing: per-thread counters on one cache line vs one line each
+//!
+//! Every thread increments only its own counter, so there is no logical
+//! sharing at all. Cores keep memory coherent per cache line though, not per
+//! variable: when the counters sit next to each other, every increment
+//! invalidates the line in the other cores' caches and the line bounces
+//! between them. Aligning each counter to 64 bytes puts it alone on its line.
+
+// Base dependencies
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::common;
+use crate::cpus;
+
+/// Number of 8-byte counters that fit on one 64-byte cache line
+const COUNTERS_PER_LINE: usize = 8;
+
+/// Every counter on the same cache line
+#[repr(align(64))]
+struct Packed {
+    counters: [AtomicU64; COUNTERS_PER_LINE],
+}
+
+/// A counter alone on its cache line
+#[repr(align(64))]
+struct Padded(AtomicU64);
+
+/// Let each thread increment its own counter `increments` times and return the elapsed time and the total count
+fn hammer(counters: &[&AtomicU64], increments: usize) -> (Duration, u64) {
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for counter in counters {
+            scope.spawn(move || {
+                for _ in 0..increments {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    let elapsed = start.elapsed();
+    (elapsed, counters.iter().map(|counter| counter.load(Ordering::Relaxed)).sum())
+}
+
+/// Thread counts to measure: powers of two up to `max_threads`, then `max_threads` itself
+pub(crate) fn thread_counts(max_threads: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = (0..).map(|power| 1 << power).take_while(|count| *count < max_threads).collect();
+    counts.push(max_threads);
+    counts
+}
+
+/// Run the false sharing example with up to `num_threads` threads
+pub fn run(num_threads: usize, increments: usize) {
+    let max_threads = num_threads.clamp(1, COUNTERS_PER_LINE);
+    if num_threads > COUNTERS_PER_LINE {
+        common::print_warning(&format!(
+            "Only {} counters fit on one cache line, using {} threads",
+            COUNTERS_PER_LINE, COUNTERS_PER_LINE
+        ));
+    }
+    common::print_info(&format!(
+        "Each thread increments its own counter {} times; packed counters share one {}-byte line, padded ones take {} bytes each",
+        increments,
+        mem::size_of::<Packed>(),
+        mem::size_of::<Padded>()
+    ));
+
+    println!();
+    println!(
+        "{:>8} {:>14} {:>14} {:>16} {:>16} {:>10}",
+        "threads", "packed", "padded", "packed incr/s", "padded incr/s", "speedup"
+    );
+    let mut correct = true;
+    for threads in thread_counts(max_threads) {
+        let packed = Packed {
+            counters: Default::default(),
+        };
+        let padded: Vec<Padded> = (0..threads).map(|_| Padded(AtomicU64::new(0))).collect();
+
+        let (packed_time, packed_total) = hammer(&packed.counters.iter().take(threads).collect::<Vec<_>>(), increments);
+        let (padded_time, padded_total) = hammer(&padded.iter().map(|counter| &counter.0).collect::<Vec<_>>(), increments);
+        correct &= packed_total == padded_total && packed_total == (threads * increments) as u64;
+
+        let total = (threads * increments) as f64;
+        println!(
+            "{:>8} {:>14?} {:>14?} {:>16.0} {:>16.0} {:>9.1}x",
+            threads,
+            packed_time,
+            padded_time,
+            total / packed_time.as_secs_f64(),
+            total / padded_time.as_secs_f64(),
+            packed_time.as_secs_f64() / padded_time.as_secs_f64()
+        );
+    }
+
+    println!();
+    if !correct {
+        common::print_warning("A counter total does not match the number of increments");
+    }
+    if cpus::available() < 2 {
+        common::print_warning("With a single CPU the threads never run at the same time, so the cache line has nowhere to bounce");
+    }
+    common::print_info("With one thread both layouts run at the same speed; as threads are added the packed line bounces between cores on every increment");
+    common::print_info("Some CPUs prefetch cache lines in pairs, so 128-byte alignment (what crossbeam's CachePadded uses on x86_64) can be needed to remove the effect completely");
+    common::print_success("Keep data written by different threads on different cache lines");
+}
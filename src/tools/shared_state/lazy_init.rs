@@ -0,0 +1,128 @@
+//! Lazy initialization of a shared resource: OnceLock and LazyLock
+//!
+//! Every thread needs an expensive resource that nobody built yet. A naive
+//! check-then-build lets several threads find it missing at once, so each
+//! of them pays for the build and all but one result are thrown away.
+//! `OnceLock::get_or_init` runs the initializer in exactly one thread and
+//! parks the others until it is done; `LazyLock` does the same for a value
+//! whose initializer is known up front, typically in a `static`.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Barrier, LazyLock, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Time it takes to build the resource
+const INIT_COST: Duration = Duration::from_millis(20);
+
+/// Number of entries in the resource's lookup table
+const TABLE_SIZE: usize = 1 << 16;
+
+/// Number of times the initializer ran, across all approaches
+static INIT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// The expensive resource: a lookup table tagged with the initializer call that built it
+struct Resource {
+    built_by: usize,
+    table: Vec<u64>,
+}
+
+/// Build the resource, counting the call
+fn build() -> Resource {
+    let built_by = INIT_CALLS.fetch_add(1, Ordering::SeqCst);
+    thread::sleep(INIT_COST);
+    Resource {
+        built_by,
+        table: (0..TABLE_SIZE as u64).map(|i| i * i).collect(),
+    }
+}
+
+/// Resource initialized on first access, wherever that happens
+static LAZY_RESOURCE: LazyLock<Resource> = LazyLock::new(build);
+
+/// Outcome of one approach
+struct InitReport {
+    calls: usize,
+    distinct: usize,
+    elapsed: Duration,
+}
+
+/// Release `num_threads` threads at once, let each one fetch the resource, and count the initializer calls
+fn race<'a>(num_threads: usize, fetch: impl Fn() -> &'a Resource + Sync) -> InitReport {
+    let barrier = Barrier::new(num_threads);
+    let calls_before = INIT_CALLS.load(Ordering::SeqCst);
+    let start = Instant::now();
+
+    let mut seen: Vec<usize> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    barrier.wait();
+                    chaos::perturb(Point::Lock);
+                    let resource = fetch();
+                    assert_eq!(resource.table[TABLE_SIZE - 1], ((TABLE_SIZE - 1) * (TABLE_SIZE - 1)) as u64);
+                    resource.built_by
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    seen.sort_unstable();
+    seen.dedup();
+
+    InitReport {
+        calls: INIT_CALLS.load(Ordering::SeqCst) - calls_before,
+        distinct: seen.len(),
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Run the lazy initialization example with `num_threads` threads racing for the resource
+pub fn run(num_threads: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads start at the same time and all need a resource that takes {:?} to build",
+        num_threads, INIT_COST
+    ));
+
+    // Check, build outside the lock, then store: every thread that saw it missing builds its own
+    let naive: RwLock<Option<&'static Resource>> = RwLock::new(None);
+    let naive_report = race(num_threads, || {
+        if let Some(resource) = *naive.read().unwrap() {
+            return resource;
+        }
+        let resource: &'static Resource = Box::leak(Box::new(build()));
+        *naive.write().unwrap().get_or_insert(resource)
+    });
+
+    let once: OnceLock<Resource> = OnceLock::new();
+    let once_report = race(num_threads, || once.get_or_init(build));
+    let lazy_report = race(num_threads, || &*LAZY_RESOURCE);
+
+    println!();
+    println!("{:<18} {:>14} {:>16} {:>12}", "approach", "init calls", "values seen", "time");
+    let reports = [("check-then-build", &naive_report), ("OnceLock", &once_report), ("LazyLock", &lazy_report)];
+    for (name, report) in reports {
+        println!("{:<18} {:>14} {:>16} {:>12?}", name, report.calls, report.distinct, report.elapsed);
+    }
+
+    println!();
+    if once_report.calls == 1 && lazy_report.calls == 1 {
+        common::print_success("OnceLock and LazyLock ran the initializer exactly once, and every thread got that one value");
+    } else {
+        common::print_warning("OnceLock or LazyLock ran the initializer more than once");
+    }
+    if naive_report.calls > 1 {
+        common::print_warning(&format!(
+            "Check-then-build ran the initializer {} times; all but one of the results were wasted (and leaked here)",
+            naive_report.calls
+        ));
+    }
+    common::print_info("Threads arriving while the OnceLock initializer runs block until it finishes, instead of building their own copy");
+    common::print_info("Use LazyLock when the initializer is fixed, as in a static, and OnceLock when it needs values only known at runtime");
+}
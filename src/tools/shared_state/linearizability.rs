@@ -0,0 +1,195 @@
+//! Linearizability checking of the lock-free stack and queue (tests only)
+//!
+//! Threads run random push/pop scripts against the structure while every
+//! operation records when it was called and when it returned, on a shared
+//! logical clock. The resulting history is linearizable if the operations
+//! can be put in one sequential order that respects real time (an operation
+//! that returned before another was called comes first) and in which a
+//! sequential model gives every pop the value it actually returned. The
+//! checker searches for such an order, backtracking over the operations that
+//! overlap in time and memoizing the states it already ruled out.
+
+// Base dependencies
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Barrier;
+use std::thread;
+
+// Third-party dependencies
+use proptest::prelude::*;
+
+// Project dependencies
+use super::ms_queue::{ConcurrentQueue, MsQueue};
+use super::treiber::{ConcurrentStack, TreiberStack};
+
+/// What an operation did
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    Push(u64),
+    Pop(Option<u64>),
+}
+
+/// One completed operation and the logical times of its call and return
+#[derive(Clone, Copy, Debug)]
+struct Operation {
+    action: Action,
+    call: u64,
+    ret: u64,
+}
+
+/// Sequential specification the history is checked against
+trait Model: Clone + Eq + Hash + Default {
+    /// Apply the action, returning whether the model agrees with its outcome
+    fn apply(&mut self, action: Action) -> bool;
+}
+
+/// A stack is sequentially a `Vec`
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+struct StackModel(Vec<u64>);
+
+impl Model for StackModel {
+    fn apply(&mut self, action: Action) -> bool {
+        match action {
+            Action::Push(value) => {
+                self.0.push(value);
+                true
+            }
+            Action::Pop(observed) => self.0.pop() == observed,
+        }
+    }
+}
+
+/// A queue is sequentially a `VecDeque`
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+struct QueueModel(VecDeque<u64>);
+
+impl Model for QueueModel {
+    fn apply(&mut self, action: Action) -> bool {
+        match action {
+            Action::Push(value) => {
+                self.0.push_back(value);
+                true
+            }
+            Action::Pop(observed) => self.0.pop_front() == observed,
+        }
+    }
+}
+
+/// Whether `history` (at most 64 operations) has a linearization valid for `M`
+fn is_linearizable<M: Model>(history: &[Operation]) -> bool {
+    assert!(history.len() <= 64, "the checker tracks linearized operations in a u64");
+    let mut ruled_out = HashSet::new();
+    search(history, 0, M::default(), &mut ruled_out)
+}
+
+/// Try every operation that may come next, given the set already linearized in `done`
+fn search<M: Model>(history: &[Operation], done: u64, model: M, ruled_out: &mut HashSet<(u64, M)>) -> bool {
+    if done.count_ones() as usize == history.len() {
+        return true;
+    }
+    if ruled_out.contains(&(done, model.clone())) {
+        return false;
+    }
+
+    // Only an operation called before every pending one returned can be next
+    let pending = || (0..history.len()).filter(|index| done & (1 << index) == 0);
+    let first_return = pending().map(|index| history[index].ret).min().unwrap();
+    for index in pending().filter(|index| history[*index].call < first_return) {
+        let mut next = model.clone();
+        if next.apply(history[index].action) && search(history, done | (1 << index), next, ruled_out) {
+            return true;
+        }
+    }
+
+    ruled_out.insert((done, model));
+    false
+}
+
+/// Run one script per thread (`true` pushes, `false` pops) and record the history
+fn record(scripts: &[Vec<bool>], push: impl Fn(u64) + Sync, pop: impl Fn() -> Option<u64> + Sync) -> Vec<Operation> {
+    let clock = AtomicU64::new(0);
+    let barrier = Barrier::new(scripts.len());
+    thread::scope(|scope| {
+        let handles: Vec<_> = scripts
+            .iter()
+            .enumerate()
+            .map(|(thread_id, script)| {
+                let (clock, barrier, push, pop) = (&clock, &barrier, &push, &pop);
+                scope.spawn(move || {
+                    barrier.wait();
+                    script
+                        .iter()
+                        .enumerate()
+                        .map(|(sequence, is_push)| {
+                            let call = clock.fetch_add(1, Ordering::SeqCst);
+                            let action = if *is_push {
+                                let value = ((thread_id as u64) << 32) | sequence as u64;
+                                push(value);
+                                Action::Push(value)
+                            } else {
+                                Action::Pop(pop())
+                            };
+                            let ret = clock.fetch_add(1, Ordering::SeqCst);
+                            Operation { action, call, ret }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Two to four threads of one to eight operations each
+fn scripts() -> impl Strategy<Value = Vec<Vec<bool>>> {
+    prop::collection::vec(prop::collection::vec(any::<bool>(), 1..=8), 2..=4)
+}
+
+proptest! {
+    #[test]
+    fn treiber_stack_is_linearizable(scripts in scripts()) {
+        let stack = TreiberStack::new();
+        let history = record(&scripts, |value| stack.push(value), || stack.pop());
+        prop_assert!(is_linearizable::<StackModel>(&history), "history {:#?}", history);
+    }
+
+    #[test]
+    fn ms_queue_is_linearizable(scripts in scripts()) {
+        let queue = MsQueue::new();
+        let history = record(&scripts, |value| queue.push(value), || queue.pop());
+        prop_assert!(is_linearizable::<QueueModel>(&history), "history {:#?}", history);
+    }
+}
+
+#[test]
+fn checker_accepts_overlapping_operations_in_either_order() {
+    // The pop overlaps both pushes, so it may take effect after either of them
+    let history = [
+        Operation { action: Action::Push(1), call: 0, ret: 3 },
+        Operation { action: Action::Push(2), call: 1, ret: 4 },
+        Operation { action: Action::Pop(Some(1)), call: 2, ret: 5 },
+    ];
+    assert!(is_linearizable::<StackModel>(&history));
+    assert!(is_linearizable::<QueueModel>(&history));
+}
+
+#[test]
+fn checker_rejects_histories_no_order_explains() {
+    // Push 1 returned before push 2 was called, so a queue must hand out 1 first
+    let reordered = [
+        Operation { action: Action::Push(1), call: 0, ret: 1 },
+        Operation { action: Action::Push(2), call: 2, ret: 3 },
+        Operation { action: Action::Pop(Some(2)), call: 4, ret: 5 },
+    ];
+    assert!(!is_linearizable::<QueueModel>(&reordered));
+    assert!(is_linearizable::<StackModel>(&reordered));
+
+    // A pop that completed after the push may not report an empty structure
+    let lost = [
+        Operation { action: Action::Push(1), call: 0, ret: 1 },
+        Operation { action: Action::Pop(None), call: 2, ret: 3 },
+    ];
+    assert!(!is_linearizable::<StackModel>(&lost));
+    assert!(!is_linearizable::<QueueModel>(&lost));
+}
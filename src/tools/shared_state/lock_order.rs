@@ -0,0 +1,236 @@
+//! Lock-order validator: a debug mutex that records acquisition order
+//!
+//! A deadlock needs an unlucky interleaving, so a test run can pass a
+//! thousand times on code that will one day hang. What the code does on
+//! every run, lucky or not, is take its locks in some order. `OrderedMutex`
+//! remembers which named locks each thread already holds when it asks for
+//! another one, and adds a "held before" edge from each of them to the new
+//! lock in one global graph. A cycle in that graph means two code paths take
+//! the same locks in opposite orders: a potential deadlock, reported even if
+//! the run never actually deadlocked. Locks are identified by name, so every
+//! instance created under the same name counts as the same lock class.
+
+// Base dependencies
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread;
+
+// Project dependencies
+use crate::common;
+
+thread_local! {
+    /// Names of the ordered locks the current thread holds, in acquisition order
+    static HELD: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Every "held before" edge seen so far, across all threads
+static GRAPH: Mutex<LockGraph> = Mutex::new(LockGraph::new());
+
+/// Directed graph of lock names: an edge from `a` to `b` means `b` was requested while holding `a`
+pub(crate) struct LockGraph {
+    /// Edge targets of each lock, with the thread that first recorded the edge
+    edges: BTreeMap<&'static str, BTreeMap<&'static str, String>>,
+}
+
+impl LockGraph {
+    const fn new() -> Self {
+        LockGraph { edges: BTreeMap::new() }
+    }
+
+    /// Record that `acquired` was requested while holding `held`, keeping the first thread seen
+    fn add(&mut self, held: &'static str, acquired: &'static str, thread: &str) {
+        self.edges
+            .entry(held)
+            .or_default()
+            .entry(acquired)
+            .or_insert_with(|| thread.to_string());
+    }
+
+    /// Thread that first recorded the edge from `held` to `acquired`
+    fn first_seen(&self, held: &str, acquired: &str) -> Option<&str> {
+        self.edges.get(held)?.get(acquired).map(String::as_str)
+    }
+
+    /// Every elementary cycle, each listed once, starting from its smallest lock name
+    pub(crate) fn cycles(&self) -> Vec<Vec<&'static str>> {
+        let mut cycles = vec![];
+        for &start in self.edges.keys() {
+            let mut path = vec![start];
+            self.extend(start, &mut path, &mut cycles);
+        }
+        cycles
+    }
+
+    /// Depth-first search from the end of `path`, only through locks named after `path[0]`
+    fn extend(&self, start: &'static str, path: &mut Vec<&'static str>, cycles: &mut Vec<Vec<&'static str>>) {
+        let last = *path.last().unwrap();
+        let Some(targets) = self.edges.get(last) else {
+            return;
+        };
+        for &next in targets.keys() {
+            if next == start {
+                cycles.push(path.clone());
+            } else if next > start && !path.contains(&next) {
+                path.push(next);
+                self.extend(start, path, cycles);
+                path.pop();
+            }
+        }
+    }
+
+    /// Number of distinct edges recorded
+    fn len(&self) -> usize {
+        self.edges.values().map(BTreeMap::len).sum()
+    }
+}
+
+/// A `Mutex` that feeds the global lock-order graph whenever it is locked
+pub struct OrderedMutex<T> {
+    name: &'static str,
+    inner: Mutex<T>,
+}
+
+/// Guard of an `OrderedMutex`; dropping it removes the lock from the thread's held list
+pub struct OrderedGuard<'a, T> {
+    name: &'static str,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> OrderedMutex<T> {
+    pub fn new(name: &'static str, value: T) -> Self {
+        OrderedMutex {
+            name,
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Record an edge from every lock this thread holds, then block on the lock
+    ///
+    /// The edges are recorded before blocking, so an acquisition that would
+    /// deadlock still shows up in the graph.
+    pub fn lock(&self) -> OrderedGuard<'_, T> {
+        HELD.with(|held| {
+            let held = held.borrow();
+            if held.is_empty() {
+                return;
+            }
+            let current = thread::current();
+            let me = current.name().unwrap_or("unnamed");
+            let mut graph = GRAPH.lock().unwrap();
+            for before in held.iter().filter(|before| **before != self.name) {
+                graph.add(before, self.name, me);
+            }
+        });
+
+        let guard = self
+            .inner
+            .lock()
+            .unwrap_or_else(|error| panic!("lock {} poisoned: {}", self.name, error));
+        self.held(guard)
+    }
+
+    /// Take the lock only if it is free; a call that cannot block cannot deadlock, so no edge is recorded
+    pub fn try_lock(&self) -> Option<OrderedGuard<'_, T>> {
+        match self.inner.try_lock() {
+            Ok(guard) => Some(self.held(guard)),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(error)) => panic!("lock {} poisoned: {}", self.name, error),
+        }
+    }
+
+    fn held<'a>(&'a self, guard: MutexGuard<'a, T>) -> OrderedGuard<'a, T> {
+        HELD.with(|held| held.borrow_mut().push(self.name));
+        OrderedGuard { name: self.name, guard }
+    }
+}
+
+impl<T> Deref for OrderedGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for OrderedGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for OrderedGuard<'_, T> {
+    fn drop(&mut self) {
+        // Guards are not always dropped in reverse order, so remove the latest entry with this name
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(index) = held.iter().rposition(|name| *name == self.name) {
+                held.remove(index);
+            }
+        });
+    }
+}
+
+/// Forget every recorded edge, so that the next report only covers what runs from now on
+pub fn reset() {
+    *GRAPH.lock().unwrap() = LockGraph::new();
+}
+
+/// Potential deadlock cycles in the lock-order graph so far
+pub fn cycles() -> Vec<Vec<&'static str>> {
+    GRAPH.lock().unwrap().cycles()
+}
+
+/// Print the potential deadlock cycles in the graph, returning how many were found
+pub fn report() -> usize {
+    let graph = GRAPH.lock().unwrap();
+    let cycles = graph.cycles();
+    if cycles.is_empty() {
+        common::print_success(&format!(
+            "Lock-order validator: {} ordering edge(s), no cycle: every thread takes the locks in one consistent order",
+            graph.len()
+        ));
+        return 0;
+    }
+
+    common::print_warning(&format!("Lock-order validator: {} potential deadlock cycle(s)", cycles.len()));
+    for cycle in &cycles {
+        let mut names = cycle.clone();
+        names.push(cycle[0]);
+        common::print_warning(&format!("  {}", names.join(" -> ")));
+        for pair in names.windows(2) {
+            common::print_warning(&format!(
+                "    {} taken while holding {}, first by {}",
+                pair[1],
+                pair[0],
+                graph.first_seen(pair[0], pair[1]).unwrap_or("?")
+            ));
+        }
+    }
+    cycles.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&'static str, &'static str)]) -> LockGraph {
+        let mut graph = LockGraph::new();
+        for (held, acquired) in edges {
+            graph.add(held, acquired, "test");
+        }
+        graph
+    }
+
+    #[test]
+    fn consistent_order_has_no_cycle() {
+        assert!(graph(&[("A", "B"), ("B", "C"), ("A", "C")]).cycles().is_empty());
+    }
+
+    #[test]
+    fn each_cycle_is_reported_once() {
+        let cycles = graph(&[("A", "B"), ("B", "A"), ("B", "C"), ("C", "D"), ("D", "B")]).cycles();
+        assert_eq!(cycles, vec![vec!["A", "B"], vec!["B", "C", "D"]]);
+    }
+}
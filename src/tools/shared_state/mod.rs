@@ -1,6 +1,38 @@
 
 // Re-export the commands from this module
 pub mod code;
+pub mod stm;
+pub mod bank;
+pub mod fair_queue;
+pub mod contention;
+pub mod concurrent_map;
+pub mod producer_consumer;
+pub mod deadlock;
+pub mod profiler;
+pub mod ordering;
+pub mod treiber;
+pub mod ms_queue;
+pub mod false_sharing;
+pub mod lazy_init;
+pub mod thread_local;
+pub mod rcu;
+pub mod seqlock;
+pub mod spinlock;
+pub mod rw_starvation;
+pub mod cas_loop;
+pub mod shutdown;
+pub mod poisoning;
+pub mod lock_order;
+pub mod scaling;
+pub mod double_checked;
+pub mod aba;
+pub mod barrier;
+pub mod scoped;
+pub mod cow;
+pub mod benchmark;
+
+#[cfg(test)]
+mod linearizability;
 
 // Re-export the run function for easier access from main.rs
 pub use code::run;
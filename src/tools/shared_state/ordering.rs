@@ -0,0 +1,182 @@
+//! Memory ordering: what Relaxed, Acquire/Release and SeqCst guarantee
+//!
+//! Two litmus tests run under each ordering and count the outcomes the
+//! ordering allows but a sequential reading of the code would not:
+//!
+//! - Message passing: a writer stores data then raises a flag, and a reader
+//!   that sees the flag checks the data. Relaxed allows the flag to become
+//!   visible before the data. Release on the flag store paired with Acquire
+//!   on the flag load forbids it.
+//! - Store buffering: each of two threads sets its own flag then reads the
+//!   other's. Both can read "not set" unless every access is SeqCst: even
+//!   Acquire/Release does not order a store before a later load.
+//!
+//! Whether a reordering that is allowed actually shows up depends on the
+//! hardware, the compiler and luck, so a zero count never proves a weaker
+//! ordering correct.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+// Project dependencies
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+
+/// Spins on a condition before yielding to the other thread
+const SPINS_BEFORE_YIELD: usize = 64;
+
+/// Orderings used for the stores and the loads of one run
+#[derive(Clone, Copy)]
+struct Orderings {
+    label: &'static str,
+    store: Ordering,
+    load: Ordering,
+}
+
+/// The three usual choices, from weakest to strongest
+const ORDERINGS: [Orderings; 3] = [
+    Orderings {
+        label: "Relaxed",
+        store: Ordering::Relaxed,
+        load: Ordering::Relaxed,
+    },
+    Orderings {
+        label: "Release/Acquire",
+        store: Ordering::Release,
+        load: Ordering::Acquire,
+    },
+    Orderings {
+        label: "SeqCst",
+        store: Ordering::SeqCst,
+        load: Ordering::SeqCst,
+    },
+];
+
+/// Spin until `condition` holds, yielding now and then so a single core still makes progress
+fn spin_until(condition: impl Fn() -> bool) {
+    let mut spins = 0;
+    while !condition() {
+        spins += 1;
+        if spins % SPINS_BEFORE_YIELD == 0 {
+            thread::yield_now();
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Publish `messages` values through a flag and count reads that saw the flag before the data
+///
+/// The writer stores `i` into `data`, then `i` into `flag`. A reader that
+/// loads `flag == i` and then `data < i` observed the flag before the data.
+fn message_passing(messages: usize, orderings: Orderings) -> (usize, usize) {
+    let data = AtomicUsize::new(0);
+    let flag = AtomicUsize::new(0);
+    let reading = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            // Start writing only once the reader is watching
+            spin_until(|| reading.load(Ordering::Acquire));
+            for i in 1..=messages {
+                data.store(i, Ordering::Relaxed);
+                flag.store(i, orderings.store);
+            }
+        });
+
+        let reader = scope.spawn(|| {
+            let (mut reads, mut early) = (0, 0);
+            reading.store(true, Ordering::Release);
+            loop {
+                let seen = flag.load(orderings.load);
+                let value = data.load(Ordering::Relaxed);
+                reads += 1;
+                if value < seen {
+                    early += 1;
+                }
+                if seen == messages {
+                    break;
+                }
+            }
+            (reads, early)
+        });
+        reader.join().unwrap()
+    })
+}
+
+/// Run `rounds` rounds of store buffering and count the rounds where neither thread saw the other's store
+///
+/// Both threads run each round in lockstep, on flags that start unset.
+fn store_buffering(rounds: usize, orderings: Orderings) -> usize {
+    let flags: [Vec<AtomicBool>; 2] = [
+        (0..rounds).map(|_| AtomicBool::new(false)).collect(),
+        (0..rounds).map(|_| AtomicBool::new(false)).collect(),
+    ];
+    let progress = [AtomicUsize::new(0), AtomicUsize::new(0)];
+
+    let saw_other: Vec<Vec<bool>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..2)
+            .map(|me| {
+                let (flags, progress) = (&flags, &progress);
+                scope.spawn(move || {
+                    let other = 1 - me;
+                    (0..rounds)
+                        .map(|round| {
+                            spin_until(|| progress[other].load(Ordering::Acquire) >= round);
+                            chaos::perturb(Point::Lock);
+                            flags[me][round].store(true, orderings.store);
+                            let seen = flags[other][round].load(orderings.load);
+                            progress[me].store(round + 1, Ordering::Release);
+                            seen
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    (0..rounds).filter(|round| !saw_other[0][*round] && !saw_other[1][*round]).count()
+}
+
+/// Run both litmus tests under every ordering
+pub fn run(iterations: usize) {
+    let iterations = iterations.max(1);
+    common::print_info(&format!(
+        "{} messages and {} store-buffering rounds per ordering on {} ({} CPU(s))",
+        iterations,
+        iterations,
+        std::env::consts::ARCH,
+        cpus::available()
+    ));
+
+    println!();
+    println!(
+        "{:<16} {:>12} {:>14} {:>14} {:>12}",
+        "ordering", "mp reads", "flag first", "sb both miss", "time"
+    );
+    for orderings in ORDERINGS {
+        let start = Instant::now();
+        let (reads, early) = message_passing(iterations, orderings);
+        let both_missed = store_buffering(iterations, orderings);
+        println!(
+            "{:<16} {:>12} {:>14} {:>14} {:>12?}",
+            orderings.label,
+            reads,
+            early,
+            both_missed,
+            start.elapsed()
+        );
+    }
+
+    println!();
+    common::print_info("flag first: the reader saw the new flag but old data. Only Relaxed allows it, and only weakly ordered CPUs (ARM, POWER) or compiler reordering produce it; x86 keeps stores in order and loads in order");
+    common::print_info("sb both miss: each thread's store was still in its store buffer when it read the other's flag. x86 and ARM both do this under Relaxed and Release/Acquire; only SeqCst forbids it");
+    if cpus::available() < 2 {
+        common::print_warning("With a single CPU the threads never run at the same time, so reorderings the orderings allow will rarely if ever show up");
+    }
+    common::print_success("Use Release/Acquire to publish data through a flag; reach for SeqCst when threads must agree on the order of stores to different variables");
+}
@@ -0,0 +1,195 @@
+//! Mutex poisoning after a panic, recovery, and a non-poisoning lock
+//!
+//! Every update of the counter happens in two steps of one, so the value is
+//! always even between updates. One thread panics between the two steps while
+//! holding the lock, leaving the value odd. A `std::sync::Mutex` remembers
+//! that a panic happened under it: every later `lock()` returns a
+//! `PoisonError`. Unwrapping it spreads the panic to every other thread;
+//! handling it gives the guard back through `into_inner`, so the invariant
+//! can be checked, repaired and the poison cleared. `parking_lot::Mutex`
+//! does not poison: the other threads carry on, and nobody notices the
+//! broken invariant.
+
+// Base dependencies
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, MutexGuard, PoisonError};
+use std::thread;
+
+// Project dependencies
+use super::code::Counter;
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// How the other threads deal with the lock after the panic
+#[derive(Clone, Copy)]
+enum Handling {
+    /// std Mutex, `lock().unwrap()` as everywhere else in the crate
+    Unwrap,
+    /// std Mutex, poison handled by repairing the value and clearing the flag
+    Recover,
+    /// parking_lot Mutex, which has no poison flag
+    NonPoisoning,
+}
+
+impl Handling {
+    const ALL: [Handling; 3] = [Handling::Unwrap, Handling::Recover, Handling::NonPoisoning];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Handling::Unwrap => "std + unwrap",
+            Handling::Recover => "std + recover",
+            Handling::NonPoisoning => "parking_lot",
+        }
+    }
+}
+
+/// The counter under either kind of mutex
+enum Shared {
+    Std(Counter),
+    ParkingLot(parking_lot::Mutex<usize>),
+}
+
+/// Poison errors seen and values repaired, across all threads
+#[derive(Default)]
+struct Poison {
+    errors: AtomicUsize,
+    repairs: AtomicUsize,
+}
+
+/// Outcome of one run
+struct PoisonReport {
+    panicked: usize,
+    poison_errors: usize,
+    repairs: usize,
+    value: usize,
+}
+
+/// Take the guard out of the error, roll back a half-done update, and clear the poison
+fn recover<'a>(counter: &Counter, poisoned: PoisonError<MutexGuard<'a, usize>>, poison: &Poison) -> MutexGuard<'a, usize> {
+    let mut value = poisoned.into_inner();
+    if *value % 2 == 1 {
+        *value -= 1;
+        poison.repairs.fetch_add(1, Ordering::Relaxed);
+    }
+    counter.clear_poison();
+    value
+}
+
+/// Apply one two-step update; the faulty thread panics between the steps
+fn update(shared: &Shared, handling: Handling, faulty: bool, poison: &Poison) {
+    chaos::perturb(Point::Lock);
+    match shared {
+        Shared::Std(counter) => {
+            let locked = counter.lock();
+            if locked.is_err() {
+                poison.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            let mut value = match handling {
+                Handling::Unwrap => locked.unwrap(),
+                _ => locked.unwrap_or_else(|poisoned| recover(counter, poisoned, poison)),
+            };
+            *value += 1;
+            if faulty {
+                panic!("worker failed halfway through an update");
+            }
+            *value += 1;
+        }
+        Shared::ParkingLot(mutex) => {
+            let mut value = mutex.lock();
+            *value += 1;
+            if faulty {
+                panic!("worker failed halfway through an update");
+            }
+            *value += 1;
+        }
+    }
+}
+
+/// Let `num_threads` threads make `updates` updates each while thread 0 panics halfway through its own
+fn run_handling(handling: Handling, num_threads: usize, updates: usize) -> PoisonReport {
+    let shared = Arc::new(match handling {
+        Handling::Unwrap | Handling::Recover => Shared::Std(Counter::new()),
+        Handling::NonPoisoning => Shared::ParkingLot(parking_lot::Mutex::new(0)),
+    });
+    audit::track("poisoning counter", &shared);
+    let poison = Arc::new(Poison::default());
+
+    let handles: Vec<_> = (0..num_threads)
+        .map(|thread_id| {
+            let (shared, poison) = (Arc::clone(&shared), Arc::clone(&poison));
+            thread::spawn(move || {
+                for item in 0..updates {
+                    update(&shared, handling, thread_id == 0 && item == updates / 2, &poison);
+                }
+            })
+        })
+        .collect();
+    let panicked = handles.into_iter().filter_map(|handle| handle.join().err()).count();
+
+    let value = match &*shared {
+        Shared::Std(counter) => *counter.lock().unwrap_or_else(PoisonError::into_inner),
+        Shared::ParkingLot(mutex) => *mutex.lock(),
+    };
+    PoisonReport {
+        panicked,
+        poison_errors: poison.errors.load(Ordering::Relaxed),
+        repairs: poison.repairs.load(Ordering::Relaxed),
+        value,
+    }
+}
+
+/// Run the poisoning example with `num_threads` threads making `updates` updates each
+pub fn run(num_threads: usize, updates: usize) {
+    let num_threads = num_threads.max(2);
+    let updates = updates.max(1);
+    common::print_info(&format!(
+        "{} threads make {} two-step updates each; thread 0 panics halfway through its update number {}",
+        num_threads,
+        updates,
+        updates / 2
+    ));
+
+    // The panics are the point of the demo, so keep their messages out of the output
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let reports: Vec<(Handling, PoisonReport)> = Handling::ALL
+        .iter()
+        .map(|handling| (*handling, run_handling(*handling, num_threads, updates)))
+        .collect();
+    panic::set_hook(default_hook);
+
+    println!();
+    println!(
+        "{:<16} {:>10} {:>14} {:>9} {:>12} {:>10}",
+        "lock", "panicked", "poison errors", "repairs", "final value", "invariant"
+    );
+    for (handling, report) in &reports {
+        println!(
+            "{:<16} {:>10} {:>14} {:>9} {:>12} {:>10}",
+            handling.label(),
+            format!("{}/{}", report.panicked, num_threads),
+            report.poison_errors,
+            report.repairs,
+            report.value,
+            if report.value % 2 == 0 { "even" } else { "BROKEN" }
+        );
+    }
+
+    println!();
+    let (unwrap, recover, parking_lot) = (&reports[0].1, &reports[1].1, &reports[2].1);
+    if unwrap.panicked > 1 {
+        common::print_warning(&format!(
+            "Unwrapping the PoisonError spread one panic to {} more thread(s)",
+            unwrap.panicked - 1
+        ));
+    }
+    if recover.panicked == 1 && recover.value % 2 == 0 {
+        common::print_success("Handling the PoisonError let the other threads repair the half-done update, clear the poison and finish");
+    }
+    if parking_lot.value % 2 == 1 {
+        common::print_warning("parking_lot released the lock during the panic without a trace: the other threads finished on a broken invariant");
+    }
+    common::print_info("Poisoning does not fix anything by itself; it tells the next owner that the data may be half-updated");
+}
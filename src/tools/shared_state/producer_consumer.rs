@@ -0,0 +1,179 @@
+//! Producer/consumer over a bounded buffer with Mutex + Condvar
+//!
+//! The classic condition variable pattern: a `Mutex` guards the buffer, one
+//! `Condvar` wakes producers when a slot frees up and another wakes consumers
+//! when an item arrives. Every wait sits in a `while` loop re-checking its
+//! condition, because a woken thread may find the condition false again.
+
+// Base dependencies
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Number of slots in the buffer
+const CAPACITY: usize = 8;
+
+/// Buffer contents, whether producers are done, and wait statistics
+#[derive(Default)]
+struct State {
+    items: VecDeque<usize>,
+    closed: bool,
+    max_len: usize,
+    producer_waits: usize,
+    consumer_waits: usize,
+    wasted_wakeups: usize,
+}
+
+/// Fixed-capacity buffer where producers block while it is full and consumers while it is empty
+struct CondvarBuffer {
+    state: Mutex<State>,
+    not_full: Condvar,
+    not_empty: Condvar,
+}
+
+impl CondvarBuffer {
+    fn new() -> Self {
+        CondvarBuffer {
+            state: Mutex::new(State::default()),
+            not_full: Condvar::new(),
+            not_empty: Condvar::new(),
+        }
+    }
+
+    /// Push an item, waiting for a free slot
+    fn push(&self, item: usize) {
+        chaos::perturb(Point::Lock);
+        let mut state = self.state.lock().unwrap();
+        if state.items.len() == CAPACITY {
+            state.producer_waits += 1;
+        }
+        while state.items.len() == CAPACITY {
+            // wait() releases the mutex while blocked and re-acquires it before returning
+            state = self.not_full.wait(state).unwrap();
+            if state.items.len() == CAPACITY {
+                state.wasted_wakeups += 1;
+            }
+        }
+        state.items.push_back(item);
+        state.max_len = state.max_len.max(state.items.len());
+        drop(state);
+
+        // Notify after unlocking, so the woken consumer does not immediately block on the mutex
+        self.not_empty.notify_one();
+    }
+
+    /// Pop an item, waiting for one to arrive; `None` once the buffer is closed and drained
+    fn pop(&self) -> Option<usize> {
+        chaos::perturb(Point::Lock);
+        let mut state = self.state.lock().unwrap();
+        if state.items.is_empty() && !state.closed {
+            state.consumer_waits += 1;
+        }
+        while state.items.is_empty() && !state.closed {
+            state = self.not_empty.wait(state).unwrap();
+            if state.items.is_empty() && !state.closed {
+                state.wasted_wakeups += 1;
+            }
+        }
+        let item = state.items.pop_front();
+        drop(state);
+
+        if item.is_some() {
+            self.not_full.notify_one();
+        }
+        item
+    }
+
+    /// Tell every consumer that no more items will come
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+
+        // Every blocked consumer has to see the flag, not just one of them
+        self.not_empty.notify_all();
+    }
+}
+
+/// Run `num_threads` producers and as many consumers through the buffer
+pub fn run(num_threads: usize, items_per_producer: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} producers push {} items each through a {}-slot buffer drained by {} consumers",
+        num_threads, items_per_producer, CAPACITY, num_threads
+    ));
+
+    let buffer = Arc::new(CondvarBuffer::new());
+    audit::track("condvar buffer", &buffer);
+    let start = Instant::now();
+
+    let consumers: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                let (mut count, mut sum) = (0, 0);
+                while let Some(item) = buffer.pop() {
+                    count += 1;
+                    sum += item;
+                }
+                (count, sum)
+            })
+        })
+        .collect();
+
+    let producers: Vec<_> = (0..num_threads)
+        .map(|producer| {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                for index in 0..items_per_producer {
+                    buffer.push(producer * items_per_producer + index);
+                }
+            })
+        })
+        .collect();
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    buffer.close();
+
+    println!();
+    println!("{:<10} {:>10}", "consumer", "items");
+    let (mut consumed, mut sum) = (0, 0);
+    for (id, consumer) in consumers.into_iter().enumerate() {
+        let (count, consumer_sum) = consumer.join().unwrap();
+        println!("{:<10} {:>10}", id, count);
+        consumed += count;
+        sum += consumer_sum;
+    }
+    let elapsed = start.elapsed();
+
+    // Every item is a distinct number in 0..total, so their sum is known in advance
+    let total = num_threads * items_per_producer;
+    let expected_sum = total * total.saturating_sub(1) / 2;
+
+    let state = buffer.state.lock().unwrap();
+    println!();
+    common::print_info(&format!(
+        "Producers blocked on a full buffer {} times, consumers on an empty one {} times",
+        state.producer_waits, state.consumer_waits
+    ));
+    common::print_info(&format!(
+        "{} wakeups found their condition still false and waited again; the buffer peaked at {}/{} items",
+        state.wasted_wakeups, state.max_len, CAPACITY
+    ));
+
+    if consumed == total && sum == expected_sum {
+        common::print_success(&format!("All {} items were consumed exactly once in {:?}", total, elapsed));
+    } else {
+        common::print_warning(&format!(
+            "Consumed {} items with sum {}, expected {} items with sum {}",
+            consumed, sum, total, expected_sum
+        ));
+    }
+    common::print_info("Always wait in a loop: another thread can take the slot or the item between the notify and the wakeup");
+}
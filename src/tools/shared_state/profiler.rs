@@ -0,0 +1,127 @@
+//! Lock contention profiler for the Mutex counter
+//!
+//! Every increment is timed in two parts: the wait, from asking for the lock
+//! to getting it, and the hold, from getting it to releasing it. Each thread
+//! records its waits in a histogram and sums both parts, so the report shows
+//! how much of the threads' time went into waiting rather than working, and
+//! how that wait is distributed.
+
+// Base dependencies
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::code::{Counter, SharedCounter};
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::histogram::Histogram;
+
+/// Lock timings of one thread
+struct ThreadProfile {
+    waits: Histogram,
+    wait: Duration,
+    hold: Duration,
+    running: Duration,
+}
+
+/// Increment the counter `increments` times, timing the wait and hold of every lock
+fn profile(counter: &Counter, increments: usize) -> ThreadProfile {
+    let mut waits = Histogram::new();
+    let (mut wait, mut hold) = (Duration::ZERO, Duration::ZERO);
+    let start = Instant::now();
+    for _ in 0..increments {
+        chaos::perturb(Point::Lock);
+        let (waited, held) = counter.increment_timed();
+        waits.record(waited);
+        wait += waited;
+        hold += held;
+    }
+    ThreadProfile {
+        waits,
+        wait,
+        hold,
+        running: start.elapsed(),
+    }
+}
+
+/// Share of `part` in `whole`, as a percentage
+fn share(part: Duration, whole: Duration) -> f64 {
+    part.as_secs_f64() / whole.as_secs_f64().max(f64::EPSILON) * 100.0
+}
+
+/// Run the contention profiler
+pub fn run(num_threads: usize, increments_per_thread: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads increment the Mutex counter {} times each, timing the wait and the hold of every lock",
+        num_threads, increments_per_thread
+    ));
+
+    let counter = Arc::new(Counter::new());
+    audit::track("profiled counter", &counter);
+    let handles: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || profile(&counter, increments_per_thread))
+        })
+        .collect();
+    let profiles: Vec<ThreadProfile> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+    println!();
+    println!(
+        "{:<8} {:>12} {:>14} {:>14} {:>9} {:>9} {:>12} {:>12}",
+        "thread", "locks", "wait", "hold", "waiting", "holding", "p50 wait", "p99 wait"
+    );
+    let mut all = Histogram::new();
+    let (mut wait, mut hold, mut running) = (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+    for (thread_id, profile) in profiles.iter().enumerate() {
+        println!(
+            "{:<8} {:>12} {:>14?} {:>14?} {:>8.1}% {:>8.1}% {:>12?} {:>12?}",
+            thread_id,
+            profile.waits.count(),
+            profile.wait,
+            profile.hold,
+            share(profile.wait, profile.running),
+            share(profile.hold, profile.running),
+            profile.waits.percentile(50.0),
+            profile.waits.percentile(99.0)
+        );
+        all.merge(&profile.waits);
+        wait += profile.wait;
+        hold += profile.hold;
+        running += profile.running;
+    }
+    println!(
+        "{:<8} {:>12} {:>14?} {:>14?} {:>8.1}% {:>8.1}% {:>12?} {:>12?}",
+        "all",
+        all.count(),
+        wait,
+        hold,
+        share(wait, running),
+        share(hold, running),
+        all.percentile(50.0),
+        all.percentile(99.0)
+    );
+
+    for (thread_id, profile) in profiles.iter().enumerate() {
+        println!();
+        common::print_info(&format!("Thread {} lock waits (max {:?})", thread_id, profile.waits.max()));
+        profile.waits.print();
+    }
+
+    let expected = num_threads * increments_per_thread;
+    println!();
+    if counter.get_value() == expected {
+        common::print_success(&format!("Counter reached {} as expected", expected));
+    } else {
+        common::print_warning(&format!("Counter reached {}, expected {}", counter.get_value(), expected));
+    }
+    common::print_info(&format!(
+        "Contention: {:.1}% of the threads' running time went into waiting for the lock, and waiting was {:.1}% of the time spent around it",
+        share(wait, running),
+        share(wait, wait + hold)
+    ));
+    common::print_info("Hold times include one clock read, so very short critical sections look a few tens of nanoseconds longer than they are");
+}
@@ -0,0 +1,195 @@
+//! Read-mostly configuration: ArcSwap snapshots vs RwLock
+//!
+//! Many readers consult a configuration that a single writer replaces now
+//! and then. Behind a `RwLock`, every read takes the lock, so readers bounce
+//! the lock's cache line between cores and stall whenever the writer holds
+//! it. `ArcSwap` works like read-copy-update: the writer builds a new
+//! version off to the side and swaps the pointer in one atomic step, and a
+//! reader just loads the current snapshot. Readers never wait for the
+//! writer, and a reader holding an old snapshot keeps it alive until it is
+//! done with it.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use arc_swap::ArcSwap;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Interval between two configuration updates
+const UPDATE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Number of entries in a configuration
+const ENTRIES: usize = 32;
+
+/// Time one read in this many, so the clock does not dominate the measurement
+const SAMPLE_EVERY: usize = 64;
+
+/// A configuration version; every entry holds the version, so a torn read is easy to spot
+struct Config {
+    version: u64,
+    limits: [u64; ENTRIES],
+}
+
+impl Config {
+    fn new(version: u64) -> Self {
+        Config {
+            version,
+            limits: [version; ENTRIES],
+        }
+    }
+
+    /// Whether every entry belongs to the same version
+    fn is_consistent(&self) -> bool {
+        self.limits.iter().all(|limit| *limit == self.version)
+    }
+}
+
+/// Shared configuration that readers load and one writer replaces
+trait ConfigStore: Send + Sync {
+    /// Run `read` on the current configuration
+    fn read<R>(&self, read: impl FnOnce(&Config) -> R) -> R;
+
+    /// Replace the configuration
+    fn update(&self, config: Config);
+}
+
+impl ConfigStore for RwLock<Config> {
+    fn read<R>(&self, read: impl FnOnce(&Config) -> R) -> R {
+        read(&self.read().unwrap())
+    }
+
+    fn update(&self, config: Config) {
+        chaos::perturb(Point::Lock);
+        *self.write().unwrap() = config;
+    }
+}
+
+impl ConfigStore for ArcSwap<Config> {
+    fn read<R>(&self, read: impl FnOnce(&Config) -> R) -> R {
+        read(&self.load())
+    }
+
+    fn update(&self, config: Config) {
+        chaos::perturb(Point::Lock);
+        self.store(Arc::new(config));
+    }
+}
+
+/// Outcome of one store under the workload
+struct SwapReport {
+    elapsed: Duration,
+    reads: usize,
+    updates: u64,
+    torn: usize,
+    versions_seen: u64,
+    slowest_read: Duration,
+}
+
+/// Let `num_readers` readers do `reads_per_reader` reads each while a writer updates the configuration
+fn run_store<S: ConfigStore + 'static>(name: &str, store: S, num_readers: usize, reads_per_reader: usize) -> SwapReport {
+    let store = Arc::new(store);
+    audit::track(name, &store);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writer = {
+        let (store, done) = (Arc::clone(&store), Arc::clone(&done));
+        thread::spawn(move || {
+            let mut version = 0;
+            while !done.load(Ordering::Relaxed) {
+                thread::sleep(UPDATE_INTERVAL);
+                version += 1;
+                store.update(Config::new(version));
+            }
+            version
+        })
+    };
+
+    let start = Instant::now();
+    let readers: Vec<_> = (0..num_readers)
+        .map(|_| {
+            let store = Arc::clone(&store);
+            thread::spawn(move || {
+                let (mut torn, mut slowest, mut last_version, mut versions) = (0, Duration::ZERO, None, 0);
+                for read in 0..reads_per_reader {
+                    let timed = (read % SAMPLE_EVERY == 0).then(Instant::now);
+                    let (version, consistent) = store.read(|config| (config.version, config.is_consistent()));
+                    if let Some(start) = timed {
+                        slowest = slowest.max(start.elapsed());
+                    }
+                    if !consistent {
+                        torn += 1;
+                    }
+                    if last_version != Some(version) {
+                        versions += 1;
+                        last_version = Some(version);
+                    }
+                }
+                (torn, slowest, versions)
+            })
+        })
+        .collect();
+
+    let results: Vec<(usize, Duration, u64)> = readers.into_iter().map(|reader| reader.join().unwrap()).collect();
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::Relaxed);
+    let updates = writer.join().unwrap();
+
+    SwapReport {
+        elapsed,
+        reads: num_readers * reads_per_reader,
+        updates,
+        torn: results.iter().map(|(torn, _, _)| torn).sum(),
+        versions_seen: results.iter().map(|(_, _, versions)| versions).sum::<u64>() / num_readers as u64,
+        slowest_read: results.iter().map(|(_, slowest, _)| *slowest).max().unwrap_or_default(),
+    }
+}
+
+/// Run the ArcSwap example and compare it with an RwLock
+pub fn run(num_threads: usize, reads_per_reader: usize) {
+    let num_readers = num_threads.max(1);
+    common::print_info(&format!(
+        "{} readers load a {}-entry configuration {} times each while a writer replaces it every {:?}",
+        num_readers, ENTRIES, reads_per_reader, UPDATE_INTERVAL
+    ));
+
+    println!();
+    println!(
+        "{:<14} {:>12} {:>14} {:>9} {:>14} {:>14} {:>6}",
+        "store", "time", "reads/s", "updates", "versions seen", "slowest read", "torn"
+    );
+    let reports = [
+        ("RwLock", run_store("RwLock config", RwLock::new(Config::new(0)), num_readers, reads_per_reader)),
+        ("ArcSwap", run_store("ArcSwap config", ArcSwap::from_pointee(Config::new(0)), num_readers, reads_per_reader)),
+    ];
+    for (name, report) in &reports {
+        println!(
+            "{:<14} {:>12?} {:>14.0} {:>9} {:>14} {:>14?} {:>6}",
+            name,
+            report.elapsed,
+            report.reads as f64 / report.elapsed.as_secs_f64(),
+            report.updates,
+            report.versions_seen,
+            report.slowest_read,
+            report.torn
+        );
+    }
+
+    println!();
+    if reports.iter().all(|(_, report)| report.torn == 0) {
+        common::print_success("Every read saw one complete version of the configuration");
+    } else {
+        common::print_warning("A reader saw entries from two different versions");
+    }
+    common::print_info("updates: versions the writer published; behind a RwLock a steady stream of readers can keep the writer waiting");
+    common::print_info("versions seen: distinct versions the average reader observed; slowest read: worst sampled read, where RwLock readers wait out the writer");
+    common::print_info("ArcSwap readers never take a lock: a load is a few atomic operations, and the writer swapping in a new version never blocks them");
+    common::print_info("The price is paid on the write side: every update allocates a whole new version, so this fits configuration that is read far more than written");
+}
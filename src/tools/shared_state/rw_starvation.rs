@@ -0,0 +1,297 @@
+//! Writer starvation under a reader-preferring RwLock, and a fair ticketed one
+//!
+//! The naive way to build a reader/writer lock lets a reader in whenever no
+//! writer holds the lock. A writer has to wait until no reader holds it, so
+//! with a continuous stream of overlapping readers the reader count never
+//! drops to zero and the writer waits for as long as the stream lasts. A
+//! ticketed lock serves arrivals in order instead: readers that arrive after
+//! a waiting writer queue behind it, and the writer only waits for the
+//! readers already inside. `std::sync::RwLock` is shown for reference; its
+//! policy depends on the platform (on Linux it prefers writers).
+
+// Base dependencies
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// How long each reader holds the lock
+const READ_HOLD: Duration = Duration::from_millis(1);
+
+/// Writes the writer tries to make
+const WRITES: usize = 20;
+
+/// Pause between two writes
+const WRITE_INTERVAL: Duration = Duration::from_millis(2);
+
+/// How long the readers keep coming before they stop, freeing any starved writer
+const READ_STREAM: Duration = Duration::from_millis(500);
+
+/// A lock with shared readers and exclusive writers
+trait ReadWriteLock: Send + Sync {
+    fn read<R>(&self, section: impl FnOnce(&u64) -> R) -> R;
+    fn write<R>(&self, section: impl FnOnce(&mut u64) -> R) -> R;
+}
+
+impl ReadWriteLock for RwLock<u64> {
+    fn read<R>(&self, section: impl FnOnce(&u64) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        section(&self.read().unwrap())
+    }
+
+    fn write<R>(&self, section: impl FnOnce(&mut u64) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        section(&mut self.write().unwrap())
+    }
+}
+
+/// Who is inside a reader-preferring lock
+#[derive(Default)]
+struct Holders {
+    readers: usize,
+    writer: bool,
+}
+
+/// Readers get in whenever no writer holds the lock, even while a writer waits
+#[derive(Default)]
+struct ReaderPreferring {
+    holders: Mutex<Holders>,
+    released: Condvar,
+    value: UnsafeCell<u64>,
+}
+
+// Safety: the holders only let one writer, or any number of readers, reach the value
+unsafe impl Sync for ReaderPreferring {}
+
+impl ReadWriteLock for ReaderPreferring {
+    fn read<R>(&self, section: impl FnOnce(&u64) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        let mut holders = self.released.wait_while(self.holders.lock().unwrap(), |holders| holders.writer).unwrap();
+        holders.readers += 1;
+        drop(holders);
+
+        // Safety: only readers hold the lock
+        let result = section(unsafe { &*self.value.get() });
+
+        let mut holders = self.holders.lock().unwrap();
+        holders.readers -= 1;
+        if holders.readers == 0 {
+            self.released.notify_all();
+        }
+        result
+    }
+
+    fn write<R>(&self, section: impl FnOnce(&mut u64) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        let mut holders = self
+            .released
+            .wait_while(self.holders.lock().unwrap(), |holders| holders.writer || holders.readers > 0)
+            .unwrap();
+        holders.writer = true;
+        drop(holders);
+
+        // Safety: the writer holds the lock alone
+        let result = section(unsafe { &mut *self.value.get() });
+
+        self.holders.lock().unwrap().writer = false;
+        self.released.notify_all();
+        result
+    }
+}
+
+/// Ticket counters and holders of a ticketed lock
+#[derive(Default)]
+struct Tickets {
+    /// Ticket handed to the next arrival
+    next: u64,
+    /// Ticket allowed in next
+    serving: u64,
+    readers: usize,
+}
+
+/// Arrivals are served in ticket order; consecutive readers share the lock
+#[derive(Default)]
+struct Ticketed {
+    tickets: Mutex<Tickets>,
+    advanced: Condvar,
+    value: UnsafeCell<u64>,
+}
+
+// Safety: the tickets only let one writer, or any number of readers, reach the value
+unsafe impl Sync for Ticketed {}
+
+impl Ticketed {
+    /// Take a ticket and wait for it to be served
+    fn wait_turn(&self) -> std::sync::MutexGuard<'_, Tickets> {
+        let mut tickets = self.tickets.lock().unwrap();
+        let ticket = tickets.next;
+        tickets.next += 1;
+        self.advanced.wait_while(tickets, |tickets| tickets.serving != ticket).unwrap()
+    }
+}
+
+impl ReadWriteLock for Ticketed {
+    fn read<R>(&self, section: impl FnOnce(&u64) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        let mut tickets = self.wait_turn();
+
+        // A reader passes its turn on right away, so the next reader can join it
+        tickets.readers += 1;
+        tickets.serving += 1;
+        drop(tickets);
+        self.advanced.notify_all();
+
+        // Safety: a writer only enters once every reader admitted before it has left
+        let result = section(unsafe { &*self.value.get() });
+
+        let mut tickets = self.tickets.lock().unwrap();
+        tickets.readers -= 1;
+        if tickets.readers == 0 {
+            self.advanced.notify_all();
+        }
+        result
+    }
+
+    fn write<R>(&self, section: impl FnOnce(&mut u64) -> R) -> R {
+        chaos::perturb(Point::Lock);
+        let tickets = self.wait_turn();
+
+        // Holding the turn keeps later arrivals out; wait for the readers already inside
+        drop(self.advanced.wait_while(tickets, |tickets| tickets.readers > 0).unwrap());
+
+        // Safety: the writer holds the turn and no reader is inside
+        let result = section(unsafe { &mut *self.value.get() });
+
+        self.tickets.lock().unwrap().serving += 1;
+        self.advanced.notify_all();
+        result
+    }
+}
+
+/// Outcome of one lock
+struct StarvationReport {
+    /// Time each write waited for the lock, sorted
+    waits: Vec<Duration>,
+    /// Writes done while the readers were still coming
+    writes_during_stream: usize,
+    /// Reads per second while the readers were running
+    read_rate: f64,
+    consistent: bool,
+}
+
+/// Let `num_readers` readers stream through the lock while one writer tries to get in
+fn measure<L: ReadWriteLock + 'static>(name: &str, lock: L, num_readers: usize) -> StarvationReport {
+    let lock = Arc::new(lock);
+    audit::track(name, &lock);
+    let stop = Arc::new(AtomicBool::new(false));
+    let start = Instant::now();
+
+    // Readers start staggered across one hold, so there is always one inside
+    let readers: Vec<_> = (0..num_readers)
+        .map(|reader| {
+            let (lock, stop) = (Arc::clone(&lock), Arc::clone(&stop));
+            thread::spawn(move || {
+                thread::sleep(READ_HOLD * reader as u32 / num_readers as u32);
+                let mut reads = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    lock.read(|_| thread::sleep(READ_HOLD));
+                    reads += 1;
+                }
+                reads
+            })
+        })
+        .collect();
+
+    let writer = {
+        let (lock, stop) = (Arc::clone(&lock), Arc::clone(&stop));
+        thread::spawn(move || {
+            thread::sleep(READ_HOLD * 5);
+            let mut waits = Vec::with_capacity(WRITES);
+            let mut writes_during_stream = 0;
+            for _ in 0..WRITES {
+                let start = Instant::now();
+                lock.write(|value| *value += 1);
+                waits.push(start.elapsed());
+                if !stop.load(Ordering::Relaxed) {
+                    writes_during_stream += 1;
+                }
+                thread::sleep(WRITE_INTERVAL);
+            }
+            (waits, writes_during_stream)
+        })
+    };
+
+    // The readers stop once the writer is done, or once the stream has lasted long enough
+    let deadline = start + READ_STREAM;
+    while !writer.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+    }
+    stop.store(true, Ordering::Relaxed);
+    let stream = start.elapsed();
+
+    let (mut waits, writes_during_stream) = writer.join().unwrap();
+    let reads: u64 = readers.into_iter().map(|reader| reader.join().unwrap()).sum();
+    waits.sort_unstable();
+    StarvationReport {
+        waits,
+        writes_during_stream,
+        read_rate: reads as f64 / stream.as_secs_f64(),
+        consistent: lock.read(|value| *value) == WRITES as u64,
+    }
+}
+
+/// Run the writer starvation example with `num_threads` readers
+pub fn run(num_threads: usize) {
+    let num_readers = num_threads.max(2);
+    common::print_info(&format!(
+        "{} readers hold the lock for {:?} each, back to back, for up to {:?}, while one writer makes {} writes",
+        num_readers, READ_HOLD, READ_STREAM, WRITES
+    ));
+
+    let reports = [
+        ("reader-preferring", measure("reader-preferring lock", ReaderPreferring::default(), num_readers)),
+        ("ticketed (fair)", measure("ticketed lock", Ticketed::default(), num_readers)),
+        ("std RwLock", measure("std RwLock", RwLock::new(0), num_readers)),
+    ];
+
+    println!();
+    println!(
+        "{:<18} {:>14} {:>14} {:>14} {:>16} {:>10}",
+        "lock", "write p50", "write max", "total wait", "during stream", "reads/s"
+    );
+    for (name, report) in &reports {
+        println!(
+            "{:<18} {:>14?} {:>14?} {:>14?} {:>16} {:>10.0}",
+            name,
+            common::percentile(&report.waits, 50.0),
+            report.waits.last().copied().unwrap_or_default(),
+            report.waits.iter().sum::<Duration>(),
+            format!("{}/{}", report.writes_during_stream, WRITES),
+            report.read_rate
+        );
+    }
+
+    println!();
+    if reports.iter().any(|(_, report)| !report.consistent) {
+        common::print_warning("A lock lost a write");
+    }
+    let (naive, fair) = (&reports[0].1, &reports[1].1);
+    if naive.writes_during_stream < fair.writes_during_stream {
+        common::print_warning(&format!(
+            "The reader-preferring lock let the writer in {} time(s) while readers kept coming; it waited up to {:?}",
+            naive.writes_during_stream,
+            naive.waits.last().copied().unwrap_or_default()
+        ));
+    }
+    if fair.writes_during_stream == WRITES {
+        common::print_success("The ticketed lock served every write during the read stream, each after the readers already inside");
+    }
+    common::print_info("A reader-preferring lock maximizes read throughput, but a writer only gets in when the readers happen to leave all at once");
+    common::print_info("Ticketing bounds the writer's wait by the readers that arrived before it, at the cost of readers queueing behind writers");
+}
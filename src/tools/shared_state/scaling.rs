@@ -0,0 +1,122 @@
+//! Throughput of the counter strategies as the thread count grows
+//!
+//! Adding threads only helps while they do not wait for each other. Every
+//! thread here does the same number of increments, so perfect scaling would
+//! multiply the throughput by the thread count. A single `Mutex` goes the
+//! other way: the threads queue on one lock and bounce one cache line
+//! between their cores, and past a few threads the total throughput drops
+//! below what one thread manages alone. The atomic counter still shares the
+//! cache line but never waits for a lock, and the sharded counter shares
+//! neither.
+
+// Project dependencies
+use super::code::{strategy_label, time_strategy};
+use super::false_sharing::thread_counts;
+use crate::common;
+use crate::cpus;
+use crate::CounterStrategy;
+
+/// Runs per cell, keeping the fastest to hide scheduling hiccups
+const REPEATS: usize = 3;
+
+/// Throughput ratio below which a drop counts as real rather than run-to-run noise
+const NOISE_BAND: f64 = 0.9;
+
+/// Increments per second of `num_threads` threads, the best of `REPEATS` runs, and whether every run counted right
+fn throughput(strategy: CounterStrategy, num_threads: usize, increments: usize) -> (f64, bool) {
+    let expected = num_threads * increments;
+    let mut correct = true;
+    let best = (0..REPEATS)
+        .map(|_| {
+            let (elapsed, value) = time_strategy(strategy, num_threads, increments);
+            correct &= value == expected;
+            expected as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+        })
+        .fold(0.0, f64::max);
+    (best, correct)
+}
+
+/// Run the counter workload for every strategy at 1, 2, 4, … `num_threads` threads
+pub fn run(num_threads: usize, increments: usize) {
+    let counts = thread_counts(num_threads.max(1));
+    common::print_info(&format!(
+        "Each thread makes {} increments, at {:?} threads, best of {} runs per cell",
+        increments, counts, REPEATS
+    ));
+
+    println!();
+    print!("{:<8}", "threads");
+    for strategy in CounterStrategy::ALL {
+        print!(" {:>24}", format!("{} (M inc/s, scale)", strategy_label(strategy)));
+    }
+    println!();
+
+    let mut rates: Vec<Vec<f64>> = vec![vec![]; CounterStrategy::ALL.len()];
+    let mut correct = true;
+    for threads in &counts {
+        print!("{:<8}", threads);
+        for (index, strategy) in CounterStrategy::ALL.into_iter().enumerate() {
+            let (rate, ok) = throughput(strategy, *threads, increments);
+            correct &= ok;
+            rates[index].push(rate);
+            print!(" {:>24}", format!("{:.2} ({:.2}x)", rate / 1e6, rate / rates[index][0]));
+        }
+        println!();
+    }
+
+    println!();
+    if !correct {
+        common::print_warning("A counter lost increments");
+    }
+    for (strategy, rates) in CounterStrategy::ALL.into_iter().zip(&rates) {
+        let (peak, best) = rates
+            .iter()
+            .enumerate()
+            .fold((0, 0.0), |(peak, best), (index, rate)| if *rate > best { (index, *rate) } else { (peak, best) });
+        let last = *rates.last().unwrap();
+        if last < rates[0] * NOISE_BAND {
+            common::print_warning(&format!(
+                "{}: negative scaling, {} threads do {:.2}x the work per second of one thread (peak at {} threads)",
+                strategy_label(strategy),
+                counts.last().unwrap(),
+                last / rates[0],
+                counts[peak]
+            ));
+        } else if last < rates[0] {
+            common::print_info(&format!(
+                "{}: flat, {} threads do {:.2}x the work per second of one thread, within run-to-run noise",
+                strategy_label(strategy),
+                counts.last().unwrap(),
+                last / rates[0]
+            ));
+        } else if last < best * NOISE_BAND {
+            common::print_info(&format!(
+                "{}: throughput peaks at {} threads, then falls as more threads contend",
+                strategy_label(strategy),
+                counts[peak]
+            ));
+        } else if peak + 1 < counts.len() {
+            common::print_success(&format!(
+                "{}: {:.2}x at {} threads, within run-to-run noise of the peak at {} threads",
+                strategy_label(strategy),
+                last / rates[0],
+                counts.last().unwrap(),
+                counts[peak]
+            ));
+        } else {
+            common::print_success(&format!(
+                "{}: throughput keeps rising, {:.2}x at {} threads",
+                strategy_label(strategy),
+                last / rates[0],
+                counts.last().unwrap()
+            ));
+        }
+    }
+    common::print_info("Contended threads do not add work, they add waiting: past the peak every new thread makes the whole counter slower");
+    if cpus::available() < counts.last().copied().unwrap_or(1) {
+        common::print_warning(&format!(
+            "Only {} CPU(s) available: beyond that the threads take turns instead of running in parallel",
+            cpus::available()
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+//! Scoped threads borrowing stack-local data with `std::thread::scope`
+//!
+//! `thread::spawn` requires `'static` data, because the spawned thread may
+//! outlive the function that spawned it. That is why the counter example
+//! wraps its state in `Arc` to share ownership and in `Mutex` to share
+//! mutation. `thread::scope` joins every thread it spawned before it
+//! returns, so its threads may borrow local variables instead. Splitting a
+//! local `Vec` with `chunks_mut` hands each thread a `&mut` slice of its own:
+//! the borrow checker proves the slices are disjoint, and no lock is needed.
+
+// Base dependencies
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Cells each thread owns in the shared vector
+const CELLS_PER_THREAD: usize = 1024;
+
+/// `thread::spawn` with the vector behind `Arc<Mutex<…>>`, locked for every update like the counter example
+fn with_arc_mutex(num_threads: usize, passes: usize) -> (Duration, Vec<u64>) {
+    let cells = Arc::new(Mutex::new(vec![0u64; num_threads * CELLS_PER_THREAD]));
+    audit::track("scoped cells (Arc<Mutex>)", &cells);
+    let start = Instant::now();
+
+    let handles: Vec<_> = (0..num_threads)
+        .map(|thread_id| {
+            let cells = Arc::clone(&cells);
+            thread::spawn(move || {
+                let chunk = thread_id * CELLS_PER_THREAD..(thread_id + 1) * CELLS_PER_THREAD;
+                for _ in 0..passes {
+                    for index in chunk.clone() {
+                        chaos::perturb(Point::Lock);
+                        cells.lock().unwrap()[index] += 1;
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let elapsed = start.elapsed();
+    let cells = Arc::try_unwrap(cells).unwrap().into_inner().unwrap();
+    (elapsed, cells)
+}
+
+/// `thread::scope` with every thread borrowing its own `&mut` chunk of a local vector
+fn with_scope(num_threads: usize, passes: usize) -> (Duration, Vec<u64>) {
+    let mut cells = vec![0u64; num_threads * CELLS_PER_THREAD];
+    let start = Instant::now();
+
+    thread::scope(|scope| {
+        for chunk in cells.chunks_mut(CELLS_PER_THREAD) {
+            scope.spawn(move || {
+                for _ in 0..passes {
+                    for cell in chunk.iter_mut() {
+                        *cell += 1;
+                    }
+                }
+            });
+        }
+    });
+
+    // Every scoped thread has been joined, so the vector is ours again
+    (start.elapsed(), cells)
+}
+
+/// Run the scoped threads example with `num_threads` threads making `passes` passes over their cells
+pub fn run(num_threads: usize, passes: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads each add 1 to their own {} cells of one vector, {} times over",
+        num_threads, CELLS_PER_THREAD, passes
+    ));
+
+    println!();
+    println!("{:<20} {:>14} {:>14} {:>10}", "approach", "time", "ns/update", "correct");
+    let updates = (num_threads * CELLS_PER_THREAD * passes).max(1);
+    let reports = [
+        ("Arc<Mutex<Vec>>", with_arc_mutex(num_threads, passes)),
+        ("thread::scope", with_scope(num_threads, passes)),
+    ];
+    for (label, (elapsed, cells)) in &reports {
+        println!(
+            "{:<20} {:>14?} {:>14.2} {:>10}",
+            label,
+            elapsed,
+            elapsed.as_nanos() as f64 / updates as f64,
+            cells.iter().all(|cell| *cell == passes as u64)
+        );
+    }
+
+    println!();
+    let (locked, scoped) = (reports[0].1 .0, reports[1].1 .0);
+    common::print_success(&format!(
+        "The scoped version needs no Arc, no Mutex and no clone per thread, and ran {:.1}x faster",
+        locked.as_secs_f64() / scoped.as_secs_f64().max(f64::EPSILON)
+    ));
+    common::print_info("The Mutex serializes every update even though no two threads ever touch the same cell");
+    common::print_info("chunks_mut gives each thread a disjoint &mut slice, and the scope joins the threads before the vector can be used again");
+    common::print_info("Arc<Mutex<…>> is still the tool when threads really share the same data, or must outlive the function that spawned them");
+}
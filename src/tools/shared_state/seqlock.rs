@@ -0,0 +1,229 @@
+//! Sequence lock for a small multi-field struct, with torn-read detection
+//!
+//! A seqlock protects data that is written rarely by one writer and read
+//! often. The writer makes a sequence number odd, updates the fields, then
+//! makes it even again. A reader reads the sequence number, the fields, and
+//! the sequence number again: if it was odd or changed in between, a write
+//! overlapped the read and the reader retries. Readers never block the
+//! writer and never write to shared memory. The fields are atomics read and
+//! written with Relaxed ordering, since a reader may race with the writer.
+//!
+//! The broken version drops the sequence number: readers that overlap a
+//! write see some fields from the old value and some from the new one.
+
+// Base dependencies
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// A position and the time it was taken; every field derives from one sample number, so a mix is detectable
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Position {
+    x: u64,
+    y: u64,
+    z: u64,
+    timestamp: u64,
+}
+
+impl Position {
+    fn sample(n: u64) -> Self {
+        Position {
+            x: n,
+            y: n * 2,
+            z: n * 3,
+            timestamp: n * 10,
+        }
+    }
+
+    /// Whether all fields come from the same sample
+    fn is_consistent(&self) -> bool {
+        self.y == self.x * 2 && self.z == self.x * 3 && self.timestamp == self.x * 10
+    }
+}
+
+/// The struct's fields, stored as atomics so racing reads are defined behaviour
+#[derive(Default)]
+struct Fields([AtomicU64; 4]);
+
+impl Fields {
+    fn store(&self, position: Position) {
+        let values = [position.x, position.y, position.z, position.timestamp];
+        for (field, value) in self.0.iter().zip(values) {
+            field.store(value, Ordering::Relaxed);
+        }
+    }
+
+    fn load(&self) -> Position {
+        let [x, y, z, timestamp] = [0, 1, 2, 3].map(|index| self.0[index].load(Ordering::Relaxed));
+        Position { x, y, z, timestamp }
+    }
+}
+
+/// A position shared between one writer and many readers
+trait SharedPosition: Send + Sync {
+    /// Publish a new position (one writer at a time)
+    fn write(&self, position: Position);
+
+    /// Read the position, returning it with the number of retries it took
+    fn read(&self) -> (Position, u64);
+}
+
+/// Sequence-locked position
+#[derive(Default)]
+struct SeqLock {
+    sequence: AtomicU64,
+    fields: Fields,
+}
+
+impl SharedPosition for SeqLock {
+    fn write(&self, position: Position) {
+        // Odd: a write is in progress. The fence keeps the field stores from moving above it
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        chaos::perturb(Point::Lock);
+        self.fields.store(position);
+
+        // Even again: Release publishes the fields to readers that see the new number
+        self.sequence.fetch_add(1, Ordering::Release);
+    }
+
+    fn read(&self) -> (Position, u64) {
+        let mut retries = 0;
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                retries += 1;
+                std::hint::spin_loop();
+                continue;
+            }
+            let position = self.fields.load();
+
+            // The fence keeps the field loads from moving below the second sequence load
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == before {
+                return (position, retries);
+            }
+            retries += 1;
+        }
+    }
+}
+
+/// The same fields without a sequence number: reads can overlap writes unnoticed
+#[derive(Default)]
+struct Unsynchronized {
+    fields: Fields,
+}
+
+impl SharedPosition for Unsynchronized {
+    fn write(&self, position: Position) {
+        chaos::perturb(Point::Lock);
+        self.fields.store(position);
+    }
+
+    fn read(&self) -> (Position, u64) {
+        (self.fields.load(), 0)
+    }
+}
+
+/// Outcome of one variant
+struct SeqLockReport {
+    elapsed: Duration,
+    reads: u64,
+    retries: u64,
+    torn: u64,
+}
+
+/// Let one writer publish `writes` samples while `num_readers` readers read until it is done
+fn run_variant<P: SharedPosition + Default + 'static>(name: &str, num_readers: usize, writes: usize) -> SeqLockReport {
+    let shared = Arc::new(P::default());
+    shared.write(Position::sample(0));
+    audit::track(name, &shared);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let start = Instant::now();
+    let readers: Vec<_> = (0..num_readers)
+        .map(|_| {
+            let (shared, done) = (Arc::clone(&shared), Arc::clone(&done));
+            thread::spawn(move || {
+                let (mut reads, mut retries, mut torn) = (0, 0, 0);
+                while !done.load(Ordering::Relaxed) {
+                    let (position, attempts) = shared.read();
+                    reads += 1;
+                    retries += attempts;
+                    if !position.is_consistent() {
+                        torn += 1;
+                    }
+                }
+                (reads, retries, torn)
+            })
+        })
+        .collect();
+
+    for n in 1..=writes as u64 {
+        shared.write(Position::sample(n));
+    }
+    done.store(true, Ordering::Relaxed);
+
+    let mut report = SeqLockReport {
+        elapsed: Duration::ZERO,
+        reads: 0,
+        retries: 0,
+        torn: 0,
+    };
+    for reader in readers {
+        let (reads, retries, torn) = reader.join().unwrap();
+        report.reads += reads;
+        report.retries += retries;
+        report.torn += torn;
+    }
+    report.elapsed = start.elapsed();
+    report
+}
+
+/// Run the seqlock example next to the broken unsynchronized version
+pub fn run(num_threads: usize, writes: usize) {
+    let num_readers = num_threads.max(1);
+    common::print_info(&format!(
+        "One writer publishes {} positions of 4 fields while {} readers read them",
+        writes, num_readers
+    ));
+
+    println!();
+    println!(
+        "{:<16} {:>12} {:>12} {:>12} {:>12}",
+        "variant", "time", "reads", "retries", "torn reads"
+    );
+    let reports = [
+        ("SeqLock", run_variant::<SeqLock>("seqlock position", num_readers, writes)),
+        ("unsynchronized", run_variant::<Unsynchronized>("unsynchronized position", num_readers, writes)),
+    ];
+    for (name, report) in &reports {
+        println!(
+            "{:<16} {:>12?} {:>12} {:>12} {:>12}",
+            name, report.elapsed, report.reads, report.retries, report.torn
+        );
+    }
+
+    println!();
+    let (seqlock, broken) = (&reports[0].1, &reports[1].1);
+    if seqlock.torn == 0 {
+        common::print_success("The seqlock readers never returned a torn position: every overlap with a write was caught and retried");
+    } else {
+        common::print_warning("The seqlock returned a torn position");
+    }
+    if broken.torn > 0 {
+        common::print_warning(&format!(
+            "The unsynchronized readers returned {} torn position(s), mixing fields of two samples",
+            broken.torn
+        ));
+    } else {
+        common::print_info("No torn read was caught in the unsynchronized version this time; raise --increments or add --chaos");
+    }
+    common::print_info("Seqlock readers retry instead of waiting, so they suit small data written rarely; a writer that never pauses can keep readers retrying");
+}
@@ -0,0 +1,172 @@
+//! Cooperative shutdown of std threads with a shared `AtomicBool` flag
+//!
+//! A std thread cannot be killed from outside: the only clean way to stop a
+//! long-running worker is to ask it. The main thread sets a shared stop flag,
+//! when a timer runs out or on Ctrl-C, and every worker checks the flag
+//! between chunks of work, finishes the chunk in hand and returns. How long a
+//! worker takes to notice depends on how much work it does between checks,
+//! so the workers here check at different granularities. The flag is stored
+//! with Release and loaded with Acquire, so whatever the main thread wrote
+//! before asking for the stop is visible to every worker that sees it.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::pacing;
+
+/// Work done between two checks of the stop flag, cycled over the workers
+const CHECK_INTERVALS: [Duration; 3] = [
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(20),
+];
+
+/// How often the main thread looks for Ctrl-C while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Set by the SIGINT handler; a plain atomic store is all a signal handler may safely do
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Route Ctrl-C to `INTERRUPTED` instead of killing the process, returning whether it worked
+#[cfg(unix)]
+fn catch_interrupt() -> bool {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+
+    // Safety: the handler only stores to an atomic, which is async-signal-safe
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) != libc::SIG_ERR }
+}
+
+/// Give Ctrl-C its default behaviour back
+#[cfg(unix)]
+fn release_interrupt() {
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn release_interrupt() {}
+
+/// What one worker did before it stopped
+struct WorkerReport {
+    check_interval: Duration,
+    chunks: u64,
+    /// When the worker saw the flag
+    noticed: Instant,
+    /// When the worker had wrapped up and returned
+    exited: Instant,
+}
+
+/// Do chunks of work until the stop flag is set, then publish the result and return
+fn worker(check_interval: Duration, stop: &AtomicBool, published: &AtomicU64) -> WorkerReport {
+    let mut chunks = 0;
+    while !stop.load(Ordering::Acquire) {
+        pacing::spin_for(check_interval);
+        chunks += 1;
+    }
+    let noticed = Instant::now();
+
+    // Wrapping up: the chunk in hand is complete, so the result is consistent
+    published.fetch_add(chunks, Ordering::Relaxed);
+    WorkerReport {
+        check_interval,
+        chunks,
+        noticed,
+        exited: Instant::now(),
+    }
+}
+
+/// Run the cooperative shutdown example: `num_threads` workers stopped after `run_ms` milliseconds or on Ctrl-C
+pub fn run(num_threads: usize, run_ms: usize) {
+    let num_threads = num_threads.max(1);
+    let run_for = Duration::from_millis(run_ms as u64);
+    let catching = catch_interrupt();
+    common::print_info(&format!(
+        "{} workers check a shared stop flag every {:?}, {:?} or {:?} of work; the main thread sets it after {:?}{}",
+        num_threads,
+        CHECK_INTERVALS[0],
+        CHECK_INTERVALS[1],
+        CHECK_INTERVALS[2],
+        run_for,
+        if catching { " or on Ctrl-C" } else { "" }
+    ));
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let published = Arc::new(AtomicU64::new(0));
+    audit::track("stop flag", &stop);
+
+    let handles: Vec<_> = (0..num_threads)
+        .map(|index| {
+            let (stop, published) = (Arc::clone(&stop), Arc::clone(&published));
+            let check_interval = CHECK_INTERVALS[index % CHECK_INTERVALS.len()];
+            thread::Builder::new()
+                .name(format!("worker-{}", index))
+                .spawn(move || worker(check_interval, &stop, &published))
+                .unwrap()
+        })
+        .collect();
+
+    // Wait for the timer or Ctrl-C, whichever comes first
+    let deadline = Instant::now() + run_for;
+    while Instant::now() < deadline && !INTERRUPTED.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+    let interrupted = INTERRUPTED.swap(false, Ordering::Relaxed);
+    let requested = Instant::now();
+    stop.store(true, Ordering::Release);
+
+    let reports: Vec<WorkerReport> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    let all_stopped = requested.elapsed();
+    release_interrupt();
+
+    if interrupted {
+        common::print_warning("Ctrl-C received: stop requested");
+    } else {
+        common::print_info("Timer expired: stop requested");
+    }
+
+    println!();
+    println!(
+        "{:<10} {:>16} {:>10} {:>16} {:>16}",
+        "worker", "check interval", "chunks", "noticed after", "exited after"
+    );
+    for (index, report) in reports.iter().enumerate() {
+        println!(
+            "{:<10} {:>16?} {:>10} {:>16?} {:>16?}",
+            format!("worker-{}", index),
+            report.check_interval,
+            report.chunks,
+            report.noticed.saturating_duration_since(requested),
+            report.exited.saturating_duration_since(requested)
+        );
+    }
+
+    println!();
+    let total: u64 = reports.iter().map(|report| report.chunks).sum();
+    if published.load(Ordering::Relaxed) == total {
+        common::print_success(&format!(
+            "Every worker finished its chunk in hand, published its result and returned; all {} joined {:?} after the request",
+            num_threads, all_stopped
+        ));
+    } else {
+        common::print_warning("The published total does not match the workers' chunks");
+    }
+    common::print_info("A worker notices the flag at its next check, so the stop latency is bounded by the work between two checks");
+    common::print_info("Nothing preempts a worker that never checks: blocking calls need their own timeouts, or a channel the worker selects on");
+}
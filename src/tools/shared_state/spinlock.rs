@@ -0,0 +1,229 @@
+//! Test-and-set spinlock with exponential backoff, benchmarked against Mutex
+//!
+//! A spinlock never sleeps: a thread that finds it taken keeps retrying,
+//! backing off for exponentially longer pauses between attempts to spare
+//! the lock's cache line. With short critical sections the lock frees up
+//! within a few pauses, and spinning beats the cost of putting a thread to
+//! sleep and waking it up again. With long critical sections the waiters
+//! burn CPU for the whole time the holder works, which is where a `Mutex`,
+//! parking its waiters in the kernel, wins.
+
+// Base dependencies
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::cpus;
+use crate::pacing;
+
+/// Longest backoff, in spin-loop hints, before a waiter yields its time slice instead
+const MAX_BACKOFF: u32 = 1 << 10;
+
+/// Work done inside the lock by the long critical sections
+const LONG_SECTION: Duration = Duration::from_micros(50);
+
+/// Critical sections per thread in the long case, which take far longer each
+const LONG_SECTION_OPS: usize = 200;
+
+/// Mutual exclusion by spinning on an atomic flag
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    /// Backoff rounds spent waiting, across all threads
+    spins: AtomicU64,
+    value: UnsafeCell<T>,
+}
+
+// Safety: the flag guarantees that only one thread at a time reaches the value
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+/// Access to the value, released when dropped
+pub struct SpinGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub fn new(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            spins: AtomicU64::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Spin until the lock is taken
+    pub fn lock(&self) -> SpinGuard<'_, T> {
+        let mut backoff = 1;
+        let mut spins = 0;
+
+        // Test-and-set: Acquire makes the previous holder's writes visible once the swap wins
+        while self.locked.swap(true, Ordering::Acquire) {
+            spins += 1;
+            if backoff <= MAX_BACKOFF {
+                for _ in 0..backoff {
+                    std::hint::spin_loop();
+                }
+                backoff *= 2;
+            } else {
+                // The holder is probably not running; spinning on would only delay it
+                thread::yield_now();
+            }
+        }
+        if spins > 0 {
+            self.spins.fetch_add(spins, Ordering::Relaxed);
+        }
+        SpinGuard { lock: self }
+    }
+
+    /// Backoff rounds all waiters spent so far
+    pub fn spins(&self) -> u64 {
+        self.spins.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Deref for SpinGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: holding the guard means holding the lock
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: holding the guard means holding the lock
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release publishes the writes made under the lock to the next holder
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// A lock the benchmark can run a critical section under
+trait Lock: Send + Sync {
+    fn with(&self, section: impl FnOnce(&mut u64));
+
+    /// Backoff rounds spent waiting, if the lock counts them
+    fn spins(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Lock for Mutex<u64> {
+    fn with(&self, section: impl FnOnce(&mut u64)) {
+        chaos::perturb(Point::Lock);
+        section(&mut self.lock().unwrap());
+    }
+}
+
+impl Lock for SpinLock<u64> {
+    fn with(&self, section: impl FnOnce(&mut u64)) {
+        chaos::perturb(Point::Lock);
+        section(&mut self.lock());
+    }
+
+    fn spins(&self) -> Option<u64> {
+        Some(SpinLock::spins(self))
+    }
+}
+
+/// Outcome of one lock under one critical section length
+struct LockReport {
+    elapsed: Duration,
+    cpu: Option<Duration>,
+    spins: Option<u64>,
+    correct: bool,
+}
+
+/// Let every thread run `ops` critical sections of length `section` (zero: just the increment)
+fn bench<L: Lock + 'static>(name: &str, lock: L, num_threads: usize, ops: usize, section: Duration) -> LockReport {
+    let lock = Arc::new(lock);
+    audit::track(name, &lock);
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || {
+                let cpu_start = common::thread_cpu_time();
+                for _ in 0..ops {
+                    lock.with(|count| {
+                        if !section.is_zero() {
+                            pacing::spin_for(section);
+                        }
+                        *count += 1;
+                    });
+                }
+                cpu_start.zip(common::thread_cpu_time()).map(|(before, after)| after - before)
+            })
+        })
+        .collect();
+    let cpu: Option<Duration> = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+    let elapsed = start.elapsed();
+
+    let mut count = 0;
+    lock.with(|value| count = *value);
+    LockReport {
+        elapsed,
+        cpu,
+        spins: lock.spins(),
+        correct: count == (num_threads * ops) as u64,
+    }
+}
+
+/// Run the spinlock example for short and long critical sections
+pub fn run(num_threads: usize, increments: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads take the lock {} times each around an increment, then {} times each around {:?} of work",
+        num_threads, increments, LONG_SECTION_OPS, LONG_SECTION
+    ));
+
+    println!();
+    println!(
+        "{:<10} {:<10} {:>14} {:>14} {:>14} {:>14}",
+        "section", "lock", "time", "ops/s", "cpu time", "backoff rounds"
+    );
+    let mut correct = true;
+    for (label, ops, section) in [("short", increments, Duration::ZERO), ("long", LONG_SECTION_OPS, LONG_SECTION)] {
+        let reports = [
+            ("Mutex", bench("Mutex benchmark lock", Mutex::new(0), num_threads, ops, section)),
+            ("SpinLock", bench("spinlock benchmark lock", SpinLock::new(0), num_threads, ops, section)),
+        ];
+        for (name, report) in &reports {
+            correct &= report.correct;
+            println!(
+                "{:<10} {:<10} {:>14?} {:>14.0} {:>14} {:>14}",
+                label,
+                name,
+                report.elapsed,
+                (num_threads * ops) as f64 / report.elapsed.as_secs_f64(),
+                report.cpu.map_or("n/a".to_string(), |cpu| format!("{:?}", cpu)),
+                report.spins.map_or("-".to_string(), |spins| spins.to_string())
+            );
+        }
+    }
+
+    println!();
+    if correct {
+        common::print_success("Both locks kept every increment");
+    } else {
+        common::print_warning("A lock lost an increment");
+    }
+    common::print_info("Short sections: the spinlock is usually released within a few backoff rounds, cheaper than parking and waking a thread");
+    common::print_info("Long sections: spinning waiters burn CPU for as long as the holder works, so compare the cpu time column with the wall time");
+    if cpus::available() < 2 {
+        common::print_warning("With a single CPU a spinning waiter only delays the holder; the backoff ends in yields to let it run");
+    }
+}
@@ -0,0 +1,161 @@
+//! Software transactional memory (STM) experiment
+//!
+//! This module implements a tiny STM: values live in versioned cells
+//! (`TVar`), transactions read optimistically and buffer their writes, and
+//! the commit validates that nothing read has changed in the meantime. On a
+//! conflict the whole transaction is retried. The bank transfer workload
+//! compares it against a single global lock and ordered per-account locks.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+// Project dependencies
+use super::bank::{self, Scheme};
+use crate::common;
+
+/// Source of unique ids, used to lock cells in a global order at commit time
+static NEXT_TVAR_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Error returned when a transaction observed a conflicting write and must retry
+#[derive(Debug)]
+pub struct Conflict;
+
+/// Result type used inside transactions, so conflicts propagate with `?`
+pub type StmResult<T> = Result<T, Conflict>;
+
+/// A value together with the version of its last committed write
+struct Versioned<T> {
+    version: u64,
+    value: T,
+}
+
+/// A transactional variable: a versioned cell only modified by committed transactions
+pub struct TVar<T> {
+    id: usize,
+    cell: Mutex<Versioned<T>>,
+}
+
+impl<T: Clone> TVar<T> {
+    /// Create a new transactional variable
+    pub fn new(value: T) -> Self {
+        TVar {
+            id: NEXT_TVAR_ID.fetch_add(1, Ordering::Relaxed),
+            cell: Mutex::new(Versioned { version: 0, value }),
+        }
+    }
+
+    /// Read the latest committed value outside of any transaction
+    pub fn load(&self) -> T {
+        self.cell.lock().unwrap().value.clone()
+    }
+}
+
+/// An in-flight transaction: the versions it read and the writes it buffered
+pub struct Transaction<'a, T> {
+    reads: Vec<(&'a TVar<T>, u64)>,
+    writes: Vec<(&'a TVar<T>, T)>,
+}
+
+impl<'a, T: Clone> Transaction<'a, T> {
+    fn new() -> Self {
+        Transaction {
+            reads: vec![],
+            writes: vec![],
+        }
+    }
+
+    /// Read a variable, seeing this transaction's own buffered writes first
+    pub fn read(&mut self, tvar: &'a TVar<T>) -> StmResult<T> {
+        if let Some((_, value)) = self.writes.iter().find(|(var, _)| var.id == tvar.id) {
+            return Ok(value.clone());
+        }
+
+        let (version, value) = {
+            let cell = tvar.cell.lock().unwrap();
+            (cell.version, cell.value.clone())
+        };
+
+        // Reading the same variable twice at different versions means we saw a torn snapshot
+        match self.reads.iter().find(|(var, _)| var.id == tvar.id) {
+            Some((_, seen)) if *seen != version => return Err(Conflict),
+            Some(_) => {}
+            None => self.reads.push((tvar, version)),
+        }
+
+        Ok(value)
+    }
+
+    /// Buffer a write, only made visible if the transaction commits
+    pub fn write(&mut self, tvar: &'a TVar<T>, value: T) {
+        match self.writes.iter_mut().find(|(var, _)| var.id == tvar.id) {
+            Some((_, buffered)) => *buffered = value,
+            None => self.writes.push((tvar, value)),
+        }
+    }
+
+    /// Validate the read set and publish the buffered writes atomically
+    fn commit(self) -> StmResult<()> {
+
+        // Lock every touched variable in id order so concurrent commits cannot deadlock
+        let mut touched: Vec<&TVar<T>> = self
+            .reads
+            .iter()
+            .map(|(var, _)| *var)
+            .chain(self.writes.iter().map(|(var, _)| *var))
+            .collect();
+        touched.sort_by_key(|var| var.id);
+        touched.dedup_by_key(|var| var.id);
+
+        let mut guards: Vec<(usize, MutexGuard<Versioned<T>>)> = touched
+            .iter()
+            .map(|var| (var.id, var.cell.lock().unwrap()))
+            .collect();
+
+        // Abort if anything we read was committed by someone else in the meantime
+        for (var, seen) in &self.reads {
+            let (_, guard) = guards.iter().find(|(id, _)| *id == var.id).unwrap();
+            if guard.version != *seen {
+                return Err(Conflict);
+            }
+        }
+
+        // Publish the writes and bump the versions while still holding every lock
+        for (var, value) in self.writes {
+            let (_, guard) = guards.iter_mut().find(|(id, _)| *id == var.id).unwrap();
+            guard.value = value;
+            guard.version += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run a transaction until it commits, returning its result and the number of retries
+pub fn atomically<'a, T, R, F>(mut body: F) -> (R, usize)
+where
+    T: Clone + 'a,
+    F: FnMut(&mut Transaction<'a, T>) -> StmResult<R>,
+{
+    let mut retries = 0;
+    loop {
+        let mut transaction = Transaction::new();
+        if let Ok(result) = body(&mut transaction) {
+            if transaction.commit().is_ok() {
+                return (result, retries);
+            }
+        }
+        retries += 1;
+    }
+}
+
+/// Run the STM bank transfer comparison
+pub fn run(num_threads: usize, transfers_per_thread: usize, num_accounts: usize) {
+    bank::compare_schemes(
+        &[Scheme::GlobalLock, Scheme::OrderedLocks, Scheme::Stm],
+        num_threads,
+        transfers_per_thread,
+        num_accounts,
+    );
+    common::print_info("STM never blocks readers: conflicting transactions are detected at commit and retried");
+}
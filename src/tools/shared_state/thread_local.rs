@@ -0,0 +1,142 @@
+//! Shared-nothing accumulation with `thread_local!`, merged at the end
+//!
+//! Threads tally values into a histogram. With one shared `Mutex`
+//! histogram, every tally takes the lock and the threads spend their time
+//! queueing for it. With `thread_local!` storage, each thread tallies into
+//! its own histogram without any synchronization, and only sends it over a
+//! channel once it is done, where a single merge adds the histograms up.
+//! Because the storage is reached through a function rather than a
+//! parameter, code deep in the call stack can record into it too.
+
+// Base dependencies
+use std::cell::RefCell;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// Number of histogram buckets
+const BUCKETS: usize = 64;
+
+type Tally = [u64; BUCKETS];
+
+thread_local! {
+    /// This thread's histogram, only ever touched by this thread
+    static LOCAL_TALLY: RefCell<Tally> = const { RefCell::new([0; BUCKETS]) };
+}
+
+/// Bucket of the `item`-th value produced by `thread_id`, a cheap stand-in for real work
+fn bucket_of(thread_id: usize, item: usize) -> usize {
+    (item.wrapping_mul(2_654_435_761) ^ thread_id.wrapping_mul(40_503)) % BUCKETS
+}
+
+/// Record a value into the calling thread's histogram
+fn record_local(bucket: usize) {
+    LOCAL_TALLY.with(|tally| tally.borrow_mut()[bucket] += 1);
+}
+
+/// Take the calling thread's histogram, leaving it empty for whatever the thread runs next
+fn take_local() -> Tally {
+    LOCAL_TALLY.with(|tally| tally.replace([0; BUCKETS]))
+}
+
+/// Every thread locks the shared histogram for every value
+fn shared_mutex(num_threads: usize, items: usize) -> (Duration, Tally) {
+    let tally = Arc::new(Mutex::new([0u64; BUCKETS]));
+    audit::track("shared tally", &tally);
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..num_threads)
+        .map(|thread_id| {
+            let tally = Arc::clone(&tally);
+            thread::spawn(move || {
+                for item in 0..items {
+                    chaos::perturb(Point::Lock);
+                    tally.lock().unwrap()[bucket_of(thread_id, item)] += 1;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let elapsed = start.elapsed();
+    let tally = *tally.lock().unwrap();
+    (elapsed, tally)
+}
+
+/// Every thread records into its thread-local histogram, then sends it to be merged
+fn thread_local_merge(num_threads: usize, items: usize) -> (Duration, Tally) {
+    let (sender, receiver) = mpsc::channel::<Tally>();
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..num_threads)
+        .map(|thread_id| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for item in 0..items {
+                    record_local(bucket_of(thread_id, item));
+                }
+                chaos::perturb(Point::Send);
+                sender.send(take_local()).unwrap();
+            })
+        })
+        .collect();
+    drop(sender);
+
+    // The channel closes once every thread has sent its histogram and dropped its sender
+    let mut merged = [0u64; BUCKETS];
+    for tally in receiver {
+        for (total, count) in merged.iter_mut().zip(tally) {
+            *total += count;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (elapsed, merged)
+}
+
+/// Run the thread-local accumulation example and compare it with a shared Mutex
+pub fn run(num_threads: usize, items: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads tally {} values each into a {}-bucket histogram",
+        num_threads, items, BUCKETS
+    ));
+
+    let (mutex_time, mutex_tally) = shared_mutex(num_threads, items);
+    let (local_time, local_tally) = thread_local_merge(num_threads, items);
+
+    println!();
+    println!("{:<22} {:>14} {:>16} {:>14}", "accumulator", "time", "values/s", "total");
+    let total = (num_threads * items) as f64;
+    for (name, elapsed, tally) in [
+        ("shared Mutex", mutex_time, &mutex_tally),
+        ("thread_local + merge", local_time, &local_tally),
+    ] {
+        println!(
+            "{:<22} {:>14?} {:>16.0} {:>14}",
+            name,
+            elapsed,
+            total / elapsed.as_secs_f64(),
+            tally.iter().sum::<u64>()
+        );
+    }
+
+    println!();
+    if mutex_tally == local_tally && mutex_tally.iter().sum::<u64>() == (num_threads * items) as u64 {
+        common::print_success("Both accumulators produced the same histogram, with every value counted once");
+    } else {
+        common::print_warning("The two histograms differ");
+    }
+    common::print_info("The shared Mutex serializes every single tally; the thread-local histograms need no synchronization until the one send per thread");
+    common::print_info("Shared-nothing then merge works whenever the merge is cheap and associative, like sums, counts, histograms or min/max");
+}
@@ -0,0 +1,236 @@
+//! Lock-free Treiber stack with epoch-based reclamation
+//!
+//! The stack is a linked list whose head is swapped with `compare_exchange`:
+//! a push links its node to the current head and tries to make it the new
+//! head, a pop tries to replace the head with its successor, and either one
+//! retries when another thread changed the head in between. A popped node
+//! cannot be freed right away, since a concurrent pop may still be reading
+//! it, so crossbeam-epoch defers the free until no thread can hold it.
+
+// Base dependencies
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::epoch::{self, Atomic, Owned};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+
+/// A stack that can be pushed to and popped from concurrently
+pub trait ConcurrentStack<T>: Send + Sync {
+    fn push(&self, value: T);
+    fn pop(&self) -> Option<T>;
+
+    /// Compare-and-swap attempts that lost a race and had to retry
+    fn cas_retries(&self) -> usize {
+        0
+    }
+}
+
+/// A node of the stack; the value is moved out by the pop that unlinks it
+struct Node<T> {
+    value: ManuallyDrop<T>,
+    next: Atomic<Node<T>>,
+}
+
+/// Lock-free LIFO stack
+pub struct TreiberStack<T> {
+    head: Atomic<Node<T>>,
+    retries: AtomicUsize,
+}
+
+impl<T> TreiberStack<T> {
+    // Structure constructor
+    pub fn new() -> Self {
+        TreiberStack {
+            head: Atomic::null(),
+            retries: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T> Default for TreiberStack<T> {
+    fn default() -> Self {
+        TreiberStack::new()
+    }
+}
+
+impl<T: Send + Sync> ConcurrentStack<T> for TreiberStack<T> {
+    fn push(&self, value: T) {
+        let mut node = Owned::new(Node {
+            value: ManuallyDrop::new(value),
+            next: Atomic::null(),
+        });
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire, &guard);
+            node.next.store(head, Ordering::Relaxed);
+            chaos::perturb(Point::Lock);
+
+            // Release publishes the node's contents to the thread that pops it
+            match self.head.compare_exchange(head, node, Ordering::Release, Ordering::Relaxed, &guard) {
+                Ok(_) => return,
+                Err(error) => {
+                    node = error.new;
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire, &guard);
+
+            // Safety: the guard keeps any node reachable from the head alive until it is unpinned
+            let node = unsafe { head.as_ref() }?;
+            let next = node.next.load(Ordering::Relaxed, &guard);
+            chaos::perturb(Point::Lock);
+
+            match self.head.compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, &guard) {
+                Ok(_) => unsafe {
+                    // Safety: only the pop that unlinked the node reads its value, and the node is
+                    // freed once every thread that might still see it has unpinned
+                    guard.defer_destroy(head);
+                    return Some(ManuallyDrop::into_inner(ptr::read(&node.value)));
+                },
+                Err(_) => {
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    fn cas_retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        // No other thread can reach the stack anymore, so the nodes can be freed right away
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut current = self.head.load(Ordering::Relaxed, guard);
+            while let Some(node) = current.as_ref() {
+                let next = node.next.load(Ordering::Relaxed, guard);
+                let mut owned = current.into_owned();
+                ManuallyDrop::drop(&mut owned.value);
+                current = next;
+            }
+        }
+    }
+}
+
+impl<T: Send> ConcurrentStack<T> for Mutex<Vec<T>> {
+    fn push(&self, value: T) {
+        chaos::perturb(Point::Lock);
+        self.lock().unwrap().push(value);
+    }
+
+    fn pop(&self) -> Option<T> {
+        chaos::perturb(Point::Lock);
+        self.lock().unwrap().pop()
+    }
+}
+
+/// Outcome of one stack under the workload
+struct StackReport {
+    elapsed: Duration,
+    operations: usize,
+    retries: usize,
+    conserved: bool,
+}
+
+/// Let every thread alternate pushes of its own values with pops, then check that each value came out exactly once
+fn run_stack<S: ConcurrentStack<u64> + 'static>(name: &str, stack: S, num_threads: usize, operations_per_thread: usize) -> StackReport {
+    let stack = Arc::new(stack);
+    audit::track(name, &stack);
+    let pushes_per_thread = operations_per_thread.div_ceil(2);
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..num_threads)
+        .map(|thread_id| {
+            let stack = Arc::clone(&stack);
+            thread::spawn(move || {
+                let mut next_value = (thread_id * pushes_per_thread) as u64;
+                let mut popped = vec![];
+                for operation in 0..operations_per_thread {
+                    if operation % 2 == 0 {
+                        stack.push(next_value);
+                        next_value += 1;
+                    } else if let Some(value) = stack.pop() {
+                        popped.push(value);
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    let mut popped: Vec<u64> = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+    let elapsed = start.elapsed();
+
+    // Whatever is left in the stack also counts: every pushed value must come out exactly once
+    while let Some(value) = stack.pop() {
+        popped.push(value);
+    }
+    let total = num_threads * pushes_per_thread;
+    let mut seen = vec![false; total];
+    let mut conserved = popped.len() == total;
+    for value in popped {
+        match seen.get_mut(value as usize) {
+            Some(slot) if !*slot => *slot = true,
+            _ => conserved = false,
+        }
+    }
+
+    StackReport {
+        elapsed,
+        operations: num_threads * operations_per_thread,
+        retries: stack.cas_retries(),
+        conserved,
+    }
+}
+
+/// Run the Treiber stack example and compare it with a Mutex-protected Vec
+pub fn run(num_threads: usize, operations_per_thread: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} threads alternate {} pushes and pops each on a shared stack",
+        num_threads, operations_per_thread
+    ));
+
+    println!();
+    println!("{:<16} {:>12} {:>14} {:>12} {:>10}", "stack", "time", "ops/s", "cas retries", "conserved");
+    let reports = [
+        ("Mutex<Vec>", run_stack("Mutex<Vec> stack", Mutex::new(Vec::new()), num_threads, operations_per_thread)),
+        ("TreiberStack", run_stack("Treiber stack", TreiberStack::new(), num_threads, operations_per_thread)),
+    ];
+    for (name, report) in &reports {
+        println!(
+            "{:<16} {:>12?} {:>14.0} {:>12} {:>10}",
+            name,
+            report.elapsed,
+            report.operations as f64 / report.elapsed.as_secs_f64(),
+            report.retries,
+            report.conserved
+        );
+    }
+
+    println!();
+    if reports.iter().all(|(_, report)| report.conserved) {
+        common::print_success("Every pushed value was popped exactly once from both stacks");
+    } else {
+        common::print_warning("A stack lost or duplicated a value");
+    }
+    common::print_info("The Treiber stack never blocks: a thread that loses the race on the head just retries, and the retries column counts how often");
+    common::print_info("Every operation still goes through the single head pointer, so under heavy contention it is not necessarily faster than the Mutex");
+}
@@ -4,18 +4,80 @@
 //! for executing tasks concurrently.
 
 // Base dependencies
-use std::sync::{mpsc, Arc, Mutex};
+use std::cell::RefCell;
+use std::hint::black_box;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Barrier, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 // Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
 use crate::common;
 
 /// Example of a job type that can be sent to the thread pool
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// Amount of stack each worker touches while prewarming, so its first pages are already mapped
+const PREWARM_STACK_BYTES: usize = 64 * 1024;
+
+/// What a worker last reported about itself, read by the pool's health check
+struct WorkerHealth {
+    epoch: Instant,
+    busy: AtomicBool,
+    last_beat_nanos: AtomicU64,
+}
+
+impl WorkerHealth {
+    fn new() -> Self {
+        WorkerHealth {
+            epoch: Instant::now(),
+            busy: AtomicBool::new(false),
+            last_beat_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Record that the worker is alive and making progress
+    fn beat(&self) {
+        self.last_beat_nanos.store(self.epoch.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Time since the last heartbeat of a busy worker; idle workers are always healthy
+    fn silence(&self) -> Option<Duration> {
+        if !self.busy.load(Ordering::Relaxed) {
+            return None;
+        }
+        let last_beat = Duration::from_nanos(self.last_beat_nanos.load(Ordering::Relaxed));
+        Some(self.epoch.elapsed().saturating_sub(last_beat))
+    }
+}
+
+thread_local! {
+    /// Health of the pool worker running on this thread, if any
+    static CURRENT_WORKER: RefCell<Option<Arc<WorkerHealth>>> = const { RefCell::new(None) };
+}
+
+/// Tell the pool that the job running on this thread is still making progress
+///
+/// Workers beat on their own when they pick up a job. A long job calls this
+/// now and then, so the health check can tell it apart from a stuck one.
+/// Outside a pool worker it does nothing.
+pub fn heartbeat() {
+    CURRENT_WORKER.with(|current| {
+        if let Some(health) = current.borrow().as_ref() {
+            health.beat();
+        }
+    });
+}
+
 /// A simple thread pool implementation
 pub struct ThreadPool {
     workers: Vec<Worker>,
+    retired: Vec<Worker>,
+    receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+    verbose: bool,
     sender: Option<mpsc::Sender<Job>>,
 }
 
@@ -24,6 +86,16 @@ impl ThreadPool {
 
     /// Create a new ThreadPool with the specified number of threads
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::build(size, true)
+    }
+
+    /// Create a new ThreadPool whose workers do not log every task, for measurements
+    pub fn new_quiet(size: usize) -> ThreadPool {
+        ThreadPool::build(size, false)
+    }
+
+    /// Spawn the workers and the job channel shared by both constructors
+    fn build(size: usize, verbose: bool) -> ThreadPool {
 
         // The number of threads must be greater than zero
         assert!(size > 0);
@@ -33,18 +105,22 @@ impl ThreadPool {
         
         // Wrap the receiver in an Arc and Mutex to allow shared ownership and thread-safe access
         let receiver = Arc::new(Mutex::new(receiver));
+        audit::track("thread pool receiver", &receiver);
         
         // Instantiate a new vector to hold the worker threads
         let mut workers = Vec::with_capacity(size);
 
         // Create the specified number of worker threads and add them to the pool
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), verbose));
         }
 
         // Create the ThreadPool instance with the workers and sender
         ThreadPool {
             workers,
+            retired: vec![],
+            receiver,
+            verbose,
             sender: Some(sender),
         }
     }
@@ -58,6 +134,100 @@ impl ThreadPool {
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
 
+    /// Execute a long task as resumable steps, time-sliced with the other jobs
+    ///
+    /// The pool calls `step` until it breaks with the task's result or until
+    /// `slice` has run out, in which case the task goes back to the end of the
+    /// queue and the worker moves on. Jobs queued behind a long task then wait
+    /// at most one slice, without the OS having to preempt anything. The
+    /// result arrives on the returned receiver.
+    pub fn execute_sliced<D, S>(&self, slice: Duration, step: S) -> mpsc::Receiver<D>
+    where
+        D: Send + 'static,
+        S: FnMut() -> ControlFlow<D> + Send + 'static,
+    {
+        let sender = self.sender.as_ref().unwrap();
+        let (done, result) = mpsc::channel();
+        sender.send(sliced_job(slice, step, sender.clone(), done)).unwrap();
+        result
+    }
+
+    /// Make sure every worker is running and has touched its stack, returning how long it took
+    ///
+    /// Spawning a thread returns before the OS has scheduled it, and its stack pages
+    /// are only mapped on first use. Prewarming pays both costs up front instead of
+    /// on the first tasks submitted to the pool.
+    pub fn prewarm(&self) -> Duration {
+        let start = Instant::now();
+
+        // One job per worker, each blocking on the barrier so no worker can run two of them
+        let barrier = Arc::new(Barrier::new(self.workers.len() + 1));
+        for _ in 0..self.workers.len() {
+            let barrier = Arc::clone(&barrier);
+            self.execute(move || {
+                let stack = [1u8; PREWARM_STACK_BYTES];
+                black_box(&stack);
+                barrier.wait();
+            });
+        }
+        barrier.wait();
+
+        start.elapsed()
+    }
+
+    /// Number of worker threads in the pool
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Workers busy with a job that has not sent a heartbeat for longer than `threshold`
+    pub fn stalled_workers(&self, threshold: Duration) -> Vec<(usize, Duration)> {
+        self.workers
+            .iter()
+            .filter_map(|worker| worker.health.silence().map(|silence| (worker.id, silence)))
+            .filter(|(_, silence)| *silence > threshold)
+            .collect()
+    }
+
+    /// Take a stuck worker out of the pool and start a fresh one in its place, returning the new worker's id
+    ///
+    /// A thread cannot be killed, so the stuck worker keeps its thread until its
+    /// job returns; it just no longer counts as one of the pool's workers.
+    /// Dropping the pool still waits for it.
+    pub fn replace_worker(&mut self, id: usize) -> Option<usize> {
+        let index = self.workers.iter().position(|worker| worker.id == id)?;
+        let new_id = self.workers.len() + self.retired.len();
+        let replacement = Worker::new(new_id, Arc::clone(&self.receiver), self.verbose);
+        let stuck = std::mem::replace(&mut self.workers[index], replacement);
+        self.retired.push(stuck);
+        Some(new_id)
+    }
+
+}
+
+/// One time slice of a stepped task, which queues the next slice if the task is not done
+///
+/// Each queued slice holds a sender, so dropping the pool waits for stepped tasks to finish.
+fn sliced_job<D, S>(slice: Duration, mut step: S, requeue: mpsc::Sender<Job>, done: mpsc::Sender<D>) -> Job
+where
+    D: Send + 'static,
+    S: FnMut() -> ControlFlow<D> + Send + 'static,
+{
+    Box::new(move || {
+        let deadline = Instant::now() + slice;
+        loop {
+            if let ControlFlow::Break(result) = step() {
+                let _ = done.send(result);
+                return;
+            }
+            heartbeat();
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        let next = sliced_job(slice, step, requeue.clone(), done);
+        requeue.send(next).unwrap();
+    })
 }
 
 // Gracefully shut down the thread pool when it goes out of scope
@@ -67,7 +237,7 @@ impl Drop for ThreadPool {
     fn drop(&mut self) {
         drop(self.sender.take());
 
-        for worker in &mut self.workers {
+        for worker in self.workers.iter_mut().chain(self.retired.iter_mut()) {
             if let Some(thread) = worker.thread.take() {
                 thread.join().unwrap();
             }
@@ -78,30 +248,44 @@ impl Drop for ThreadPool {
 
 /// Worker struct representing a single thread in the pool
 struct Worker {
-    #[allow(dead_code)]
     id: usize,
+    health: Arc<WorkerHealth>,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv();
-
-            match message {
-                Ok(job) => {
-                    common::print_info(&format!("Worker {id} executing task"));
-                    job();
-                }
-                Err(_) => {
-                    common::print_info(&format!("Worker {id} shutting down"));
-                    break;
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, verbose: bool) -> Worker {
+        let health = Arc::new(WorkerHealth::new());
+        let worker_health = Arc::clone(&health);
+        let thread = thread::spawn(move || {
+            CURRENT_WORKER.with(|current| *current.borrow_mut() = Some(Arc::clone(&worker_health)));
+            loop {
+                let message = receiver.lock().unwrap().recv();
+
+                match message {
+                    Ok(job) => {
+                        if verbose {
+                            common::print_info(&format!("Worker {id} executing task"));
+                        }
+                        chaos::perturb(Point::TaskStart);
+                        worker_health.beat();
+                        worker_health.busy.store(true, Ordering::Relaxed);
+                        job();
+                        worker_health.busy.store(false, Ordering::Relaxed);
+                    }
+                    Err(_) => {
+                        if verbose {
+                            common::print_info(&format!("Worker {id} shutting down"));
+                        }
+                        break;
+                    }
                 }
             }
         });
 
         Worker {
             id,
+            health,
             thread: Some(thread),
         }
     }
@@ -0,0 +1,270 @@
+//! Multi-tenant thread pool with weighted fair queuing
+//!
+//! With a single FIFO queue, a tenant that submits a flood of tasks pushes
+//! everybody else's tasks behind its own. This pool keeps one queue per
+//! tenant instead and serves them by weighted fair queuing: every tenant has
+//! a virtual clock that advances by `1 / weight` for each task it runs, and
+//! workers always take the next task of the backlogged tenant whose clock is
+//! furthest behind. While all tenants have work waiting, each one gets a
+//! share of the workers proportional to its weight, however much it queued.
+
+// Base dependencies
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::pacing;
+
+/// Busy time of one task
+const TASK_COST: Duration = Duration::from_micros(200);
+
+/// Demo tenants: name, weight and how many times `num_tasks` it submits
+const TENANTS: [(&str, u32, usize); 3] = [("flood", 1, 6), ("steady", 2, 1), ("light", 1, 1)];
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// How the pool picks the next task
+#[derive(Clone, Copy)]
+pub enum Policy {
+    /// One queue in submission order, whoever submitted
+    Fifo,
+    /// One queue per tenant, served in proportion to the tenants' weights
+    WeightedFair,
+}
+
+/// A tenant's queue and virtual clock
+struct Tenant {
+    weight: u32,
+    queue: VecDeque<Job>,
+    /// Virtual time at which the tenant's next task finishes
+    finish: f64,
+}
+
+/// Everything the workers share, behind one mutex
+struct Scheduler {
+    policy: Policy,
+    tenants: Vec<Tenant>,
+    fifo: VecDeque<Job>,
+    /// Virtual time of the last dispatched task
+    now: f64,
+    closed: bool,
+}
+
+impl Scheduler {
+    /// Take the next task according to the policy
+    fn next(&mut self) -> Option<Job> {
+        match self.policy {
+            Policy::Fifo => self.fifo.pop_front(),
+            Policy::WeightedFair => {
+                let tenant = self
+                    .tenants
+                    .iter_mut()
+                    .filter(|tenant| !tenant.queue.is_empty())
+                    .min_by(|a, b| a.finish.total_cmp(&b.finish))?;
+                self.now = tenant.finish;
+                tenant.finish += 1.0 / tenant.weight as f64;
+                tenant.queue.pop_front()
+            }
+        }
+    }
+}
+
+/// Thread pool that schedules tasks across tenants
+pub struct FairPool {
+    scheduler: Arc<(Mutex<Scheduler>, Condvar)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl FairPool {
+    /// Create a pool of `size` workers serving tenants with the given weights
+    pub fn new(size: usize, weights: &[u32], policy: Policy) -> Self {
+        let scheduler = Arc::new((
+            Mutex::new(Scheduler {
+                policy,
+                tenants: weights
+                    .iter()
+                    .map(|weight| Tenant {
+                        weight: (*weight).max(1),
+                        queue: VecDeque::new(),
+                        finish: 0.0,
+                    })
+                    .collect(),
+                fifo: VecDeque::new(),
+                now: 0.0,
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
+        audit::track("fair pool scheduler", &scheduler);
+
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let scheduler = Arc::clone(&scheduler);
+                thread::spawn(move || loop {
+                    let (lock, available) = &*scheduler;
+                    let mut state = lock.lock().unwrap();
+                    let job = loop {
+                        if let Some(job) = state.next() {
+                            break job;
+                        }
+                        if state.closed {
+                            return;
+                        }
+                        state = available.wait(state).unwrap();
+                    };
+                    drop(state);
+                    chaos::perturb(Point::TaskStart);
+                    job();
+                })
+            })
+            .collect();
+
+        FairPool { scheduler, workers }
+    }
+
+    /// Queue a task on behalf of `tenant`
+    pub fn submit<F>(&self, tenant: usize, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let (lock, available) = &*self.scheduler;
+        let mut state = lock.lock().unwrap();
+        match state.policy {
+            Policy::Fifo => state.fifo.push_back(Box::new(f)),
+            Policy::WeightedFair => {
+                // A tenant coming back from idle starts at the current virtual time instead of spending credit it banked while idle
+                let now = state.now;
+                let tenant = &mut state.tenants[tenant];
+                if tenant.queue.is_empty() {
+                    tenant.finish = tenant.finish.max(now + 1.0 / tenant.weight as f64);
+                }
+                tenant.queue.push_back(Box::new(f));
+            }
+        }
+        drop(state);
+        available.notify_one();
+    }
+}
+
+impl Drop for FairPool {
+    fn drop(&mut self) {
+        // Workers drain every queue before they see the pool closed
+        let (lock, available) = &*self.scheduler;
+        lock.lock().unwrap().closed = true;
+        available.notify_all();
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+/// Per-tenant results of one run
+struct TenantReport {
+    submitted: usize,
+    /// Completions while every tenant still had tasks queued
+    contended: usize,
+    mean_latency: Duration,
+}
+
+/// Submit every tenant's load at once (the flooding tenant first) and record the completion order
+fn run_policy(num_threads: usize, num_tasks: usize, policy: Policy) -> (Vec<TenantReport>, usize) {
+    let weights: Vec<u32> = TENANTS.iter().map(|(_, weight, _)| *weight).collect();
+    let completions = Arc::new(Mutex::new(Vec::new()));
+    audit::track("fair pool completions", &completions);
+
+    let pool = FairPool::new(num_threads, &weights, policy);
+    let start = Instant::now();
+    for (tenant, (_, _, multiplier)) in TENANTS.iter().enumerate() {
+        for _ in 0..num_tasks * multiplier {
+            let completions = Arc::clone(&completions);
+            pool.submit(tenant, move || {
+                pacing::spin_for(TASK_COST);
+                completions.lock().unwrap().push((tenant, start.elapsed()));
+            });
+        }
+    }
+    drop(pool);
+    let completions = completions.lock().unwrap();
+
+    // The contended window ends when the first tenant runs out of tasks
+    let mut remaining: Vec<usize> = TENANTS.iter().map(|(_, _, multiplier)| num_tasks * multiplier).collect();
+    let mut window = completions.len();
+    for (index, (tenant, _)) in completions.iter().enumerate() {
+        remaining[*tenant] -= 1;
+        if remaining[*tenant] == 0 {
+            window = index + 1;
+            break;
+        }
+    }
+
+    let reports = (0..TENANTS.len())
+        .map(|tenant| {
+            let latencies: Vec<Duration> = completions
+                .iter()
+                .filter(|(owner, _)| *owner == tenant)
+                .map(|(_, latency)| *latency)
+                .collect();
+            TenantReport {
+                submitted: latencies.len(),
+                contended: completions[..window].iter().filter(|(owner, _)| *owner == tenant).count(),
+                mean_latency: latencies.iter().sum::<Duration>() / latencies.len().max(1) as u32,
+            }
+        })
+        .collect();
+    (reports, window)
+}
+
+/// Run the multi-tenant example under FIFO and weighted fair queuing
+pub fn run(num_threads: usize, num_tasks: usize) {
+    let num_tasks = num_tasks.max(1);
+    let total_weight: u32 = TENANTS.iter().map(|(_, weight, _)| weight).sum();
+    common::print_info(&format!(
+        "{} workers run {:?} tasks; the flood tenant queues {} tasks before the others queue {} each",
+        num_threads,
+        TASK_COST,
+        num_tasks * TENANTS[0].2,
+        num_tasks
+    ));
+
+    let mut fair_shares_hold = true;
+    for (label, policy) in [("FIFO", Policy::Fifo), ("Weighted fair queuing", Policy::WeightedFair)] {
+        let (reports, window) = run_policy(num_threads, num_tasks, policy);
+
+        println!();
+        common::print_info(&format!("{}: {} completions while every tenant had tasks queued", label, window));
+        println!(
+            "{:<10} {:>8} {:>10} {:>14} {:>16} {:>14}",
+            "tenant", "weight", "submitted", "weight share", "contended share", "mean latency"
+        );
+        for ((name, weight, _), report) in TENANTS.iter().zip(&reports) {
+            let share = report.contended as f64 / window as f64;
+            let expected = *weight as f64 / total_weight as f64;
+            if matches!(policy, Policy::WeightedFair) && (share - expected).abs() > 0.1 {
+                fair_shares_hold = false;
+            }
+            println!(
+                "{:<10} {:>8} {:>10} {:>13.0}% {:>15.0}% {:>14?}",
+                name,
+                weight,
+                report.submitted,
+                100.0 * expected,
+                100.0 * share,
+                report.mean_latency
+            );
+        }
+    }
+
+    println!();
+    if fair_shares_hold {
+        common::print_success("Under weighted fair queuing every tenant got its weighted share while all of them were busy");
+    } else {
+        common::print_warning("A tenant's share under weighted fair queuing strayed more than 10 points from its weight");
+    }
+    common::print_info("Under FIFO the flood tenant's backlog runs first, and the other tenants wait behind all of it");
+    common::print_info("Weighted fair queuing isolates tenants: a flood only delays the tenant that caused it");
+}
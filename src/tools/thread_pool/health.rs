@@ -0,0 +1,148 @@
+//! Heartbeat health checks and replacement of stuck workers
+//!
+//! Every `ThreadPool` worker beats when it picks up a job, and long jobs call
+//! `heartbeat()` while they make progress. A worker that has been busy
+//! without beating for longer than a threshold is most likely stuck. The
+//! demo wedges one worker on a job that waits for a release that only comes
+//! at the end, and runs a monitor that flags it, once without and once with
+//! replacing it by a fresh worker.
+
+// Base dependencies
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::common;
+use crate::tools::thread_pool::code::{heartbeat, ThreadPool};
+
+/// Work between two heartbeats of a healthy task
+const TASK_CHUNK: Duration = Duration::from_millis(10);
+
+/// Number of chunks in a healthy task
+const CHUNKS_PER_TASK: usize = 5;
+
+/// Silence after which a busy worker is considered stuck
+const STALL_THRESHOLD: Duration = Duration::from_millis(200);
+
+/// Interval between two health checks of the monitor
+const CHECK_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Outcome of one monitored run
+struct HealthReport {
+    elapsed: Duration,
+    flagged: Vec<(usize, Duration)>,
+    replaced: Vec<(usize, usize)>,
+    /// Workers not stuck when the run ended
+    healthy: usize,
+}
+
+/// Run `num_tasks` healthy tasks next to one wedged task, checking the workers' health until all of them are done
+fn monitored_run(num_threads: usize, num_tasks: usize, replace: bool) -> HealthReport {
+    let mut pool = ThreadPool::new_quiet(num_threads);
+    let completed = Arc::new(AtomicUsize::new(0));
+
+    // The wedged task neither finishes nor beats until it is released
+    let (release, wedge) = mpsc::channel::<()>();
+    pool.execute(move || {
+        let _ = wedge.recv();
+    });
+
+    let start = Instant::now();
+    for _ in 0..num_tasks {
+        let completed = Arc::clone(&completed);
+        pool.execute(move || {
+            for _ in 0..CHUNKS_PER_TASK {
+                thread::sleep(TASK_CHUNK);
+                heartbeat();
+            }
+            completed.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    // Keep checking until every healthy task is done and the wedged worker was caught
+    let mut flagged = vec![];
+    let mut replaced = vec![];
+    let mut seen = HashSet::new();
+    let mut elapsed = None;
+    let deadline = STALL_THRESHOLD * 10;
+    while elapsed.is_none() || (flagged.is_empty() && start.elapsed() < deadline) {
+        thread::sleep(CHECK_INTERVAL);
+        if elapsed.is_none() && completed.load(Ordering::Relaxed) == num_tasks {
+            elapsed = Some(start.elapsed());
+        }
+        for (id, silence) in pool.stalled_workers(STALL_THRESHOLD) {
+            if !seen.insert(id) {
+                continue;
+            }
+            common::print_warning(&format!("Worker {} has been silent for {:?} while busy", id, silence));
+            flagged.push((id, silence));
+            if replace {
+                if let Some(new_id) = pool.replace_worker(id) {
+                    common::print_info(&format!("Worker {} replaced by worker {}", id, new_id));
+                    replaced.push((id, new_id));
+                }
+            }
+        }
+    }
+
+    let healthy = pool.size() - pool.stalled_workers(STALL_THRESHOLD).len();
+
+    // Let the wedged job return, so dropping the pool can join its thread
+    drop(release);
+    drop(pool);
+
+    HealthReport {
+        elapsed: elapsed.unwrap_or_else(|| start.elapsed()),
+        flagged,
+        replaced,
+        healthy,
+    }
+}
+
+/// Run the health check example, first only flagging the stuck worker, then replacing it
+pub fn run(num_threads: usize, num_tasks: usize) {
+    let num_threads = num_threads.max(2);
+    common::print_info(&format!(
+        "{} workers run {} tasks of {:?} that beat every {:?}, next to one task that never returns on its own",
+        num_threads,
+        num_tasks,
+        TASK_CHUNK * CHUNKS_PER_TASK as u32,
+        TASK_CHUNK
+    ));
+    common::print_info(&format!(
+        "A worker busy without a heartbeat for more than {:?} is flagged as stuck",
+        STALL_THRESHOLD
+    ));
+
+    println!();
+    common::print_info("Monitoring only");
+    let flag_only = monitored_run(num_threads, num_tasks, false);
+    println!();
+    common::print_info("Monitoring and replacing stuck workers");
+    let with_replacement = monitored_run(num_threads, num_tasks, true);
+
+    println!();
+    println!("{:<12} {:>14} {:>10} {:>10} {:>10}", "mode", "tasks done in", "flagged", "replaced", "healthy");
+    for (mode, report) in [("flag", &flag_only), ("replace", &with_replacement)] {
+        println!(
+            "{:<12} {:>14?} {:>10} {:>10} {:>10}",
+            mode,
+            report.elapsed,
+            report.flagged.len(),
+            report.replaced.len(),
+            report.healthy
+        );
+    }
+
+    println!();
+    if flag_only.flagged.len() == 1 && with_replacement.replaced.len() == 1 {
+        common::print_success("The wedged worker was caught in both runs and replaced in the second");
+    } else {
+        common::print_warning("The health check did not single out exactly the wedged worker");
+    }
+    common::print_info("Without replacement the pool finishes with one worker short; the replacement restores its capacity once the stall is detected");
+    common::print_info("A thread cannot be killed: the stuck worker keeps its thread until its job returns, it just stops counting as part of the pool");
+}
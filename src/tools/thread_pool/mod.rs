@@ -1,6 +1,13 @@
 
 // Re-export the commands from this module
 pub mod code;
+pub mod warmup;
+pub mod payload;
+pub mod work_stealing;
+pub mod health;
+pub mod quota;
+pub mod fair_share;
+pub mod time_slice;
 
 // Re-export the run function for easier access from main.rs
 pub use code::run;
@@ -0,0 +1,210 @@
+//! Owned vs shared vs borrowed task payloads
+//!
+//! Jobs submitted to `ThreadPool` must be `'static`, so data they read has
+//! to be either owned by the job (cloned per task) or shared through an
+//! `Arc`. A scoped pool lifts that restriction and lets jobs borrow. This
+//! module measures what each choice costs in submission time and memory.
+
+// Base dependencies
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::channel::{self, Sender};
+
+// Project dependencies
+use super::code::ThreadPool;
+use crate::audit;
+use crate::common;
+
+/// Size of the payload every task reads
+const PAYLOAD_BYTES: usize = 1024 * 1024;
+
+/// A job that may borrow from the environment of the scoped pool
+type ScopedJob<'env> = Box<dyn FnOnce() + Send + 'env>;
+
+/// Run `submit` against a pool of scoped workers, which may run jobs borrowing data that outlives the pool
+fn with_scoped_pool<'env, F>(num_threads: usize, submit: F)
+where
+    F: FnOnce(&Sender<ScopedJob<'env>>),
+{
+    let (tx, rx) = channel::unbounded::<ScopedJob<'env>>();
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            let rx = rx.clone();
+            scope.spawn(move || {
+                for job in rx {
+                    job();
+                }
+            });
+        }
+        submit(&tx);
+
+        // Closing the queue lets the workers exit, and the scope joins them
+        drop(tx);
+    });
+}
+
+/// How the payload reaches each task
+#[derive(Clone, Copy)]
+enum Payload {
+    /// Every task owns a clone of the payload
+    Owned,
+    /// Every task holds an `Arc` to a single payload
+    Shared,
+    /// Every task borrows the payload, in a scoped pool
+    Borrowed,
+}
+
+impl Payload {
+    fn label(&self) -> &'static str {
+        match self {
+            Payload::Owned => "owned (clone)",
+            Payload::Shared => "Arc",
+            Payload::Borrowed => "scoped borrow",
+        }
+    }
+}
+
+/// Measurements of one payload strategy
+struct PayloadReport {
+    submit_time: Duration,
+    total_time: Duration,
+    queued_memory: Option<u64>,
+    checksum: u64,
+}
+
+/// The work done by every task: read the whole payload
+fn checksum(payload: &[u8]) -> u64 {
+    payload.iter().map(|byte| *byte as u64).sum()
+}
+
+/// Block every worker on the gate, so submitted tasks stay queued until it opens
+fn hold_workers(pool: &ThreadPool, gate: &Arc<RwLock<()>>) {
+    for _ in 0..pool.size() {
+        let gate = Arc::clone(gate);
+        pool.execute(move || {
+            let _open = gate.read().unwrap();
+        });
+    }
+}
+
+/// Submit `num_tasks` tasks reading the payload, measuring submission, queued memory and total time
+fn measure(strategy: Payload, num_threads: usize, num_tasks: usize, payload: &Arc<Vec<u8>>) -> PayloadReport {
+    let (results, sums) = channel::unbounded::<u64>();
+    common::release_free_memory();
+    let baseline = common::resident_memory_bytes();
+
+    let start = Instant::now();
+    let (submit_time, queued) = match strategy {
+        Payload::Owned | Payload::Shared => {
+            let pool = ThreadPool::new_quiet(num_threads);
+            let gate = Arc::new(RwLock::new(()));
+            let closed = gate.write().unwrap();
+            hold_workers(&pool, &gate);
+
+            let submitted = Instant::now();
+            for _ in 0..num_tasks {
+                let results = results.clone();
+                if let Payload::Owned = strategy {
+                    let owned: Vec<u8> = payload.as_ref().clone();
+                    pool.execute(move || results.send(checksum(&owned)).unwrap());
+                } else {
+                    let shared = Arc::clone(payload);
+                    pool.execute(move || results.send(checksum(&shared)).unwrap());
+                }
+            }
+            let submit_time = submitted.elapsed();
+
+            // Every task is queued with its payload: this is the memory the strategy costs
+            let queued = common::resident_memory_bytes();
+            drop(closed);
+            drop(pool);
+            (submit_time, queued)
+        }
+        Payload::Borrowed => {
+            let borrowed: &[u8] = payload;
+            let mut submit_time = Duration::ZERO;
+            let mut queued = None;
+
+            // Hold the workers here too, so queued memory is comparable; jobs borrow the gate as well
+            let gate = RwLock::new(());
+            let gate = &gate;
+            with_scoped_pool(num_threads, |pool| {
+                let closed = gate.write().unwrap();
+                for _ in 0..num_threads {
+                    pool.send(Box::new(move || drop(gate.read().unwrap()))).unwrap();
+                }
+
+                let submitted = Instant::now();
+                for _ in 0..num_tasks {
+                    let results = results.clone();
+                    pool.send(Box::new(move || results.send(checksum(borrowed)).unwrap())).unwrap();
+                }
+                submit_time = submitted.elapsed();
+
+                queued = common::resident_memory_bytes();
+                drop(closed);
+            });
+            (submit_time, queued)
+        }
+    };
+    drop(results);
+
+    PayloadReport {
+        submit_time,
+        total_time: start.elapsed(),
+        queued_memory: baseline.zip(queued).map(|(baseline, queued)| queued.saturating_sub(baseline)),
+        checksum: sums.iter().sum(),
+    }
+}
+
+/// Run the payload strategy benchmark
+pub fn run(num_threads: usize, num_tasks: usize) {
+    common::print_info(&format!(
+        "{} tasks on {} workers each read a {} payload, held in the queue until all are submitted",
+        num_tasks,
+        num_threads,
+        common::format_bytes(PAYLOAD_BYTES as u64)
+    ));
+
+    let payload: Arc<Vec<u8>> = Arc::new((0..PAYLOAD_BYTES).map(|index| (index % 251) as u8).collect());
+    audit::track("shared payload", &payload);
+    let expected = checksum(&payload) * num_tasks as u64;
+
+    println!();
+    println!(
+        "{:<16} {:>14} {:>14} {:>14} {:>14} {:>9}",
+        "payload", "submit time", "per task", "total time", "queued memory", "checksum"
+    );
+
+    let mut mismatched = Vec::new();
+    for strategy in [Payload::Owned, Payload::Shared, Payload::Borrowed] {
+        let report = measure(strategy, num_threads, num_tasks, &payload);
+        let memory = report
+            .queued_memory
+            .map(common::format_bytes)
+            .unwrap_or_else(|| "n/a".to_string());
+        println!(
+            "{:<16} {:>14?} {:>14?} {:>14?} {:>14} {:>9}",
+            strategy.label(),
+            report.submit_time,
+            report.submit_time / num_tasks.max(1) as u32,
+            report.total_time,
+            memory,
+            if report.checksum == expected { "ok" } else { "MISMATCH" }
+        );
+        if report.checksum != expected {
+            mismatched.push(strategy.label());
+        }
+    }
+
+    println!();
+    if mismatched.is_empty() {
+        common::print_success("Every strategy computed the same checksums");
+    } else {
+        common::print_error(&format!("Checksum mismatch for: {}", mismatched.join(", ")));
+    }
+    common::print_info("Cloning copies the payload per task; an Arc costs one atomic increment; a scoped borrow costs nothing but ties the tasks to a scope");
+}
@@ -0,0 +1,198 @@
+//! CPU quota for low-priority background work
+//!
+//! Background tasks on a pool will happily take every core, and the threads
+//! serving latency-sensitive requests then wait for the scheduler. A
+//! `CpuQuota` duty-cycles the background work instead: time is cut into
+//! fixed windows, each window grants the background tasks a budget of CPU
+//! time, and a task that finds the budget spent sleeps until the next window.
+//! Work is charged by the CPU time its thread actually used, so time spent
+//! preempted does not eat into the budget.
+//! The demo measures foreground latency with no background work, with
+//! unthrottled background work, and with background work held to a quota.
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::audit;
+use crate::common;
+use crate::cpus;
+use crate::pacing;
+use crate::tools::thread_pool::code::ThreadPool;
+
+/// Length of one quota window
+const WINDOW: Duration = Duration::from_millis(20);
+
+/// Busy time of one slice of background work, charged to the quota as it completes
+///
+/// Tasks that start a slice together can all pass the budget check, so shorter slices overshoot the quota less.
+const BACKGROUND_SLICE: Duration = Duration::from_micros(500);
+
+/// Interval between two foreground requests
+const FOREGROUND_INTERVAL: Duration = Duration::from_millis(10);
+
+/// CPU time needed to serve one foreground request
+const FOREGROUND_WORK: Duration = Duration::from_millis(1);
+
+/// Budget already spent in the current window
+struct Window {
+    start: Instant,
+    used: Duration,
+}
+
+/// Limits a group of tasks to a share of the machine's CPU time
+pub struct CpuQuota {
+    budget: Duration,
+    window: Mutex<Window>,
+}
+
+impl CpuQuota {
+    /// Allow the tasks sharing this quota `percent` of the CPU time of every core
+    pub fn new(percent: u8) -> Self {
+        let cores = cpus::available() as u32;
+        CpuQuota {
+            budget: (WINDOW * cores).mul_f64(f64::from(percent.min(100)) / 100.0),
+            window: Mutex::new(Window {
+                start: Instant::now(),
+                used: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Wait for budget in the current window, run one slice of work, and charge the CPU time it used
+    pub fn run<R>(&self, work: impl FnOnce() -> R) -> R {
+        loop {
+            let resume = {
+                let mut window = self.window.lock().unwrap();
+                let now = Instant::now();
+                if now.duration_since(window.start) >= WINDOW {
+                    window.start = now;
+                    window.used = Duration::ZERO;
+                }
+                if window.used < self.budget {
+                    break;
+                }
+                window.start + WINDOW
+            };
+            thread::sleep(resume.saturating_duration_since(Instant::now()));
+        }
+
+        let (result, cost) = cpu_cost(work);
+        self.window.lock().unwrap().used += cost;
+        result
+    }
+}
+
+/// Run `work` and return its result with the CPU time it used, or the wall time where thread CPU time is unavailable
+fn cpu_cost<R>(work: impl FnOnce() -> R) -> (R, Duration) {
+    let (start, cpu_start) = (Instant::now(), common::thread_cpu_time());
+    let result = work();
+    let cost = match (cpu_start, common::thread_cpu_time()) {
+        (Some(before), Some(after)) => after.saturating_sub(before),
+        _ => start.elapsed(),
+    };
+    (result, cost)
+}
+
+/// Outcome of one run
+struct QuotaReport {
+    latencies: Vec<Duration>,
+    background_cpu: Duration,
+    elapsed: Duration,
+}
+
+/// Serve `requests` foreground requests while `background_tasks` tasks burn CPU on a pool, optionally under a quota
+fn measure(background_tasks: usize, requests: usize, quota: Option<Arc<CpuQuota>>) -> QuotaReport {
+    let pool = ThreadPool::new_quiet(background_tasks.max(1));
+    let stop = Arc::new(AtomicBool::new(false));
+    let background_nanos = Arc::new(AtomicU64::new(0));
+    audit::track("background CPU time", &background_nanos);
+
+    for _ in 0..background_tasks {
+        let (stop, background_nanos, quota) = (Arc::clone(&stop), Arc::clone(&background_nanos), quota.clone());
+        pool.execute(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let ((), cost) = cpu_cost(|| match &quota {
+                    Some(quota) => quota.run(|| pacing::spin_for(BACKGROUND_SLICE)),
+                    None => pacing::spin_for(BACKGROUND_SLICE),
+                });
+                background_nanos.fetch_add(cost.as_nanos() as u64, Ordering::Relaxed);
+            }
+        });
+    }
+
+    // Requests are due at a fixed rate; latency runs from the due time to the end of the work
+    let start = Instant::now();
+    let mut latencies = Vec::with_capacity(requests);
+    for request in 0..requests {
+        let due = start + FOREGROUND_INTERVAL * request as u32;
+        pacing::wait_until(due, pacing::PacingStrategy::Sleep);
+        pacing::spin_for(FOREGROUND_WORK);
+        latencies.push(due.elapsed());
+    }
+    let elapsed = start.elapsed();
+
+    stop.store(true, Ordering::Relaxed);
+    drop(pool);
+    latencies.sort_unstable();
+    QuotaReport {
+        latencies,
+        background_cpu: Duration::from_nanos(background_nanos.load(Ordering::Relaxed)),
+        elapsed,
+    }
+}
+
+/// Run the CPU quota example with `num_threads` background tasks, `num_tasks` foreground requests and a quota of `percent`
+pub fn run(num_threads: usize, num_tasks: usize, percent: u8) {
+    let num_tasks = num_tasks.max(1);
+    let cores = cpus::available();
+    common::print_info(&format!(
+        "{} background tasks burn CPU in {:?} slices while a foreground thread serves {} requests of {:?}, one every {:?}",
+        num_threads, BACKGROUND_SLICE, num_tasks, FOREGROUND_WORK, FOREGROUND_INTERVAL
+    ));
+    common::print_info(&format!(
+        "The quota grants the background tasks {}% of {} core(s) in every {:?} window",
+        percent.min(100),
+        cores,
+        WINDOW
+    ));
+
+    let quota_label = format!("quota {}%", percent.min(100));
+    let runs = [
+        ("idle", measure(0, num_tasks, None)),
+        ("unthrottled", measure(num_threads, num_tasks, None)),
+        (quota_label.as_str(), measure(num_threads, num_tasks, Some(Arc::new(CpuQuota::new(percent))))),
+    ];
+
+    println!();
+    println!(
+        "{:<14} {:>12} {:>12} {:>12} {:>14} {:>14}",
+        "background", "p50", "p99", "max", "bg cpu time", "bg cpu share"
+    );
+    for (label, report) in &runs {
+        println!(
+            "{:<14} {:>12?} {:>12?} {:>12?} {:>14?} {:>13.0}%",
+            label,
+            common::percentile(&report.latencies, 50.0),
+            common::percentile(&report.latencies, 99.0),
+            report.latencies.last().copied().unwrap_or_default(),
+            report.background_cpu,
+            100.0 * report.background_cpu.as_secs_f64() / (report.elapsed.as_secs_f64() * cores as f64)
+        );
+    }
+
+    println!();
+    let p99 = |index: usize| common::percentile(&runs[index].1.latencies, 99.0);
+    if p99(2) < p99(1) {
+        common::print_success("The quota kept the background work from crowding out the foreground requests");
+    } else {
+        common::print_warning("The quota did not lower the foreground tail latency in this run");
+    }
+    if num_threads <= cores {
+        common::print_info("There are no more background tasks than cores, so the foreground thread may find a free core even without a quota");
+    }
+    common::print_info("Background tasks that start a slice at the same time can all pass the budget check, so the share can land somewhat above the quota");
+}
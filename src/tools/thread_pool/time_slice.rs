@@ -0,0 +1,159 @@
+//! Cooperative time slicing of long tasks on the thread pool
+//!
+//! A pool worker runs each job to completion, so when long jobs occupy every
+//! worker, a short job submitted behind them waits until one of them ends.
+//! The OS could preempt the threads, but preempting a worker does not hand
+//! its slot to another job. Written as resumable steps instead, a long task
+//! gives its worker back after every time slice and queues itself again, and
+//! the short jobs that arrived in the meantime get to run in between.
+
+// Base dependencies
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use crate::common;
+use crate::pacing;
+use crate::tools::thread_pool::code::ThreadPool;
+
+/// Busy time of one step of a long task
+const STEP: Duration = Duration::from_micros(100);
+
+/// Steps in one long task
+const LONG_STEPS: usize = 400;
+
+/// Time a long task may keep its worker before it goes back to the queue
+const SLICE: Duration = Duration::from_millis(1);
+
+/// Interval between two short tasks
+const SHORT_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Busy time of one short task
+const SHORT_WORK: Duration = Duration::from_micros(100);
+
+/// How the long tasks are submitted
+#[derive(Clone, Copy)]
+enum Mode {
+    /// One job that runs every step
+    Monolithic,
+    /// One step at a time, time-sliced by the pool
+    Sliced,
+}
+
+/// A long task as resumable steps, finishing with the time it completed at
+fn long_task(start: Instant) -> impl FnMut() -> ControlFlow<Duration> + Send + 'static {
+    let mut remaining = LONG_STEPS;
+    move || {
+        pacing::spin_for(STEP);
+        remaining -= 1;
+        if remaining == 0 {
+            ControlFlow::Break(start.elapsed())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+/// Outcome of one mode
+struct SliceReport {
+    /// Time short tasks spent queued, sorted
+    waits: Vec<Duration>,
+    /// Completion times of the long tasks, sorted
+    long_done: Vec<Duration>,
+    elapsed: Duration,
+}
+
+/// Occupy every worker with a long task, then submit `short_tasks` short ones at a fixed rate
+fn measure(num_threads: usize, short_tasks: usize, mode: Mode) -> SliceReport {
+    let pool = ThreadPool::new_quiet(num_threads);
+    pool.prewarm();
+    let start = Instant::now();
+
+    let long_results: Vec<mpsc::Receiver<Duration>> = (0..num_threads)
+        .map(|_| match mode {
+            Mode::Monolithic => {
+                let (done, result) = mpsc::channel();
+                let mut step = long_task(start);
+                pool.execute(move || {
+                    let finished = loop {
+                        if let ControlFlow::Break(finished) = step() {
+                            break finished;
+                        }
+                    };
+                    done.send(finished).unwrap();
+                });
+                result
+            }
+            Mode::Sliced => pool.execute_sliced(SLICE, long_task(start)),
+        })
+        .collect();
+
+    let (wait_sender, wait_receiver) = mpsc::channel();
+    for task in 0..short_tasks {
+        pacing::wait_until(start + SHORT_INTERVAL * (task as u32 + 1), pacing::PacingStrategy::Sleep);
+        let (submitted, wait_sender) = (Instant::now(), wait_sender.clone());
+        pool.execute(move || {
+            let waited = submitted.elapsed();
+            pacing::spin_for(SHORT_WORK);
+            wait_sender.send(waited).unwrap();
+        });
+    }
+    drop(wait_sender);
+
+    let mut waits: Vec<Duration> = wait_receiver.iter().collect();
+    let mut long_done: Vec<Duration> = long_results.iter().map(|result| result.recv().unwrap()).collect();
+    let elapsed = start.elapsed();
+    drop(pool);
+
+    waits.sort_unstable();
+    long_done.sort_unstable();
+    SliceReport {
+        waits,
+        long_done,
+        elapsed,
+    }
+}
+
+/// Run the time-slicing example, comparing monolithic and sliced long tasks
+pub fn run(num_threads: usize, num_tasks: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} long tasks of {} x {:?} steps occupy every worker while {} short tasks of {:?} arrive, one every {:?}",
+        num_threads, LONG_STEPS, STEP, num_tasks, SHORT_WORK, SHORT_INTERVAL
+    ));
+    common::print_info(&format!("Sliced long tasks give their worker back every {:?}", SLICE));
+
+    let reports = [
+        ("monolithic", measure(num_threads, num_tasks, Mode::Monolithic)),
+        ("sliced", measure(num_threads, num_tasks, Mode::Sliced)),
+    ];
+
+    println!();
+    println!(
+        "{:<12} {:>14} {:>14} {:>14} {:>14} {:>14} {:>12}",
+        "long tasks", "short p50", "short p99", "short max", "first long", "last long", "total"
+    );
+    for (label, report) in &reports {
+        println!(
+            "{:<12} {:>14?} {:>14?} {:>14?} {:>14?} {:>14?} {:>12?}",
+            label,
+            common::percentile(&report.waits, 50.0),
+            common::percentile(&report.waits, 99.0),
+            report.waits.last().copied().unwrap_or_default(),
+            report.long_done.first().copied().unwrap_or_default(),
+            report.long_done.last().copied().unwrap_or_default(),
+            report.elapsed
+        );
+    }
+
+    println!();
+    let p99 = |index: usize| common::percentile(&reports[index].1.waits, 99.0);
+    if p99(1) < p99(0) {
+        common::print_success("Time slicing cut the queueing delay of the short tasks behind the long ones");
+    } else {
+        common::print_warning("Time slicing did not lower the short tasks' queueing delay in this run");
+    }
+    common::print_info("Monolithic long tasks hold their workers to the end, so short tasks queue until one finishes");
+    common::print_info("Sliced long tasks finish a little later: they share the workers, and each slice goes to the back of the queue");
+}
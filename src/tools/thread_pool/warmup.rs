@@ -0,0 +1,113 @@
+//! Cold start vs warm start latency of the thread pool
+//!
+//! Creating a pool returns as soon as the worker threads are spawned, not
+//! when they are running: the first tasks also pay for thread scheduling
+//! and for the first page faults on each worker stack. This module measures
+//! that cold-start latency, the steady-state latency, and how much of the
+//! gap `ThreadPool::prewarm()` closes.
+
+// Base dependencies
+use std::hint::black_box;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// Project dependencies
+use super::code::ThreadPool;
+use crate::common;
+
+/// Stack touched by every probe task, so a cold worker takes its first page faults
+const PROBE_STACK_BYTES: usize = 16 * 1024;
+
+/// Probe tasks submitted one by one after the first, to measure the steady state
+const STEADY_TASKS: usize = 20;
+
+/// Submit a probe task and wait for it, returning the delay between submission and start
+fn probe(pool: &ThreadPool) -> Duration {
+    let (tx, rx) = mpsc::channel();
+    let submitted = Instant::now();
+    pool.execute(move || {
+        let started = Instant::now();
+        let stack = [1u8; PROBE_STACK_BYTES];
+        black_box(&stack);
+        tx.send(started).unwrap();
+    });
+    rx.recv().unwrap() - submitted
+}
+
+/// Latencies collected over every trial of one start mode
+#[derive(Default)]
+struct Samples {
+    creation: Vec<Duration>,
+    prewarm: Vec<Duration>,
+    first_task: Vec<Duration>,
+    steady: Vec<Duration>,
+}
+
+/// Create a pool, optionally prewarm it, and probe the first task then the steady state
+fn measure(num_threads: usize, trials: usize, prewarm: bool) -> Samples {
+    let mut samples = Samples::default();
+
+    for _ in 0..trials {
+        let start = Instant::now();
+        let pool = ThreadPool::new_quiet(num_threads);
+        samples.creation.push(start.elapsed());
+
+        if prewarm {
+            samples.prewarm.push(pool.prewarm());
+        }
+
+        samples.first_task.push(probe(&pool));
+        for _ in 0..STEADY_TASKS {
+            samples.steady.push(probe(&pool));
+        }
+    }
+
+    samples
+}
+
+/// Median of a set of samples
+fn median(samples: &mut [Duration]) -> Duration {
+    samples.sort();
+    common::percentile(samples, 50.0)
+}
+
+/// Run the cold start vs warm start measurement
+pub fn run(num_threads: usize, trials: usize) {
+    let trials = trials.max(1);
+    common::print_info(&format!(
+        "Creating a {}-thread pool {} times per mode, probing the first task then {} more one by one",
+        num_threads, trials, STEADY_TASKS
+    ));
+
+    println!();
+    println!(
+        "{:<12} {:>12} {:>12} {:>14} {:>14} {:>12}",
+        "mode", "creation", "prewarm", "first p50", "first max", "steady p50"
+    );
+
+    let mut first_medians = vec![];
+    for (label, prewarm) in [("cold", false), ("prewarmed", true)] {
+        let mut samples = measure(num_threads, trials, prewarm);
+        let first_max = samples.first_task.iter().max().copied().unwrap_or_default();
+        let first_p50 = median(&mut samples.first_task);
+        let prewarm_p50 = if prewarm { format!("{:?}", median(&mut samples.prewarm)) } else { "-".to_string() };
+        println!(
+            "{:<12} {:>12?} {:>12} {:>14?} {:>14?} {:>12?}",
+            label,
+            median(&mut samples.creation),
+            prewarm_p50,
+            first_p50,
+            first_max,
+            median(&mut samples.steady)
+        );
+        first_medians.push(first_p50);
+    }
+
+    println!();
+    common::print_success(&format!(
+        "Prewarming changed the median first-task latency from {:?} to {:?}",
+        first_medians[0], first_medians[1]
+    ));
+    common::print_info("A cold pool's first task waits for a worker to be scheduled and to fault in its stack pages");
+    common::print_info("prewarm() pays that cost up front, so latency-sensitive work starts on a pool that is already in steady state");
+}
@@ -0,0 +1,163 @@
+//! Work-stealing pool with per-worker steal statistics
+//!
+//! The repository's `ThreadPool` hands jobs out from one shared queue, so
+//! there is nothing to steal. This module runs a small work-stealing pool
+//! instead: every worker owns a deque, idle workers steal from random
+//! victims, and every attempt is recorded. All tasks start on worker 0, so
+//! the steal matrix printed at the end shows the imbalance being corrected.
+
+// Base dependencies
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Third-party dependencies
+use crossbeam::deque::{Steal, Stealer, Worker};
+use rand::Rng;
+
+// Project dependencies
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::pacing;
+
+/// Shortest and longest busy time of a single task
+const TASK_COST: (Duration, Duration) = (Duration::from_micros(20), Duration::from_micros(200));
+
+/// Steal statistics gathered by one worker
+struct WorkerStats {
+    executed: usize,
+    attempts: usize,
+    successes: usize,
+    /// Tasks obtained by stealing, counting every task moved by a batch steal
+    stolen_tasks: usize,
+    /// How often each other worker was picked as the victim
+    chosen: Vec<usize>,
+    /// How often stealing from each other worker succeeded
+    stolen_from: Vec<usize>,
+}
+
+/// Run a worker: drain the local deque, then steal from random victims until every task is done
+fn work(id: usize, local: Worker<Duration>, stealers: Arc<Vec<Stealer<Duration>>>, remaining: Arc<AtomicUsize>) -> WorkerStats {
+    let workers = stealers.len();
+    let mut stats = WorkerStats {
+        executed: 0,
+        attempts: 0,
+        successes: 0,
+        stolen_tasks: 0,
+        chosen: vec![0; workers],
+        stolen_from: vec![0; workers],
+    };
+    let mut rng = rand::thread_rng();
+
+    while remaining.load(Ordering::Acquire) > 0 {
+        if let Some(cost) = local.pop() {
+            chaos::perturb(Point::TaskStart);
+            pacing::spin_for(cost);
+            stats.executed += 1;
+            remaining.fetch_sub(1, Ordering::AcqRel);
+            continue;
+        }
+        if workers == 1 {
+            continue;
+        }
+
+        // Pick a victim other than ourselves and try to take half of its deque
+        let victim = (id + rng.gen_range(1..workers)) % workers;
+        stats.attempts += 1;
+        stats.chosen[victim] += 1;
+        let before = local.len();
+        match stealers[victim].steal_batch_and_pop(&local) {
+            Steal::Success(cost) => {
+                stats.successes += 1;
+                stats.stolen_from[victim] += 1;
+                stats.stolen_tasks += 1 + local.len() - before;
+                pacing::spin_for(cost);
+                stats.executed += 1;
+                remaining.fetch_sub(1, Ordering::AcqRel);
+            }
+            Steal::Empty | Steal::Retry => thread::yield_now(),
+        }
+    }
+
+    stats
+}
+
+/// Run the work-stealing example, starting with every task on worker 0
+pub fn run(num_threads: usize, num_tasks: usize) {
+    let num_threads = num_threads.max(1);
+    common::print_info(&format!(
+        "{} tasks of {:?} - {:?} are all queued on worker 0 of {} workers",
+        num_tasks, TASK_COST.0, TASK_COST.1, num_threads
+    ));
+
+    // Every worker owns a deque; the others only see its stealer
+    let locals: Vec<Worker<Duration>> = (0..num_threads).map(|_| Worker::new_fifo()).collect();
+    let stealers = Arc::new(locals.iter().map(Worker::stealer).collect::<Vec<_>>());
+    let mut rng = rand::thread_rng();
+    for _ in 0..num_tasks {
+        locals[0].push(rng.gen_range(TASK_COST.0..TASK_COST.1));
+    }
+    let remaining = Arc::new(AtomicUsize::new(num_tasks));
+
+    let start = Instant::now();
+    let handles: Vec<_> = locals
+        .into_iter()
+        .enumerate()
+        .map(|(id, local)| {
+            let stealers = Arc::clone(&stealers);
+            let remaining = Arc::clone(&remaining);
+            thread::spawn(move || work(id, local, stealers, remaining))
+        })
+        .collect();
+    let stats: Vec<WorkerStats> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    let elapsed = start.elapsed();
+
+    // Per-worker summary
+    println!();
+    println!(
+        "{:<8} {:>10} {:>10} {:>10} {:>10} {:>14}",
+        "worker", "executed", "attempts", "steals", "success", "tasks stolen"
+    );
+    for (id, worker) in stats.iter().enumerate() {
+        println!(
+            "{:<8} {:>10} {:>10} {:>10} {:>9.1}% {:>14}",
+            id,
+            worker.executed,
+            worker.attempts,
+            worker.successes,
+            worker.successes as f64 / worker.attempts.max(1) as f64 * 100.0,
+            worker.stolen_tasks
+        );
+    }
+
+    // Steal matrix: successful steals / victim picks, thief per row and victim per column
+    println!();
+    common::print_info("Steal matrix: successful steals / times picked as victim (row = thief, column = victim)");
+    print!("{:<8}", "thief");
+    for victim in 0..num_threads {
+        print!(" {:>13}", format!("victim {}", victim));
+    }
+    println!();
+    for (thief, worker) in stats.iter().enumerate() {
+        print!("{:<8}", thief);
+        for victim in 0..num_threads {
+            let cell = if victim == thief {
+                "-".to_string()
+            } else {
+                format!("{}/{}", worker.stolen_from[victim], worker.chosen[victim])
+            };
+            print!(" {:>13}", cell);
+        }
+        println!();
+    }
+
+    let executed: usize = stats.iter().map(|worker| worker.executed).sum();
+    println!();
+    if executed == num_tasks {
+        common::print_success(&format!("All {} tasks executed in {:?}", executed, elapsed));
+    } else {
+        common::print_warning(&format!("Executed {} tasks, expected {}", executed, num_tasks));
+    }
+    common::print_info("Only worker 0 starts with work: the first column shows the others pulling it over, and later columns show them stealing from each other");
+}
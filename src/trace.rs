@@ -0,0 +1,203 @@
+/*
+    Message-flow tracing used to visualize how messages move between threads
+*/
+
+// Base dependencies
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+/// Kind of a traced channel operation
+#[derive(Clone, Copy, PartialEq)]
+pub enum TraceKind {
+    Send,
+    Receive,
+}
+
+/// A single traced channel operation
+struct TraceEvent {
+    kind: TraceKind,
+    micros: u64,
+    thread: usize,
+    channel: String,
+    message: String,
+}
+
+/// Collected events plus a compact numbering of the threads that produced them
+struct Tracer {
+    start: Instant,
+    state: Mutex<TracerState>,
+}
+
+#[derive(Default)]
+struct TracerState {
+    events: Vec<TraceEvent>,
+    threads: HashMap<ThreadId, usize>,
+}
+
+/// Global tracer, only initialized when tracing is enabled
+static TRACER: OnceLock<Tracer> = OnceLock::new();
+
+/// Start recording channel events for the rest of the process
+pub fn enable() {
+    TRACER.get_or_init(|| Tracer {
+        start: Instant::now(),
+        state: Mutex::new(TracerState::default()),
+    });
+}
+
+/// Whether tracing was enabled
+pub fn is_enabled() -> bool {
+    TRACER.get().is_some()
+}
+
+/// Record a send or receive on the named channel (no-op when tracing is disabled)
+pub fn record(kind: TraceKind, channel: &str, message: &str) {
+    let Some(tracer) = TRACER.get() else {
+        return;
+    };
+
+    let micros = tracer.start.elapsed().as_micros() as u64;
+    let mut state = tracer.state.lock().unwrap();
+    let next_thread = state.threads.len();
+    let thread = *state.threads.entry(thread::current().id()).or_insert(next_thread);
+    state.events.push(TraceEvent {
+        kind,
+        micros,
+        thread,
+        channel: channel.to_string(),
+        message: message.to_string(),
+    });
+}
+
+/// Drop the most recent matching event, for an operation that turned out to fail
+pub fn retract(kind: TraceKind, channel: &str, message: &str) {
+    let Some(tracer) = TRACER.get() else {
+        return;
+    };
+
+    let mut state = tracer.state.lock().unwrap();
+    if let Some(index) = state
+        .events
+        .iter()
+        .rposition(|event| event.kind == kind && event.channel == channel && event.message == message)
+    {
+        state.events.remove(index);
+    }
+}
+
+/// Escape a string for inclusion in a JSON document
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Pair every receive with the send of the same message on the same channel
+fn matched_pairs(events: &[TraceEvent]) -> Vec<(&TraceEvent, &TraceEvent)> {
+    let mut pending: HashMap<(&str, &str), VecDeque<&TraceEvent>> = HashMap::new();
+    let mut pairs = vec![];
+
+    let mut ordered: Vec<&TraceEvent> = events.iter().collect();
+    ordered.sort_by_key(|event| (event.micros, event.kind == TraceKind::Receive));
+
+    for event in ordered {
+        let key = (event.channel.as_str(), event.message.as_str());
+        match event.kind {
+            TraceKind::Send => pending.entry(key).or_default().push_back(event),
+            TraceKind::Receive => {
+                if let Some(send) = pending.get_mut(&key).and_then(VecDeque::pop_front) {
+                    pairs.push((send, event));
+                }
+            }
+        }
+    }
+
+    pairs
+}
+
+/// Render events as Chrome trace JSON (chrome://tracing, Perfetto), with flow arrows send -> receive
+fn to_chrome_trace(events: &[TraceEvent]) -> String {
+    let mut entries = vec![];
+
+    for event in events {
+        let name = match event.kind {
+            TraceKind::Send => "send",
+            TraceKind::Receive => "receive",
+        };
+        entries.push(format!(
+            "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{},\"pid\":1,\"tid\":{},\"args\":{{\"message\":\"{}\"}}}}",
+            name,
+            json_escape(&event.channel),
+            event.micros,
+            event.thread,
+            json_escape(&event.message)
+        ));
+    }
+
+    for (id, (send, receive)) in matched_pairs(events).into_iter().enumerate() {
+        entries.push(format!(
+            "{{\"name\":\"message\",\"cat\":\"{}\",\"ph\":\"s\",\"id\":{},\"ts\":{},\"pid\":1,\"tid\":{}}}",
+            json_escape(&send.channel),
+            id,
+            send.micros,
+            send.thread
+        ));
+        entries.push(format!(
+            "{{\"name\":\"message\",\"cat\":\"{}\",\"ph\":\"f\",\"bp\":\"e\",\"id\":{},\"ts\":{},\"pid\":1,\"tid\":{}}}",
+            json_escape(&receive.channel),
+            id,
+            receive.micros,
+            receive.thread
+        ));
+    }
+
+    format!("{{\"traceEvents\":[\n{}\n]}}\n", entries.join(",\n"))
+}
+
+/// Render matched send/receive pairs as a Mermaid sequence diagram
+fn to_sequence_diagram(events: &[TraceEvent]) -> String {
+    let mut diagram = String::from("sequenceDiagram\n");
+    for (send, receive) in matched_pairs(events) {
+        diagram.push_str(&format!(
+            "    T{}->>T{}: [{}] {} (+{}us)\n",
+            send.thread,
+            receive.thread,
+            send.channel,
+            send.message.replace([';', '#'], " "),
+            receive.micros - send.micros
+        ));
+    }
+    diagram
+}
+
+/// Write the recorded events to `path`, returning how many events were written
+///
+/// Files ending in `.json` get Chrome trace JSON; anything else gets a Mermaid sequence diagram.
+pub fn write(path: &Path) -> io::Result<usize> {
+    let Some(tracer) = TRACER.get() else {
+        return Ok(0);
+    };
+
+    let state = tracer.state.lock().unwrap();
+    let contents = if path.extension().is_some_and(|extension| extension == "json") {
+        to_chrome_trace(&state.events)
+    } else {
+        to_sequence_diagram(&state.events)
+    };
+
+    fs::write(path, contents)?;
+    Ok(state.events.len())
+}
@@ -0,0 +1,256 @@
+/*
+    Channel wrappers measuring per-message latency and queue depth
+*/
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
+
+// Third-party dependencies
+use crossbeam::channel;
+use tokio::sync::mpsc as tokio_mpsc;
+
+// Project dependencies
+use crate::audit;
+use crate::chaos::{self, Point};
+use crate::common;
+use crate::histogram::Histogram;
+use crate::metrics::{self, Gauge};
+use crate::trace::{self, TraceKind};
+
+/// A message travelling through a traced channel, stamped when it was sent
+pub struct Stamped<T> {
+    value: T,
+    id: u64,
+    sent_at: Instant,
+}
+
+/// Statistics gathered for one traced channel
+struct ChannelStats {
+    name: String,
+    latency: Mutex<Histogram>,
+    depth: AtomicI64,
+    max_depth: AtomicI64,
+    next_id: AtomicU64,
+}
+
+/// Whether per-channel statistics are collected
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Every traced channel created while statistics or the audit are enabled
+static REGISTRY: Mutex<Vec<Arc<ChannelStats>>> = Mutex::new(vec![]);
+
+/// Shared instrumentation of both ends of a traced channel
+///
+/// Every send and receive also goes to the trace recorder and to the backlog
+/// gauges, which only do work when `--trace` or `--metrics-out` are enabled.
+#[derive(Clone)]
+struct Probe {
+    stats: Arc<ChannelStats>,
+    gauge: Gauge,
+}
+
+impl Probe {
+    fn new(name: &str) -> Self {
+        let stats = Arc::new(ChannelStats {
+            name: name.to_string(),
+            latency: Mutex::new(Histogram::new()),
+            depth: AtomicI64::new(0),
+            max_depth: AtomicI64::new(0),
+            next_id: AtomicU64::new(0),
+        });
+        if ENABLED.load(Ordering::Relaxed) || audit::is_enabled() {
+            REGISTRY.lock().unwrap().push(Arc::clone(&stats));
+        }
+        Probe {
+            stats,
+            gauge: metrics::gauge(name),
+        }
+    }
+
+    /// Stamp an outgoing message and count it as queued, until `on_failed` says otherwise
+    fn on_send<T>(&self, value: T) -> Stamped<T> {
+        chaos::perturb(Point::Send);
+        let id = self.stats.next_id.fetch_add(1, Ordering::Relaxed);
+        trace::record(TraceKind::Send, &self.stats.name, &format!("message {}", id));
+        self.gauge.add(1);
+        let depth = self.stats.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.stats.max_depth.fetch_max(depth, Ordering::Relaxed);
+        Stamped {
+            value,
+            id,
+            sent_at: Instant::now(),
+        }
+    }
+
+    /// Undo `on_send` for a message the channel refused, handing back its value
+    fn on_failed<T>(&self, stamped: Stamped<T>) -> T {
+        self.stats.depth.fetch_sub(1, Ordering::Relaxed);
+        self.gauge.sub(1);
+        trace::retract(TraceKind::Send, &self.stats.name, &format!("message {}", stamped.id));
+        stamped.value
+    }
+
+    /// Record how long a message spent in the channel and hand back its value
+    fn on_recv<T>(&self, stamped: Stamped<T>) -> T {
+        let latency = stamped.sent_at.elapsed();
+        self.stats.depth.fetch_sub(1, Ordering::Relaxed);
+        self.gauge.sub(1);
+        trace::record(TraceKind::Receive, &self.stats.name, &format!("message {}", stamped.id));
+        if ENABLED.load(Ordering::Relaxed) {
+            self.stats.latency.lock().unwrap().record(latency);
+        }
+        stamped.value
+    }
+}
+
+/// Sending side of a traced channel, wrapping the sender of the underlying channel
+pub struct TracedSender<S> {
+    inner: S,
+    probe: Probe,
+}
+
+/// Receiving side of a traced channel, wrapping the receiver of the underlying channel
+pub struct TracedReceiver<R> {
+    inner: R,
+    probe: Probe,
+}
+
+impl<S: Clone> Clone for TracedSender<S> {
+    fn clone(&self) -> Self {
+        TracedSender {
+            inner: self.inner.clone(),
+            probe: self.probe.clone(),
+        }
+    }
+}
+
+impl<R: Clone> Clone for TracedReceiver<R> {
+    fn clone(&self) -> Self {
+        TracedReceiver {
+            inner: self.inner.clone(),
+            probe: self.probe.clone(),
+        }
+    }
+}
+
+/// Traced ends of a standard library `mpsc` channel
+pub type StdSender<T> = TracedSender<mpsc::Sender<Stamped<T>>>;
+pub type StdReceiver<T> = TracedReceiver<mpsc::Receiver<Stamped<T>>>;
+
+/// Traced ends of a crossbeam channel
+pub type CrossbeamSender<T> = TracedSender<channel::Sender<Stamped<T>>>;
+pub type CrossbeamReceiver<T> = TracedReceiver<channel::Receiver<Stamped<T>>>;
+
+/// Traced ends of a tokio unbounded `mpsc` channel
+pub type TokioSender<T> = TracedSender<tokio_mpsc::UnboundedSender<Stamped<T>>>;
+pub type TokioReceiver<T> = TracedReceiver<tokio_mpsc::UnboundedReceiver<Stamped<T>>>;
+
+/// Traced standard library `mpsc::channel`
+pub fn std_channel<T>(name: &str) -> (StdSender<T>, StdReceiver<T>) {
+    let (tx, rx) = mpsc::channel();
+    let probe = Probe::new(name);
+    (
+        TracedSender { inner: tx, probe: probe.clone() },
+        TracedReceiver { inner: rx, probe },
+    )
+}
+
+impl<T> StdSender<T> {
+    pub fn send(&self, value: T) -> Result<(), mpsc::SendError<T>> {
+        self.inner
+            .send(self.probe.on_send(value))
+            .map_err(|error| mpsc::SendError(self.probe.on_failed(error.0)))
+    }
+}
+
+impl<T> StdReceiver<T> {
+    pub fn recv(&self) -> Result<T, mpsc::RecvError> {
+        self.inner.recv().map(|stamped| self.probe.on_recv(stamped))
+    }
+}
+
+/// Traced crossbeam `channel::unbounded`
+pub fn crossbeam_unbounded<T>(name: &str) -> (CrossbeamSender<T>, CrossbeamReceiver<T>) {
+    let (tx, rx) = channel::unbounded();
+    let probe = Probe::new(name);
+    (
+        TracedSender { inner: tx, probe: probe.clone() },
+        TracedReceiver { inner: rx, probe },
+    )
+}
+
+impl<T> CrossbeamSender<T> {
+    pub fn send(&self, value: T) -> Result<(), channel::SendError<T>> {
+        self.inner
+            .send(self.probe.on_send(value))
+            .map_err(|error| channel::SendError(self.probe.on_failed(error.0)))
+    }
+}
+
+impl<T> CrossbeamReceiver<T> {
+    pub fn recv(&self) -> Result<T, channel::RecvError> {
+        self.inner.recv().map(|stamped| self.probe.on_recv(stamped))
+    }
+}
+
+/// Traced tokio `mpsc::unbounded_channel`
+pub fn tokio_unbounded<T>(name: &str) -> (TokioSender<T>, TokioReceiver<T>) {
+    let (tx, rx) = tokio_mpsc::unbounded_channel();
+    let probe = Probe::new(name);
+    (
+        TracedSender { inner: tx, probe: probe.clone() },
+        TracedReceiver { inner: rx, probe },
+    )
+}
+
+impl<T> TokioSender<T> {
+    pub fn send(&self, value: T) -> Result<(), tokio_mpsc::error::SendError<T>> {
+        self.inner
+            .send(self.probe.on_send(value))
+            .map_err(|error| tokio_mpsc::error::SendError(self.probe.on_failed(error.0)))
+    }
+}
+
+impl<T> TokioReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        let stamped = self.inner.recv().await?;
+        Some(self.probe.on_recv(stamped))
+    }
+}
+
+/// Start collecting latency histograms for traced channels created from now on
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Name and remaining depth of every traced channel that still holds messages
+pub fn undrained() -> Vec<(String, i64)> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|stats| (stats.name.clone(), stats.depth.load(Ordering::Relaxed)))
+        .filter(|(_, depth)| *depth > 0)
+        .collect()
+}
+
+/// Print the latency histogram and queue depth of every traced channel
+pub fn report() {
+    for stats in REGISTRY.lock().unwrap().iter() {
+        let latency = stats.latency.lock().unwrap();
+        println!();
+        common::print_info(&format!(
+            "Channel '{}': {} messages, latency mean {:?} / p50 {:?} / p99 {:?} / max {:?}, max queue depth {}",
+            stats.name,
+            latency.count(),
+            latency.mean(),
+            latency.percentile(50.0),
+            latency.percentile(99.0),
+            latency.max(),
+            stats.max_depth.load(Ordering::Relaxed)
+        ));
+        latency.print();
+    }
+}
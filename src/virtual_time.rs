@@ -0,0 +1,41 @@
+/*
+    Virtual time for the async demos: a paused Tokio clock that jumps straight to the next timer
+*/
+
+// Base dependencies
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Third-party dependencies
+use tokio::runtime::{Builder, Runtime};
+
+// Project dependencies
+use crate::cpus;
+
+/// Whether the timed async demos run on a paused clock
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Run the timed async demos on virtual time for the rest of the process
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether virtual time was enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runtime for a timed async demo
+///
+/// With virtual time this is a current-thread runtime whose clock starts
+/// paused: whenever every task is waiting on a timer, the clock advances to
+/// the earliest one instead of sleeping. Sleeps, timeouts and intervals then
+/// complete instantly, and a single thread polls the tasks in the same order
+/// on every run. `tokio::time::Instant` reads the virtual clock, so durations
+/// measured with it are the simulated ones.
+pub fn runtime() -> Runtime {
+    if is_enabled() {
+        Builder::new_current_thread().enable_all().start_paused(true).build().unwrap()
+    } else {
+        cpus::multi_thread_runtime()
+    }
+}